@@ -1,6 +1,16 @@
+use super::cache::DirectoryCache;
+use super::config::{Config, InitialFocus};
+use super::fs::DiskState;
 use super::git::{FileStatus, GitRepository};
+use super::keymap::Keymap;
+use super::merge::{merge3, MARKER_OURS, MARKER_THEIRS};
+use super::preview::PreviewCache;
+use super::security::check_file_size_within;
+use super::theme::Theme;
+use super::tree::{is_hidden, is_placeholder, FileTree};
+use super::watch::FsWatcher;
 use anyhow::{Context, Result};
-use ratatui::prelude::*;
+use std::cell::Cell;
 use std::io;
 use std::{
     collections::{HashMap, HashSet},
@@ -48,22 +58,38 @@ pub struct FileNode {
 
 #[derive(Debug)]
 pub struct App {
+    pub config: Config,
     pub root: PathBuf,
     pub focus: Focus,
     // Dual panes like MC
     pub left_dir: PathBuf,
     pub right_dir: PathBuf,
     pub left_tree: Vec<TreeItem<'static, String>>,
-    pub right_tree: Vec<TreeItem<'static, String>>,
+    /// Directory listings behind `left_tree`, read as folders are expanded
+    pub files: FileTree,
     pub left_state: TreeState<String>,
+    /// Folders open in the tree as of the last [`App::poll_tree`]
+    expanded: HashSet<PathBuf>,
     // Multi-selection for main file tree
     pub tree_selection: HashSet<String>, // Using file paths as keys
     pub tree_selection_anchor: Option<String>,
     pub editor: TextArea<'static>,
     pub opened: Option<PathBuf>,
-    pub last_saved_text: Option<String>,
+    /// Buffer text as last read or written; set through `set_saved_text`
+    last_saved_text: Option<String>,
+    /// Revision `is_dirty` last compared the buffer at, and its answer
+    dirty: Cell<Option<(u64, bool)>>,
+    /// The open file as it was on disk when loaded or last saved
+    pub disk_state: Option<DiskState>,
+    /// Bumped on every change to the editor buffer; keys the preview cache
+    pub revision: u64,
+    pub preview_cache: PreviewCache,
     pub status: String,
     pub show_help: bool,
+    /// First help line shown
+    pub help_scroll: u16,
+    pub keymap: Keymap,
+    pub theme: Theme,
     pub show_left_pane: bool,
     pub creating_file: bool,
     pub filename_input: TextArea<'static>,
@@ -106,6 +132,8 @@ pub struct App {
     // Git integration
     pub git_repo: Option<GitRepository>,
     pub git_status: HashMap<PathBuf, FileStatus>,
+    /// HEAD when the status was last read; code previews diff against it
+    git_head: Option<git2::Oid>,
     // Move destination picker
     pub showing_move_dest: bool,
     pub move_dest_dir: PathBuf,
@@ -115,6 +143,10 @@ pub struct App {
     // Git status display
     pub showing_git_status: bool,
     pub git_status_text: String,
+    // Filesystem watching
+    pub watcher: Option<FsWatcher>,
+    /// The open file changed on disk while it had unsaved edits
+    pub disk_changed: bool,
     // Pane resizing
     pub left_pane_width: u16,  // Current width of left pane (percentage)
     pub min_pane_width: u16,   // Minimum pane width (percentage)
@@ -124,8 +156,15 @@ pub struct App {
 
 impl App {
     pub fn new(root: PathBuf) -> Result<Self> {
-        let left_tree = build_tree(&root)?;
-        let right_tree = build_tree(&root)?;
+        Self::with_config(Config::new().with_base_directory(root))
+    }
+
+    /// Create the app rooted at `config.base_directory`
+    pub fn with_config(config: Config) -> Result<Self> {
+        let root = config.base_directory.clone();
+        let theme = Theme::default();
+        let files = FileTree::new(root.clone(), DirectoryCache::default());
+        let left_tree = files.items(config.ui.show_hidden, &HashSet::new(), &theme);
         let mut left_state = TreeState::<String>::default();
         let mut right_state = TreeState::<String>::default();
         left_state.select(vec![root.display().to_string()]);
@@ -134,28 +173,49 @@ impl App {
         editor.set_placeholder_text("Select a file in the tree (Enter) …");
         let mut filename_input = TextArea::default();
         filename_input.set_placeholder_text("new-note.md");
-        let git_repo = GitRepository::open(&root).ok();
+        let git_repo = if config.git.enabled {
+            GitRepository::open(&root).ok()
+        } else {
+            None
+        };
         let git_status: HashMap<PathBuf, FileStatus> = if let Some(ref repo) = git_repo {
             repo.status().unwrap_or_default()
         } else {
             HashMap::new()
         };
 
+        let (focus, show_raw_editor) = match config.ui.initial_focus {
+            InitialFocus::Files => (Focus::Left, false),
+            InitialFocus::Preview => (Focus::Preview, false),
+            InitialFocus::Editor => (Focus::Editor, true),
+        };
+        let ui = config.ui.clone();
+        let autoplay_video = config.preview.autoplay_video;
+
         Ok(Self {
+            config,
             root: root.clone(),
-            focus: Focus::Left,
+            focus,
             left_dir: root.clone(),
             right_dir: root.clone(),
             left_tree,
-            right_tree,
+            files,
             left_state,
+            expanded: HashSet::new(),
             tree_selection: HashSet::new(),
             tree_selection_anchor: None,
             editor,
             opened: None,
             last_saved_text: None,
+            dirty: Cell::new(None),
+            disk_state: None,
+            revision: 0,
+            preview_cache: PreviewCache::default(),
             status: "Ready".into(),
             show_help: false,
+            help_scroll: 0,
+            keymap: Keymap::default(),
+            theme,
             show_left_pane: true,
             creating_file: false,
             filename_input,
@@ -173,8 +233,8 @@ impl App {
             preview_cursor: 0,
             editing_line: false,
             line_input: TextArea::default(),
-            show_raw_editor: false,
-            prefer_raw_editor: false,
+            show_raw_editor,
+            prefer_raw_editor: show_raw_editor,
             editor_cmd_mode: false,
             editor_cmd_input: TextArea::default(),
             preview_col: 0,
@@ -182,9 +242,10 @@ impl App {
             preview_viewport: 0,
             undo_stack: Vec::new(),
             redo_stack: Vec::new(),
-            autoplay_video: false,
+            autoplay_video,
             video_player: None,
             video_path: None,
+            git_head: git_repo.as_ref().and_then(GitRepository::head_id),
             git_repo,
             git_status,
             showing_move_dest: false,
@@ -194,11 +255,12 @@ impl App {
             move_source: None,
             showing_git_status: false,
             git_status_text: String::new(),
-            // Default pane settings: 30% left pane, 70% right pane
-            left_pane_width: 30,
-            min_pane_width: 15,
-            max_pane_width: 85,
-            pane_resize_step: 5,
+            watcher: FsWatcher::new().ok(),
+            disk_changed: false,
+            left_pane_width: ui.pane_width,
+            min_pane_width: ui.min_pane_width,
+            max_pane_width: ui.max_pane_width,
+            pane_resize_step: ui.resize_step,
         })
     }
 
@@ -207,17 +269,206 @@ impl App {
             if path.is_dir() {
                 return Ok(());
             }
-            let text =
-                fs::read_to_string(&path).with_context(|| format!("Reading {}", path.display()))?;
-            self.editor = TextArea::from(text.lines().map(|s| s.to_string()).collect::<Vec<_>>());
-            self.opened = Some(path);
-            self.last_saved_text = Some(text);
-            self.status = "File opened".into();
-            self.focus = Focus::Preview;
+            self.open_file(&path)?;
         }
         Ok(())
     }
 
+    /// Load `path` into the editor and focus the preview
+    pub fn open_file(&mut self, path: &Path) -> Result<()> {
+        check_file_size_within(path, self.config.security.max_file_size)
+            .with_context(|| format!("Opening {}", path.display()))?;
+        let text =
+            fs::read_to_string(path).with_context(|| format!("Reading {}", path.display()))?;
+        self.editor = TextArea::from(text.lines().map(|s| s.to_string()).collect::<Vec<_>>());
+        self.revision += 1;
+        self.set_opened(Some(path.to_path_buf()));
+        self.disk_state = Some(DiskState::new(path, &text));
+        self.set_saved_text(text);
+        self.status = if self.config.read_only {
+            "File opened (read-only)".into()
+        } else {
+            "File opened".into()
+        };
+        self.focus = Focus::Preview;
+        self.preview_cursor = 0;
+        self.preview_col = 0;
+        self.preview_scroll = 0;
+        Ok(())
+    }
+
+    /// Switch the open file, and stop watching the previous file's folder
+    /// unless the tree still shows it
+    fn set_opened(&mut self, path: Option<PathBuf>) {
+        let previous = std::mem::replace(&mut self.opened, path);
+        let Some(dir) = previous.as_deref().and_then(Path::parent) else {
+            return;
+        };
+        let still_needed = self.opened.as_deref().and_then(Path::parent) == Some(dir)
+            || self.expanded.contains(dir)
+            || dir == self.files.root();
+        if let Some(watcher) = self.watcher.as_mut().filter(|_| !still_needed) {
+            watcher.unwatch(dir);
+        }
+    }
+
+    /// Whether the editor holds changes that are not on disk
+    ///
+    /// The buffer is compared once per revision; the answer is reused until
+    /// it changes or the saved text is replaced.
+    pub fn is_dirty(&self) -> bool {
+        if let Some((revision, dirty)) = self.dirty.get() {
+            if revision == self.revision {
+                return dirty;
+            }
+        }
+        let dirty = self.last_saved_text.as_ref().is_some_and(|saved| {
+            saved
+                .lines()
+                .ne(self.editor.lines().iter().map(String::as_str))
+        });
+        self.dirty.set(Some((self.revision, dirty)));
+        dirty
+    }
+
+    fn set_saved_text(&mut self, text: String) {
+        self.last_saved_text = Some(text);
+        self.dirty.set(None);
+    }
+
+    /// Replace the buffer with the open file as it is on disk, keeping the
+    /// cursor where it was
+    pub fn reload_from_disk(&mut self) -> Result<()> {
+        self.disk_changed = false;
+        let Some(path) = self.opened.clone() else {
+            return Ok(());
+        };
+        let text =
+            fs::read_to_string(&path).with_context(|| format!("Reading {}", path.display()))?;
+        self.editor = TextArea::from(text.lines().map(|s| s.to_string()).collect::<Vec<_>>());
+        self.revision += 1;
+        self.disk_state = Some(DiskState::new(&path, &text));
+        self.set_saved_text(text);
+        let last = self.editor.lines().len().saturating_sub(1);
+        self.preview_cursor = self.preview_cursor.min(last);
+        self.editor.move_cursor(tui_textarea::CursorMove::Jump(
+            self.preview_cursor as u16,
+            self.preview_col as u16,
+        ));
+        self.status = format!("Reloaded {} from disk", path.display());
+        Ok(())
+    }
+
+    /// Dismiss the changed-on-disk prompt and carry on with the buffer
+    pub fn keep_edits(&mut self) {
+        self.disk_changed = false;
+        self.status = "Kept your edits; the file on disk is unchanged".into();
+    }
+
+    /// Combine the buffer with the file on disk, taking the text as loaded as
+    /// their common ancestor, and open the result in the raw editor
+    pub fn merge_with_disk(&mut self) -> Result<()> {
+        self.disk_changed = false;
+        let Some(path) = self.opened.clone() else {
+            return Ok(());
+        };
+        let theirs =
+            fs::read_to_string(&path).with_context(|| format!("Reading {}", path.display()))?;
+        let base = self.last_saved_text.clone().unwrap_or_default();
+        let merged = merge3(&base, &self.editor.lines().join("\n"), &theirs);
+        self.editor = TextArea::from(merged.lines);
+        self.revision += 1;
+        // What is on disk now is what the merge result will replace
+        self.disk_state = Some(DiskState::new(&path, &theirs));
+        self.set_saved_text(theirs);
+        self.show_raw_editor = true;
+        self.focus = Focus::Editor;
+        self.status = match merged.conflicts {
+            0 => "Merged the changes on disk; save to keep the result".into(),
+            n => format!(
+                "{n} conflict(s) between {MARKER_OURS} and {MARKER_THEIRS}; resolve them and save"
+            ),
+        };
+        Ok(())
+    }
+
+    /// Watch what is on screen and apply changes made outside sb: reload
+    /// affected listings, refresh git status, and reload the open file, or
+    /// ask first if it has unsaved edits
+    pub fn poll_watcher(&mut self) {
+        let Some(watcher) = self.watcher.as_mut() else {
+            return;
+        };
+        if let Some(parent) = self.opened.as_deref().and_then(Path::parent) {
+            let _ = watcher.watch(parent);
+        }
+        if let Some(repo) = &self.git_repo {
+            // HEAD and the index change on commits, checkouts and staging
+            let _ = watcher.watch(&repo.root().join(".git"));
+        }
+        let Some(changed) = watcher.poll() else {
+            return;
+        };
+        for dir in changed.iter().filter_map(|p| p.parent()) {
+            self.files.reload(dir);
+        }
+        self.refresh_git_status();
+        let Some(path) = self.opened.clone().filter(|p| changed.contains(p)) else {
+            return;
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) if self.last_saved_text.as_deref() == Some(text.as_str()) => {}
+            Ok(_) if self.is_dirty() => {
+                self.disk_changed = true;
+            }
+            Ok(_) => {
+                if let Err(e) = self.reload_from_disk() {
+                    self.status = format!("Reload failed: {e}");
+                }
+            }
+            Err(_) if !path.exists() => {
+                self.status = format!("{} was removed from disk", path.display());
+            }
+            Err(e) => self.status = format!("Reading {}: {e}", path.display()),
+        }
+    }
+
+    /// Select `path` in the file tree, expanding the directories above it
+    pub fn reveal_in_tree(&mut self, path: &Path) {
+        let Ok(rel) = path.strip_prefix(&self.left_dir) else {
+            return;
+        };
+        let mut ids = vec![self.left_dir.display().to_string()];
+        let mut current = self.left_dir.clone();
+        for component in rel.components() {
+            self.left_state.open(ids.clone());
+            current.push(component);
+            ids.push(current.display().to_string());
+        }
+        self.left_state.select(ids);
+    }
+
+    /// Move the preview cursor to `line` (counting from 1)
+    pub fn jump_to_line(&mut self, line: usize) {
+        let last = self.editor.lines().len().saturating_sub(1);
+        self.preview_cursor = line.saturating_sub(1).min(last);
+        self.preview_col = 0;
+        // Keep a few lines of context above the target
+        self.preview_scroll = self.preview_cursor.saturating_sub(3);
+        self.editor.move_cursor(tui_textarea::CursorMove::Jump(
+            self.preview_cursor as u16,
+            0,
+        ));
+    }
+
+    /// False (with a status message) when running with --read-only
+    fn writable(&mut self) -> bool {
+        if self.config.read_only {
+            self.status = "Read-only mode: changes are disabled".into();
+        }
+        !self.config.read_only
+    }
+
     pub fn activate_on_tree(&mut self) -> Result<()> {
         // If dir: toggle open; if file: open
         let current_path = self.left_state.selected().last().cloned();
@@ -232,12 +483,43 @@ impl App {
     }
 
     pub fn save(&mut self) -> Result<()> {
-        if let Some(path) = &self.opened {
-            let text = self.editor.lines().join("\n");
-            fs::write(path, text).with_context(|| format!("Saving {}", path.display()))?;
-            self.last_saved_text = Some(self.editor.lines().join("\n"));
-            self.status = "Saved".into();
+        if !self.writable() {
+            return Ok(());
+        }
+        let Some(path) = self.opened.clone() else {
+            return Ok(());
+        };
+        if self
+            .disk_state
+            .as_ref()
+            .is_some_and(|state| !state.is_current(&path))
+        {
+            // Let the user choose between overwriting, reloading and merging
+            self.disk_changed = true;
+            self.status = format!("{} changed on disk since it was loaded", path.display());
+            return Ok(());
+        }
+        self.write_buffer(&path)
+    }
+
+    /// Save even though the file changed on disk
+    pub fn overwrite(&mut self) -> Result<()> {
+        self.disk_changed = false;
+        if !self.writable() {
+            return Ok(());
+        }
+        match self.opened.clone() {
+            Some(path) => self.write_buffer(&path),
+            None => Ok(()),
         }
+    }
+
+    fn write_buffer(&mut self, path: &Path) -> Result<()> {
+        let text = self.editor.lines().join("\n");
+        fs::write(path, &text).with_context(|| format!("Saving {}", path.display()))?;
+        self.disk_state = Some(DiskState::new(path, &text));
+        self.set_saved_text(text);
+        self.status = "Saved".into();
         Ok(())
     }
 
@@ -249,10 +531,18 @@ impl App {
     }
 
     pub fn open_in_editor(&mut self, path: &Path) -> Result<()> {
-        // Get editor from environment variables, default to system opener
-        let editor = std::env::var("EDITOR")
-            .or_else(|_| std::env::var("VISUAL"))
-            .unwrap_or_else(|_| "opener".to_string());
+        // Configured command first, then the environment, then the system opener
+        let command = self
+            .config
+            .editor
+            .command
+            .clone()
+            .or_else(|| std::env::var("EDITOR").ok())
+            .or_else(|| std::env::var("VISUAL").ok())
+            .unwrap_or_else(|| "opener".to_string());
+        let mut words = command.split_whitespace();
+        let editor = words.next().unwrap_or("opener").to_string();
+        let args: Vec<&str> = words.collect();
 
         if editor == "opener" {
             // Fallback to system default application
@@ -260,7 +550,7 @@ impl App {
             self.status = format!("Opened {} with system default", path.display());
         } else {
             // Launch the specified editor
-            match Command::new(&editor).arg(path).spawn() {
+            match Command::new(&editor).args(&args).arg(path).spawn() {
                 Ok(_) => {
                     self.status = format!("Opened {} with {}", path.display(), editor);
                 }
@@ -277,6 +567,7 @@ impl App {
 
     pub fn toggle_help(&mut self) {
         self.show_help = !self.show_help;
+        self.help_scroll = 0;
     }
 
     pub fn toggle_left_pane(&mut self) {
@@ -345,7 +636,6 @@ impl App {
         match cmd {
             "w" => {
                 self.save()?;
-                self.status = "Saved".into();
             }
             "q" => {
                 /* Optional: set a flag the main loop reads to quit */
@@ -353,7 +643,9 @@ impl App {
             }
             "wq" => {
                 self.save()?;
-                self.status = "Saved (use F10/Q to quit)".into();
+                if !self.disk_changed {
+                    self.status = "Saved (use F10/Q to quit)".into();
+                }
             }
             _ => {
                 self.status = format!("Unknown :{cmd}");
@@ -371,10 +663,12 @@ impl App {
 
     #[allow(dead_code)]
     fn save_lines(&mut self, lines: Vec<String>) {
-        self.editor = TextArea::from(lines.clone());
-        if let Some(path) = &self.opened {
-            let _ = std::fs::write(path, lines.join("\n"));
+        if !self.writable() {
+            return;
         }
+        self.editor = TextArea::from(lines);
+        self.revision += 1;
+        let _ = self.save();
     }
 
     #[allow(dead_code)]
@@ -462,24 +756,70 @@ impl App {
         self.preview_col = 0;
     }
 
+    /// Re-read the expanded directories; the tree updates as the listings
+    /// come back from [`App::poll_tree`]
     pub fn refresh_tree(&mut self) -> Result<()> {
-        self.left_tree = build_tree_with_selection(&self.left_dir, &self.tree_selection)?;
-        self.right_tree = build_tree(&self.right_dir)?;
+        self.files.refresh();
+        self.update_tree_selection_display();
         Ok(())
     }
 
-    /// Fast update of tree selection display without full rebuild
+    /// Rebuild the tree items from the listings already read, without any I/O
     pub fn update_tree_selection_display(&mut self) {
-        // Use a more efficient method that only updates text formatting
-        // without doing filesystem I/O for each selection change
-        if let Ok(new_tree) =
-            build_tree_with_selection_cached(&self.left_dir, &self.tree_selection, &self.left_tree)
-        {
-            self.left_tree = new_tree;
+        self.left_tree = self.files.items(
+            self.config.ui.show_hidden,
+            &self.tree_selection,
+            &self.theme,
+        );
+    }
+
+    /// Request listings for newly expanded folders and pick up finished ones
+    ///
+    /// The root and the open folders are watched once their listings are
+    /// read; collapsed folders and ones that fail to read are not.
+    pub fn poll_tree(&mut self) {
+        let opened: HashSet<PathBuf> = self
+            .left_state
+            .opened()
+            .iter()
+            .filter_map(|id| id.last().map(PathBuf::from))
+            .collect();
+        for dir in &opened {
+            self.files.load(dir);
+        }
+        let arrived = self.files.poll();
+        if let Some(watcher) = self.watcher.as_mut() {
+            // The open file's folder is watched for the editor's sake
+            let keep = self.opened.as_deref().and_then(Path::parent);
+            for dir in self.expanded.difference(&opened) {
+                if Some(dir.as_path()) != keep {
+                    watcher.unwatch(dir);
+                }
+            }
+            // Expanding again shows the listing read before
+            for dir in opened.difference(&self.expanded) {
+                if self.files.is_loaded(dir) {
+                    let _ = watcher.watch(dir);
+                }
+            }
+            for (dir, readable) in &arrived {
+                if !readable {
+                    watcher.unwatch(dir);
+                } else if dir == self.files.root() || opened.contains(dir) {
+                    let _ = watcher.watch(dir);
+                }
+            }
+        }
+        self.expanded = opened;
+        if !arrived.is_empty() {
+            self.update_tree_selection_display();
         }
     }
 
     pub fn begin_create_file(&mut self) {
+        if !self.writable() {
+            return;
+        }
         self.creating_file = true;
         self.filename_input = TextArea::default();
         self.filename_input.set_placeholder_text("new-note.md");
@@ -525,9 +865,11 @@ impl App {
             if title.is_empty() { "New Note" } else { &title }
         );
         fs::write(&new_path, &initial)?;
-        self.opened = Some(new_path.clone());
+        self.set_opened(Some(new_path.clone()));
         self.editor = TextArea::from(initial.lines().map(|s| s.to_string()).collect::<Vec<_>>());
-        self.last_saved_text = Some(initial);
+        self.revision += 1;
+        self.disk_state = Some(DiskState::new(&new_path, &initial));
+        self.set_saved_text(initial);
         self.creating_file = false;
         self.refresh_tree()?;
         // Select the new file in the left tree
@@ -550,6 +892,9 @@ impl App {
     }
 
     pub fn begin_delete(&mut self) {
+        if !self.writable() {
+            return;
+        }
         // Determine target from selection
         if let Some(id) = self.left_state.selected().last() {
             self.delete_target = Some(PathBuf::from(id));
@@ -571,8 +916,9 @@ impl App {
                 std::fs::remove_file(&path)?;
             }
             if self.opened.as_ref().map(|p| p == &path).unwrap_or(false) {
-                self.opened = None;
+                self.set_opened(None);
                 self.editor = TextArea::default();
+                self.revision += 1;
             }
             self.refresh_tree()?;
             self.status = format!("Deleted {}", path.display());
@@ -735,7 +1081,10 @@ impl App {
 
     fn collect_all_tree_items(&self, selection: &mut HashSet<String>) {
         fn collect_recursive(items: &[TreeItem<String>], selection: &mut HashSet<String>) {
-            for item in items {
+            for item in items
+                .iter()
+                .filter(|item| !is_placeholder(item.identifier()))
+            {
                 selection.insert(item.identifier().clone());
                 collect_recursive(item.children(), selection);
             }
@@ -815,6 +1164,9 @@ impl App {
     ) {
         for item in items {
             let id = item.identifier();
+            if is_placeholder(id) {
+                continue;
+            }
 
             // Check if this is one of our range endpoints
             if id == anchor || id == current {
@@ -867,15 +1219,11 @@ impl App {
     }
 
     fn load_picker_dir(&mut self, dir: PathBuf) -> Result<()> {
+        let show_hidden = self.config.ui.show_hidden;
         let mut items: Vec<PathBuf> = std::fs::read_dir(&dir)?
             .filter_map(|e| e.ok())
             .map(|e| e.path())
-            .filter(|p| {
-                !p.file_name()
-                    .unwrap_or_default()
-                    .to_string_lossy()
-                    .starts_with('.')
-            })
+            .filter(|p| show_hidden || !is_hidden(p))
             .collect();
         items.sort_by_key(|p| {
             (
@@ -950,6 +1298,7 @@ impl App {
         };
         // Insert at cursor
         self.editor.insert_str(&link);
+        self.revision += 1;
         self.status = format!("Inserted link to {}", rel.display());
         Ok(())
     }
@@ -1027,6 +1376,9 @@ impl App {
 
     /// Delete with Git awareness (D command)
     pub fn picker_delete_with_git_check(&mut self) -> Result<()> {
+        if !self.writable() {
+            return Ok(());
+        }
         if !self.picker_selection.is_empty() {
             // Bulk delete mode
             let selected_files: Vec<PathBuf> = self
@@ -1209,16 +1561,12 @@ impl App {
 
     /// Load directory for move destination picker
     fn load_move_dest_dir(&mut self, dir: PathBuf) -> Result<()> {
+        let show_hidden = self.config.ui.show_hidden;
         let mut items: Vec<PathBuf> = std::fs::read_dir(&dir)?
             .filter_map(|e| e.ok())
             .map(|e| e.path())
             .filter(|p| p.is_dir()) // Only show directories for move destination
-            .filter(|p| {
-                !p.file_name()
-                    .unwrap_or_default()
-                    .to_string_lossy()
-                    .starts_with('.')
-            })
+            .filter(|p| show_hidden || !is_hidden(p))
             .collect();
 
         items.sort_by_key(|p| {
@@ -1329,6 +1677,12 @@ impl App {
             if let Ok(status) = repo.status() {
                 self.git_status = status;
             }
+            // A commit or checkout changes what the code preview diffs against
+            let head = repo.head_id();
+            if head != self.git_head {
+                self.git_head = head;
+                self.preview_cache.clear();
+            }
         }
     }
 
@@ -1377,12 +1731,15 @@ impl App {
     }
 
     pub fn current_selection_path(&self) -> Option<PathBuf> {
-        let id = self.left_state.selected().last()?.clone();
-        Some(PathBuf::from(id))
+        let id = self.left_state.selected().last()?;
+        (!is_placeholder(id)).then(|| PathBuf::from(id))
     }
 
     // --- MC style operations ----------------------------------------------
     pub fn begin_copy(&mut self) {
+        if !self.writable() {
+            return;
+        }
         self.op_mode = OpMode::Copy;
         self.op_input = TextArea::default();
         if let Some(src) = self.current_selection_path() {
@@ -1397,6 +1754,9 @@ impl App {
     }
 
     pub fn begin_move(&mut self) {
+        if !self.writable() {
+            return;
+        }
         self.op_mode = OpMode::Move;
         self.op_input = TextArea::default();
         if let Some(src) = self.current_selection_path() {
@@ -1411,6 +1771,9 @@ impl App {
     }
 
     pub fn begin_mkdir(&mut self) {
+        if !self.writable() {
+            return;
+        }
         self.op_mode = OpMode::Mkdir;
         self.op_input = TextArea::default();
     }
@@ -1489,6 +1852,9 @@ impl App {
     // --- Inline editing in Preview ----------------------------------------
     #[allow(dead_code)]
     pub fn begin_line_edit(&mut self) {
+        if !self.writable() {
+            return;
+        }
         if self.preview_cursor >= self.editor.lines().len() {
             return;
         }
@@ -1514,13 +1880,13 @@ impl App {
             // Replace the specific line in the editor buffer
             let mut lines = self.editor.lines().to_vec();
             lines[self.preview_cursor] = new_line.clone();
-            self.editor = TextArea::from(lines.clone());
+            self.editor = TextArea::from(lines);
+            self.revision += 1;
             // Immediate save if file open
-            if let Some(path) = &self.opened {
-                let text = lines.join("\n");
-                let _ = std::fs::write(path, text);
+            let _ = self.save();
+            if !self.disk_changed {
+                self.status = format!("Updated line {}", self.preview_cursor + 1);
             }
-            self.status = format!("Updated line {}", self.preview_cursor + 1);
         }
         self.editing_line = false;
     }
@@ -1819,155 +2185,6 @@ impl VideoPlayer {
     }
 }
 
-// --- Tree helpers -----------------------------------------------------------
-
-/// Fast tree building that reuses existing tree structure and only updates text
-fn build_tree_with_selection_cached(
-    _root: &Path,
-    selection: &HashSet<String>,
-    existing_tree: &[TreeItem<String>],
-) -> Result<Vec<TreeItem<'static, String>>> {
-    use ratatui::style::{Color, Stylize};
-    use ratatui::text::Line;
-
-    fn update_node_cached(
-        item: &TreeItem<String>,
-        selection: &HashSet<String>,
-    ) -> TreeItem<'static, String> {
-        let path_str = item.identifier();
-        let path = Path::new(path_str);
-
-        // Create new display text based on selection state
-        let new_text = if let Some(filename) = path.file_name() {
-            let filename_str = filename.to_string_lossy().to_string();
-            if selection.contains(path_str) {
-                // Add checkmark for selected items
-                Line::from(vec![
-                    "✓ ".fg(Color::Green).bold(),
-                    filename_str.fg(Color::Yellow).bold(),
-                ])
-            } else {
-                Line::from(filename_str)
-            }
-        } else {
-            Line::from(path_str.to_string())
-        };
-
-        // Recursively update children
-        let updated_children: Vec<TreeItem<'static, String>> = item
-            .children()
-            .iter()
-            .map(|child| update_node_cached(child, selection))
-            .collect();
-
-        // Create new TreeItem with updated text and children
-        if path.is_dir() && !updated_children.is_empty() {
-            TreeItem::new(path_str.to_string(), new_text.clone(), updated_children)
-                .unwrap_or_else(|_| TreeItem::new_leaf(path_str.to_string(), new_text))
-        } else {
-            TreeItem::new_leaf(path_str.to_string(), new_text)
-        }
-    }
-
-    // Update all nodes in the existing tree
-    let updated_tree: Vec<TreeItem<'static, String>> = existing_tree
-        .iter()
-        .map(|item| update_node_cached(item, selection))
-        .collect();
-
-    Ok(updated_tree)
-}
-
-fn build_tree(root: &Path) -> Result<Vec<TreeItem<'static, String>>> {
-    fn build_node(dir: &Path) -> TreeItem<'static, String> {
-        let mut children: Vec<TreeItem<'static, String>> = std::fs::read_dir(dir)
-            .ok()
-            .into_iter()
-            .flatten()
-            .filter_map(|e| e.ok())
-            .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
-            .map(|e| {
-                let p = e.path();
-                if p.is_dir() {
-                    build_node(&p)
-                } else {
-                    let text = Line::from(e.file_name().to_string_lossy().to_string());
-                    TreeItem::new_leaf(p.display().to_string(), text)
-                }
-            })
-            .collect();
-        children.sort_by_key(|item| item.identifier().clone());
-        let text = Line::from(
-            dir.file_name()
-                .map(|s| s.to_string_lossy().to_string())
-                .unwrap_or_else(|| dir.display().to_string()),
-        );
-        TreeItem::new(dir.display().to_string(), text.clone(), children)
-            .unwrap_or_else(|_| TreeItem::new_leaf(dir.display().to_string(), text))
-    }
-
-    let root_item = build_node(root);
-    Ok(vec![root_item])
-}
-
-fn build_tree_with_selection(
-    root: &Path,
-    selection: &HashSet<String>,
-) -> Result<Vec<TreeItem<'static, String>>> {
-    fn build_node(dir: &Path, selection: &HashSet<String>) -> TreeItem<'static, String> {
-        let mut children: Vec<TreeItem<'static, String>> = std::fs::read_dir(dir)
-            .ok()
-            .into_iter()
-            .flatten()
-            .filter_map(|e| e.ok())
-            .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
-            .map(|e| {
-                let p = e.path();
-                if p.is_dir() {
-                    build_node(&p, selection)
-                } else {
-                    let path_str = p.display().to_string();
-                    let filename = e.file_name().to_string_lossy().to_string();
-                    let text = if selection.contains(&path_str) {
-                        // Add checkmark for selected items
-                        Line::from(vec![
-                            "✓ ".fg(Color::Green).bold(),
-                            filename.fg(Color::Yellow).bold(),
-                        ])
-                    } else {
-                        Line::from(filename)
-                    };
-                    TreeItem::new_leaf(path_str, text)
-                }
-            })
-            .collect();
-        children.sort_by_key(|item| item.identifier().clone());
-
-        let path_str = dir.display().to_string();
-        let dir_name = dir
-            .file_name()
-            .map(|s| s.to_string_lossy().to_string())
-            .unwrap_or_else(|| dir.display().to_string());
-
-        let text = if selection.contains(&path_str) {
-            // Add checkmark for selected directories
-            Line::from(vec![
-                "✓ ".fg(Color::Green).bold(),
-                "📁 ".fg(Color::Blue),
-                dir_name.fg(Color::Yellow).bold(),
-            ])
-        } else {
-            Line::from(vec!["📁 ".fg(Color::Blue), dir_name.into()])
-        };
-
-        TreeItem::new(path_str.clone(), text.clone(), children)
-            .unwrap_or_else(|_| TreeItem::new_leaf(path_str, text))
-    }
-
-    let root_item = build_node(root, selection);
-    Ok(vec![root_item])
-}
-
 fn copy_dir_all(src: &Path, dst: &Path) -> io::Result<()> {
     if !dst.exists() {
         std::fs::create_dir_all(dst)?;
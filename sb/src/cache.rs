@@ -15,8 +15,9 @@ pub struct CacheEntry {
     pub modified_time: SystemTime,
 }
 
-/// Directory cache with TTL and invalidation support
-#[derive(Debug)]
+/// Directory cache with TTL and invalidation support; clones share the
+/// same entries
+#[derive(Clone, Debug)]
 pub struct DirectoryCache {
     /// Cache storage
     cache: Arc<Mutex<HashMap<PathBuf, CacheEntry>>>,
@@ -118,7 +119,7 @@ impl DirectoryCache {
 
     /// Get the number of cached entries (used for testing)
     #[cfg(test)]
-    pub fn len(&self) -> usize {
+    fn len(&self) -> usize {
         self.cache.lock().map(|c| c.len()).unwrap_or(0)
     }
 }
@@ -1,27 +1,185 @@
 //! Configuration management for Saorsa Browser
+//!
+//! Settings are read from `~/.config/sb/config.toml` (or `$XDG_CONFIG_HOME`,
+//! or the file given with `--config`). Every key is optional:
+//!
+//! ```toml
+//! [ui]
+//! pane_width = 30          # initial width of the files pane, in percent
+//! min_pane_width = 15
+//! max_pane_width = 85
+//! resize_step = 5
+//! initial_focus = "files"  # files, preview or editor
+//! wrap = true              # wrap long lines in the preview
+//! show_hidden = false
+//! theme = "auto"           # auto, dark, light or a file in ~/.config/sb/themes
+//!
+//! [preview]
+//! autoplay_video = false
+//!
+//! [git]
+//! enabled = true
+//!
+//! [editor]
+//! command = "nvim"         # defaults to $EDITOR, $VISUAL, then the system opener
+//!
+//! [security]
+//! max_file_size = 10485760     # bytes
+//! max_preview_size = 1048576   # bytes
+//! ```
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-/// Application configuration
-#[derive(Debug, Clone)]
-pub struct Config {
-    /// Base directory for file operations
-    pub base_directory: PathBuf,
+use serde::Deserialize;
+use thiserror::Error;
+
+/// `$XDG_CONFIG_HOME/sb`, defaulting to `~/.config/sb`
+pub fn config_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config")))?;
+    Some(base.join("sb"))
+}
+
+/// Errors reading or validating a configuration file
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("cannot read {}: {source}", path.display())]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid configuration in {}: {message}", path.display())]
+    Parse { path: PathBuf, message: String },
+    #[error("invalid configuration in {}: `{key}` {message}", path.display())]
+    Invalid {
+        path: PathBuf,
+        key: &'static str,
+        message: String,
+    },
+}
+
+/// Which pane has focus at startup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InitialFocus {
+    #[default]
+    Files,
+    Preview,
+    Editor,
+}
+
+/// Layout and behaviour of the panes
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UiConfig {
+    /// Initial width of the files pane (percentage)
+    pub pane_width: u16,
+    pub min_pane_width: u16,
+    pub max_pane_width: u16,
+    /// Step size for keyboard resize (percentage)
+    pub resize_step: u16,
+    pub initial_focus: InitialFocus,
+    /// Wrap long lines in the preview
+    pub wrap: bool,
+    /// Whether to show hidden files
+    pub show_hidden: bool,
+    /// Theme name, or "auto" to follow the terminal background
+    pub theme: String,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        Self {
+            pane_width: 30,
+            min_pane_width: 15,
+            max_pane_width: 85,
+            resize_step: 5,
+            initial_focus: InitialFocus::Files,
+            wrap: true,
+            show_hidden: false,
+            theme: "auto".into(),
+        }
+    }
+}
+
+/// Preview rendering
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PreviewConfig {
+    /// Start playing videos as soon as they are previewed
+    pub autoplay_video: bool,
+}
+
+/// Git integration
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GitConfig {
+    /// Whether to look for a git repository and show file status
+    pub enabled: bool,
+}
+
+impl Default for GitConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+/// External editor
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EditorConfig {
+    /// Command line used to open files, e.g. "code --wait"
+    pub command: Option<String>,
+}
+
+/// Resource limits
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecurityConfig {
     /// Maximum file size allowed for operations
     pub max_file_size: u64,
     /// Maximum preview size
     pub max_preview_size: u64,
-    /// Whether to allow hidden files
-    pub allow_hidden_files: bool,
 }
 
-impl Default for Config {
+impl Default for SecurityConfig {
     fn default() -> Self {
         Self {
-            base_directory: std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
             max_file_size: crate::security::MAX_FILE_SIZE,
             max_preview_size: crate::security::MAX_PREVIEW_SIZE,
-            allow_hidden_files: false,
+        }
+    }
+}
+
+/// Application configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Base directory for file operations
+    #[serde(skip)]
+    pub base_directory: PathBuf,
+    /// Refuse every operation that writes to disk
+    #[serde(skip)]
+    pub read_only: bool,
+    pub ui: UiConfig,
+    pub preview: PreviewConfig,
+    pub git: GitConfig,
+    pub editor: EditorConfig,
+    pub security: SecurityConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            base_directory: std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+            read_only: false,
+            ui: UiConfig::default(),
+            preview: PreviewConfig::default(),
+            git: GitConfig::default(),
+            editor: EditorConfig::default(),
+            security: SecurityConfig::default(),
         }
     }
 }
@@ -32,6 +190,95 @@ impl Config {
         Self::default()
     }
 
+    /// `config.toml` in [`config_dir`]
+    pub fn default_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("config.toml"))
+    }
+
+    /// Load the default configuration file, if there is one
+    pub fn load_default() -> Result<Self, ConfigError> {
+        match Self::default_path() {
+            Some(path) if path.exists() => Self::load(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Load and validate a configuration file
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::parse(&text, path)
+    }
+
+    fn parse(text: &str, path: &Path) -> Result<Self, ConfigError> {
+        let config: Config = toml::from_str(text).map_err(|e| ConfigError::Parse {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })?;
+        config
+            .check()
+            .map_err(|(key, message)| ConfigError::Invalid {
+                path: path.to_path_buf(),
+                key,
+                message,
+            })?;
+        Ok(config)
+    }
+
+    /// The first setting that is out of range, with the reason
+    fn check(&self) -> Result<(), (&'static str, String)> {
+        let ui = &self.ui;
+        if !(5..=95).contains(&ui.min_pane_width) {
+            return Err(("ui.min_pane_width", "must be between 5 and 95".into()));
+        }
+        if !(ui.min_pane_width..=95).contains(&ui.max_pane_width) {
+            return Err((
+                "ui.max_pane_width",
+                format!(
+                    "must be between ui.min_pane_width ({}) and 95",
+                    ui.min_pane_width
+                ),
+            ));
+        }
+        if !(ui.min_pane_width..=ui.max_pane_width).contains(&ui.pane_width) {
+            return Err((
+                "ui.pane_width",
+                format!(
+                    "must be between {} and {}",
+                    ui.min_pane_width, ui.max_pane_width
+                ),
+            ));
+        }
+        if ui.resize_step == 0 || ui.resize_step > 50 {
+            return Err(("ui.resize_step", "must be between 1 and 50".into()));
+        }
+        if ui.theme.trim().is_empty() {
+            return Err(("ui.theme", "must not be empty".into()));
+        }
+        if let Some(command) = &self.editor.command {
+            if command.trim().is_empty() {
+                return Err(("editor.command", "must not be empty".into()));
+            }
+        }
+        if self.security.max_file_size == 0 {
+            return Err(("security.max_file_size", "must be greater than 0".into()));
+        }
+        if self.security.max_preview_size == 0
+            || self.security.max_preview_size > self.security.max_file_size
+        {
+            return Err((
+                "security.max_preview_size",
+                format!(
+                    "must be between 1 and security.max_file_size ({})",
+                    self.security.max_file_size
+                ),
+            ));
+        }
+        Ok(())
+    }
+
     /// Set the base directory
     pub fn with_base_directory<P: Into<PathBuf>>(mut self, path: P) -> Self {
         self.base_directory = path.into();
@@ -40,13 +287,88 @@ impl Config {
 
     /// Set the maximum file size
     pub fn with_max_file_size(mut self, size: u64) -> Self {
-        self.max_file_size = size;
+        self.security.max_file_size = size;
         self
     }
 
     /// Enable or disable hidden files
     pub fn with_hidden_files(mut self, allow: bool) -> Self {
-        self.allow_hidden_files = allow;
+        self.ui.show_hidden = allow;
         self
     }
+
+    /// Enable or disable read-only mode
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Enable or disable git integration
+    pub fn with_git(mut self, enabled: bool) -> Self {
+        self.git.enabled = enabled;
+        self
+    }
+
+    /// Maximum file size
+    #[deprecated(note = "use `security.max_file_size`")]
+    pub fn max_file_size(&self) -> u64 {
+        self.security.max_file_size
+    }
+
+    /// Maximum preview size
+    #[deprecated(note = "use `security.max_preview_size`")]
+    pub fn max_preview_size(&self) -> u64 {
+        self.security.max_preview_size
+    }
+
+    /// Whether hidden files are shown
+    #[deprecated(note = "use `ui.show_hidden`")]
+    pub fn allow_hidden_files(&self) -> bool {
+        self.ui.show_hidden
+    }
+
+    /// Whether git integration is enabled
+    #[deprecated(note = "use `git.enabled`")]
+    pub fn git_enabled(&self) -> bool {
+        self.git.enabled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Config, ConfigError> {
+        Config::parse(text, Path::new("config.toml"))
+    }
+
+    #[test]
+    fn test_sections_override_defaults() {
+        let config = parse(
+            "[ui]\npane_width = 40\ninitial_focus = \"preview\"\nwrap = false\n\n\
+             [editor]\ncommand = \"code --wait\"\n\n[security]\nmax_file_size = 2048\nmax_preview_size = 1024\n",
+        )
+        .unwrap();
+        assert_eq!(config.ui.pane_width, 40);
+        assert_eq!(config.ui.min_pane_width, 15);
+        assert_eq!(config.ui.initial_focus, InitialFocus::Preview);
+        assert!(!config.ui.wrap);
+        assert!(config.git.enabled);
+        assert_eq!(config.editor.command.as_deref(), Some("code --wait"));
+        assert_eq!(config.security.max_file_size, 2048);
+        assert_eq!(parse("").unwrap().ui, UiConfig::default());
+    }
+
+    #[test]
+    fn test_errors_name_the_problem() {
+        let message = |text: &str| parse(text).unwrap_err().to_string();
+        assert_eq!(
+            message("[ui]\npane_width = 90"),
+            "invalid configuration in config.toml: `ui.pane_width` must be between 15 and 85"
+        );
+        assert!(message("[ui]\npane_widht = 30").contains("unknown field `pane_widht`"));
+        assert!(message("[ui]\ntheme = \"\"").contains("`ui.theme`"));
+        assert!(message("[security]\nmax_preview_size = 99999999999").contains("max_file_size"));
+        assert!(message("[ui]\ninitial_focus = \"left\"").contains("unknown variant"));
+    }
 }
@@ -1,562 +1,495 @@
 //! Event handling module for better organization of input events
 //!
-//! This module provides a clear, organized way to handle different types of
-//! input events and application modes, making the main event loop cleaner
-//! and more maintainable.
+//! Key presses are looked up in the [`Keymap`](crate::keymap::Keymap) for the
+//! current mode and the resulting [`Action`] is run here. Keys with no binding
+//! fall through to the text field or editor that has focus.
 
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::path::{Path, PathBuf};
+
+use ratatui::crossterm::event::{KeyCode, KeyEvent};
+use tui_textarea::TextArea;
 
 use crate::app::{App, Focus, OpMode};
+use crate::keymap::{Action, Mode, Resolved};
 
 /// Main event handler dispatcher
 ///
-/// Routes key events to the appropriate handler based on the current application state.
-/// This provides a clean separation of concerns for different input modes.
-///
-/// # Arguments
-///
-/// * `app` - Mutable reference to the application state
-/// * `key_event` - The key event to process
-///
 /// # Returns
 ///
 /// * `Some(())` - Event was handled, continue the main loop
 /// * `None` - Event requests application exit
 pub fn handle_key_event(app: &mut App, key_event: KeyEvent) -> Option<()> {
-    match app.current_mode() {
-        AppMode::FileCreation => handle_file_creation(app, key_event),
-        AppMode::FilePicker => handle_file_picker(app, key_event),
-        AppMode::MoveDestination => handle_move_destination(app, key_event),
-        AppMode::GitStatus => handle_git_status(app, key_event),
-        AppMode::Operation => handle_operation(app, key_event),
-        AppMode::LineEdit => handle_line_edit(app, key_event),
-        AppMode::EditorCommand => handle_editor_command(app, key_event),
-        AppMode::RawEditor => handle_raw_editor(app, key_event),
-        AppMode::Help => handle_help(app, key_event),
-        AppMode::DeleteConfirmation => handle_delete_confirmation(app, key_event),
-        AppMode::Normal => handle_normal_mode(app, key_event),
+    let mode = app.key_mode();
+    match app.keymap.resolve(mode, key_event) {
+        Resolved::Action(action) => return run_action(app, action),
+        Resolved::Pending => {}
+        Resolved::Unbound => match mode {
+            Mode::Prompt => {
+                prompt_input(app).input(key_event);
+            }
+            Mode::Editor if app.editor.input(key_event) => {
+                app.revision += 1;
+            }
+            Mode::Picker => {
+                if let KeyCode::Char(c) = key_event.code {
+                    app.status = format!("File picker: Unknown key '{}'", c);
+                }
+            }
+            _ => {}
+        },
     }
-}
-
-/// Application modes for cleaner event routing
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum AppMode {
-    /// Creating a new file
-    FileCreation,
-    /// File picker overlay is active
-    FilePicker,
-    /// Move destination picker is active  
-    MoveDestination,
-    /// Git status display is active
-    GitStatus,
-    /// File operation mode (copy/move/etc.)
-    Operation,
-    /// Inline line editing mode
-    LineEdit,
-    /// Editor command mode (:w, :q, etc.)
-    EditorCommand,
-    /// Raw editor mode
-    RawEditor,
-    /// Help screen is displayed
-    Help,
-    /// Delete confirmation dialog
-    DeleteConfirmation,
-    /// Normal browsing/navigation mode
-    Normal,
+    Some(())
 }
 
 impl App {
-    /// Determine the current application mode for event routing
-    pub fn current_mode(&self) -> AppMode {
-        if self.creating_file {
-            AppMode::FileCreation
+    /// Keymap section for the current state, overlays first
+    pub fn key_mode(&self) -> Mode {
+        if self.disk_changed {
+            Mode::DiskChanged
+        } else if self.creating_file {
+            Mode::Prompt
         } else if self.picking_file {
-            AppMode::FilePicker
+            Mode::Picker
         } else if self.showing_move_dest {
-            AppMode::MoveDestination
+            Mode::Move
         } else if self.showing_git_status {
-            AppMode::GitStatus
-        } else if !matches!(self.op_mode, OpMode::None) {
-            AppMode::Operation
-        } else if self.editing_line {
-            AppMode::LineEdit
-        } else if self.editor_cmd_mode {
-            AppMode::EditorCommand
+            Mode::GitStatus
+        } else if !matches!(self.op_mode, OpMode::None) || self.editing_line || self.editor_cmd_mode
+        {
+            Mode::Prompt
         } else if self.show_raw_editor {
-            AppMode::RawEditor
+            Mode::Editor
         } else if self.show_help {
-            AppMode::Help
+            Mode::Help
         } else if self.confirming_delete {
-            AppMode::DeleteConfirmation
+            Mode::ConfirmDelete
         } else {
-            AppMode::Normal
+            match self.focus {
+                Focus::Left => Mode::Files,
+                Focus::Preview => Mode::Preview,
+                Focus::Editor => Mode::Editor,
+            }
         }
     }
 }
 
-/// Handle file creation mode events
-fn handle_file_creation(app: &mut App, key_event: KeyEvent) -> Option<()> {
-    match (key_event.code, key_event.modifiers) {
-        (KeyCode::Enter, _) => {
-            let _ = app.confirm_create_file();
-        }
-        (KeyCode::Esc, _) => {
-            app.cancel_create_file();
-        }
-        _ => {
-            let _ = app.filename_input.input(key_event);
-        }
+/// The text field of the active prompt
+fn prompt_input(app: &mut App) -> &mut TextArea<'static> {
+    if app.creating_file {
+        &mut app.filename_input
+    } else if !matches!(app.op_mode, OpMode::None) {
+        &mut app.op_input
+    } else if app.editing_line {
+        &mut app.line_input
+    } else {
+        &mut app.editor_cmd_input
     }
-    Some(())
 }
 
-/// Handle file picker mode events
-fn handle_file_picker(app: &mut App, key_event: KeyEvent) -> Option<()> {
-    match (key_event.code, key_event.modifiers) {
-        (KeyCode::Esc, _) => app.picker_cancel(),
-        (KeyCode::Enter, _) => {
-            let _ = app.picker_activate();
+/// Run an action; `None` means quit
+pub fn run_action(app: &mut App, action: Action) -> Option<()> {
+    match action {
+        Action::Quit => return None,
+        Action::Help | Action::HelpClose => app.toggle_help(),
+        Action::HelpUp => app.help_scroll = app.help_scroll.saturating_sub(1),
+        Action::HelpDown => app.help_scroll = app.help_scroll.saturating_add(1),
+        Action::ToggleFiles => app.toggle_left_pane(),
+        Action::FocusNext | Action::FocusPrev => switch_pane(app),
+        Action::PaneGrow => app.increase_left_pane_width(),
+        Action::PaneShrink => app.decrease_left_pane_width(),
+        Action::PickerOpen => {
+            let _ = app.begin_file_picker();
         }
-        (KeyCode::Up, modifiers) if modifiers.contains(KeyModifiers::SHIFT) => {
-            app.picker_up_with_selection()
+        Action::Save => {
+            let _ = app.save();
         }
-        (KeyCode::Down, modifiers) if modifiers.contains(KeyModifiers::SHIFT) => {
-            app.picker_down_with_selection()
+        Action::OpenExternal => {
+            // Bulk open when files are selected in the tree
+            if !app.tree_selection.is_empty() && matches!(app.focus, Focus::Left) {
+                let _ = app.tree_open_selected();
+            } else {
+                let _ = app.open_externally();
+            }
         }
-        (KeyCode::Up, _) | (KeyCode::Char('k'), _) => {
-            app.picker_clear_selection();
-            app.picker_up();
+        Action::Copy => app.begin_copy(),
+        Action::Move => app.begin_move(),
+        Action::Mkdir => app.begin_mkdir(),
+        Action::Delete | Action::TreeDelete => app.begin_delete(),
+        Action::VideoTogglePause => app.toggle_pause_video(),
+        Action::VideoStop => app.stop_video(),
+        Action::VideoAutoplay => {
+            app.autoplay_video = !app.autoplay_video;
+            app.status = format!(
+                "Autoplay video {}",
+                if app.autoplay_video { "on" } else { "off" }
+            );
         }
-        (KeyCode::Down, _) | (KeyCode::Char('j'), _) => {
-            app.picker_clear_selection();
-            app.picker_down();
+        Action::TreeUp => {
+            app.left_state.key_up();
         }
-        (KeyCode::Char('d') | KeyCode::Char('D'), _) => {
-            let _ = app.picker_delete_with_git_check();
+        Action::TreeDown => {
+            app.left_state.key_down();
         }
-        (KeyCode::Char('m') | KeyCode::Char('M'), _) => {
-            let _ = app.picker_start_move();
+        Action::TreeFirst => {
+            app.left_state.select_first();
         }
-        (KeyCode::Char('p') | KeyCode::Char('P'), _) => {
-            let _ = app.picker_parent_dir();
+        Action::TreeLast => {
+            app.left_state.select_last();
         }
-        (KeyCode::Char('g') | KeyCode::Char('G'), _) => {
-            app.picker_show_git_status();
-        }
-        (KeyCode::Char('o') | KeyCode::Char('O'), _) => {
-            if !app.picker_selection.is_empty() {
-                // Bulk open mode
-                let selected_files: Vec<PathBuf> = app
-                    .picker_selection
-                    .iter()
-                    .filter_map(|&i| app.picker_items.get(i).cloned())
-                    .filter(|p| p.is_file())
-                    .collect();
-
-                if !selected_files.is_empty() {
-                    let count = selected_files.len();
-                    for path in &selected_files {
-                        let _ = app.open_in_editor(path);
-                    }
-                    app.status = format!("Opened {} files in editor", count);
-                } else {
-                    app.status = "No files selected to open".to_string();
-                }
-            } else if app.picker_index < app.picker_items.len() {
-                // Single file open (original behavior)
-                let path = app.picker_items[app.picker_index].clone();
-                let _ = app.open_in_editor(&path);
-            }
+        Action::TreeCollapse => {
+            app.left_state.key_left();
         }
-        (KeyCode::Char(' '), _) => {
-            // Space to toggle selection of current item
-            app.picker_toggle_selection();
+        Action::TreeExpand => tree_expand(app),
+        Action::TreeActivate => {
+            let _ = app.activate_on_tree();
+        }
+        Action::TreeRefresh => {
+            let _ = app.refresh_tree();
         }
-        (KeyCode::Char('a'), modifiers) if modifiers.contains(KeyModifiers::CONTROL) => {
-            // Ctrl+A to select all
-            app.picker_selection.clear();
-            for i in 0..app.picker_items.len() {
-                app.picker_selection.insert(i);
+        Action::TreeNewFile => app.begin_create_file(),
+        Action::TreeToggleSelect => app.tree_toggle_selection(),
+        Action::TreeSelect => app.tree_accumulate_selection(),
+        Action::TreeExtendUp => app.tree_up_with_selection(),
+        Action::TreeExtendDown => app.tree_down_with_selection(),
+        Action::TreeSelectAll => app.tree_select_all(),
+        Action::TreeClearSelection => app.tree_clear_selection(),
+        Action::PreviewUp => app.move_cursor_up(),
+        Action::PreviewDown => app.move_cursor_down(),
+        Action::PreviewPageUp => {
+            for _ in 0..app.preview_viewport.max(1) {
+                app.move_cursor_up();
             }
-            app.picker_selection_anchor = None;
         }
-        (KeyCode::Char(c), _) => {
-            app.status = format!("File picker: Unknown key '{}'", c);
+        Action::PreviewPageDown => {
+            for _ in 0..app.preview_viewport.max(1) {
+                app.move_cursor_down();
+            }
         }
-        _ => {}
-    }
-    Some(())
-}
-
-/// Handle move destination picker mode events
-fn handle_move_destination(app: &mut App, key_event: KeyEvent) -> Option<()> {
-    match (key_event.code, key_event.modifiers) {
-        (KeyCode::Esc, _) => app.cancel_move(),
-        (KeyCode::Enter, _) => {
-            let _ = app.confirm_move();
+        Action::PreviewTop => app.jump_to_line(1),
+        Action::PreviewBottom => app.jump_to_line(usize::MAX),
+        Action::PreviewScrollRight => app.move_col_right(),
+        Action::PreviewToFiles => {
+            if !app.show_left_pane {
+                app.toggle_left_pane();
+            }
+            // Keep tree selection in sync with currently opened file
+            if let Some(p) = &app.opened {
+                app.left_state.select(vec![p.display().to_string()]);
+            }
+            app.focus = Focus::Left;
         }
-        (KeyCode::Up, _) | (KeyCode::Char('k'), _) => app.move_dest_up(),
-        (KeyCode::Down, _) | (KeyCode::Char('j'), _) => app.move_dest_down(),
-        (KeyCode::Right, _) | (KeyCode::Char('l'), _) => {
-            let _ = app.move_dest_enter();
+        Action::PreviewLineEdit => app.begin_line_edit(),
+        Action::EditorOpen => {
+            if app.opened.is_none() {
+                app.status = "No file open".into();
+            } else {
+                app.focus = Focus::Editor;
+                app.show_raw_editor = true;
+                app.prefer_raw_editor = true;
+                // Set editor cursor to match preview cursor position
+                app.editor.move_cursor(tui_textarea::CursorMove::Jump(
+                    app.preview_cursor as u16,
+                    app.preview_col as u16,
+                ));
+            }
         }
-        _ => {}
-    }
-    Some(())
-}
-
-/// Handle Git status display mode events
-fn handle_git_status(app: &mut App, key_event: KeyEvent) -> Option<()> {
-    match (key_event.code, key_event.modifiers) {
-        (KeyCode::Esc, _) | (KeyCode::Enter, _) | (KeyCode::Char('g'), _) => {
-            app.close_git_status();
+        Action::EditorClose => {
+            app.show_raw_editor = false;
+            app.prefer_raw_editor = false;
+            app.focus = Focus::Preview;
+        }
+        Action::EditorCommand => app.begin_editor_cmd(),
+        Action::PromptConfirm => {
+            if app.creating_file {
+                let _ = app.confirm_create_file();
+            } else if !matches!(app.op_mode, OpMode::None) {
+                let _ = app.confirm_op();
+            } else if app.editing_line {
+                app.confirm_line_edit();
+            } else if app.editor_cmd_mode {
+                let _ = app.confirm_editor_cmd();
+            }
         }
-        _ => {}
-    }
-    Some(())
-}
-
-/// Handle file operation mode events
-fn handle_operation(app: &mut App, key_event: KeyEvent) -> Option<()> {
-    match (key_event.code, key_event.modifiers) {
-        (KeyCode::Esc, _) => app.cancel_op(),
-        (KeyCode::Enter, _) => {
-            let _ = app.confirm_op();
+        Action::PromptCancel => {
+            if app.creating_file {
+                app.cancel_create_file();
+            } else if !matches!(app.op_mode, OpMode::None) {
+                app.cancel_op();
+            } else if app.editing_line {
+                app.cancel_line_edit();
+            } else if app.editor_cmd_mode {
+                app.cancel_editor_cmd();
+            }
         }
-        _ => {
-            let _ = app.op_input.input(key_event);
+        Action::PickerUp => {
+            app.picker_clear_selection();
+            app.picker_up();
         }
-    }
-    Some(())
-}
-
-/// Handle inline line editing mode events
-fn handle_line_edit(app: &mut App, key_event: KeyEvent) -> Option<()> {
-    match (key_event.code, key_event.modifiers) {
-        (KeyCode::Enter, _) => {
-            app.confirm_line_edit();
+        Action::PickerDown => {
+            app.picker_clear_selection();
+            app.picker_down();
         }
-        (KeyCode::Esc, _) => app.cancel_line_edit(),
-        _ => {
-            let _ = app.line_input.input(key_event);
+        Action::PickerExtendUp => app.picker_up_with_selection(),
+        Action::PickerExtendDown => app.picker_down_with_selection(),
+        Action::PickerToggleSelect => app.picker_toggle_selection(),
+        Action::PickerSelectAll => {
+            app.picker_selection = (0..app.picker_items.len()).collect();
+            app.picker_selection_anchor = None;
         }
-    }
-    Some(())
-}
-
-/// Handle editor command mode events
-fn handle_editor_command(app: &mut App, key_event: KeyEvent) -> Option<()> {
-    match (key_event.code, key_event.modifiers) {
-        (KeyCode::Esc, _) => app.cancel_editor_cmd(),
-        (KeyCode::Enter, _) => {
-            let _ = app.confirm_editor_cmd();
+        Action::PickerActivate => {
+            let _ = app.picker_activate();
         }
-        _ => {
-            let _ = app.editor_cmd_input.input(key_event);
+        Action::PickerCancel => app.picker_cancel(),
+        Action::PickerDelete => {
+            let _ = app.picker_delete_with_git_check();
         }
-    }
-    Some(())
-}
-
-/// Handle raw editor mode events
-fn handle_raw_editor(app: &mut App, key_event: KeyEvent) -> Option<()> {
-    match (key_event.code, key_event.modifiers) {
-        (KeyCode::Esc, _) => {
-            app.show_raw_editor = false;
-            app.prefer_raw_editor = false;
-            return Some(());
+        Action::PickerMove => {
+            let _ = app.picker_start_move();
         }
-        (KeyCode::Tab, _) => {
-            // Exit raw editor and switch focus
-            app.show_raw_editor = false;
-            app.prefer_raw_editor = false;
-            app.focus = Focus::Left;
-            return Some(());
+        Action::PickerParent => {
+            let _ = app.picker_parent_dir();
         }
-        _ => {
-            if !app.editor_cmd_mode {
-                app.editor.input(key_event);
-            }
-            return Some(());
+        Action::PickerGitStatus => app.picker_show_git_status(),
+        Action::PickerOpenExternal => picker_open_external(app),
+        Action::MoveUp => app.move_dest_up(),
+        Action::MoveDown => app.move_dest_down(),
+        Action::MoveEnter => {
+            let _ = app.move_dest_enter();
         }
-    }
-}
-
-/// Handle help screen mode events
-fn handle_help(app: &mut App, key_event: KeyEvent) -> Option<()> {
-    match (key_event.code, key_event.modifiers) {
-        (KeyCode::Esc, _) | (KeyCode::Char('?'), _) | (KeyCode::Char('h'), _) => {
-            app.toggle_help();
+        Action::MoveConfirm => {
+            let _ = app.confirm_move();
         }
-        _ => {}
-    }
-    Some(())
-}
-
-/// Handle delete confirmation mode events
-fn handle_delete_confirmation(app: &mut App, key_event: KeyEvent) -> Option<()> {
-    match key_event.code {
-        KeyCode::Enter | KeyCode::Char('d') => {
+        Action::MoveCancel => app.cancel_move(),
+        Action::GitStatusClose => app.close_git_status(),
+        Action::DeleteConfirm => {
             let _ = app.confirm_delete_with_git();
         }
-        KeyCode::Esc => app.cancel_delete(),
-        _ => {}
-    }
-    Some(())
-}
-
-/// Handle normal browsing/navigation mode events
-fn handle_normal_mode(app: &mut App, key_event: KeyEvent) -> Option<()> {
-    match (key_event.code, key_event.modifiers) {
-        // Application exit commands
-        (KeyCode::Char('q'), _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
-            return None; // Signal exit
-        }
-        (KeyCode::Esc, _) => {
-            if matches!(app.focus, Focus::Preview) {
-                return None; // Signal exit
+        Action::DeleteCancel => app.cancel_delete(),
+        Action::DiskOverwrite => {
+            if let Err(e) = app.overwrite() {
+                app.status = format!("Save failed: {e}");
             }
         }
-
-        // Focus and navigation commands
-        (KeyCode::Tab, mods) => handle_tab_navigation(app, mods),
-        (KeyCode::BackTab, _) => handle_back_tab_navigation(app),
-
-        // Application commands
-        (KeyCode::Char('?'), _) => app.toggle_help(),
-        (KeyCode::Char('i'), mods) if mods.contains(KeyModifiers::CONTROL) => {
-            let _ = app.begin_file_picker();
-        }
-        (KeyCode::Char('s'), KeyModifiers::CONTROL) => {
-            let _ = app.save();
-        }
-        (KeyCode::Char('o'), _) if matches!(app.focus, Focus::Left | Focus::Editor) => {
-            // Check if we have tree selections for bulk open
-            if !app.tree_selection.is_empty() && matches!(app.focus, Focus::Left) {
-                let _ = app.tree_open_selected();
-            } else {
-                let _ = app.open_externally();
+        Action::DiskMerge => {
+            if let Err(e) = app.merge_with_disk() {
+                app.status = format!("Merge failed: {e}");
             }
         }
-
-        // Context-specific commands
-        (KeyCode::Char('n'), _) if matches!(app.focus, Focus::Left) => {
-            app.begin_create_file();
-        }
-        (KeyCode::Char('d'), _) if matches!(app.focus, Focus::Left) => {
-            app.begin_delete();
-        }
-        (KeyCode::Char(':'), _) if matches!(app.focus, Focus::Editor) => {
-            app.begin_editor_cmd();
+        Action::DiskReload => {
+            if let Err(e) = app.reload_from_disk() {
+                app.status = format!("Reload failed: {e}");
+            }
         }
-
-        // Delegate to focus-specific handlers
-        _ => handle_focus_specific(app, key_event),
+        Action::DiskKeep => app.keep_edits(),
     }
-
     Some(())
 }
 
-/// Handle Tab navigation between panes
-fn handle_tab_navigation(app: &mut App, modifiers: KeyModifiers) {
-    if modifiers.contains(KeyModifiers::CONTROL) {
-        // Ctrl+I for file picker
-        let _ = app.begin_file_picker();
-    } else if app.show_left_pane {
-        // Tab between left pane and right pane
-        app.focus = match app.focus {
-            Focus::Left => {
-                // When moving from left to right, go to Preview by default
-                // Only go to Editor if raw editor is already active
-                if app.show_raw_editor && app.opened.is_some() {
-                    Focus::Editor
-                } else {
-                    Focus::Preview
-                }
-            }
-            Focus::Preview => Focus::Left,
-            Focus::Editor => {
-                // When leaving editor, turn off raw editor mode
-                app.show_raw_editor = false;
-                app.prefer_raw_editor = false;
-                Focus::Left
-            }
-        };
+/// Move focus between the files pane and the right pane
+fn switch_pane(app: &mut App) {
+    if app.show_raw_editor {
+        // Leave the raw editor but come back to it next time
+        app.show_raw_editor = false;
+        app.prefer_raw_editor = true;
     }
-}
-
-/// Handle BackTab (Shift+Tab) navigation between panes
-fn handle_back_tab_navigation(app: &mut App) {
-    if app.show_left_pane {
-        // Same logic as Tab for 2-pane navigation
-        app.focus = match app.focus {
-            Focus::Left => {
-                // When moving from left to right, go to Preview by default
-                if app.show_raw_editor && app.opened.is_some() {
-                    Focus::Editor
-                } else {
-                    Focus::Preview
-                }
-            }
-            Focus::Preview => Focus::Left,
-            Focus::Editor => {
-                // When leaving editor, turn off raw editor mode
-                app.show_raw_editor = false;
-                app.prefer_raw_editor = false;
-                Focus::Left
-            }
-        };
+    if !app.show_left_pane {
+        // If left pane is hidden, Tab toggles it and focuses Files
+        app.toggle_left_pane();
+        app.focus = Focus::Left;
+        return;
     }
+    app.focus = match app.focus {
+        Focus::Left => {
+            // Ensure selected file is opened before switching
+            let _ = app.open_selected();
+            right_pane_focus(app)
+        }
+        Focus::Preview | Focus::Editor => Focus::Left,
+    };
 }
 
-/// Handle focus-specific key events
-fn handle_focus_specific(app: &mut App, key_event: KeyEvent) {
-    match app.focus {
-        Focus::Left => handle_left_pane_keys(app, key_event),
-        Focus::Editor => handle_editor_keys(app, key_event),
-        Focus::Preview => handle_preview_keys(app, key_event),
+/// Preview, or the raw editor if that is what the user was last using
+fn right_pane_focus(app: &mut App) -> Focus {
+    if app.prefer_raw_editor && app.opened.is_some() {
+        app.show_raw_editor = true;
+        Focus::Editor
+    } else {
+        Focus::Preview
     }
 }
 
-/// Handle key events when left pane has focus
-fn handle_left_pane_keys(app: &mut App, key_event: KeyEvent) {
-    match (key_event.code, key_event.modifiers) {
-        (KeyCode::Up, _) | (KeyCode::Char('k'), _) => {
-            let _ = app.left_state.key_up();
-        }
-        (KeyCode::Down, _) | (KeyCode::Char('j'), _) => {
-            let _ = app.left_state.key_down();
-        }
-        (KeyCode::Left, _) => {
-            // Try to collapse tree node
-            let _ = app.left_state.key_left();
-        }
-        (KeyCode::Right, _) => {
-            // Try to expand tree node or move to preview if it's a file
-            let is_file = app
-                .left_state
-                .selected()
-                .first()
-                .and_then(|s| std::path::Path::new(s).to_str())
-                .map(|s| std::path::Path::new(s).is_file())
-                .unwrap_or(false);
-
-            if is_file {
-                // If it's a file, open it and switch to preview pane
-                let _ = app.open_selected();
-                app.focus = Focus::Preview;
-            } else {
-                // If it's a directory, try to expand it
-                let _ = app.left_state.key_right();
-            }
-        }
-        (KeyCode::Enter, _) => {
-            let _ = app.open_selected();
-        }
-        (KeyCode::Char('r'), _) => {
-            let _ = app.refresh_tree();
-        }
-        (KeyCode::Char('h'), _) => app.toggle_left_pane(),
-        (KeyCode::Char('s') | KeyCode::Char('S'), _) => {
-            app.tree_accumulate_selection();
-        }
-        (KeyCode::Esc, _) if !app.tree_selection.is_empty() => {
-            // Clear all selections when Esc pressed in left pane with selections
-            app.tree_clear_selection();
-        }
-        (KeyCode::Char('n'), _) => app.begin_create_file(),
-        (KeyCode::Char('d'), _) => app.begin_delete(),
-        (KeyCode::F(5), _) => app.begin_copy(),
-        (KeyCode::F(6), _) => app.begin_move(),
-        (KeyCode::F(7), _) => app.begin_create_file(),
-        (KeyCode::F(8), _) => app.begin_delete(),
-        _ => {}
+/// Open the selected file, or expand the selected directory
+fn tree_expand(app: &mut App) {
+    let selected = app
+        .left_state
+        .selected()
+        .last()
+        .cloned()
+        .unwrap_or_default();
+    if Path::new(&selected).is_file() {
+        let _ = app.open_selected();
+        app.focus = right_pane_focus(app);
+        return;
     }
-}
-
-/// Handle key events when editor has focus
-fn handle_editor_keys(app: &mut App, key_event: KeyEvent) {
-    match (key_event.code, key_event.modifiers) {
-        (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
-            // Toggle to raw editor mode
-            app.show_raw_editor = true;
-            app.prefer_raw_editor = true;
-        }
-        _ => {
-            // Default editor input handling
-            app.editor.input(key_event);
-        }
+    // Moving right from the directory of the open file returns to it
+    let holds_opened = app
+        .opened
+        .as_ref()
+        .and_then(|opened| opened.parent())
+        .is_some_and(|parent| parent == Path::new(&selected));
+    if holds_opened {
+        app.focus = right_pane_focus(app);
     }
+    app.left_state.key_right();
 }
 
-/// Handle key events when preview pane has focus
-fn handle_preview_keys(app: &mut App, key_event: KeyEvent) {
-    match (key_event.code, key_event.modifiers) {
-        (KeyCode::Up, _) | (KeyCode::Char('k'), _) => app.move_cursor_up(),
-        (KeyCode::Down, _) | (KeyCode::Char('j'), _) => app.move_cursor_down(),
-        (KeyCode::Char('i'), _) => app.begin_line_edit(),
-        (KeyCode::Char('e'), _) => app.begin_line_edit(),
-        (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
-            // Switch to raw editor mode
-            app.focus = Focus::Editor;
-            app.show_raw_editor = true;
-            app.prefer_raw_editor = true;
-        }
-        (KeyCode::PageUp, _) => {
-            for _ in 0..10 {
-                app.move_cursor_up();
-            }
-        }
-        (KeyCode::PageDown, _) => {
-            for _ in 0..10 {
-                app.move_cursor_down();
-            }
+/// Open the picker selection, or the highlighted item, in the external editor
+fn picker_open_external(app: &mut App) {
+    if app.picker_selection.is_empty() {
+        if let Some(path) = app.picker_items.get(app.picker_index).cloned() {
+            let _ = app.open_in_editor(&path);
         }
-        _ => {}
+        return;
+    }
+    let selected_files: Vec<PathBuf> = app
+        .picker_selection
+        .iter()
+        .filter_map(|&i| app.picker_items.get(i).cloned())
+        .filter(|p| p.is_file())
+        .collect();
+    if selected_files.is_empty() {
+        app.status = "No files selected to open".to_string();
+        return;
+    }
+    for path in &selected_files {
+        let _ = app.open_in_editor(path);
     }
+    app.status = format!("Opened {} files in editor", selected_files.len());
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::path::PathBuf;
+    use ratatui::crossterm::event::KeyModifiers;
+
+    fn press(app: &mut App, code: KeyCode) -> Option<()> {
+        handle_key_event(app, KeyEvent::new(code, KeyModifiers::NONE))
+    }
 
     #[test]
-    fn test_app_mode_detection() {
+    fn test_key_mode_detection() {
         let mut app = App::new(PathBuf::from(".")).unwrap();
+        assert_eq!(app.key_mode(), Mode::Files);
 
-        // Test normal mode
-        assert_eq!(app.current_mode(), AppMode::Normal);
-
-        // Test file creation mode
         app.creating_file = true;
-        assert_eq!(app.current_mode(), AppMode::FileCreation);
+        assert_eq!(app.key_mode(), Mode::Prompt);
         app.creating_file = false;
 
-        // Test file picker mode
         app.picking_file = true;
-        assert_eq!(app.current_mode(), AppMode::FilePicker);
+        assert_eq!(app.key_mode(), Mode::Picker);
         app.picking_file = false;
 
-        // Test help mode
         app.show_help = true;
-        assert_eq!(app.current_mode(), AppMode::Help);
-        app.show_help = false;
+        assert_eq!(app.key_mode(), Mode::Help);
     }
 
     #[test]
     fn test_key_event_routing() {
         let mut app = App::new(PathBuf::from(".")).unwrap();
 
-        // Test normal mode key handling
-        let key_event = KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE);
-        let result = handle_key_event(&mut app, key_event);
-        assert!(result.is_none()); // Should signal exit
+        assert!(!app.show_help);
+        press(&mut app, KeyCode::Char('?'));
+        assert!(app.show_help);
+        press(&mut app, KeyCode::Esc);
+        assert!(!app.show_help);
+
+        // Unbound keys in a prompt are typed into it
+        app.begin_create_file();
+        press(&mut app, KeyCode::Char('q'));
+        assert_eq!(app.filename_input.lines(), ["q"]);
+        press(&mut app, KeyCode::Esc);
+
+        assert!(press(&mut app, KeyCode::Char('q')).is_none());
+    }
+
+    #[test]
+    fn test_changes_on_disk_reload_or_prompt() {
+        use std::time::{Duration, Instant};
+
+        let temp = tempfile::tempdir().unwrap();
+        let file = temp.path().join("note.md");
+        std::fs::write(&file, "one\ntwo").unwrap();
+        let mut app = App::new(temp.path().to_path_buf()).unwrap();
+        app.open_file(&file).unwrap();
+        app.poll_watcher();
+
+        let wait_for = |app: &mut App, done: &dyn Fn(&App) -> bool| {
+            let start = Instant::now();
+            while !done(app) {
+                assert!(start.elapsed() < Duration::from_secs(5), "{}", app.status);
+                std::thread::sleep(Duration::from_millis(20));
+                app.poll_watcher();
+            }
+        };
 
-        // Test help toggle
-        let key_event = KeyEvent::new(KeyCode::Char('?'), KeyModifiers::NONE);
-        assert_eq!(app.show_help, false);
-        handle_key_event(&mut app, key_event);
-        assert_eq!(app.show_help, true);
+        // No unsaved edits: the buffer follows the file
+        std::fs::write(&file, "one\nTWO").unwrap();
+        wait_for(&mut app, &|app| app.editor.lines() == ["one", "TWO"]);
+        assert!(!app.disk_changed);
+
+        // Unsaved edits: ask first
+        app.editor.insert_str("zero ");
+        app.revision += 1;
+        std::fs::write(&file, "three").unwrap();
+        wait_for(&mut app, &|app| app.disk_changed);
+        assert_eq!(app.key_mode(), Mode::DiskChanged);
+        assert_eq!(app.editor.lines(), ["zero one", "TWO"]);
+        press(&mut app, KeyCode::Char('r'));
+        assert!(!app.disk_changed);
+        assert_eq!(app.editor.lines(), ["three"]);
+        assert!(!app.is_dirty());
+    }
+    #[test]
+    fn test_save_detects_changes_on_disk() {
+        let temp = tempfile::tempdir().unwrap();
+        let file = temp.path().join("note.md");
+        std::fs::write(&file, "one\ntwo\nthree").unwrap();
+        let mut app = App::new(temp.path().to_path_buf()).unwrap();
+        app.watcher = None;
+        app.open_file(&file).unwrap();
+
+        app.editor.insert_str("zero ");
+        app.revision += 1;
+        std::fs::write(&file, "one\ntwo\nthree\nfour").unwrap();
+        app.save().unwrap();
+        assert!(app.disk_changed);
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "one\ntwo\nthree\nfour"
+        );
+
+        // Merging keeps both changes, and the result saves without asking
+        press(&mut app, KeyCode::Char('m'));
+        assert!(!app.disk_changed);
+        assert!(app.show_raw_editor);
+        assert_eq!(app.editor.lines(), ["zero one", "two", "three", "four"]);
+        app.save().unwrap();
+        assert!(!app.disk_changed);
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "zero one\ntwo\nthree\nfour"
+        );
+
+        // Or overwrite what is on disk
+        app.editor.insert_str("mine ");
+        app.revision += 1;
+        std::fs::write(&file, "theirs").unwrap();
+        app.save().unwrap();
+        assert!(app.disk_changed);
+        press(&mut app, KeyCode::Char('o'));
+        assert!(!app.disk_changed);
+        assert!(std::fs::read_to_string(&file)
+            .unwrap()
+            .starts_with("mine zero one"));
     }
 }
@@ -1,4 +1,7 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 #[allow(dead_code)]
 pub fn resolve_link(current_file: &Path, link: &str) -> PathBuf {
@@ -12,3 +15,42 @@ pub fn resolve_link(current_file: &Path, link: &str) -> PathBuf {
             .join(p)
     }
 }
+
+/// What a file looked like when it was loaded or last saved, to tell whether
+/// something else has written to it since
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskState {
+    modified: Option<SystemTime>,
+    hash: u64,
+}
+
+impl DiskState {
+    /// Record `text`, just read from or written to `path`
+    pub fn new(path: &Path, text: &str) -> Self {
+        Self {
+            modified: std::fs::metadata(path).and_then(|m| m.modified()).ok(),
+            hash: content_hash(text),
+        }
+    }
+
+    /// Whether `path` still holds what was recorded; a file that is gone
+    /// counts as unchanged, since saving just puts it back
+    pub fn is_current(&self, path: &Path) -> bool {
+        let modified = match std::fs::metadata(path).and_then(|m| m.modified()) {
+            Ok(modified) => modified,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return true,
+            Err(_) => return false,
+        };
+        if self.modified == Some(modified) {
+            return true;
+        }
+        // Touched but possibly not changed
+        std::fs::read_to_string(path).is_ok_and(|text| content_hash(&text) == self.hash)
+    }
+}
+
+fn content_hash(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
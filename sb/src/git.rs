@@ -90,6 +90,11 @@ impl GitRepository {
         &self.root
     }
 
+    /// Get the commit HEAD points at, if there is one yet
+    pub fn head_id(&self) -> Option<git2::Oid> {
+        self.repo.head().ok()?.target()
+    }
+
     /// Get the Git status of all files in the repository
     pub fn status(&self) -> Result<HashMap<PathBuf, FileStatus>, GitError> {
         let mut status_opts = StatusOptions::new();
@@ -283,6 +288,23 @@ mod tests {
         assert!(!GitRepository::is_git_repo(temp_dir.path()));
     }
 
+    #[test]
+    fn test_head_id_follows_commits() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = Repository::init(temp_dir.path()).unwrap();
+        let git = GitRepository::open(temp_dir.path()).unwrap();
+        assert_eq!(git.head_id(), None);
+
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let tree = repo
+            .find_tree(repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        let commit = repo
+            .commit(Some("HEAD"), &signature, &signature, "first", &tree, &[])
+            .unwrap();
+        assert_eq!(git.head_id(), Some(commit));
+    }
+
     #[test]
     fn test_file_status_conversion() {
         let status = Status::WT_NEW;
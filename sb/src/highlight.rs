@@ -0,0 +1,192 @@
+//! Incremental syntax highlighting
+//!
+//! [`Highlighted`] keeps the syntect parser state at the start of every line,
+//! so after an edit only the changed lines are highlighted again, plus the
+//! lines after them until the parser state matches what it was before (an
+//! unclosed block comment, say, carries on until it does).
+
+use std::rc::Rc;
+
+use once_cell::sync::Lazy;
+use ratatui::style::{Color, Style};
+use ratatui::text::Span;
+use similar::{Algorithm, DiffOp};
+use syntect::highlighting::{HighlightIterator, HighlightState, Highlighter, Theme};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet};
+
+pub static SYNTAX_SET: Lazy<SyntaxSet> = Lazy::new(SyntaxSet::load_defaults_newlines);
+
+type State = Rc<(ParseState, HighlightState)>;
+
+/// Highlighted lines of one buffer
+pub struct Highlighted {
+    theme: &'static Theme,
+    lines: Vec<String>,
+    spans: Vec<Vec<Span<'static>>>,
+    /// Parser state before each line, and after the last
+    states: Vec<State>,
+}
+
+impl Highlighted {
+    pub fn new(syntax: &'static SyntaxReference, theme: &'static Theme) -> Self {
+        let highlighter = Highlighter::new(theme);
+        let start = Rc::new((
+            ParseState::new(syntax),
+            HighlightState::new(&highlighter, ScopeStack::new()),
+        ));
+        Self {
+            theme,
+            lines: Vec::new(),
+            spans: Vec::new(),
+            states: vec![start],
+        }
+    }
+
+    /// For a file extension, plain text if syntect does not know it
+    pub fn for_extension(ext: &str, theme: &'static Theme) -> Self {
+        let syntax = SYNTAX_SET
+            .find_syntax_by_extension(ext)
+            .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text());
+        Self::new(syntax, theme)
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Styled spans of line `index`
+    pub fn line(&self, index: usize) -> &[Span<'static>] {
+        self.spans.get(index).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// The source lines
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    /// Brings the highlighting up to date with `text`; returns how many lines
+    /// had to be highlighted
+    pub fn update(&mut self, text: &str) -> usize {
+        let new: Vec<&str> = text.lines().collect();
+        let ops = {
+            let old: Vec<&str> = self.lines.iter().map(String::as_str).collect();
+            similar::capture_diff_slices(Algorithm::Myers, &old, &new)
+        };
+        let mut old_lines = std::mem::take(&mut self.lines);
+        let mut old_spans = std::mem::take(&mut self.spans);
+        let old_states = std::mem::take(&mut self.states);
+
+        let highlighter = Highlighter::new(self.theme);
+        let mut state = old_states[0].clone();
+        let mut count = 0;
+        for op in ops {
+            let (old_start, new_range) = match op {
+                DiffOp::Equal {
+                    old_index,
+                    new_index,
+                    len,
+                } => (Some(old_index), new_index..new_index + len),
+                DiffOp::Delete { .. } => continue,
+                DiffOp::Insert {
+                    new_index, new_len, ..
+                }
+                | DiffOp::Replace {
+                    new_index, new_len, ..
+                } => (None, new_index..new_index + new_len),
+            };
+            for (k, i) in new_range.enumerate() {
+                self.states.push(state.clone());
+                // An unchanged line entered in the same state comes out the same
+                if let Some(j) = old_start.map(|start| start + k) {
+                    if Rc::ptr_eq(&state, &old_states[j]) || state == old_states[j] {
+                        self.lines.push(std::mem::take(&mut old_lines[j]));
+                        self.spans.push(std::mem::take(&mut old_spans[j]));
+                        state = old_states[j + 1].clone();
+                        continue;
+                    }
+                }
+                let (mut parse, mut highlight) = (*state).clone();
+                self.spans.push(highlight_line(
+                    new[i],
+                    &mut parse,
+                    &mut highlight,
+                    &highlighter,
+                ));
+                self.lines.push(new[i].to_string());
+                state = Rc::new((parse, highlight));
+                count += 1;
+            }
+        }
+        self.states.push(state);
+        count
+    }
+}
+
+fn highlight_line(
+    line: &str,
+    parse: &mut ParseState,
+    highlight: &mut HighlightState,
+    highlighter: &Highlighter,
+) -> Vec<Span<'static>> {
+    // The syntaxes expect each line to end in a newline
+    let line = format!("{line}\n");
+    let ops = parse.parse_line(&line, &SYNTAX_SET).unwrap_or_default();
+    HighlightIterator::new(highlight, &ops, &line, highlighter)
+        .filter_map(|(style, segment)| {
+            let segment = segment.trim_end_matches('\n');
+            let fg = style.foreground;
+            (!segment.is_empty()).then(|| {
+                Span::styled(
+                    segment.to_string(),
+                    Style::default().fg(Color::Rgb(fg.r, fg.g, fg.b)),
+                )
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::theme::THEME_SET;
+
+    fn plain(h: &Highlighted, index: usize) -> String {
+        h.line(index).iter().map(|s| s.content.as_ref()).collect()
+    }
+
+    #[test]
+    fn test_update_only_rehighlights_what_changed() {
+        let theme = &THEME_SET.themes["base16-ocean.dark"];
+        let source: Vec<String> = (0..200).map(|i| format!("let x{i} = {i};")).collect();
+        let mut h = Highlighted::for_extension("rs", theme);
+        assert_eq!(h.update(&source.join("\n")), 200);
+        assert_eq!(h.update(&source.join("\n")), 0);
+
+        // Editing one line re-highlights just that line
+        let mut edited = source.clone();
+        edited[100] = "let changed = 1;".into();
+        assert_eq!(h.update(&edited.join("\n")), 1);
+        assert_eq!(plain(&h, 100), "let changed = 1;");
+        assert_eq!(h.len(), 200);
+
+        // Inserting and deleting lines keeps the rest aligned
+        edited.insert(10, "let inserted = 2;".into());
+        edited.remove(150);
+        assert_eq!(h.update(&edited.join("\n")), 1);
+        assert_eq!(h.text(), edited.join("\n"));
+
+        // Opening a block comment changes everything after it
+        edited.insert(50, "/*".into());
+        let count = h.update(&edited.join("\n"));
+        assert_eq!(count, edited.len() - 50);
+        let mut fresh = Highlighted::for_extension("rs", theme);
+        fresh.update(&edited.join("\n"));
+        for i in 0..edited.len() {
+            assert_eq!(h.line(i), fresh.line(i), "line {i}");
+        }
+    }
+}
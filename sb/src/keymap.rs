@@ -0,0 +1,582 @@
+//! Action registry and key bindings
+//!
+//! Every command sb can run from the keyboard is an [`Action`] with a stable
+//! id such as `tree.delete`. A [`Keymap`] maps keys, or sequences of keys
+//! like `g g`, to actions per [`Mode`]. The defaults live in
+//! `default_keymap.toml`; `~/.config/sb/keymap.toml` is layered on top.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::config::{self, ConfigError};
+
+const DEFAULT_KEYMAP: &str = include_str!("default_keymap.toml");
+
+macro_rules! actions {
+    ($($variant:ident => $id:literal, $description:literal;)*) => {
+        /// Something a key can be bound to
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum Action {
+            $($variant,)*
+        }
+
+        impl Action {
+            pub const ALL: &'static [Action] = &[$(Action::$variant,)*];
+
+            /// Name used in keymap files
+            pub fn id(self) -> &'static str {
+                match self {
+                    $(Action::$variant => $id,)*
+                }
+            }
+
+            /// One line for the help overlay
+            pub fn description(self) -> &'static str {
+                match self {
+                    $(Action::$variant => $description,)*
+                }
+            }
+        }
+    };
+}
+
+actions! {
+    Quit => "app.quit", "Quit";
+    Help => "app.help", "Show this help";
+    ToggleFiles => "app.toggle_files", "Show or hide the files pane";
+    FocusNext => "focus.next", "Switch pane";
+    FocusPrev => "focus.prev", "Switch pane";
+    PaneGrow => "pane.grow", "Widen the files pane";
+    PaneShrink => "pane.shrink", "Narrow the files pane";
+    PickerOpen => "picker.open", "Insert a link with the file picker";
+    Save => "file.save", "Save";
+    OpenExternal => "file.open_external", "Open externally (all selected files)";
+    Copy => "file.copy", "Copy";
+    Move => "file.move", "Move";
+    Mkdir => "file.mkdir", "New directory";
+    Delete => "file.delete", "Delete";
+    VideoTogglePause => "video.toggle_pause", "Pause or resume video";
+    VideoStop => "video.stop", "Stop video";
+    VideoAutoplay => "video.autoplay", "Toggle video autoplay";
+    TreeUp => "tree.up", "Up";
+    TreeDown => "tree.down", "Down";
+    TreeFirst => "tree.first", "First entry";
+    TreeLast => "tree.last", "Last entry";
+    TreeCollapse => "tree.collapse", "Collapse directory";
+    TreeExpand => "tree.expand", "Expand directory or open file";
+    TreeActivate => "tree.activate", "Open file or toggle directory";
+    TreeRefresh => "tree.refresh", "Reload the tree";
+    TreeNewFile => "tree.new_file", "New file";
+    TreeDelete => "tree.delete", "Delete (asks first)";
+    TreeToggleSelect => "tree.toggle_select", "Select or unselect";
+    TreeSelect => "tree.select", "Add to selection";
+    TreeExtendUp => "tree.extend_up", "Extend selection up";
+    TreeExtendDown => "tree.extend_down", "Extend selection down";
+    TreeSelectAll => "tree.select_all", "Select all";
+    TreeClearSelection => "tree.clear_selection", "Clear selection";
+    PreviewUp => "preview.up", "Line up";
+    PreviewDown => "preview.down", "Line down";
+    PreviewPageUp => "preview.page_up", "Page up";
+    PreviewPageDown => "preview.page_down", "Page down";
+    PreviewTop => "preview.top", "First line";
+    PreviewBottom => "preview.bottom", "Last line";
+    PreviewScrollRight => "preview.scroll_right", "Scroll right";
+    PreviewToFiles => "preview.to_files", "Back to the files pane";
+    PreviewLineEdit => "preview.line_edit", "Edit the current line";
+    EditorOpen => "editor.open", "Edit the raw file";
+    EditorClose => "editor.close", "Back to the preview";
+    EditorCommand => "editor.command", "Editor command (:w, :wq)";
+    PromptConfirm => "prompt.confirm", "Confirm";
+    PromptCancel => "prompt.cancel", "Cancel";
+    PickerUp => "picker.up", "Up";
+    PickerDown => "picker.down", "Down";
+    PickerExtendUp => "picker.extend_up", "Extend selection up";
+    PickerExtendDown => "picker.extend_down", "Extend selection down";
+    PickerToggleSelect => "picker.toggle_select", "Select or unselect";
+    PickerSelectAll => "picker.select_all", "Select all";
+    PickerActivate => "picker.activate", "Insert link or enter directory";
+    PickerCancel => "picker.cancel", "Close";
+    PickerDelete => "picker.delete", "Delete";
+    PickerMove => "picker.move", "Move";
+    PickerParent => "picker.parent", "Parent directory";
+    PickerGitStatus => "picker.git_status", "Git status";
+    PickerOpenExternal => "picker.open_external", "Open externally";
+    MoveUp => "move.up", "Up";
+    MoveDown => "move.down", "Down";
+    MoveEnter => "move.enter", "Enter directory";
+    MoveConfirm => "move.confirm", "Move here";
+    MoveCancel => "move.cancel", "Cancel";
+    GitStatusClose => "git_status.close", "Close";
+    HelpClose => "help.close", "Close";
+    HelpUp => "help.up", "Scroll up";
+    HelpDown => "help.down", "Scroll down";
+    DeleteConfirm => "delete.confirm", "Delete";
+    DeleteCancel => "delete.cancel", "Cancel";
+    DiskOverwrite => "disk.overwrite", "Save over the file on disk";
+    DiskReload => "disk.reload", "Reload from disk, dropping your edits";
+    DiskMerge => "disk.merge", "Merge with the file on disk";
+    DiskKeep => "disk.keep", "Keep your edits";
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Action::ALL
+            .iter()
+            .copied()
+            .find(|a| a.id() == s)
+            .ok_or_else(|| format!("unknown action `{}`", s))
+    }
+}
+
+/// Binding sections, one per input mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Mode {
+    Global,
+    Files,
+    Preview,
+    Editor,
+    Prompt,
+    Picker,
+    Move,
+    GitStatus,
+    Help,
+    ConfirmDelete,
+    DiskChanged,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 11] = [
+        Mode::Global,
+        Mode::Files,
+        Mode::Preview,
+        Mode::Editor,
+        Mode::Prompt,
+        Mode::Picker,
+        Mode::Move,
+        Mode::GitStatus,
+        Mode::Help,
+        Mode::ConfirmDelete,
+        Mode::DiskChanged,
+    ];
+
+    /// Section name in keymap files
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Global => "global",
+            Mode::Files => "files",
+            Mode::Preview => "preview",
+            Mode::Editor => "editor",
+            Mode::Prompt => "prompt",
+            Mode::Picker => "picker",
+            Mode::Move => "move",
+            Mode::GitStatus => "git_status",
+            Mode::Help => "help",
+            Mode::ConfirmDelete => "confirm_delete",
+            Mode::DiskChanged => "disk_changed",
+        }
+    }
+
+    /// Heading in the help overlay
+    pub fn title(self) -> &'static str {
+        match self {
+            Mode::Global => "Everywhere",
+            Mode::Files => "Files",
+            Mode::Preview => "Preview",
+            Mode::Editor => "Raw editor",
+            Mode::Prompt => "Prompts",
+            Mode::Picker => "File picker",
+            Mode::Move => "Move destination",
+            Mode::GitStatus => "Git status",
+            Mode::Help => "Help",
+            Mode::ConfirmDelete => "Delete confirmation",
+            Mode::DiskChanged => "File changed on disk",
+        }
+    }
+
+    /// Sections consulted in this mode, most specific first
+    pub fn lookup(self) -> &'static [Mode] {
+        match self {
+            Mode::Files => &[Mode::Files, Mode::Global],
+            Mode::Preview => &[Mode::Preview, Mode::Global],
+            Mode::Global => &[Mode::Global],
+            Mode::Editor => &[Mode::Editor],
+            Mode::Prompt => &[Mode::Prompt],
+            Mode::Picker => &[Mode::Picker],
+            Mode::Move => &[Mode::Move],
+            Mode::GitStatus => &[Mode::GitStatus],
+            Mode::Help => &[Mode::Help],
+            Mode::ConfirmDelete => &[Mode::ConfirmDelete],
+            Mode::DiskChanged => &[Mode::DiskChanged],
+        }
+    }
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Mode::ALL
+            .iter()
+            .copied()
+            .find(|m| m.name() == s)
+            .ok_or_else(|| format!("unknown section [{}]", s))
+    }
+}
+
+/// One key press with its modifiers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut modifiers =
+            modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        // Shift is already part of the character (`G`, `?`) and of BackTab
+        if matches!(code, KeyCode::Char(_) | KeyCode::BackTab) {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Self { code, modifiers }
+    }
+}
+
+impl From<KeyEvent> for KeyChord {
+    fn from(key: KeyEvent) -> Self {
+        Self::new(key.code, key.modifiers)
+    }
+}
+
+const NAMED_KEYS: &[(&str, KeyCode)] = &[
+    ("enter", KeyCode::Enter),
+    ("esc", KeyCode::Esc),
+    ("tab", KeyCode::Tab),
+    ("backtab", KeyCode::BackTab),
+    ("space", KeyCode::Char(' ')),
+    ("backspace", KeyCode::Backspace),
+    ("delete", KeyCode::Delete),
+    ("insert", KeyCode::Insert),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+];
+
+impl FromStr for KeyChord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s;
+        loop {
+            let lower = rest.to_ascii_lowercase();
+            let (modifier, len) = if lower.starts_with("ctrl+") && rest.len() > 5 {
+                (KeyModifiers::CONTROL, 5)
+            } else if lower.starts_with("alt+") && rest.len() > 4 {
+                (KeyModifiers::ALT, 4)
+            } else if lower.starts_with("shift+") && rest.len() > 6 {
+                (KeyModifiers::SHIFT, 6)
+            } else {
+                break;
+            };
+            modifiers |= modifier;
+            rest = &rest[len..];
+        }
+        let lower = rest.to_ascii_lowercase();
+        let code = if let Some((_, code)) = NAMED_KEYS.iter().find(|(name, _)| *name == lower) {
+            *code
+        } else if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+            if !(1..=12).contains(&n) {
+                return Err(format!("unknown key `{}`", s));
+            }
+            KeyCode::F(n)
+        } else {
+            let mut chars = rest.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => KeyCode::Char(c),
+                _ => return Err(format!("unknown key `{}`", s)),
+            }
+        };
+        Ok(KeyChord::new(code, modifiers))
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            f.write_str("ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            f.write_str("alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            f.write_str("shift+")?;
+        }
+        if let Some((name, _)) = NAMED_KEYS.iter().find(|(_, code)| *code == self.code) {
+            return f.write_str(name);
+        }
+        match self.code {
+            KeyCode::F(n) => write!(f, "f{}", n),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
+fn parse_sequence(s: &str) -> Result<Vec<KeyChord>, String> {
+    let chords = s
+        .split_whitespace()
+        .map(KeyChord::from_str)
+        .collect::<Result<Vec<_>, _>>()?;
+    if chords.is_empty() {
+        return Err("empty key".into());
+    }
+    Ok(chords)
+}
+
+fn sequence_string(chords: &[KeyChord]) -> String {
+    chords
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// What a key press amounted to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolved {
+    Action(Action),
+    /// The start of a longer sequence; wait for the next key
+    Pending,
+    Unbound,
+}
+
+/// Key bindings per mode, plus any half-typed sequence
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: BTreeMap<Mode, Vec<(Vec<KeyChord>, Action)>>,
+    pending: Vec<KeyChord>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut keymap = Keymap {
+            bindings: BTreeMap::new(),
+            pending: Vec::new(),
+        };
+        keymap
+            .merge(DEFAULT_KEYMAP, Path::new("default_keymap.toml"))
+            .expect("default keymap is valid");
+        keymap
+    }
+}
+
+impl Keymap {
+    /// `keymap.toml` next to the configuration file
+    pub fn default_path() -> Option<PathBuf> {
+        config::config_dir().map(|dir| dir.join("keymap.toml"))
+    }
+
+    /// The defaults with the user's keymap file, if there is one, on top
+    pub fn load_default() -> Result<Self, ConfigError> {
+        let mut keymap = Keymap::default();
+        if let Some(path) = Self::default_path().filter(|p| p.exists()) {
+            let text = std::fs::read_to_string(&path).map_err(|source| ConfigError::Read {
+                path: path.clone(),
+                source,
+            })?;
+            keymap.merge(&text, &path)?;
+        }
+        Ok(keymap)
+    }
+
+    /// Layer bindings from keymap file `text` over the current ones
+    pub fn merge(&mut self, text: &str, path: &Path) -> Result<(), ConfigError> {
+        let error = |message: String| ConfigError::Parse {
+            path: path.to_path_buf(),
+            message,
+        };
+        let table: toml::Table = toml::from_str(text).map_err(|e| error(e.to_string()))?;
+        for (section, entries) in &table {
+            let mode = Mode::from_str(section).map_err(&error)?;
+            let entries = entries
+                .as_table()
+                .ok_or_else(|| error(format!("[{}] must be a table of key = action", section)))?;
+            for (keys, action) in entries {
+                let at = |message: String| error(format!("[{}] {:?}: {}", section, keys, message));
+                let chords = parse_sequence(keys).map_err(at)?;
+                let action = action
+                    .as_str()
+                    .ok_or_else(|| at("the action must be a string".into()))?;
+                let bindings = self.bindings.entry(mode).or_default();
+                bindings.retain(|(existing, _)| *existing != chords);
+                if action != "none" {
+                    bindings.push((chords, action.parse().map_err(at)?));
+                }
+            }
+        }
+        for (mode, bindings) in &self.bindings {
+            for (a, _) in bindings {
+                if let Some((b, _)) = bindings
+                    .iter()
+                    .find(|(b, _)| b.len() > a.len() && b.starts_with(a))
+                {
+                    return Err(error(format!(
+                        "[{}] {:?} is the start of {:?}; bind one of them to \"none\"",
+                        mode.name(),
+                        sequence_string(a),
+                        sequence_string(b)
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Feed one key press, given the current mode
+    pub fn resolve(&mut self, mode: Mode, key: KeyEvent) -> Resolved {
+        let chord = KeyChord::from(key);
+        let mut sequence = std::mem::take(&mut self.pending);
+        sequence.push(chord);
+        for section in mode.lookup() {
+            let Some(bindings) = self.bindings.get(section) else {
+                continue;
+            };
+            if let Some((_, action)) = bindings.iter().find(|(keys, _)| *keys == sequence) {
+                return Resolved::Action(*action);
+            }
+            if bindings.iter().any(|(keys, _)| keys.starts_with(&sequence)) {
+                self.pending = sequence;
+                return Resolved::Pending;
+            }
+        }
+        if sequence.len() > 1 {
+            // Abandon the sequence and treat this key on its own
+            return self.resolve(mode, key);
+        }
+        Resolved::Unbound
+    }
+
+    /// Keys typed so far of an unfinished sequence
+    pub fn pending(&self) -> String {
+        sequence_string(&self.pending)
+    }
+
+    /// Bound keys and what they do, per mode, in keymap order
+    pub fn help(&self) -> Vec<(Mode, Vec<(String, &'static str)>)> {
+        self.bindings
+            .iter()
+            .filter(|(_, bindings)| !bindings.is_empty())
+            .map(|(mode, bindings)| {
+                let mut rows: Vec<(Action, Vec<String>)> = Vec::new();
+                for (keys, action) in bindings {
+                    let keys = sequence_string(keys);
+                    match rows.iter_mut().find(|(a, _)| a == action) {
+                        Some((_, all)) => all.push(keys),
+                        None => rows.push((*action, vec![keys])),
+                    }
+                }
+                let rows = rows
+                    .into_iter()
+                    .map(|(action, keys)| (keys.join(", "), action.description()))
+                    .collect();
+                (*mode, rows)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_chords_round_trip() {
+        for s in [
+            "ctrl+s", "shift+up", "f5", "G", "?", "space", "ctrl+.", "alt+x", "-",
+        ] {
+            assert_eq!(KeyChord::from_str(s).unwrap().to_string(), s);
+        }
+        assert_eq!(
+            KeyChord::from(KeyEvent::new(KeyCode::Char('G'), KeyModifiers::SHIFT)),
+            KeyChord::from_str("G").unwrap()
+        );
+        assert!(KeyChord::from_str("f13").is_err());
+        assert!(KeyChord::from_str("hyper+x").is_err());
+    }
+
+    #[test]
+    fn test_sequences_and_fallback_to_global() {
+        let mut keymap = Keymap::default();
+        let g = key(KeyCode::Char('g'));
+        assert_eq!(keymap.resolve(Mode::Preview, g), Resolved::Pending);
+        assert_eq!(keymap.pending(), "g");
+        assert_eq!(
+            keymap.resolve(Mode::Preview, g),
+            Resolved::Action(Action::PreviewTop)
+        );
+        // A broken sequence is dropped and the last key handled alone
+        keymap.resolve(Mode::Preview, g);
+        assert_eq!(
+            keymap.resolve(Mode::Preview, key(KeyCode::Char('q'))),
+            Resolved::Action(Action::Quit)
+        );
+        assert_eq!(
+            keymap.resolve(Mode::Editor, key(KeyCode::Char('q'))),
+            Resolved::Unbound
+        );
+    }
+
+    #[test]
+    fn test_user_keymap_overrides_and_errors() {
+        let mut keymap = Keymap::default();
+        keymap
+            .merge(
+                "[files]\n\"x\" = \"tree.delete\"\n\"d\" = \"none\"\n",
+                Path::new("keymap.toml"),
+            )
+            .unwrap();
+        assert_eq!(
+            keymap.resolve(Mode::Files, key(KeyCode::Char('x'))),
+            Resolved::Action(Action::TreeDelete)
+        );
+        assert_eq!(
+            keymap.resolve(Mode::Files, key(KeyCode::Char('d'))),
+            Resolved::Unbound
+        );
+
+        let error = |text: &str| {
+            Keymap::default()
+                .merge(text, Path::new("keymap.toml"))
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(
+            error("[files]\n\"x\" = \"tree.explode\"").contains("unknown action `tree.explode`")
+        );
+        assert!(error("[sidebar]\n\"x\" = \"app.quit\"").contains("unknown section [sidebar]"));
+        assert!(error("[files]\n\"g\" = \"app.quit\"").contains("\"g\" is the start of \"g g\""));
+    }
+
+    #[test]
+    fn test_help_lists_every_key_of_an_action() {
+        let help = Keymap::default().help();
+        let (_, global) = help.iter().find(|(m, _)| *m == Mode::Global).unwrap();
+        assert!(global.contains(&("q, ctrl+c, f10".to_string(), "Quit")));
+    }
+}
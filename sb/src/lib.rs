@@ -3,8 +3,17 @@
 //! A secure, performant terminal-based file browser with markdown preview capabilities.
 
 pub mod app;
+pub mod cache;
+pub mod error;
+pub mod event_handler;
 pub mod fs;
+pub mod highlight;
+pub mod keymap;
+pub mod merge;
 pub mod preview;
+pub mod theme;
+pub mod tree;
+pub mod watch;
 
 // New modules for security and configuration
 pub mod config;
@@ -15,9 +24,11 @@ pub mod security;
 
 // Re-export commonly used types
 pub use app::App;
+pub use cache::DirectoryCache;
 pub use config::Config;
 pub use git::{FileStatus, GitError, GitRepository};
-pub use security::{check_file_size, validate_path, SecurityError};
+pub use preview::{MarkdownView, Preview, PreviewContext};
+pub use security::{check_file_size, check_file_size_within, validate_path, SecurityError};
 
 /// Current version of the application
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
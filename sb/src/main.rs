@@ -1,4 +1,5 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
+use clap::Parser;
 use std::io::{self};
 use std::path::{Path, PathBuf};
 use std::time::Duration;
@@ -14,13 +15,12 @@ use ratatui::crossterm::{
 use ratatui::{prelude::*, widgets::*};
 use tui_textarea::TextArea;
 
-mod app;
-use app::*;
-mod error;
-mod preview;
-use preview::*;
-mod fs;
-mod git;
+use sb::app::*;
+use sb::config::Config;
+use sb::keymap::Keymap;
+use sb::preview::*;
+use sb::theme::Theme;
+use sb::{app, error, event_handler, git};
 
 // Ensures terminal is restored even if the app panics or exits abruptly
 struct TermGuard;
@@ -32,12 +32,135 @@ impl Drop for TermGuard {
     }
 }
 
+/// sb: Terminal Markdown browser and editor
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Cli {
+    /// Directory to browse or file to open; `FILE:LINE` or `FILE +LINE`
+    /// jumps to a line
+    #[arg(value_name = "PATH", num_args = 0..=2)]
+    targets: Vec<String>,
+
+    /// Open everything without allowing changes on disk
+    #[arg(long)]
+    read_only: bool,
+    /// Show dotfiles in the file tree and pickers
+    #[arg(long)]
+    show_hidden: bool,
+    /// Colour theme: auto, dark, light or a file in ~/.config/sb/themes
+    #[arg(long, value_name = "NAME")]
+    theme: Option<String>,
+    /// Configuration file [default: ~/.config/sb/config.toml]
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// Do not look for a git repository
+    #[arg(long)]
+    no_git: bool,
+}
+
+/// What to open, as given on the command line
+#[derive(Debug, PartialEq, Eq)]
+struct Target {
+    path: Option<PathBuf>,
+    line: Option<usize>,
+}
+
+/// Split `PATH`, `+LINE` and `PATH:LINE` arguments
+fn parse_targets(args: &[String]) -> Result<Target> {
+    let mut target = Target {
+        path: None,
+        line: None,
+    };
+    for arg in args {
+        if let Some(line) = arg.strip_prefix('+') {
+            match line.parse() {
+                Ok(line) if target.line.is_none() => target.line = Some(line),
+                Ok(_) => bail!("more than one line number given"),
+                Err(_) => bail!("invalid line number {:?}", arg),
+            }
+            continue;
+        }
+        if target.path.is_some() {
+            bail!("more than one path given");
+        }
+        // `notes.md:12`, unless a file with that exact name exists
+        let split = arg
+            .rsplit_once(':')
+            .filter(|_| !Path::new(arg).exists())
+            .and_then(|(path, line)| Some((path, line.parse::<usize>().ok()?)));
+        match split {
+            Some((path, line)) if !path.is_empty() => {
+                target.path = Some(PathBuf::from(path));
+                target.line = Some(line);
+            }
+            _ => target.path = Some(PathBuf::from(arg)),
+        }
+    }
+    Ok(target)
+}
+
+/// Tree root for a file: its repository's work tree, else its directory
+fn root_for_file(file: &Path, git: bool) -> PathBuf {
+    let parent = file.parent().unwrap_or(Path::new(".")).to_path_buf();
+    if git {
+        if let Ok(repo) = git::GitRepository::open(&parent) {
+            return repo.root().to_path_buf();
+        }
+    }
+    parent
+}
+
 fn main() -> Result<()> {
-    let root = std::env::args()
-        .nth(1)
-        .map(PathBuf::from)
-        .unwrap_or(std::env::current_dir()?);
-    let mut app = App::new(root)?;
+    let cli = Cli::parse();
+    let mut config = match &cli.config {
+        Some(path) => Config::load(path)?,
+        None => Config::load_default()?,
+    };
+    // Command line flags win over the config file
+    if let Some(theme) = &cli.theme {
+        config.ui.theme = theme.clone();
+    }
+    if cli.show_hidden {
+        config = config.with_hidden_files(true);
+    }
+    if cli.no_git {
+        config = config.with_git(false);
+    }
+    let target = parse_targets(&cli.targets)?;
+    let path = match target.path {
+        Some(path) => std::path::absolute(&path)?,
+        None => std::env::current_dir()?,
+    };
+    if !path.exists() {
+        bail!("{} does not exist", path.display());
+    }
+    let (root, file) = if path.is_dir() {
+        if target.line.is_some() {
+            bail!(
+                "a line number needs a file, but {} is a directory",
+                path.display()
+            );
+        }
+        (path, None)
+    } else {
+        (root_for_file(&path, config.git.enabled), Some(path))
+    };
+    let config = config
+        .with_base_directory(root)
+        .with_read_only(cli.read_only);
+    // Resolved before the alternate screen so `auto` can query the terminal
+    let theme = Theme::load(&config.ui.theme)?;
+    let mut app = App::with_config(config)?;
+    app.keymap = Keymap::load_default()?;
+    app.theme = theme;
+    app.refresh_tree()?;
+    if let Some(file) = file {
+        app.reveal_in_tree(&file);
+        app.open_file(&file)?;
+        if let Some(line) = target.line {
+            app.jump_to_line(line);
+        }
+    }
     run(&mut app)
 }
 
@@ -53,553 +176,17 @@ fn run(app: &mut App) -> Result<()> {
 
     // Main loop
     loop {
+        app.poll_watcher();
+        app.poll_tree();
         terminal.draw(|f| ui(f, app).expect("Failed to render UI"))?;
-        if event::poll(Duration::from_millis(200))? {
+        // Check back soon while directory listings or file changes are due
+        let busy = app.files.is_loading() || app.watcher.as_ref().is_some_and(|w| w.is_busy());
+        let timeout = if busy { 20 } else { 200 };
+        if event::poll(Duration::from_millis(timeout))? {
             match event::read()? {
                 Event::Key(k) => {
-                    if app.creating_file {
-                        match (k.code, k.modifiers) {
-                            (KeyCode::Enter, _) => {
-                                let _ = app.confirm_create_file();
-                            }
-                            (KeyCode::Esc, _) => app.cancel_create_file(),
-                            _ => {
-                                let _ = app.filename_input.input(k);
-                            }
-                        }
-                        continue;
-                    }
-                    if app.picking_file {
-                        match (k.code, k.modifiers) {
-                            (KeyCode::Esc, _) => app.picker_cancel(),
-                            (KeyCode::Enter, _) => {
-                                let _ = app.picker_activate();
-                            }
-                            (KeyCode::Up, modifiers) if modifiers.contains(KeyModifiers::SHIFT) => {
-                                app.picker_up_with_selection()
-                            }
-                            (KeyCode::Down, modifiers)
-                                if modifiers.contains(KeyModifiers::SHIFT) =>
-                            {
-                                app.picker_down_with_selection()
-                            }
-                            (KeyCode::Up, _) | (KeyCode::Char('k'), _) => {
-                                app.picker_clear_selection();
-                                app.picker_up();
-                            }
-                            (KeyCode::Down, _) | (KeyCode::Char('j'), _) => {
-                                app.picker_clear_selection();
-                                app.picker_down();
-                            }
-                            // New Git-aware file picker commands
-                            (KeyCode::Char('d') | KeyCode::Char('D'), _) => {
-                                let _ = app.picker_delete_with_git_check();
-                            }
-                            (KeyCode::Char('m') | KeyCode::Char('M'), _) => {
-                                let _ = app.picker_start_move();
-                            }
-                            (KeyCode::Char('p') | KeyCode::Char('P'), _) => {
-                                let _ = app.picker_parent_dir();
-                            }
-                            (KeyCode::Char('g') | KeyCode::Char('G'), _) => {
-                                app.picker_show_git_status();
-                            }
-                            (KeyCode::Char('o') | KeyCode::Char('O'), _) => {
-                                // Open selected file(s) in external editor
-                                if !app.picker_selection.is_empty() {
-                                    // Bulk open mode
-                                    let selected_files: Vec<PathBuf> = app
-                                        .picker_selection
-                                        .iter()
-                                        .filter_map(|&i| app.picker_items.get(i).cloned())
-                                        .filter(|p| p.is_file())
-                                        .collect();
-
-                                    if !selected_files.is_empty() {
-                                        let count = selected_files.len();
-                                        for path in &selected_files {
-                                            let _ = app.open_in_editor(path);
-                                        }
-                                        app.status = format!("Opened {} files in editor", count);
-                                    } else {
-                                        app.status = "No files selected to open".to_string();
-                                    }
-                                } else if app.picker_index < app.picker_items.len() {
-                                    // Single file open (original behavior)
-                                    let path = app.picker_items[app.picker_index].clone();
-                                    let _ = app.open_in_editor(&path);
-                                }
-                            }
-                            (KeyCode::Char(' '), _) => {
-                                // Space to toggle selection of current item
-                                app.picker_toggle_selection();
-                            }
-                            (KeyCode::Char('a'), modifiers)
-                                if modifiers.contains(KeyModifiers::CONTROL) =>
-                            {
-                                // Ctrl+A to select all
-                                app.picker_selection.clear();
-                                for i in 0..app.picker_items.len() {
-                                    app.picker_selection.insert(i);
-                                }
-                                app.picker_selection_anchor = None;
-                            }
-                            (KeyCode::Char(c), _) => {
-                                // Debug: show what key was pressed
-                                app.status = format!("File picker: Unknown key '{}'", c);
-                            }
-                            (code, modifiers) => {
-                                // Debug: show all key combinations
-                                app.status = format!(
-                                    "File picker: Key {:?} with modifiers {:?}",
-                                    code, modifiers
-                                );
-                            }
-                        }
-                        continue;
-                    }
-                    // Handle move destination picker
-                    if app.showing_move_dest {
-                        match (k.code, k.modifiers) {
-                            (KeyCode::Esc, _) => app.cancel_move(),
-                            (KeyCode::Enter, _) => {
-                                let _ = app.confirm_move();
-                            }
-                            (KeyCode::Up, _) | (KeyCode::Char('k'), _) => app.move_dest_up(),
-                            (KeyCode::Down, _) | (KeyCode::Char('j'), _) => app.move_dest_down(),
-                            (KeyCode::Right, _) | (KeyCode::Char('l'), _) => {
-                                let _ = app.move_dest_enter();
-                            }
-                            _ => {}
-                        }
-                        continue;
-                    }
-                    // Handle Git status display
-                    if app.showing_git_status {
-                        match (k.code, k.modifiers) {
-                            (KeyCode::Esc, _) | (KeyCode::Enter, _) | (KeyCode::Char('g'), _) => {
-                                app.close_git_status();
-                            }
-                            _ => {}
-                        }
-                        continue;
-                    }
-                    if !matches!(app.op_mode, app::OpMode::None) {
-                        match (k.code, k.modifiers) {
-                            (KeyCode::Esc, _) => app.cancel_op(),
-                            (KeyCode::Enter, _) => {
-                                let _ = app.confirm_op();
-                            }
-                            _ => {
-                                let _ = app.op_input.input(k);
-                            }
-                        }
-                        continue;
-                    }
-                    // Inline line edit in Preview
-                    if app.editing_line {
-                        match (k.code, k.modifiers) {
-                            (KeyCode::Enter, _) => {
-                                app.confirm_line_edit();
-                            }
-                            (KeyCode::Esc, _) => app.cancel_line_edit(),
-                            _ => {
-                                let _ = app.line_input.input(k);
-                            }
-                        }
-                        continue;
-                    }
-                    // Editor command mode
-                    if app.editor_cmd_mode {
-                        match (k.code, k.modifiers) {
-                            (KeyCode::Esc, _) => app.cancel_editor_cmd(),
-                            (KeyCode::Enter, _) => {
-                                let _ = app.confirm_editor_cmd();
-                            }
-                            _ => {
-                                let _ = app.editor_cmd_input.input(k);
-                            }
-                        }
-                        continue;
-                    }
-                    // Raw edit mode in Preview: route keys to the text editor; Esc exits back to preview
-                    if app.show_raw_editor {
-                        match (k.code, k.modifiers) {
-                            (KeyCode::Esc, _) => {
-                                app.show_raw_editor = false;
-                                app.prefer_raw_editor = false;
-                                app.focus = Focus::Preview; // Ensure we're back in preview mode
-                            }
-                            (KeyCode::Tab, _) => {
-                                // Temporarily exit raw editor but remember preference
-                                app.show_raw_editor = false;
-                                app.prefer_raw_editor = true;
-                                // Don't continue - let Tab be handled by the normal focus switching below
-                            }
-                            _ => {
-                                if !app.editor_cmd_mode {
-                                    app.editor.input(k);
-                                }
-                                continue;
-                            }
-                        }
-                        // Only continue if we didn't handle Tab
-                        if k.code != KeyCode::Tab {
-                            continue;
-                        }
-                    }
-                    if app.show_help {
-                        match (k.code, k.modifiers) {
-                            (KeyCode::Esc, _)
-                            | (KeyCode::Char('?'), _)
-                            | (KeyCode::Char('h'), _) => app.toggle_help(),
-                            _ => {}
-                        }
-                        continue;
-                    }
-                    // Handle delete confirmation modal
-                    if app.confirming_delete {
-                        match k.code {
-                            KeyCode::Enter | KeyCode::Char('d') => {
-                                let _ = app.confirm_delete_with_git();
-                            }
-                            KeyCode::Esc => app.cancel_delete(),
-                            _ => {}
-                        }
-                        continue;
-                    }
-                    // Debug already at top, don't duplicate
-
-                    match (k.code, k.modifiers) {
-                        (KeyCode::Char('q'), _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
-                            break
-                        }
-                        (KeyCode::Esc, _) => {
-                            if matches!(app.focus, Focus::Preview) {
-                                break;
-                            } else if matches!(app.focus, Focus::Left)
-                                && !app.tree_selection.is_empty()
-                            {
-                                // Clear all selections when Esc pressed in left pane with selections
-                                app.tree_clear_selection();
-                            }
-                        }
-                        (KeyCode::Tab, mods) => {
-                            // Check if this is Ctrl+I (which sends Tab with CONTROL modifier)
-                            if mods.contains(KeyModifiers::CONTROL) {
-                                // This is actually Ctrl+I for file picker
-                                let _ = app.begin_file_picker();
-                            } else if app.show_left_pane {
-                                // Tab between left pane and right pane (in whatever mode it's in)
-                                app.focus = match app.focus {
-                                    Focus::Left => {
-                                        // Ensure selected file is opened before switching
-                                        let _ = app.open_selected();
-                                        // Moving to right pane - decide which mode based on preference
-                                        if app.prefer_raw_editor && app.opened.is_some() {
-                                            Focus::Editor
-                                        } else {
-                                            Focus::Preview
-                                        }
-                                    }
-                                    Focus::Preview | Focus::Editor => Focus::Left,
-                                };
-                                // Restore raw editor mode if user was in it and we're now on Editor focus
-                                if matches!(app.focus, Focus::Editor) && app.prefer_raw_editor {
-                                    app.show_raw_editor = true;
-                                }
-                            } else {
-                                // If left pane is hidden, Tab toggles it and focuses Files
-                                app.toggle_left_pane();
-                                app.focus = Focus::Left;
-                            }
-                        }
-                        (KeyCode::BackTab, _) => {
-                            if app.show_left_pane {
-                                // BackTab between left pane and right pane (same as Tab since only 2 panes)
-                                app.focus = match app.focus {
-                                    Focus::Left => {
-                                        // Ensure selected file is opened before switching
-                                        let _ = app.open_selected();
-                                        // Moving to right pane - decide which mode based on preference
-                                        if app.prefer_raw_editor && app.opened.is_some() {
-                                            Focus::Editor
-                                        } else {
-                                            Focus::Preview
-                                        }
-                                    }
-                                    Focus::Preview | Focus::Editor => Focus::Left,
-                                };
-                                // Restore raw editor mode if user was in it and we're now on Editor focus
-                                if matches!(app.focus, Focus::Editor) && app.prefer_raw_editor {
-                                    app.show_raw_editor = true;
-                                }
-                            } else {
-                                // If left pane is hidden, BackTab toggles it and focuses Files
-                                app.toggle_left_pane();
-                                app.focus = Focus::Left;
-                            }
-                        }
-                        // 'p' previously toggled preview; now preview is always on, so ignore or repurpose later
-                        (KeyCode::Char('?'), _) => app.toggle_help(),
-                        // Pane resizing with Ctrl + < and Ctrl + >
-                        (KeyCode::Char(','), modifiers)
-                            if modifiers.contains(KeyModifiers::CONTROL) =>
-                        {
-                            app.decrease_left_pane_width();
-                        }
-                        (KeyCode::Char('.'), modifiers)
-                            if modifiers.contains(KeyModifiers::CONTROL) =>
-                        {
-                            app.increase_left_pane_width();
-                        }
-                        // Alternative pane resizing with Ctrl + - and Ctrl + =
-                        (KeyCode::Char('-'), modifiers)
-                            if modifiers.contains(KeyModifiers::CONTROL) =>
-                        {
-                            app.decrease_left_pane_width();
-                        }
-                        (KeyCode::Char('='), modifiers)
-                            if modifiers.contains(KeyModifiers::CONTROL) =>
-                        {
-                            app.increase_left_pane_width();
-                        }
-                        // Add explicit handler for 'i' with Control modifier as fallback
-                        (KeyCode::Char('i'), mods) if mods.contains(KeyModifiers::CONTROL) => {
-                            let _ = app.begin_file_picker();
-                        }
-                        (KeyCode::Char('n'), _) if matches!(app.focus, Focus::Left) => {
-                            app.begin_create_file()
-                        }
-                        (KeyCode::Char('d'), _) if matches!(app.focus, Focus::Left) => {
-                            app.begin_delete()
-                        }
-                        // Note: Ctrl+I is handled above in the Tab handler because terminals
-                        // send Tab for Ctrl+I (historical terminal convention)
-                        (KeyCode::Char('s'), KeyModifiers::CONTROL) => {
-                            let _ = app.save();
-                        }
-                        (KeyCode::Char('o'), _)
-                            if matches!(app.focus, Focus::Left | Focus::Editor) =>
-                        {
-                            // Check if we have tree selections for bulk open
-                            if !app.tree_selection.is_empty() && matches!(app.focus, Focus::Left) {
-                                let _ = app.tree_open_selected();
-                            } else {
-                                let _ = app.open_externally();
-                            }
-                        }
-                        // Tree selection controls
-                        (KeyCode::Char(' '), _) if matches!(app.focus, Focus::Left) => {
-                            app.tree_toggle_selection();
-                        }
-                        (KeyCode::Char('s') | KeyCode::Char('S'), _)
-                            if matches!(app.focus, Focus::Left) =>
-                        {
-                            app.tree_accumulate_selection();
-                        }
-                        (KeyCode::Char('a'), modifiers)
-                            if modifiers.contains(KeyModifiers::CONTROL)
-                                && matches!(app.focus, Focus::Left) =>
-                        {
-                            app.tree_select_all();
-                        }
-                        // Editor command mode (minimal)
-                        (KeyCode::Char(':'), _) => {
-                            if matches!(app.focus, Focus::Editor) {
-                                app.begin_editor_cmd();
-                            }
-                        }
-                        // Video controls
-                        (KeyCode::Char(' '), _) => {
-                            app.toggle_pause_video();
-                        }
-                        (KeyCode::Char('s'), _) => {
-                            app.stop_video();
-                        }
-                        (KeyCode::Char('v'), KeyModifiers::CONTROL) => {
-                            app.autoplay_video = !app.autoplay_video;
-                            app.status = format!(
-                                "Autoplay video {}",
-                                if app.autoplay_video { "on" } else { "off" }
-                            );
-                        }
-                        // Toggle left pane
-                        (KeyCode::Char('b'), KeyModifiers::CONTROL) => {
-                            app.toggle_left_pane();
-                        }
-                        (KeyCode::F(9), _) => {
-                            app.toggle_left_pane();
-                        }
-                        // F-keys like MC
-                        (KeyCode::F(2), _) => {
-                            // F2 as alternative to Ctrl+I for file picker
-                            let _ = app.begin_file_picker();
-                        }
-                        (KeyCode::F(3), _) => { /* Quick view handled by preview always-on */ }
-                        (KeyCode::F(4), _) => {
-                            app.focus = Focus::Editor;
-                            // Restore raw editor mode if user prefers it
-                            if app.prefer_raw_editor {
-                                app.show_raw_editor = true;
-                            }
-                        }
-                        (KeyCode::F(5), _) => {
-                            app.begin_copy();
-                        }
-                        (KeyCode::F(6), _) => {
-                            app.begin_move();
-                        }
-                        (KeyCode::F(7), _) => {
-                            app.begin_mkdir();
-                        }
-                        (KeyCode::F(8), _) => {
-                            app.begin_delete();
-                        }
-                        (KeyCode::F(10), _) => break,
-                        (KeyCode::Enter, _) => {
-                            match app.focus {
-                                Focus::Left => {
-                                    let _ = app.activate_on_tree();
-                                }
-                                Focus::Editor => { /* enter does nothing special here */ }
-                                Focus::Preview => {}
-                            }
-                        }
-                        // Toggle files pane with 'h' when in Preview
-                        (KeyCode::Char('h'), _) => {
-                            if matches!(app.focus, Focus::Preview) {
-                                app.toggle_left_pane();
-                            }
-                        }
-                        (KeyCode::Char('e'), _) => {
-                            if matches!(app.focus, Focus::Preview) {
-                                app.focus = Focus::Editor;
-                                app.show_raw_editor = true;
-                                app.prefer_raw_editor = true;
-                                // Set editor cursor to match preview cursor position
-                                app.editor.move_cursor(tui_textarea::CursorMove::Jump(
-                                    app.preview_cursor as u16,
-                                    app.preview_col as u16,
-                                ));
-                            }
-                        }
-                        (KeyCode::Up, _)
-                        | (KeyCode::Down, _)
-                        | (KeyCode::Left, _)
-                        | (KeyCode::Right, _)
-                        | (KeyCode::Char('j'), _)
-                        | (KeyCode::Char('k'), _) => match app.focus {
-                            Focus::Left => match (k.code, k.modifiers) {
-                                (KeyCode::Up, modifiers)
-                                    if modifiers.contains(KeyModifiers::SHIFT) =>
-                                {
-                                    app.tree_up_with_selection();
-                                }
-                                (KeyCode::Down, modifiers)
-                                    if modifiers.contains(KeyModifiers::SHIFT) =>
-                                {
-                                    app.tree_down_with_selection();
-                                }
-                                (KeyCode::Up, _) | (KeyCode::Char('k'), _) => {
-                                    let _ = app.left_state.key_up();
-                                }
-                                (KeyCode::Down, _) | (KeyCode::Char('j'), _) => {
-                                    let _ = app.left_state.key_down();
-                                }
-                                (KeyCode::Left, _) => {
-                                    // Try to collapse tree node
-                                    let _ = app.left_state.key_left();
-                                }
-                                (KeyCode::Right, _) => {
-                                    // Check if current selection is a file (can't be expanded)
-                                    let is_file = app
-                                        .left_state
-                                        .selected()
-                                        .first()
-                                        .and_then(|s| Path::new(s).to_str())
-                                        .map(|s| Path::new(s).is_file())
-                                        .unwrap_or(false);
-
-                                    if is_file {
-                                        // If it's a file, open it and switch to preview/editor pane
-                                        let _ = app.open_selected();
-                                        app.focus = if app.prefer_raw_editor && app.opened.is_some()
-                                        {
-                                            Focus::Editor
-                                        } else {
-                                            Focus::Preview
-                                        };
-                                        // Restore raw editor mode if needed
-                                        if matches!(app.focus, Focus::Editor)
-                                            && app.prefer_raw_editor
-                                        {
-                                            app.show_raw_editor = true;
-                                        }
-                                    } else {
-                                        // If it's a directory, still allow switching right if it contains the opened file
-                                        if let Some(opened) = &app.opened {
-                                            if let Some(parent) = opened.parent() {
-                                                let sel_dir = app
-                                                    .left_state
-                                                    .selected()
-                                                    .last()
-                                                    .cloned()
-                                                    .unwrap_or_default();
-                                                if Path::new(&sel_dir) == parent {
-                                                    app.focus = if app.prefer_raw_editor
-                                                        && app.opened.is_some()
-                                                    {
-                                                        Focus::Editor
-                                                    } else {
-                                                        Focus::Preview
-                                                    };
-                                                    if matches!(app.focus, Focus::Editor)
-                                                        && app.prefer_raw_editor
-                                                    {
-                                                        app.show_raw_editor = true;
-                                                    }
-                                                    // Switch focus and skip expanding directory handling
-                                                    // by continuing the event loop
-                                                    // (no early return from run())
-                                                    // No-op; fall through to end of match arm
-                                                }
-                                            }
-                                        }
-                                        // If it's a directory, try to expand it
-                                        let _ = app.left_state.key_right();
-                                    }
-                                }
-                                (_, _) => {}
-                            },
-                            Focus::Editor => {
-                                if !app.editor_cmd_mode {
-                                    app.editor.input(k);
-                                }
-                            }
-                            Focus::Preview => match k.code {
-                                KeyCode::Up | KeyCode::Char('k') => app.move_cursor_up(),
-                                KeyCode::Down | KeyCode::Char('j') => app.move_cursor_down(),
-                                KeyCode::Left => {
-                                    // Return focus to Files reliably
-                                    if !app.show_left_pane {
-                                        app.toggle_left_pane();
-                                    }
-                                    // Keep tree selection in sync with currently opened file
-                                    if let Some(p) = &app.opened {
-                                        let _ =
-                                            app.left_state.select(vec![p.display().to_string()]);
-                                    }
-                                    app.focus = Focus::Left;
-                                }
-                                KeyCode::Right => {
-                                    // In preview mode, right arrow just scrolls horizontally
-                                    app.move_col_right();
-                                }
-                                _ => {}
-                            },
-                        },
-                        _ => {}
+                    if event_handler::handle_key_event(app, k).is_none() {
+                        break;
                     }
                 }
                 Event::Mouse(me) => match me.kind {
@@ -668,12 +255,10 @@ fn run(app: &mut App) -> Result<()> {
                             }
                         }
                     }
-                    MouseEventKind::Drag(_) => {
-                        // Handle pane resizing by dragging
-                        if app.show_left_pane {
-                            let terminal_size = crossterm::terminal::size().unwrap_or((80, 24));
-                            app.resize_pane_from_mouse(terminal_size.0, me.column);
-                        }
+                    // Handle pane resizing by dragging
+                    MouseEventKind::Drag(_) if app.show_left_pane => {
+                        let terminal_size = crossterm::terminal::size().unwrap_or((80, 24));
+                        app.resize_pane_from_mouse(terminal_size.0, me.column);
                     }
                     _ => {}
                 },
@@ -714,14 +299,19 @@ fn ui(f: &mut Frame, app: &mut App) -> Result<()> {
     // --- Left pane
     if app.show_left_pane {
         let left_border = if matches!(app.focus, Focus::Left) {
-            Color::Cyan
+            app.theme.border_focused
         } else {
-            Color::Blue
+            app.theme.border
+        };
+        let title = if app.files.is_loading() {
+            "Files (loading…)"
+        } else {
+            "Files"
         };
         let left_block = Block::default()
-            .title("Files")
+            .title(title)
             .borders(Borders::ALL)
-            .border_style(Style::default().fg(left_border));
+            .border_style(left_border);
         let left_tree = tui_tree_widget::Tree::new(&app.left_tree)
             .map_err(|e| {
                 error::SbError::tree_widget(format!("Failed to create file tree widget: {}", e))
@@ -734,62 +324,6 @@ fn ui(f: &mut Frame, app: &mut App) -> Result<()> {
     // (Right tree and standalone editor hidden in 2-pane UX)
 
     // --- Unified preview/editor (right)
-    // Prepare current editor buffer and metadata
-    let text = app.editor.lines().join("\n");
-    // Provide current path/text and preview cursor to preview for code highlighting/diff and raw-line overlay
-    if let Some(path) = app.opened.as_ref() {
-        std::env::set_var("SB_CURRENT_FILE", path);
-        std::env::set_var("SB_CURRENT_TEXT", &text);
-        // Only enable raw-line overlay when inline editing is active
-        if app.editing_line {
-            std::env::set_var("SB_OVERLAY", "1");
-        } else {
-            std::env::remove_var("SB_OVERLAY");
-        }
-        std::env::set_var("SB_PREVIEW_CURSOR", app.preview_cursor.to_string());
-        std::env::set_var("SB_PREVIEW_COL", app.preview_col.to_string());
-        std::env::set_var("SB_PREVIEW_SCROLL", app.preview_scroll.to_string());
-    }
-    let preview = if let Some(path) = app.opened.clone() {
-        // Check if we should show diff instead of regular preview
-        if app.should_show_diff(&path) {
-            if let Some(diff_content) = app.get_file_diff(&path) {
-                Preview {
-                    text: Text::raw(diff_content),
-                    images: vec![],
-                    videos: vec![],
-                }
-            } else {
-                Preview::from_markdown(&path, &text).unwrap_or_else(|_| Preview {
-                    text: Text::raw("(preview error)"),
-                    images: vec![],
-                    videos: vec![],
-                })
-            }
-        } else {
-            Preview::from_markdown(&path, &text).unwrap_or_else(|_| Preview {
-                text: Text::raw("(preview error)"),
-                images: vec![],
-                videos: vec![],
-            })
-        }
-    } else {
-        Preview {
-            text: Text::raw("(no file)"),
-            images: vec![],
-            videos: vec![],
-        }
-    };
-    // Auto start/stop video based on first detected link
-    if app.autoplay_video {
-        if let Some(first) = preview.videos.first() {
-            if app.video_path.as_ref() != Some(first) {
-                app.start_video(first.clone());
-            }
-        } else if app.video_player.is_some() {
-            app.stop_video();
-        }
-    }
     // Set preview viewport height (usable rows for text block)
     let preview_text_rows = chunks[1].height.saturating_sub(2) as usize;
     app.preview_viewport = preview_text_rows;
@@ -800,18 +334,70 @@ fn ui(f: &mut Frame, app: &mut App) -> Result<()> {
             .saturating_sub(app.preview_viewport)
             .saturating_sub(0);
     }
+    let placeholder;
+    let preview = if let Some(path) = app.opened.as_deref() {
+        // Check if we should show diff instead of regular preview
+        let show_diff = !is_code_file(path) && app.should_show_diff(path);
+        let max_preview_size = app.config.security.max_preview_size;
+        let (git_repo, theme, editor) = (&app.git_repo, &app.theme, &app.editor);
+        // Parsed and highlighted once per buffer revision
+        app.preview_cache
+            .get_or_update(path, app.revision, show_diff, |previous| {
+                let diff = show_diff
+                    .then(|| git_repo.as_ref()?.file_diff(path).ok())
+                    .flatten();
+                let text = editor.lines().join("\n");
+                if text.len() as u64 > max_preview_size {
+                    Preview::raw(format!(
+                        "(too large to preview: {} bytes, limit is security.max_preview_size = {})",
+                        text.len(),
+                        max_preview_size
+                    ))
+                } else if is_code_file(path) {
+                    Preview::from_code(path, &text, theme, previous)
+                } else if let Some(diff_content) = diff {
+                    Preview::raw(diff_content)
+                } else {
+                    Preview::from_markdown(path, &text, theme, previous)
+                        .unwrap_or_else(|_| Preview::raw("(preview error)"))
+                }
+            })
+    } else {
+        placeholder = Preview::raw("(no file)");
+        &placeholder
+    };
+    let first_video = preview.videos.first().cloned();
     // Right pane: preview or full raw editor
     if app.show_raw_editor {
         let block = Block::default()
             .title("Edit (raw)")
             .borders(Borders::ALL)
-            .border_style(Style::default().fg(Color::Green));
+            .border_style(app.theme.border_editor);
         let area = chunks[1];
         f.render_widget(block.clone(), area);
         let inner = block.inner(area);
         f.render_widget(&app.editor, inner);
     } else {
-        preview::render_preview(f, chunks[1], &preview);
+        let context = PreviewContext {
+            lines: app.editor.lines(),
+            cursor: app.preview_cursor,
+            col: app.preview_col,
+            scroll: app.preview_scroll,
+            // Only enable raw-line overlay when inline editing is active
+            overlay: app.editing_line,
+            wrap: app.config.ui.wrap,
+        };
+        f.render_widget(MarkdownView::new(preview, context, &app.theme), chunks[1]);
+    }
+    // Auto start/stop video based on first detected link
+    if app.autoplay_video {
+        if let Some(first) = first_video {
+            if app.video_path.as_ref() != Some(&first) {
+                app.start_video(first);
+            }
+        } else if app.video_player.is_some() {
+            app.stop_video();
+        }
     }
     // Editor command mode prompt overlays at bottom when active
     if app.editor_cmd_mode {
@@ -840,7 +426,7 @@ fn ui(f: &mut Frame, app: &mut App) -> Result<()> {
                 app.preview_cursor + 1
             ))
             .borders(Borders::ALL)
-            .border_style(Style::default().fg(Color::Green));
+            .border_style(app.theme.border_editor);
         f.render_widget(Clear, area);
         f.render_widget(block.clone(), area);
         let inner = block.inner(area);
@@ -887,7 +473,7 @@ fn ui(f: &mut Frame, app: &mut App) -> Result<()> {
         };
         f.render_widget(Clear, area);
         let p = Paragraph::new(badge)
-            .style(Style::default().fg(Color::DarkGray).bg(Color::Black))
+            .style(app.theme.overlay.patch(app.theme.dimmed))
             .alignment(Alignment::Center);
         f.render_widget(p, area);
     }
@@ -901,8 +487,7 @@ fn ui(f: &mut Frame, app: &mut App) -> Result<()> {
         height: 1,
     };
     f.render_widget(Clear, quit_area);
-    let quit_widget =
-        Paragraph::new(quit_hint).style(Style::default().fg(Color::Black).bg(Color::Yellow));
+    let quit_widget = Paragraph::new(quit_hint).style(app.theme.status_bar_hint);
     f.render_widget(quit_widget, quit_area);
 
     // Footer hint in Preview to restore Files pane (one row above global status bar)
@@ -924,7 +509,7 @@ fn ui(f: &mut Frame, app: &mut App) -> Result<()> {
         };
         f.render_widget(Clear, area);
         let p = Paragraph::new(hint)
-            .style(Style::default().fg(Color::DarkGray))
+            .style(app.theme.dimmed)
             .alignment(Alignment::Center);
         f.render_widget(p, area);
     }
@@ -938,20 +523,16 @@ fn ui(f: &mut Frame, app: &mut App) -> Result<()> {
             .unwrap_or("(no file)");
         let line = app.preview_cursor + 1;
         let col = app.preview_col + 1;
-        let dirty = match &app.last_saved_text {
-            Some(saved) => saved != &text,
-            None => false,
-        };
         (
             name.to_string(),
             format!("L{} C{}", line, col),
-            if dirty { "*" } else { "" },
+            if app.is_dirty() { "*" } else { "" },
         )
     } else {
         ("(no file)".to_string(), String::new(), "")
     };
 
-    let status_text = match (&app.focus, app.show_raw_editor, app.picking_file) {
+    let mut status_text = match (&app.focus, app.show_raw_editor, app.picking_file) {
         // File picker mode
         (_, _, true) => {
             // File picker has its own status bar, skip main status
@@ -991,14 +572,15 @@ fn ui(f: &mut Frame, app: &mut App) -> Result<()> {
         }
     };
 
+    // Show the start of a multi-key sequence until it completes
+    let pending = app.keymap.pending();
+    if !pending.is_empty() {
+        status_text = format!("{} … │ {}", pending, status_text);
+    }
+
     if !status_text.is_empty() {
         let status = Paragraph::new(status_text)
-            .style(
-                Style::default()
-                    .fg(Color::Black)
-                    .bg(Color::Cyan)
-                    .add_modifier(Modifier::BOLD),
-            )
+            .style(app.theme.status_bar)
             .alignment(ratatui::layout::Alignment::Center);
 
         // Use the reserved status bar area from main_chunks[1]
@@ -1007,7 +589,7 @@ fn ui(f: &mut Frame, app: &mut App) -> Result<()> {
 
     // --- Help overlay
     if app.show_help {
-        draw_centered_help(f, f.area());
+        draw_centered_help(f, f.area(), app);
     }
 
     // --- New file overlay
@@ -1017,7 +599,7 @@ fn ui(f: &mut Frame, app: &mut App) -> Result<()> {
 
     // --- Delete confirm overlay
     if app.confirming_delete {
-        draw_delete_confirm(f, f.area(), app.delete_target.as_deref());
+        draw_delete_confirm(f, f.area(), app.delete_target.as_deref(), &app.theme);
     }
 
     // --- File picker overlay
@@ -1041,42 +623,49 @@ fn ui(f: &mut Frame, app: &mut App) -> Result<()> {
         draw_git_status(f, f.area(), app);
     }
 
+    // --- Changed on disk overlay
+    if app.disk_changed {
+        draw_disk_changed(f, f.area(), app.opened.as_deref(), &app.theme);
+    }
+
     Ok(())
 }
 
-fn draw_centered_help(f: &mut Frame, area: Rect) {
-    let help = [
-        "sb — Markdown TUI",
-        "",
-        "Focus: Tab / Shift+Tab",
-        "Tree: ↑↓←→ or j/k, Enter toggles/open",
-        "Editor: type freely (Enter = newline)",
-        "New file: N",
-        "Delete: d (confirm)",
-        "Insert link (picker): F2 or Ctrl+I",
-        "Save: Ctrl+S",
-        "Open externally: o",
-        "",
-        "Selection:",
-        "Select/Unselect: S (accumulates)",
-        "Range select: Shift+↑↓",
-        "Select all: Ctrl+A",
-        "Clear selections: Esc",
-        "",
-        "Pane Resize:",
-        "Widen left: Ctrl+. or Ctrl+=",
-        "Narrow left: Ctrl+, or Ctrl+-",
-        "Mouse drag: Click and drag separator",
-        "",
-        "Help: ? (toggle)",
-        "Quit: Q / Esc",
-    ]
-    .join("\n");
-    let paragraph = Paragraph::new(help)
-        .block(Block::default().title("Help").borders(Borders::ALL))
-        .wrap(Wrap { trim: false });
-    let w = area.width.min(60);
-    let h = area.height.min(22);
+fn draw_centered_help(f: &mut Frame, area: Rect, app: &mut App) {
+    // Generated from the active keymap, so user bindings show up here
+    let mut lines = vec![Line::from("sb — Markdown TUI")];
+    for (mode, rows) in app.keymap.help() {
+        lines.push(Line::from(""));
+        lines.push(Line::styled(
+            mode.title(),
+            Style::default().add_modifier(Modifier::BOLD),
+        ));
+        let width = rows
+            .iter()
+            .map(|(keys, _)| keys.chars().count())
+            .max()
+            .unwrap_or(0);
+        for (keys, description) in rows {
+            lines.push(Line::from(vec![
+                Span::styled(
+                    format!("  {:<width$}  ", keys, width = width),
+                    app.theme.warning,
+                ),
+                Span::raw(description),
+            ]));
+        }
+    }
+    let w = area.width.min(72);
+    let h = area.height.saturating_sub(4).max(3);
+    let max_scroll = (lines.len() as u16).saturating_sub(h.saturating_sub(2));
+    app.help_scroll = app.help_scroll.min(max_scroll);
+    let paragraph = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .title("Help (↑↓ scroll, Esc close)")
+                .borders(Borders::ALL),
+        )
+        .scroll((app.help_scroll, 0));
     let x = area.x + (area.width.saturating_sub(w)) / 2;
     let y = area.y + (area.height.saturating_sub(h)) / 2;
     let popup = Rect {
@@ -1109,9 +698,9 @@ fn draw_new_file_prompt(f: &mut Frame, area: Rect, input: &TextArea) {
     f.render_widget(input, inner);
 }
 
-fn draw_delete_confirm(f: &mut Frame, area: Rect, target: Option<&std::path::Path>) {
+fn draw_delete_confirm(f: &mut Frame, area: Rect, target: Option<&std::path::Path>, theme: &Theme) {
     // Create a semi-transparent background overlay
-    let overlay_block = Block::default().style(Style::default().bg(Color::Black));
+    let overlay_block = Block::default().style(theme.overlay);
     f.render_widget(overlay_block, area);
 
     let w = area.width.min(60);
@@ -1129,9 +718,9 @@ fn draw_delete_confirm(f: &mut Frame, area: Rect, target: Option<&std::path::Pat
     let block = Block::default()
         .title(" ⚠️  Confirm Delete ")
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(Color::Cyan))
+        .border_style(theme.overlay_border)
         .border_type(BorderType::Rounded)
-        .style(Style::default().bg(Color::Black));
+        .style(theme.overlay);
 
     f.render_widget(Clear, popup);
     f.render_widget(block.clone(), popup);
@@ -1152,24 +741,16 @@ fn draw_delete_confirm(f: &mut Frame, area: Rect, target: Option<&std::path::Pat
         Line::from(""),
         Line::from(vec![
             Span::raw("Are you sure you want to delete "),
-            Span::styled(
-                format!("'{file_name}'"),
-                Style::default()
-                    .fg(Color::Yellow)
-                    .add_modifier(Modifier::BOLD),
-            ),
+            Span::styled(format!("'{file_name}'"), theme.warning),
             Span::raw("?"),
         ]),
         Line::from(""),
-        Line::from("This action cannot be undone.").style(Style::default().fg(Color::Red)),
+        Line::from("This action cannot be undone.").style(theme.error),
         Line::from(""),
         Line::from(vec![
-            Span::styled(
-                " Enter ",
-                Style::default().fg(Color::Black).bg(Color::Green),
-            ),
+            Span::styled(" Enter ", theme.button_confirm),
             Span::raw(" Confirm  "),
-            Span::styled(" Esc ", Style::default().fg(Color::Black).bg(Color::Red)),
+            Span::styled(" Esc ", theme.button_cancel),
             Span::raw(" Cancel"),
         ]),
     ];
@@ -1178,6 +759,63 @@ fn draw_delete_confirm(f: &mut Frame, area: Rect, target: Option<&std::path::Pat
     f.render_widget(body, inner);
 }
 
+fn draw_disk_changed(f: &mut Frame, area: Rect, path: Option<&std::path::Path>, theme: &Theme) {
+    let overlay_block = Block::default().style(theme.overlay);
+    f.render_widget(overlay_block, area);
+
+    let w = area.width.min(64);
+    let h = 8;
+    let x = area.x + (area.width.saturating_sub(w)) / 2;
+    let y = area.y + (area.height.saturating_sub(h)) / 2;
+    let popup = Rect {
+        x,
+        y,
+        width: w,
+        height: h,
+    };
+
+    let block = Block::default()
+        .title(" ⚠️  Changed on Disk ")
+        .borders(Borders::ALL)
+        .border_style(theme.overlay_border)
+        .border_type(BorderType::Rounded)
+        .style(theme.overlay);
+
+    f.render_widget(Clear, popup);
+    f.render_widget(block.clone(), popup);
+
+    let inner = block.inner(popup);
+
+    let file_name = path
+        .and_then(|p| p.file_name())
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "the open file".to_string());
+
+    let content = vec![
+        Line::from(""),
+        Line::from(vec![
+            Span::styled(format!("'{file_name}'"), theme.warning),
+            Span::raw(" was changed by another program."),
+        ]),
+        Line::from(""),
+        Line::from("Overwriting drops their changes, reloading drops yours.").style(theme.error),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled(" o ", theme.button_confirm),
+            Span::raw(" Overwrite  "),
+            Span::styled(" r ", theme.button_confirm),
+            Span::raw(" Reload  "),
+            Span::styled(" m ", theme.button_confirm),
+            Span::raw(" Merge  "),
+            Span::styled(" Esc ", theme.button_cancel),
+            Span::raw(" Keep editing"),
+        ]),
+    ];
+
+    let body = Paragraph::new(content).alignment(Alignment::Center);
+    f.render_widget(body, inner);
+}
+
 fn draw_file_picker(f: &mut Frame, area: Rect, app: &App) {
     // Create centered popup
     let w = 70.min(area.width - 4);
@@ -1201,8 +839,8 @@ fn draw_file_picker(f: &mut Frame, area: Rect, app: &App) {
             app.picker_items.len()
         ))
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(Color::Cyan))
-        .style(Style::default().bg(Color::Black));
+        .border_style(app.theme.overlay_border)
+        .style(app.theme.overlay);
 
     f.render_widget(block.clone(), popup);
     let inner = block.inner(popup);
@@ -1232,52 +870,40 @@ fn draw_file_picker(f: &mut Frame, area: Rect, app: &App) {
             let selection_marker = if is_in_selection { "✓ " } else { "  " };
 
             // Add Git status if available
-            let status_indicator = if let Some(ref repo) = app.git_repo {
-                if let Ok(statuses) = repo.status() {
-                    if let Some(status) = statuses.get(path) {
-                        match status {
-                            crate::git::FileStatus::Modified => " [M]",
-                            crate::git::FileStatus::Added => " [A]",
-                            crate::git::FileStatus::Deleted => " [D]",
-                            crate::git::FileStatus::Untracked => " [?]",
-                            crate::git::FileStatus::Conflicted => " [C]",
-                            _ => "",
-                        }
-                    } else {
-                        ""
-                    }
-                } else {
-                    ""
-                }
-            } else {
-                ""
+            let status = app
+                .git_repo
+                .as_ref()
+                .and_then(|repo| repo.status().ok())
+                .and_then(|statuses| statuses.get(path).copied());
+            let status_indicator = match status {
+                Some(git::FileStatus::Modified) => " [M]",
+                Some(git::FileStatus::Added) => " [A]",
+                Some(git::FileStatus::Deleted) => " [D]",
+                Some(git::FileStatus::Untracked) => " [?]",
+                Some(git::FileStatus::Conflicted) => " [C]",
+                _ => "",
             };
 
-            let text = format!(
-                "{}{}{}{}",
-                selection_marker, prefix, display_name, status_indicator
-            );
+            let text = format!("{}{}{}", selection_marker, prefix, display_name);
 
             let style = if is_current && is_in_selection {
                 // Current item that's also selected (bright highlight)
-                Style::default()
-                    .bg(Color::Cyan)
-                    .fg(Color::Black)
-                    .add_modifier(Modifier::BOLD)
+                app.theme.selection_cursor_marked
             } else if is_current {
                 // Current item (normal cursor highlight)
-                Style::default()
-                    .bg(Color::Blue)
-                    .fg(Color::White)
-                    .add_modifier(Modifier::BOLD)
+                app.theme.selection_cursor
             } else if is_in_selection {
                 // Selected items (subtle highlight)
-                Style::default().bg(Color::DarkGray).fg(Color::Yellow)
+                app.theme.selection
             } else {
-                Style::default().fg(Color::White)
+                app.theme.text
             };
 
-            ListItem::new(text).style(style)
+            let mut spans = vec![Span::raw(text)];
+            if let Some(status) = status.filter(|_| !status_indicator.is_empty()) {
+                spans.push(Span::styled(status_indicator, app.theme.git(status)));
+            }
+            ListItem::new(Line::from(spans)).style(style)
         })
         .collect();
 
@@ -1295,12 +921,7 @@ fn draw_file_picker(f: &mut Frame, area: Rect, app: &App) {
         "↑↓:navigate  Shift+↑↓:select  Space:toggle  Ctrl+A:all  O:open  D:delete  P:parent  ESC:cancel".to_string()
     };
     let status = Paragraph::new(status_text)
-        .style(
-            Style::default()
-                .fg(Color::Black)
-                .bg(Color::Yellow)
-                .add_modifier(Modifier::BOLD),
-        )
+        .style(app.theme.status_bar_hint)
         .alignment(ratatui::layout::Alignment::Center);
 
     f.render_widget(status, chunks[1]);
@@ -1327,7 +948,7 @@ fn draw_op_input(f: &mut Frame, area: Rect, app: &App) {
     let block = Block::default()
         .title(title)
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(Color::Yellow));
+        .border_style(app.theme.prompt_border);
     f.render_widget(Clear, popup);
     f.render_widget(block.clone(), popup);
     let inner = block.inner(popup);
@@ -1361,7 +982,7 @@ fn draw_move_destination_picker(f: &mut Frame, area: Rect, app: &App) {
     let block = Block::default()
         .title(title)
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(Color::Green));
+        .border_style(app.theme.overlay_border);
 
     f.render_widget(Clear, popup);
     f.render_widget(block.clone(), popup);
@@ -1388,9 +1009,7 @@ fn draw_move_destination_picker(f: &mut Frame, area: Rect, app: &App) {
             };
 
             let style = if i == app.move_dest_index {
-                Style::default()
-                    .fg(Color::Green)
-                    .add_modifier(Modifier::BOLD)
+                app.theme.selection_cursor
             } else {
                 Style::default()
             };
@@ -1404,8 +1023,7 @@ fn draw_move_destination_picker(f: &mut Frame, area: Rect, app: &App) {
 
     // Status bar
     let status_text = "ENTER:move-here ↑↓:navigate →:enter ESC:cancel";
-    let status_bar =
-        Paragraph::new(status_text).style(Style::default().fg(Color::White).bg(Color::Green));
+    let status_bar = Paragraph::new(status_text).style(app.theme.status_bar_hint);
     f.render_widget(status_bar, status_area);
 }
 
@@ -1431,7 +1049,7 @@ fn draw_git_status(f: &mut Frame, area: Rect, app: &App) {
     let block = Block::default()
         .title(title)
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(Color::Magenta));
+        .border_style(app.theme.overlay_border);
 
     f.render_widget(Clear, popup);
     f.render_widget(block.clone(), popup);
@@ -1454,7 +1072,7 @@ fn draw_git_status(f: &mut Frame, area: Rect, app: &App) {
     };
 
     let status_paragraph = Paragraph::new(content)
-        .style(Style::default().fg(Color::White))
+        .style(app.theme.text)
         .alignment(Alignment::Left)
         .wrap(ratatui::widgets::Wrap { trim: true });
 
@@ -1462,7 +1080,62 @@ fn draw_git_status(f: &mut Frame, area: Rect, app: &App) {
 
     // Status bar
     let status_text = "ESC:close ENTER:close S:refresh";
-    let status_bar =
-        Paragraph::new(status_text).style(Style::default().fg(Color::White).bg(Color::Magenta));
+    let status_bar = Paragraph::new(status_text).style(app.theme.status_bar_hint);
     f.render_widget(status_bar, status_area);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets(args: &[&str]) -> Result<Target> {
+        parse_targets(&args.iter().map(|a| a.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_parse_targets() {
+        let t = targets(&["notes.md:12"]).unwrap();
+        assert_eq!(t.path, Some(PathBuf::from("notes.md")));
+        assert_eq!(t.line, Some(12));
+
+        let t = targets(&["+7", "notes.md"]).unwrap();
+        assert_eq!(t.path, Some(PathBuf::from("notes.md")));
+        assert_eq!(t.line, Some(7));
+
+        let t = targets(&["C:notes"]).unwrap();
+        assert_eq!(t.path, Some(PathBuf::from("C:notes")));
+        assert_eq!(t.line, None);
+
+        assert_eq!(
+            targets(&[]).unwrap(),
+            Target {
+                path: None,
+                line: None
+            }
+        );
+        assert!(targets(&["a", "b"]).is_err());
+        assert!(targets(&["+x"]).is_err());
+    }
+
+    #[test]
+    fn test_file_opens_with_tree_at_parent_and_line() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let dir = temp.path().join("docs");
+        std::fs::create_dir(&dir).unwrap();
+        let file = dir.join("a.md");
+        std::fs::write(&file, "one\ntwo\nthree\n").unwrap();
+
+        let root = root_for_file(&file, false);
+        assert_eq!(root, dir);
+        let mut app =
+            App::with_config(Config::new().with_base_directory(root).with_read_only(true)).unwrap();
+        app.reveal_in_tree(&file);
+        app.open_file(&file).unwrap();
+        app.jump_to_line(3);
+
+        assert_eq!(app.current_selection_path(), Some(file.clone()));
+        assert_eq!(app.preview_cursor, 2);
+        app.save().unwrap();
+        assert!(app.status.starts_with("Read-only"));
+    }
+}
@@ -0,0 +1,203 @@
+//! Three-way merge of text
+//!
+//! Used when the open file changed on disk while it had unsaved edits: the
+//! text as loaded is the common ancestor of the buffer and the file on disk.
+//! Changes that do not overlap are combined, and overlapping ones are left
+//! between diff3-style conflict markers for the user to resolve.
+
+use std::ops::Range;
+
+use similar::{Algorithm, DiffOp};
+
+pub const MARKER_OURS: &str = "<<<<<<< buffer";
+pub const MARKER_BASE: &str = "||||||| original";
+pub const MARKER_SEPARATOR: &str = "=======";
+pub const MARKER_THEIRS: &str = ">>>>>>> disk";
+
+/// Result of [`merge3`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Merged {
+    pub lines: Vec<String>,
+    /// Number of regions left between conflict markers
+    pub conflicts: usize,
+}
+
+/// A changed region: lines `base` of the ancestor became lines `new`
+#[derive(Debug, Clone)]
+struct Hunk {
+    base: Range<usize>,
+    new: Range<usize>,
+}
+
+/// Changes from `base` to `new`, with touching changes joined up
+fn hunks(base: &[&str], new: &[&str]) -> Vec<Hunk> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    for op in similar::capture_diff_slices(Algorithm::Myers, base, new) {
+        let hunk = match op {
+            DiffOp::Equal { .. } => continue,
+            DiffOp::Delete {
+                old_index,
+                old_len,
+                new_index,
+            } => Hunk {
+                base: old_index..old_index + old_len,
+                new: new_index..new_index,
+            },
+            DiffOp::Insert {
+                old_index,
+                new_index,
+                new_len,
+            } => Hunk {
+                base: old_index..old_index,
+                new: new_index..new_index + new_len,
+            },
+            DiffOp::Replace {
+                old_index,
+                old_len,
+                new_index,
+                new_len,
+            } => Hunk {
+                base: old_index..old_index + old_len,
+                new: new_index..new_index + new_len,
+            },
+        };
+        match hunks.last_mut() {
+            Some(last) if last.base.end == hunk.base.start && last.new.end == hunk.new.start => {
+                last.base.end = hunk.base.end;
+                last.new.end = hunk.new.end;
+            }
+            _ => hunks.push(hunk),
+        }
+    }
+    hunks
+}
+
+/// One side's version of `base[region]`, given its hunks inside the region
+fn side<'a>(
+    base: &[&'a str],
+    new: &[&'a str],
+    hunks: &[Hunk],
+    region: &Range<usize>,
+) -> Vec<&'a str> {
+    match (hunks.first(), hunks.last()) {
+        (Some(first), Some(last)) => {
+            let start = first.new.start - (first.base.start - region.start);
+            let end = last.new.end + (region.end - last.base.end);
+            new[start..end].to_vec()
+        }
+        _ => base[region.clone()].to_vec(),
+    }
+}
+
+/// Merge the changes from `base` to `ours` and from `base` to `theirs`
+pub fn merge3(base: &str, ours: &str, theirs: &str) -> Merged {
+    let base: Vec<&str> = base.lines().collect();
+    let ours: Vec<&str> = ours.lines().collect();
+    let theirs: Vec<&str> = theirs.lines().collect();
+    let ours_hunks = hunks(&base, &ours);
+    let theirs_hunks = hunks(&base, &theirs);
+
+    let mut merged = Merged {
+        lines: Vec::new(),
+        conflicts: 0,
+    };
+    let (mut i, mut j, mut pos) = (0, 0, 0);
+    while i < ours_hunks.len() || j < theirs_hunks.len() {
+        // Start a region at the earliest hunk and grow it while hunks from
+        // either side overlap or touch it
+        let start = match (ours_hunks.get(i), theirs_hunks.get(j)) {
+            (Some(o), Some(t)) => o.base.start.min(t.base.start),
+            (Some(o), None) => o.base.start,
+            (None, Some(t)) => t.base.start,
+            (None, None) => unreachable!(),
+        };
+        let mut region = start..start;
+        let (first_ours, first_theirs) = (i, j);
+        loop {
+            if let Some(o) = ours_hunks.get(i).filter(|o| o.base.start <= region.end) {
+                region.end = region.end.max(o.base.end);
+                i += 1;
+            } else if let Some(t) = theirs_hunks.get(j).filter(|t| t.base.start <= region.end) {
+                region.end = region.end.max(t.base.end);
+                j += 1;
+            } else {
+                break;
+            }
+        }
+        merged
+            .lines
+            .extend(base[pos..region.start].iter().map(|s| s.to_string()));
+        pos = region.end;
+
+        let ours_changed = &ours_hunks[first_ours..i];
+        let theirs_changed = &theirs_hunks[first_theirs..j];
+        let mine = side(&base, &ours, ours_changed, &region);
+        let other = side(&base, &theirs, theirs_changed, &region);
+        if theirs_changed.is_empty() || mine == other {
+            merged.lines.extend(mine.iter().map(|s| s.to_string()));
+        } else if ours_changed.is_empty() {
+            merged.lines.extend(other.iter().map(|s| s.to_string()));
+        } else {
+            merged.conflicts += 1;
+            merged.lines.push(MARKER_OURS.into());
+            merged.lines.extend(mine.iter().map(|s| s.to_string()));
+            merged.lines.push(MARKER_BASE.into());
+            merged
+                .lines
+                .extend(base[region.clone()].iter().map(|s| s.to_string()));
+            merged.lines.push(MARKER_SEPARATOR.into());
+            merged.lines.extend(other.iter().map(|s| s.to_string()));
+            merged.lines.push(MARKER_THEIRS.into());
+        }
+    }
+    merged
+        .lines
+        .extend(base[pos..].iter().map(|s| s.to_string()));
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge3() {
+        let base = "a\nb\nc\nd\ne";
+
+        // Changes to different lines are combined
+        let merged = merge3(base, "A\nb\nc\nd\ne", "a\nb\nc\nd\nE\nf");
+        assert_eq!(merged.lines, ["A", "b", "c", "d", "E", "f"]);
+        assert_eq!(merged.conflicts, 0);
+
+        // The same change on both sides is taken once
+        let merged = merge3(base, "a\nB\nc\nd\ne", "a\nB\nc\nd\ne");
+        assert_eq!(merged.lines, ["a", "B", "c", "d", "e"]);
+        assert_eq!(merged.conflicts, 0);
+
+        // Different changes to the same line conflict
+        let merged = merge3(base, "a\nb\nmine\nd\ne", "a\nb\ntheirs\nd");
+        assert_eq!(
+            merged.lines,
+            [
+                "a",
+                "b",
+                MARKER_OURS,
+                "mine",
+                MARKER_BASE,
+                "c",
+                MARKER_SEPARATOR,
+                "theirs",
+                MARKER_THEIRS,
+                "d"
+            ]
+        );
+        assert_eq!(merged.conflicts, 1);
+
+        // Insertions at the same place conflict too
+        let merged = merge3(base, "a\nx\nb\nc\nd\ne", "a\ny\nb\nc\nd\ne");
+        assert_eq!(merged.conflicts, 1);
+        assert_eq!(merged.lines[1..4], [MARKER_OURS, "x", MARKER_BASE]);
+
+        assert_eq!(merge3(base, base, base).lines.join("\n"), base);
+    }
+}
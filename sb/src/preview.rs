@@ -1,30 +1,87 @@
 use anyhow::Result;
 use image::ImageReader;
-use once_cell::sync::Lazy;
 use pathdiff::diff_paths;
 use ratatui::{prelude::*, widgets::*};
-use ratatui_image::{picker::Picker, Resize, StatefulImage};
+use ratatui_image::{picker::Picker, protocol::StatefulProtocol, Resize, StatefulImage};
 use similar::{ChangeTag, TextDiff};
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::process::Command;
-use syntect::{easy::HighlightLines, highlighting::ThemeSet, parsing::SyntaxSet};
 use tui_markdown as md;
+
+use crate::highlight::Highlighted;
+use crate::theme::{Theme, THEME_SET};
 // v8 Picker re-exports ProtocolType from picker; no direct use needed here
 
+/// A decoded image ready to draw, or why it could not be loaded
+type LoadedImage = std::result::Result<RefCell<StatefulProtocol>, String>;
+
+/// A parsed document: styled text plus the media it links to
 pub struct Preview {
     pub text: Text<'static>,
     pub images: Vec<PathBuf>,
     pub videos: Vec<PathBuf>,
+    /// Highlighted source for code files, drawn instead of `text`
+    pub code: Option<CodeView>,
+    /// Markdown blocks and their rendered lines, reused by the next revision
+    blocks: Vec<(String, Vec<Line<'static>>)>,
+    /// `images`, decoded once and kept across revisions
+    decoded: HashMap<PathBuf, LoadedImage>,
 }
 
 impl Preview {
-    pub fn from_markdown(_path: &Path, src: &str) -> Result<Self> {
-        // Normalize headings so `##Heading` (no space) becomes `## Heading`, and add spacing
-        let normalized = normalize_headings(src);
-        // Convert markdown → styled Text, then take an owned copy (no unsafe lifetime tricks)
-        let text_parsed: Text<'_> = md::from_str(&normalized);
-        let text_owned: Text<'static> = to_owned_text(text_parsed);
-        let text: Text<'static> = apply_heading_styles(text_owned);
+    /// Plain text, such as a placeholder or a message
+    pub fn raw(text: impl Into<String>) -> Self {
+        Self {
+            text: Text::raw(text.into()),
+            images: vec![],
+            videos: vec![],
+            code: None,
+            blocks: vec![],
+            decoded: HashMap::new(),
+        }
+    }
+
+    /// A code file, reusing the highlighting of `previous` where it can
+    pub fn from_code(path: &Path, src: &str, theme: &Theme, previous: Option<Preview>) -> Self {
+        let code = CodeView::new(path, src, theme, previous.and_then(|p| p.code));
+        Self {
+            code: Some(code),
+            ..Self::raw("")
+        }
+    }
+
+    /// A markdown document; blocks unchanged since `previous` keep their
+    /// rendering and linked images stay decoded, so an edit only parses the
+    /// block it touched
+    pub fn from_markdown(
+        _path: &Path,
+        src: &str,
+        theme: &Theme,
+        previous: Option<Preview>,
+    ) -> Result<Self> {
+        let (mut rendered, mut decoded) = match previous {
+            Some(previous) => (
+                previous.blocks.into_iter().collect::<HashMap<_, _>>(),
+                previous.decoded,
+            ),
+            None => Default::default(),
+        };
+        let mut blocks = Vec::new();
+        let mut lines: Vec<Line<'static>> = Vec::new();
+        for block in split_blocks(src) {
+            let block_lines = rendered
+                .remove(block)
+                .unwrap_or_else(|| render_block(block, theme));
+            // Blocks are separated by one blank line, as in a whole-document render
+            if !lines.is_empty() {
+                lines.push(Line::default());
+            }
+            lines.extend(block_lines.iter().cloned());
+            blocks.push((block.to_string(), block_lines));
+        }
+        let text = Text::from(lines);
 
         let mut images = vec![];
         for (_alt, p) in find_md_images(src) {
@@ -33,6 +90,13 @@ impl Preview {
                 images.push(abs);
             }
         }
+        let decoded = images
+            .iter()
+            .map(|path| {
+                let image = decoded.remove(path).unwrap_or_else(|| load_image(path));
+                (path.clone(), image)
+            })
+            .collect();
         let mut videos = vec![];
         for (_alt, p) in find_md_videos(src) {
             let abs = resolve_relative(_path, &p);
@@ -44,28 +108,137 @@ impl Preview {
             text,
             images,
             videos,
+            code: None,
+            blocks,
+            decoded,
         })
     }
 }
-fn to_owned_text(input: Text<'_>) -> Text<'static> {
+
+/// Render one markdown block to styled lines
+fn render_block(src: &str, theme: &Theme) -> Vec<Line<'static>> {
+    // Normalize headings so `##Heading` (no space) becomes `## Heading`, and add spacing
+    let normalized = normalize_headings(src);
+    // Convert markdown → styled Text, then take an owned copy (no unsafe lifetime tricks)
+    let text_parsed: Text<'_> = md::from_str(&normalized);
+    let text_owned: Text<'static> = to_owned_text(text_parsed, theme);
+    apply_heading_styles(text_owned, theme).lines
+}
+
+/// Split markdown into blocks that render the same on their own as in the
+/// whole document
+///
+/// A block ends at a blank line followed by an unindented line that does not
+/// continue a list the block started with. Fenced code is never split, and documents whose parts
+/// refer to each other (link reference definitions, raw HTML that may span
+/// blank lines) stay one block.
+fn split_blocks(src: &str) -> Vec<&str> {
+    let whole = src.lines().any(|line| {
+        let line = line.trim_start();
+        (line.starts_with('[') && line.contains("]:"))
+            || ["<!--", "<pre", "<script", "<style"]
+                .iter()
+                .any(|tag| line.starts_with(tag))
+    });
+    if whole || src.trim().is_empty() {
+        return if src.trim().is_empty() {
+            vec![]
+        } else {
+            vec![src]
+        };
+    }
+
+    let is_list_item = |line: &str| {
+        let digits = line.chars().take_while(char::is_ascii_digit).count();
+        ["- ", "* ", "+ "].iter().any(|m| line.starts_with(m))
+            || (digits > 0 && [". ", ") "].iter().any(|m| line[digits..].starts_with(m)))
+    };
+    let mut blocks = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut fence: Option<&str> = None;
+    let mut prev_blank = true;
+    let mut in_list = false;
+    let mut offset = 0;
+    for line in src.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\n', '\r']);
+        let blank = content.trim().is_empty();
+        let trimmed = content.trim_start();
+        let marker = ["```", "~~~"]
+            .into_iter()
+            .find(|m| trimmed.starts_with(m) && content.len() - trimmed.len() < 4);
+        let boundary = fence.is_none()
+            && prev_blank
+            && !blank
+            && !content.starts_with([' ', '\t'])
+            && !(in_list && is_list_item(content));
+        if boundary {
+            if let Some(s) = start {
+                blocks.push(src[s..offset].trim_end());
+            }
+            start = Some(offset);
+            in_list = is_list_item(content);
+        }
+        match (fence, marker) {
+            (None, Some(m)) => fence = Some(m),
+            (Some(f), Some(m)) if f == m => fence = None,
+            _ => {}
+        }
+        prev_blank = blank && fence.is_none();
+        offset += line.len();
+    }
+    if let Some(s) = start {
+        blocks.push(src[s..].trim_end());
+    }
+    blocks
+}
+
+fn load_image(path: &Path) -> LoadedImage {
+    let image = ImageReader::open(path)
+        .map_err(|_| format!("(image load failed) {}", path.display()))?
+        .decode()
+        .map_err(|_| format!("(image decode failed) {}", path.display()))?;
+    let picker = Picker::from_fontsize((8, 12));
+    Ok(RefCell::new(picker.new_resize_protocol(image)))
+}
+// tui-markdown's fixed styles, swapped for the theme's in `to_owned_text`
+const MD_CODE: Style = Style::new().fg(Color::White).bg(Color::Black);
+const MD_LINK: Style = Style::new()
+    .fg(Color::Blue)
+    .add_modifier(Modifier::UNDERLINED);
+const MD_QUOTE: Style = Style::new().fg(Color::Green);
+
+fn themed(style: Style, theme: &Theme) -> Style {
+    match style {
+        s if s == MD_CODE => theme.code,
+        s if s == MD_LINK => theme.link,
+        s if s == MD_QUOTE => theme.quote,
+        s => s,
+    }
+}
+
+fn to_owned_text(input: Text<'_>, theme: &Theme) -> Text<'static> {
     let mut out_lines: Vec<Line<'static>> = Vec::with_capacity(input.lines.len());
     for line in input.lines.iter() {
         let mut spans_owned: Vec<Span<'static>> = Vec::with_capacity(line.spans.len());
         for span in line.spans.iter() {
-            spans_owned.push(Span::styled(span.content.to_string(), span.style));
+            spans_owned.push(Span::styled(
+                span.content.to_string(),
+                themed(span.style, theme),
+            ));
         }
-        out_lines.push(Line::from(spans_owned));
+        // Keep code block and quote line styles; headings are restyled later
+        let line_style = match themed(line.style, theme) {
+            s if s == theme.code || s == theme.quote => s,
+            _ => Style::default(),
+        };
+        out_lines.push(Line::from(spans_owned).style(line_style));
     }
     Text::from(out_lines)
 }
 
-fn apply_heading_styles(text: Text<'static>) -> Text<'static> {
+fn apply_heading_styles(text: Text<'static>, theme: &Theme) -> Text<'static> {
     // Convert leading "# ", "## ", ... into styled heading lines without the markers.
     // This compensates for markdown backends that leave '#' in plain text.
-    let is_light = std::env::var("SB_THEME")
-        .ok()
-        .map(|v| v.eq_ignore_ascii_case("light"))
-        .unwrap_or(false);
     let mut new_lines: Vec<Line<'static>> = Vec::with_capacity(text.lines.len());
     for line in text.lines.into_iter() {
         // Reconstruct full content to detect heading markers
@@ -87,25 +260,7 @@ fn apply_heading_styles(text: Text<'static>) -> Text<'static> {
         let is_heading = hashes > 0 && trimmed.chars().nth(hashes) == Some(' ');
         if is_heading {
             let title = trimmed[hashes + 1..].to_string();
-            // Color scheme without bold/underline
-            // Light: H1/H2 Blue, H3 DarkGray, others Gray
-            // Dark: H1/H2 Yellow, H3 Cyan, others Cyan
-            let style = if is_light {
-                match hashes {
-                    1 => Style::default().fg(Color::Blue),
-                    2 => Style::default().fg(Color::Blue),
-                    3 => Style::default().fg(Color::DarkGray),
-                    _ => Style::default().fg(Color::Gray),
-                }
-            } else {
-                match hashes {
-                    1 => Style::default().fg(Color::Yellow),
-                    2 => Style::default().fg(Color::Yellow),
-                    3 => Style::default().fg(Color::Cyan),
-                    _ => Style::default().fg(Color::Cyan),
-                }
-            };
-            new_lines.push(Line::from(Span::styled(title, style)));
+            new_lines.push(Line::from(Span::styled(title, theme.heading(hashes))));
         } else {
             new_lines.push(line);
         }
@@ -190,22 +345,116 @@ pub fn find_md_images(src: &str) -> Vec<(String, String)> {
     out
 }
 
-pub fn render_preview(f: &mut Frame, area: Rect, preview: &Preview) {
-    // If opened file is a code file, show highlighted + diff view
-    if try_render_code_preview(f, area).is_some() {
-        return;
+/// The open buffer's preview, rebuilt only when the buffer changes
+#[derive(Default)]
+pub struct PreviewCache {
+    key: Option<(PathBuf, u64, bool)>,
+    preview: Option<Preview>,
+}
+
+impl std::fmt::Debug for PreviewCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PreviewCache")
+            .field("key", &self.key)
+            .finish_non_exhaustive()
     }
-    // Determine if we should overlay raw current line and dim rendered output
-    // Show raw-line overlay only when explicitly enabled (e.g., during inline edit)
-    let show_overlay = matches!(std::env::var("SB_OVERLAY").as_deref(), Ok("1"));
-    // Text preview
-    let mut paragraph = Paragraph::new(preview.text.clone())
-        .wrap(Wrap { trim: true })
-        .block(Block::default().title("Preview").borders(Borders::ALL));
-    if show_overlay {
-        paragraph = paragraph.style(Style::default().fg(Color::DarkGray));
+}
+
+impl PreviewCache {
+    /// The preview of `path` at buffer `revision`; `diff` tells a diff
+    /// preview from a rendered one. On a miss `build` makes a new one from
+    /// the previous preview, which code previews update incrementally.
+    pub fn get_or_update(
+        &mut self,
+        path: &Path,
+        revision: u64,
+        diff: bool,
+        build: impl FnOnce(Option<Preview>) -> Preview,
+    ) -> &Preview {
+        let key = (path.to_path_buf(), revision, diff);
+        if self.key.as_ref() != Some(&key) || self.preview.is_none() {
+            self.preview = Some(build(self.preview.take()));
+            self.key = Some(key);
+        }
+        self.preview.get_or_insert_with(|| Preview::raw(""))
+    }
+
+    /// Forget the cached preview, e.g. after the file changed on disk
+    pub fn clear(&mut self) {
+        self.key = None;
+        self.preview = None;
     }
+}
+
+/// Editor state the preview draws around the document
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PreviewContext<'a> {
+    /// Current buffer contents, one entry per line
+    pub lines: &'a [String],
+    /// Cursor line and column, zero based
+    pub cursor: usize,
+    pub col: usize,
+    /// First visible line
+    pub scroll: usize,
+    /// Show the raw cursor line over the rendered one, for inline editing
+    pub overlay: bool,
+    pub wrap: bool,
+}
+
+/// Renders a [`Preview`] with a cursor line and optional raw-line overlay
+///
+/// ```
+/// use ratatui::{buffer::Buffer, layout::Rect, widgets::Widget};
+/// use sb::preview::{MarkdownView, Preview, PreviewContext};
+/// use sb::theme::Theme;
+///
+/// let theme = Theme::default();
+/// let text = "# Notes\n\nHello";
+/// let preview = Preview::from_markdown("notes.md".as_ref(), text, &theme, None).unwrap();
+/// let lines: Vec<String> = text.lines().map(String::from).collect();
+/// let context = PreviewContext { lines: &lines, wrap: true, ..Default::default() };
+/// let area = Rect::new(0, 0, 40, 10);
+/// let mut buf = Buffer::empty(area);
+/// MarkdownView::new(&preview, context, &theme).render(area, &mut buf);
+/// ```
+pub struct MarkdownView<'a> {
+    preview: &'a Preview,
+    context: PreviewContext<'a>,
+    theme: &'a Theme,
+}
 
+impl<'a> MarkdownView<'a> {
+    pub fn new(preview: &'a Preview, context: PreviewContext<'a>, theme: &'a Theme) -> Self {
+        Self {
+            preview,
+            context,
+            theme,
+        }
+    }
+}
+
+impl Widget for MarkdownView<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let Self {
+            preview,
+            context,
+            theme,
+        } = self;
+        // Code files show a highlighted + diff view
+        match &preview.code {
+            Some(code) => render_code(area, buf, code, &context, theme),
+            None => render_markdown(area, buf, preview, &context, theme),
+        }
+    }
+}
+
+fn render_markdown(
+    area: Rect,
+    buf: &mut Buffer,
+    preview: &Preview,
+    context: &PreviewContext,
+    theme: &Theme,
+) {
     // Split area vertically and render text + images + video placeholders
     let total_media = preview.images.len() + preview.videos.len();
     let mut rows = vec![Constraint::Min(
@@ -218,126 +467,101 @@ pub fn render_preview(f: &mut Frame, area: Rect, preview: &Preview) {
         .direction(Direction::Vertical)
         .constraints(rows)
         .split(area);
-    // Apply vertical scroll from env so caller can keep cursor in view
-    let scroll_top: u16 = std::env::var("SB_PREVIEW_SCROLL")
-        .ok()
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(0);
-    let paragraph = paragraph.scroll((scroll_top, 0));
-    f.render_widget(paragraph, chunks[0]);
+
+    // Text preview. Only lines that can reach the viewport are copied: each
+    // takes at least one row, so when wrapping nothing past scroll + height shows.
+    // Caller keeps the cursor in view through the scroll offset.
+    let height = chunks[0].height as usize;
+    let (skip, scroll_top) = if context.wrap {
+        (0, context.scroll.min(u16::MAX as usize) as u16)
+    } else {
+        (context.scroll, 0)
+    };
+    let visible: Vec<Line> = preview.text.lines[skip.min(preview.text.lines.len())..]
+        .iter()
+        .take(context.scroll - skip + height)
+        .cloned()
+        .collect();
+    let mut paragraph = Paragraph::new(visible)
+        .block(Block::default().title("Preview").borders(Borders::ALL))
+        .scroll((scroll_top, 0));
+    if context.wrap {
+        paragraph = paragraph.wrap(Wrap { trim: true });
+    }
+    // Dim the rendered output under the raw-line overlay
+    if context.overlay {
+        paragraph = paragraph.style(theme.dimmed);
+    }
+    paragraph.render(chunks[0], buf);
 
     // Always show a visible cursor line highlight in the preview's inner area
-    if let Some(cursor) = std::env::var("SB_PREVIEW_CURSOR")
-        .ok()
-        .and_then(|s| s.parse::<usize>().ok())
-    {
-        let inner_y = chunks[0].y.saturating_add(1);
-        let inner_x = chunks[0].x.saturating_add(1);
-        let inner_h = chunks[0].height.saturating_sub(2);
-        let inner_w = chunks[0].width.saturating_sub(2);
-        if inner_h > 0 && inner_w > 0 && cursor >= scroll_top as usize {
-            let rel = cursor - scroll_top as usize;
-            if (rel as u16) < inner_h {
-                let area = Rect {
-                    x: inner_x,
-                    y: inner_y + rel as u16,
-                    width: inner_w,
-                    height: 1,
-                };
-                let highlight = Paragraph::new("").style(Style::default().bg(Color::DarkGray));
-                f.render_widget(highlight, area);
-            }
+    let cursor = context.cursor;
+    let inner_y = chunks[0].y.saturating_add(1);
+    let inner_x = chunks[0].x.saturating_add(1);
+    let inner_h = chunks[0].height.saturating_sub(2);
+    let inner_w = chunks[0].width.saturating_sub(2);
+    if inner_h > 0 && inner_w > 0 && cursor >= context.scroll {
+        let rel = cursor - context.scroll;
+        if rel < inner_h as usize {
+            let area = Rect {
+                x: inner_x,
+                y: inner_y + rel as u16,
+                width: inner_w,
+                height: 1,
+            };
+            buf.set_style(area, theme.cursor_line);
         }
     }
 
     // If enabled, overlay a single raw line with a caret and gutter
-    if show_overlay {
-        if let (Ok(raw_line), Ok(cursor_str)) = (
-            std::env::var("SB_CURRENT_TEXT"),
-            std::env::var("SB_PREVIEW_CURSOR"),
-        ) {
-            if let Ok(cursor) = cursor_str.parse::<usize>() {
-                let mut lines_iter = raw_line.lines();
-                if let Some(raw0) = lines_iter.nth(cursor) {
-                    let raw = sanitize_line(raw0);
-                    // draw on top: a one-line block showing the raw markdown of the focused line
-                    let inner_y = chunks[0].y.saturating_add(1);
-                    let inner_x = chunks[0].x.saturating_add(1);
-                    let inner_h = chunks[0].height.saturating_sub(2);
-                    let inner_w = chunks[0].width.saturating_sub(2);
-                    // place relative to scroll
-                    let rel = cursor.saturating_sub(scroll_top as usize) as u16;
-                    if rel >= inner_h {
-                        return;
-                    }
-                    let y = inner_y + rel;
-                    // Gutter with line number
-                    let gutter_w: u16 = 6;
-                    if inner_w > gutter_w {
-                        let gutter_area = Rect {
-                            x: inner_x,
-                            y,
-                            width: gutter_w,
-                            height: 1,
-                        };
-                        let ln = format!("{:>4} ", cursor + 1);
-                        let gutter = Paragraph::new(ln)
-                            .style(Style::default().fg(Color::White).bg(Color::Blue));
-                        f.render_widget(gutter, gutter_area);
-                        // Raw line area beside gutter
-                        let raw_area = Rect {
-                            x: gutter_area.x + gutter_area.width,
-                            y,
-                            width: inner_w.saturating_sub(gutter_w),
-                            height: 1,
-                        };
-                        let col = std::env::var("SB_PREVIEW_COL")
-                            .ok()
-                            .and_then(|s| s.parse::<usize>().ok())
-                            .unwrap_or(0);
-                        // Split raw into left of col, cursor char, and right for a visual caret block
-                        let chars: Vec<char> = raw.chars().collect();
-                        let col_idx = col.min(chars.len());
-                        let (left, cur, right) = (
-                            chars[..col_idx].iter().collect::<String>(),
-                            chars.get(col_idx).cloned(),
-                            if col_idx < chars.len() {
-                                chars[col_idx + 1..].iter().collect::<String>()
-                            } else {
-                                String::new()
-                            },
-                        );
-                        let mut spans = vec![Span::styled(
-                            left,
-                            Style::default().fg(Color::Black).bg(Color::Yellow),
-                        )];
-                        // Draw caret block on the current character or a block if at EOL
-                        if let Some(c) = cur {
-                            spans.push(Span::styled(
-                                c.to_string(),
-                                Style::default()
-                                    .fg(Color::Black)
-                                    .bg(Color::White)
-                                    .add_modifier(Modifier::BOLD),
-                            ));
-                        } else {
-                            spans.push(Span::styled(
-                                " ",
-                                Style::default()
-                                    .fg(Color::Black)
-                                    .bg(Color::White)
-                                    .add_modifier(Modifier::BOLD),
-                            ));
-                        }
-                        spans.push(Span::styled(
-                            right,
-                            Style::default().fg(Color::Black).bg(Color::Yellow),
-                        ));
-                        let styled = Line::from(spans);
-                        let para = Paragraph::new(styled).wrap(Wrap { trim: true });
-                        f.render_widget(para, raw_area);
-                    }
-                }
+    if context.overlay {
+        if let Some(raw0) = context.lines.get(cursor) {
+            let raw = sanitize_line(raw0);
+            // draw on top: a one-line block showing the raw markdown of the focused line
+            // place relative to scroll
+            let rel = cursor.saturating_sub(context.scroll);
+            // Gutter with line number
+            let gutter_w: u16 = 6;
+            if rel < inner_h as usize && inner_w > gutter_w {
+                let y = inner_y + rel as u16;
+                let gutter_area = Rect {
+                    x: inner_x,
+                    y,
+                    width: gutter_w,
+                    height: 1,
+                };
+                let ln = format!("{:>4} ", cursor + 1);
+                Paragraph::new(ln)
+                    .style(theme.edit_gutter)
+                    .render(gutter_area, buf);
+                // Raw line area beside gutter
+                let raw_area = Rect {
+                    x: gutter_area.x + gutter_area.width,
+                    y,
+                    width: inner_w.saturating_sub(gutter_w),
+                    height: 1,
+                };
+                // Split raw into left of col, cursor char, and right for a visual caret block
+                let chars: Vec<char> = raw.chars().collect();
+                let col_idx = context.col.min(chars.len());
+                let (left, cur, right) = (
+                    chars[..col_idx].iter().collect::<String>(),
+                    chars.get(col_idx).cloned(),
+                    if col_idx < chars.len() {
+                        chars[col_idx + 1..].iter().collect::<String>()
+                    } else {
+                        String::new()
+                    },
+                );
+                let mut spans = vec![Span::styled(left, theme.edit_line)];
+                // Draw caret block on the current character or a block if at EOL
+                let caret = cur.map(String::from).unwrap_or_else(|| " ".into());
+                spans.push(Span::styled(caret, theme.edit_caret));
+                spans.push(Span::styled(right, theme.edit_line));
+                let styled = Line::from(spans);
+                Paragraph::new(styled)
+                    .wrap(Wrap { trim: true })
+                    .render(raw_area, buf);
             }
         }
     }
@@ -350,46 +574,31 @@ pub fn render_preview(f: &mut Frame, area: Rect, preview: &Preview) {
 
     let mut idx = 1;
     for path in preview.images.iter() {
-        if let Ok(reader) = ImageReader::open(path) {
-            if let Ok(img) = reader.decode() {
-                let picker = Picker::from_fontsize((8, 12));
-                let mut state = picker.new_resize_protocol(img);
+        match preview.decoded.get(path) {
+            Some(Ok(state)) => {
                 let widget = StatefulImage::new().resize(Resize::Fit(None));
-                f.render_stateful_widget(widget, chunks[idx], &mut state);
-            } else {
-                let line = Line::from(format!("(image decode failed) {}", path.display()));
-                let p = Paragraph::new(line);
-                f.render_widget(p, chunks[idx]);
+                widget.render(chunks[idx], buf, &mut *state.borrow_mut());
             }
-        } else {
-            let line = Line::from(format!("(image load failed) {}", path.display()));
-            let p = Paragraph::new(line);
-            f.render_widget(p, chunks[idx]);
+            Some(Err(message)) => Paragraph::new(message.as_str()).render(chunks[idx], buf),
+            None => {}
         }
         idx += 1;
     }
     for path in preview.videos.iter() {
         let line = Line::from(format!("video: {}", path.display()));
-        let p = Paragraph::new(line);
-        f.render_widget(p, chunks[idx]);
+        Paragraph::new(line).render(chunks[idx], buf);
         idx += 1;
     }
 }
 
-static SYNTAX_SET: Lazy<SyntaxSet> = Lazy::new(SyntaxSet::load_defaults_newlines);
-static THEME_SET: Lazy<ThemeSet> = Lazy::new(ThemeSet::load_defaults);
-
-fn try_render_code_preview(f: &mut Frame, area: Rect) -> Option<()> {
-    // We need the current file path and buffer; pull from global if exposed
-    // Since we don't have direct access to `App` here, detect via environment variables
-    // Simplify: read env SB_CURRENT_FILE and SB_CURRENT_TEXT set by caller
-    let path = std::env::var("SB_CURRENT_FILE").ok()?;
-    let ext = std::path::Path::new(&path)
+/// Extensions shown as highlighted code rather than rendered markdown
+pub fn is_code_file(path: &Path) -> bool {
+    let ext = path
         .extension()
         .and_then(|e| e.to_str())
         .unwrap_or("")
         .to_lowercase();
-    let is_code = matches!(
+    matches!(
         ext.as_str(),
         "rs" | "ts"
             | "tsx"
@@ -424,285 +633,227 @@ fn try_render_code_preview(f: &mut Frame, area: Rect) -> Option<()> {
             | "sass"
             | "sql"
             | "tex"
-    );
-    if !is_code {
-        return None;
+    )
+}
+
+/// One row of the code view: a line of the buffer, or a line deleted since HEAD
+#[derive(Debug, Clone, Copy)]
+struct CodeRow {
+    tag: ChangeTag,
+    /// Line number shown in the gutter
+    number: usize,
+    /// Index into the HEAD lines for deletions, the buffer lines otherwise
+    index: usize,
+}
+
+/// Highlighted code with its diff against HEAD
+///
+/// Built once per buffer revision; passing the previous view to
+/// [`CodeView::new`] re-highlights only the lines an edit touched.
+pub struct CodeView {
+    path: PathBuf,
+    syntax: String,
+    /// HEAD version, when the file is tracked
+    original: Option<Highlighted>,
+    current: Highlighted,
+    rows: Vec<CodeRow>,
+}
+
+impl CodeView {
+    pub fn new(path: &Path, text: &str, theme: &Theme, previous: Option<CodeView>) -> Self {
+        let mut view = match previous {
+            Some(view) if view.path == path && view.syntax == theme.syntax => view,
+            _ => Self::load(path, theme),
+        };
+        view.current.update(text);
+        view.rows = match &view.original {
+            Some(original) => diff_rows(&original.text(), text),
+            None => (0..view.current.len())
+                .map(|index| CodeRow {
+                    tag: ChangeTag::Equal,
+                    number: index + 1,
+                    index,
+                })
+                .collect(),
+        };
+        view
+    }
+
+    fn load(path: &Path, theme: &Theme) -> Self {
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        // Checked when the theme is loaded
+        let syntax_theme = theme
+            .syntax_theme()
+            .unwrap_or_else(|| &THEME_SET.themes["base16-ocean.dark"]);
+        let original = head_version(path).map(|text| {
+            let mut original = Highlighted::for_extension(&ext, syntax_theme);
+            original.update(&text);
+            original
+        });
+        Self {
+            path: path.to_path_buf(),
+            syntax: theme.syntax.clone(),
+            original,
+            current: Highlighted::for_extension(&ext, syntax_theme),
+            rows: Vec::new(),
+        }
+    }
+}
+
+fn diff_rows(original: &str, text: &str) -> Vec<CodeRow> {
+    let diff = TextDiff::from_lines(original, text);
+    let mut number = 1;
+    let mut rows = Vec::new();
+    for change in diff.iter_all_changes() {
+        let tag = change.tag();
+        let index = match tag {
+            ChangeTag::Delete => change.old_index(),
+            ChangeTag::Insert | ChangeTag::Equal => change.new_index(),
+        };
+        rows.push(CodeRow {
+            tag,
+            number,
+            index: index.unwrap_or(0),
+        });
+        if tag != ChangeTag::Delete {
+            number += 1;
+        }
     }
-    let text = std::env::var("SB_CURRENT_TEXT").ok().unwrap_or_default();
+    rows
+}
 
-    // Get git diff if available
-    let rel_for_git = Command::new("git")
+/// The file as committed at HEAD, if it is in a git repository
+fn head_version(path: &Path) -> Option<String> {
+    let root = Command::new("git")
         .arg("rev-parse")
         .arg("--show-toplevel")
-        .current_dir(
-            std::path::Path::new(&path)
-                .parent()
-                .unwrap_or(std::path::Path::new(".")),
-        )
+        .current_dir(path.parent().unwrap_or(Path::new(".")))
         .output()
         .ok()
-        .and_then(|o| {
-            if o.status.success() {
-                Some(String::from_utf8_lossy(&o.stdout).trim().to_string())
-            } else {
-                None
-            }
-        });
-    let spec = if let Some(root) = rel_for_git {
-        let rel = diff_paths(&path, root).unwrap_or_else(|| std::path::PathBuf::from(&path));
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string());
+    let spec = if let Some(root) = root {
+        let rel = diff_paths(path, root).unwrap_or_else(|| path.to_path_buf());
         format!("HEAD:{}", rel.to_string_lossy())
     } else {
-        format!("HEAD:{path}")
+        format!("HEAD:{}", path.display())
     };
-
-    // Get original content from git if available
-    let original = Command::new("git")
+    Command::new("git")
         .args(["show", &spec])
+        .current_dir(path.parent().unwrap_or(Path::new(".")))
         .output()
         .ok()
         .filter(|o| o.status.success())
-        .map(|o| String::from_utf8_lossy(&o.stdout).to_string());
-
-    // Prepare syntax highlighting
-    let syntax = SYNTAX_SET
-        .find_syntax_by_extension(&ext)
-        .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text());
-    let theme_opt = THEME_SET
-        .themes
-        .get("base16-ocean.dark")
-        .or_else(|| THEME_SET.themes.get("Solarized (dark)"))
-        .or_else(|| THEME_SET.themes.get("Monokai"))
-        .or_else(|| THEME_SET.themes.get("InspiredGitHub"))
-        .or_else(|| THEME_SET.themes.values().next());
-
-    let mut lines: Vec<Line> = Vec::new();
-    let has_diff = original.is_some();
-
-    // Get cursor position for highlighting
-    let cursor_line = std::env::var("SB_PREVIEW_CURSOR")
-        .ok()
-        .and_then(|s| s.parse::<usize>().ok())
-        .unwrap_or(0);
-
-    // If we have a diff, show unified inline diff with syntax highlighting
-    if let Some(orig) = original {
-        let diff = TextDiff::from_lines(&orig, &text);
-        let mut line_num = 1;
-
-        if let Some(theme) = theme_opt {
-            let mut h = HighlightLines::new(syntax, theme);
-
-            for change in diff.iter_all_changes() {
-                let line_content = change.to_string_lossy();
-                let line_trimmed = line_content.trim_end_matches('\n');
-
-                // Get syntax highlighting for the line content
-                let regions = h
-                    .highlight_line(line_trimmed, &SYNTAX_SET)
-                    .unwrap_or_default();
-                let mut spans: Vec<Span> = Vec::new();
-
-                // Check if this is the cursor line (only for non-deleted lines)
-                let is_cursor_line = match change.tag() {
-                    ChangeTag::Delete => false, // Deleted lines don't count for cursor position
-                    ChangeTag::Insert | ChangeTag::Equal => line_num - 1 == cursor_line,
-                };
+        .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
+}
 
-                // Add diff marker and line number
-                match change.tag() {
-                    ChangeTag::Delete => {
-                        let prefix_style =
-                            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD);
-                        spans.push(Span::styled(format!("{line_num:4} - "), prefix_style));
-                        // Apply syntax highlighting with red tint
-                        for (style, segment) in regions {
-                            let fg = style.foreground;
-                            spans.push(Span::styled(
-                                segment.to_string(),
-                                Style::default()
-                                    .fg(Color::Rgb(
-                                        fg.r.saturating_sub(50),
-                                        fg.g.saturating_sub(100),
-                                        fg.b.saturating_sub(100),
-                                    ))
-                                    .add_modifier(Modifier::DIM),
-                            ));
-                        }
-                    }
-                    ChangeTag::Insert => {
-                        let prefix_style = if is_cursor_line {
-                            Style::default()
-                                .fg(Color::Green)
-                                .bg(Color::DarkGray)
-                                .add_modifier(Modifier::BOLD)
-                        } else {
-                            Style::default()
-                                .fg(Color::Green)
-                                .add_modifier(Modifier::BOLD)
-                        };
-                        spans.push(Span::styled(format!("{line_num:4} + "), prefix_style));
-                        // Apply syntax highlighting with green tint and cursor highlight
-                        for (style, segment) in regions {
-                            let fg = style.foreground;
-                            let segment_style = if is_cursor_line {
-                                Style::default()
-                                    .fg(Color::Rgb(
-                                        fg.r.saturating_sub(100),
-                                        fg.g,
-                                        fg.b.saturating_sub(100),
-                                    ))
-                                    .bg(Color::DarkGray)
-                            } else {
-                                Style::default().fg(Color::Rgb(
-                                    fg.r.saturating_sub(100),
-                                    fg.g,
-                                    fg.b.saturating_sub(100),
-                                ))
-                            };
-                            spans.push(Span::styled(segment.to_string(), segment_style));
-                        }
-                        line_num += 1;
-                    }
-                    ChangeTag::Equal => {
-                        let prefix_style = if is_cursor_line {
-                            Style::default().fg(Color::DarkGray).bg(Color::DarkGray)
-                        } else {
-                            Style::default().fg(Color::DarkGray)
-                        };
-                        spans.push(Span::styled(format!("{line_num:4}   "), prefix_style));
-                        // Normal syntax highlighting with cursor highlight
-                        for (style, segment) in regions {
-                            let fg = style.foreground;
-                            let segment_style = if is_cursor_line {
-                                Style::default()
-                                    .fg(Color::Rgb(fg.r, fg.g, fg.b))
-                                    .bg(Color::DarkGray)
-                            } else {
-                                Style::default().fg(Color::Rgb(fg.r, fg.g, fg.b))
-                            };
-                            spans.push(Span::styled(segment.to_string(), segment_style));
-                        }
-                        line_num += 1;
-                    }
-                }
-                lines.push(Line::from(spans));
-            }
+fn tint(
+    spans: &[Span<'static>],
+    f: impl Fn(u8, u8, u8) -> Color,
+    extra: Style,
+) -> Vec<Span<'static>> {
+    spans
+        .iter()
+        .map(|span| {
+            let style = match span.style.fg {
+                Some(Color::Rgb(r, g, b)) => span.style.fg(f(r, g, b)),
+                _ => span.style,
+            };
+            Span::styled(span.content.clone(), style.patch(extra))
+        })
+        .collect()
+}
+
+fn render_code(
+    area: Rect,
+    buf: &mut Buffer,
+    code: &CodeView,
+    context: &PreviewContext,
+    theme: &Theme,
+) {
+    let cursor_line = context.cursor;
+    let height = area.height.saturating_sub(2) as usize;
+    // Only the visible rows are styled
+    let mut lines: Vec<Line> = Vec::with_capacity(height);
+    for row in code.rows.iter().skip(context.scroll).take(height) {
+        let is_cursor_line = row.tag != ChangeTag::Delete && row.index == cursor_line;
+        let cursor = if is_cursor_line {
+            theme.cursor_line
         } else {
-            // Fallback without syntax highlighting
-            for change in diff.iter_all_changes() {
-                let (prefix, color) = match change.tag() {
-                    ChangeTag::Delete => (format!("{line_num:4} - "), Color::Red),
-                    ChangeTag::Insert => {
-                        let p = format!("{line_num:4} + ");
-                        line_num += 1;
-                        (p, Color::Green)
-                    }
-                    ChangeTag::Equal => {
-                        let p = format!("{line_num:4}   ");
-                        line_num += 1;
-                        (p, Color::Gray)
-                    }
-                };
-                let content = change.to_string_lossy();
-                lines.push(Line::from(vec![
-                    Span::styled(prefix, Style::default().fg(color)),
-                    Span::styled(content.to_string(), Style::default().fg(color)),
-                ]));
+            Style::default()
+        };
+        let number = row.number;
+        let spans = match row.tag {
+            ChangeTag::Delete => {
+                let original = code.original.as_ref().map(|o| o.line(row.index));
+                let mut spans = vec![Span::styled(format!("{number:4} - "), theme.diff_removed)];
+                // Syntax highlighting with a red tint
+                spans.extend(tint(
+                    original.unwrap_or(&[]),
+                    |r, g, b| {
+                        Color::Rgb(
+                            r.saturating_sub(50),
+                            g.saturating_sub(100),
+                            b.saturating_sub(100),
+                        )
+                    },
+                    Style::default().add_modifier(Modifier::DIM),
+                ));
+                spans
             }
-        }
-    } else {
-        // No diff available, just show syntax highlighted code with line numbers
-        if let Some(theme) = theme_opt {
-            let mut h = HighlightLines::new(syntax, theme);
-            let mut line_num = 1;
-            for raw in text.lines() {
-                let is_cursor_line = (line_num - 1) == cursor_line;
-                let regions = h.highlight_line(raw, &SYNTAX_SET).unwrap_or_default();
-                let mut spans: Vec<Span> = Vec::new();
-
-                let prefix_style = if is_cursor_line {
-                    Style::default().fg(Color::White).bg(Color::DarkGray)
-                } else {
-                    Style::default().fg(Color::DarkGray)
-                };
-                spans.push(Span::styled(format!("{line_num:4}   "), prefix_style));
-
-                for (style, segment) in regions {
-                    let fg = style.foreground;
-                    let segment_style = if is_cursor_line {
-                        Style::default()
-                            .fg(Color::Rgb(fg.r, fg.g, fg.b))
-                            .bg(Color::DarkGray)
-                    } else {
-                        Style::default().fg(Color::Rgb(fg.r, fg.g, fg.b))
-                    };
-                    spans.push(Span::styled(segment.to_string(), segment_style));
-                }
-                lines.push(Line::from(spans));
-                line_num += 1;
+            ChangeTag::Insert => {
+                let mut spans = vec![Span::styled(
+                    format!("{number:4} + "),
+                    theme.diff_added.patch(cursor),
+                )];
+                // Syntax highlighting with a green tint
+                spans.extend(tint(
+                    code.current.line(row.index),
+                    |r, g, b| Color::Rgb(r.saturating_sub(100), g, b.saturating_sub(100)),
+                    cursor,
+                ));
+                spans
             }
-        } else {
-            // Fallback: plain text with line numbers
-            let mut line_num = 1;
-            for raw in text.lines() {
-                let is_cursor_line = (line_num - 1) == cursor_line;
+            ChangeTag::Equal => {
                 let prefix_style = if is_cursor_line {
-                    Style::default().fg(Color::White).bg(Color::DarkGray)
+                    theme.line_number_current
                 } else {
-                    Style::default().fg(Color::DarkGray)
+                    theme.line_number
                 };
-                let text_style = if is_cursor_line {
-                    Style::default().bg(Color::DarkGray)
-                } else {
-                    Style::default()
-                };
-
-                lines.push(Line::from(vec![
-                    Span::styled(format!("{line_num:4}   "), prefix_style),
-                    Span::styled(raw.to_string(), text_style),
-                ]));
-                line_num += 1;
+                let mut spans = vec![Span::styled(format!("{number:4}   "), prefix_style)];
+                spans.extend(tint(code.current.line(row.index), Color::Rgb, cursor));
+                spans
             }
-        }
+        };
+        lines.push(Line::from(spans));
     }
 
     // Render as a single unified view
-    let total_lines = text.lines().count();
-    let title = if has_diff {
+    let total_lines = code.current.len();
+    let name = code
+        .path
+        .file_name()
+        .map(|n| n.to_string_lossy())
+        .unwrap_or_else(|| code.path.to_string_lossy());
+    let title = if code.original.is_some() {
         format!(
             "Code (Diff vs HEAD) - {} (Line {}/{})",
-            std::path::Path::new(&path)
-                .file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or(&path),
+            name,
             cursor_line + 1,
             total_lines
         )
     } else {
-        format!(
-            "Code - {} (Line {}/{})",
-            std::path::Path::new(&path)
-                .file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or(&path),
-            cursor_line + 1,
-            total_lines
-        )
+        format!("Code - {} (Line {}/{})", name, cursor_line + 1, total_lines)
     };
 
-    let para = Paragraph::new(Text::from(lines))
+    Paragraph::new(Text::from(lines))
         .wrap(Wrap { trim: false })
         .block(Block::default().title(title).borders(Borders::ALL))
-        .scroll((
-            std::env::var("SB_PREVIEW_SCROLL")
-                .ok()
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(0),
-            0,
-        ));
-    f.render_widget(para, area);
-
-    Some(())
+        .render(area, buf);
 }
 
 pub fn find_md_videos(src: &str) -> Vec<(String, String)> {
@@ -748,3 +899,105 @@ pub fn line_rect(area: Rect, line_idx: usize, total_lines: usize) -> Option<Rect
         height: 1,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(path: &str, text: &str, context: PreviewContext) -> Buffer {
+        let theme = Theme::default();
+        let preview = Preview::from_markdown(Path::new(path), text, &theme, None).unwrap();
+        let area = Rect::new(0, 0, 30, 6);
+        let mut buf = Buffer::empty(area);
+        MarkdownView::new(&preview, context, &theme).render(area, &mut buf);
+        buf
+    }
+
+    fn row(buf: &Buffer, y: u16) -> String {
+        (0..buf.area.width).map(|x| buf[(x, y)].symbol()).collect()
+    }
+
+    #[test]
+    fn test_markdown_view_draws_from_context() {
+        let text = "# Title\nbody **bold**";
+        let theme = Theme::default();
+        let lines: Vec<String> = text.lines().map(String::from).collect();
+        let context = PreviewContext {
+            lines: &lines,
+            cursor: 0,
+            ..Default::default()
+        };
+        let buf = render("notes.md", text, context);
+        assert!(row(&buf, 1).contains("Title"));
+        assert!(!row(&buf, 1).contains('#'));
+        assert_eq!(buf[(1, 1)].bg, theme.cursor_line.bg.unwrap());
+
+        // Inline editing shows the raw line with its number
+        let context = PreviewContext {
+            overlay: true,
+            ..context
+        };
+        let buf = render("notes.md", text, context);
+        assert!(row(&buf, 1).contains("1  # Title"));
+    }
+
+    #[test]
+    fn test_cache_rebuilds_once_per_revision() {
+        let theme = Theme::default();
+        let path = Path::new("main.rs");
+        let mut cache = PreviewCache::default();
+        let mut builds = 0;
+        let mut get = |cache: &mut PreviewCache, revision, text: &str| {
+            let preview = cache.get_or_update(path, revision, false, |previous| {
+                builds += 1;
+                Preview::from_code(path, text, &theme, previous)
+            });
+            preview.code.as_ref().map(|c| c.current.len())
+        };
+        assert_eq!(get(&mut cache, 1, "fn main() {}"), Some(1));
+        assert_eq!(get(&mut cache, 1, "ignored"), Some(1));
+        assert_eq!(get(&mut cache, 2, "fn main() {\n}"), Some(2));
+        assert_eq!(builds, 2);
+
+        // Code files draw numbered, highlighted lines
+        let preview = cache.get_or_update(path, 2, false, |_| unreachable!());
+        let area = Rect::new(0, 0, 30, 5);
+        let mut buf = Buffer::empty(area);
+        let context = PreviewContext::default();
+        MarkdownView::new(preview, context, &theme).render(area, &mut buf);
+        assert!(row(&buf, 1).contains("1   fn main() {"));
+        assert!(row(&buf, 2).contains("2   }"));
+    }
+
+    #[test]
+    fn test_markdown_blocks_render_as_the_whole_document() {
+        let theme = Theme::default();
+        let doc = "# Title\n\nPara one\nline two\n\n\n- a\n\n- b\n  more\n\n\
+                   ```rust\nfn x() {}\n\nlet y;\n```\n\n> quote\n\n##Sub\ntext\n\n\
+                   1. one\n\n    indented\n\nend\n";
+        assert_eq!(
+            split_blocks(doc),
+            [
+                "# Title",
+                "Para one\nline two",
+                "- a\n\n- b\n  more",
+                "```rust\nfn x() {}\n\nlet y;\n```",
+                "> quote",
+                "##Sub\ntext",
+                "1. one\n\n    indented",
+                "end",
+            ]
+        );
+        let preview = Preview::from_markdown(Path::new("a.md"), doc, &theme, None).unwrap();
+        assert_eq!(preview.text.lines, render_block(doc, &theme));
+
+        // Editing one block gives the same result as rendering from scratch
+        let edited = doc.replace("Para one", "Para 1");
+        let updated =
+            Preview::from_markdown(Path::new("a.md"), &edited, &theme, Some(preview)).unwrap();
+        assert_eq!(updated.text.lines, render_block(&edited, &theme));
+
+        // Reference links need the whole document
+        assert_eq!(split_blocks("[a][x]\n\n[x]: https://example.com").len(), 1);
+    }
+}
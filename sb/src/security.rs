@@ -98,14 +98,22 @@ pub fn validate_path(path: &Path, base_dir: &Path) -> Result<PathBuf, SecurityEr
     }
 }
 
-/// Checks if a file size is within allowed limits
+/// Checks if a file size is within the default limit, [`MAX_FILE_SIZE`]
 ///
 /// Returns the file size if it's within limits, or an error if too large.
 pub fn check_file_size(path: &Path) -> Result<u64, SecurityError> {
+    check_file_size_within(path, MAX_FILE_SIZE)
+}
+
+/// Checks if a file size is within `max_size` bytes
+///
+/// Like [`check_file_size`], for a limit taken from the configuration
+/// (`security.max_file_size`).
+pub fn check_file_size_within(path: &Path, max_size: u64) -> Result<u64, SecurityError> {
     let metadata = fs::metadata(path)?;
     let size = metadata.len();
 
-    if size > MAX_FILE_SIZE {
+    if size > max_size {
         Err(SecurityError::FileTooLarge(size))
     } else {
         Ok(size)
@@ -0,0 +1,417 @@
+//! Colour themes
+//!
+//! A theme gives a style to each part of the UI (headings, links, code, diff
+//! lines, git states, overlays) and names the syntect theme used for code.
+//! `dark` and `light` are built in (see `themes/`); more can be added as
+//! `~/.config/sb/themes/<name>.toml`. `auto` picks dark or light from the
+//! terminal's background colour.
+
+use std::collections::BTreeMap;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use ratatui::style::{Color, Modifier, Style};
+use serde::Deserialize;
+use syntect::highlighting::ThemeSet;
+
+use crate::config::{self, ConfigError};
+use crate::git::FileStatus;
+
+/// syntect themes available to the `syntax` key
+pub static THEME_SET: Lazy<ThemeSet> = Lazy::new(ThemeSet::load_defaults);
+
+const BUILT_IN: &[(&str, &str)] = &[
+    ("dark", include_str!("themes/dark.toml")),
+    ("light", include_str!("themes/light.toml")),
+];
+
+macro_rules! styles {
+    ($($field:ident => $key:literal,)*) => {
+        /// Resolved styles of one theme
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct Theme {
+            pub name: String,
+            /// Name of the syntect theme in [`THEME_SET`]
+            pub syntax: String,
+            $(pub $field: Style,)*
+        }
+
+        impl Theme {
+            fn empty(name: &str) -> Self {
+                Self {
+                    name: name.to_string(),
+                    syntax: String::new(),
+                    $($field: Style::default(),)*
+                }
+            }
+
+            fn style_mut(&mut self, key: &str) -> Option<&mut Style> {
+                match key {
+                    $($key => Some(&mut self.$field),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+styles! {
+    heading1 => "heading.1",
+    heading2 => "heading.2",
+    heading3 => "heading.3",
+    heading4 => "heading.4",
+    link => "link",
+    code => "code",
+    quote => "quote",
+    text => "text",
+    dimmed => "dimmed",
+    cursor_line => "cursor_line",
+    line_number => "line_number",
+    line_number_current => "line_number.current",
+    edit_gutter => "edit.gutter",
+    edit_line => "edit.line",
+    edit_caret => "edit.caret",
+    diff_added => "diff.added",
+    diff_removed => "diff.removed",
+    diff_context => "diff.context",
+    git_modified => "git.modified",
+    git_added => "git.added",
+    git_deleted => "git.deleted",
+    git_untracked => "git.untracked",
+    git_conflicted => "git.conflicted",
+    border => "border",
+    border_focused => "border.focused",
+    border_editor => "border.editor",
+    tree_directory => "tree.directory",
+    selection => "selection",
+    selection_mark => "selection.mark",
+    selection_cursor => "selection.cursor",
+    selection_cursor_marked => "selection.cursor_marked",
+    overlay => "overlay",
+    overlay_border => "overlay.border",
+    prompt_border => "prompt.border",
+    warning => "warning",
+    error => "error",
+    button_confirm => "button.confirm",
+    button_cancel => "button.cancel",
+    status_bar => "status_bar",
+    status_bar_hint => "status_bar.hint",
+}
+
+/// A theme file as written on disk
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeFile {
+    extends: Option<String>,
+    syntax: Option<String>,
+    #[serde(default)]
+    styles: BTreeMap<String, StyleSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StyleSpec {
+    fg: Option<String>,
+    bg: Option<String>,
+    #[serde(default)]
+    modifiers: Vec<String>,
+}
+
+impl StyleSpec {
+    fn to_style(&self) -> Result<Style, String> {
+        let color = |c: &str| Color::from_str(c).map_err(|_| format!("unknown colour `{}`", c));
+        let mut style = Style::default();
+        if let Some(fg) = &self.fg {
+            style = style.fg(color(fg)?);
+        }
+        if let Some(bg) = &self.bg {
+            style = style.bg(color(bg)?);
+        }
+        for m in &self.modifiers {
+            style = style.add_modifier(match m.as_str() {
+                "bold" => Modifier::BOLD,
+                "dim" => Modifier::DIM,
+                "italic" => Modifier::ITALIC,
+                "underlined" => Modifier::UNDERLINED,
+                "reversed" => Modifier::REVERSED,
+                "crossed_out" => Modifier::CROSSED_OUT,
+                other => return Err(format!("unknown modifier `{}`", other)),
+            });
+        }
+        Ok(style)
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::built_in("dark").expect("built-in dark theme")
+    }
+}
+
+impl Theme {
+    /// A theme by name: a user theme file, a built-in theme, or `auto`
+    pub fn load(name: &str) -> Result<Self, ConfigError> {
+        let name = match name {
+            "auto" => match detect_background() {
+                Some(Background::Light) => "light",
+                _ => "dark",
+            },
+            name => name,
+        };
+        if let Some(path) = user_theme_path(name).filter(|p| p.exists()) {
+            let text = std::fs::read_to_string(&path).map_err(|source| ConfigError::Read {
+                path: path.clone(),
+                source,
+            })?;
+            return Self::parse(name, &text, &path);
+        }
+        Self::built_in(name).ok_or_else(|| ConfigError::Parse {
+            path: user_theme_path(name).unwrap_or_else(|| PathBuf::from(name)),
+            message: format!(
+                "no theme named `{}` (built in: {})",
+                name,
+                BUILT_IN
+                    .iter()
+                    .map(|(n, _)| *n)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        })
+    }
+
+    fn built_in(name: &str) -> Option<Self> {
+        let (name, text) = BUILT_IN.iter().find(|(n, _)| *n == name)?;
+        let theme = Self::parse(name, text, Path::new(name)).expect("built-in theme is valid");
+        Some(theme)
+    }
+
+    fn parse(name: &str, text: &str, path: &Path) -> Result<Self, ConfigError> {
+        let error = |message: String| ConfigError::Parse {
+            path: path.to_path_buf(),
+            message,
+        };
+        let file: ThemeFile = toml::from_str(text).map_err(|e| error(e.to_string()))?;
+        let mut theme = match file.extends.as_deref() {
+            Some(base) => Self::built_in(base).ok_or_else(|| {
+                error(format!(
+                    "`extends` must be a built-in theme, not `{}`",
+                    base
+                ))
+            })?,
+            None if BUILT_IN.iter().any(|(n, _)| *n == name) => Self::empty(name),
+            None => Self::default(),
+        };
+        theme.name = name.to_string();
+        if let Some(syntax) = file.syntax {
+            if !THEME_SET.themes.contains_key(&syntax) {
+                let known: Vec<&str> = THEME_SET.themes.keys().map(|k| k.as_str()).collect();
+                return Err(error(format!(
+                    "unknown syntax theme `{}` (available: {})",
+                    syntax,
+                    known.join(", ")
+                )));
+            }
+            theme.syntax = syntax;
+        }
+        for (key, spec) in &file.styles {
+            let style = spec
+                .to_style()
+                .map_err(|message| error(format!("styles.\"{}\": {}", key, message)))?;
+            *theme
+                .style_mut(key)
+                .ok_or_else(|| error(format!("unknown style `{}`", key)))? = style;
+        }
+        Ok(theme)
+    }
+
+    /// Style for a markdown heading of `level` (1-6)
+    pub fn heading(&self, level: usize) -> Style {
+        match level {
+            1 => self.heading1,
+            2 => self.heading2,
+            3 => self.heading3,
+            _ => self.heading4,
+        }
+    }
+
+    /// Style for a file's git state
+    pub fn git(&self, status: FileStatus) -> Style {
+        match status {
+            FileStatus::Modified | FileStatus::Renamed | FileStatus::Copied => self.git_modified,
+            FileStatus::Added => self.git_added,
+            FileStatus::Deleted => self.git_deleted,
+            FileStatus::Untracked => self.git_untracked,
+            FileStatus::Conflicted => self.git_conflicted,
+            FileStatus::Unmodified | FileStatus::Ignored => self.text,
+        }
+    }
+
+    /// The syntect theme for code previews
+    pub fn syntax_theme(&self) -> Option<&'static syntect::highlighting::Theme> {
+        THEME_SET.themes.get(&self.syntax)
+    }
+}
+
+fn user_theme_path(name: &str) -> Option<PathBuf> {
+    // Names only; no paths out of the themes directory
+    if name.is_empty() || name.contains(['/', '\\', '.']) {
+        return None;
+    }
+    config::config_dir().map(|dir| dir.join("themes").join(format!("{}.toml", name)))
+}
+
+/// Brightness of the terminal background
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Background {
+    Dark,
+    Light,
+}
+
+/// Ask the terminal for its background colour (OSC 11), falling back to
+/// `$COLORFGBG`. Call before the UI takes over the terminal.
+pub fn detect_background() -> Option<Background> {
+    query_background().or_else(|| {
+        std::env::var("COLORFGBG")
+            .ok()
+            .and_then(|v| parse_colorfgbg(&v))
+    })
+}
+
+fn query_background() -> Option<Background> {
+    use ratatui::crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        return None;
+    }
+    enable_raw_mode().ok()?;
+    // OSC 11 asks for the background; the status report (CSI 5n) that follows
+    // is answered by every terminal, so the reader always finishes
+    let mut stdout = std::io::stdout();
+    let sent = stdout
+        .write_all(b"\x1b]11;?\x1b\\\x1b[5n")
+        .and_then(|_| stdout.flush());
+    let reply = sent
+        .ok()
+        .and_then(|_| read_reply(Duration::from_millis(200)));
+    let _ = disable_raw_mode();
+    parse_background_reply(&String::from_utf8_lossy(&reply?))
+}
+
+/// Read the terminal's answer up to the status report, giving up after
+/// `timeout`; nothing is left reading stdin once this returns
+#[cfg(unix)]
+fn read_reply(timeout: Duration) -> Option<Vec<u8>> {
+    let deadline = std::time::Instant::now() + timeout;
+    let mut reply = Vec::new();
+    while !reply.ends_with(b"\x1b[0n") {
+        let left = deadline.saturating_duration_since(std::time::Instant::now());
+        let mut fd = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut fd, 1, left.as_millis() as libc::c_int) } <= 0 {
+            return None;
+        }
+        // One byte at a time, so keys typed after the reply stay for the UI
+        let mut byte = 0u8;
+        if unsafe { libc::read(libc::STDIN_FILENO, (&mut byte as *mut u8).cast(), 1) } != 1 {
+            return None;
+        }
+        reply.push(byte);
+    }
+    Some(reply)
+}
+
+#[cfg(not(unix))]
+fn read_reply(_timeout: Duration) -> Option<Vec<u8>> {
+    None
+}
+
+/// `ESC ] 11 ; rgb:RRRR/GGGG/BBBB` (terminated by BEL or ST)
+fn parse_background_reply(reply: &str) -> Option<Background> {
+    let rgb = &reply[reply.find("]11;rgb:")? + 8..];
+    let end = rgb.find(['\x07', '\x1b']).unwrap_or(rgb.len());
+    let channels: Vec<f64> = rgb[..end]
+        .split('/')
+        .map(|hex| {
+            let max = 16f64.powi(hex.len() as i32) - 1.0;
+            u32::from_str_radix(hex, 16).ok().map(|v| v as f64 / max)
+        })
+        .collect::<Option<_>>()?;
+    let [r, g, b] = channels[..] else {
+        return None;
+    };
+    let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    Some(if luminance > 0.5 {
+        Background::Light
+    } else {
+        Background::Dark
+    })
+}
+
+/// `fg;bg` with ANSI colour indexes, as set by rxvt and some other terminals
+fn parse_colorfgbg(value: &str) -> Option<Background> {
+    let bg: u8 = value.rsplit(';').next()?.parse().ok()?;
+    Some(if matches!(bg, 7 | 9..=15) {
+        Background::Light
+    } else {
+        Background::Dark
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_built_in_themes_set_every_style() {
+        for (name, _) in BUILT_IN {
+            let theme = Theme::built_in(name).unwrap();
+            assert!(theme.syntax_theme().is_some(), "{}", name);
+            assert_ne!(theme.heading(1), Style::default(), "{}", name);
+            assert_ne!(theme.status_bar, Style::default(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_user_theme_extends_and_reports_errors() {
+        let path = Path::new("solar.toml");
+        let theme = Theme::parse(
+            "solar",
+            "extends = \"light\"\n[styles]\n\"link\" = { fg = \"#268bd2\", modifiers = [\"italic\"] }\n",
+            path,
+        )
+        .unwrap();
+        assert_eq!(theme.syntax, "InspiredGitHub");
+        assert_eq!(
+            theme.link,
+            Style::default()
+                .fg(Color::Rgb(0x26, 0x8b, 0xd2))
+                .add_modifier(Modifier::ITALIC)
+        );
+
+        let error = |text: &str| Theme::parse("x", text, path).unwrap_err().to_string();
+        assert!(error("[styles]\n\"lnk\" = { fg = \"red\" }").contains("unknown style `lnk`"));
+        assert!(error("[styles]\n\"link\" = { fg = \"redish\" }").contains("unknown colour"));
+        assert!(error("syntax = \"nope\"").contains("unknown syntax theme `nope`"));
+    }
+
+    #[test]
+    fn test_background_detection_replies() {
+        assert_eq!(
+            parse_background_reply("\x1b]11;rgb:ffff/ffff/dddd\x1b\\\x1b[0n"),
+            Some(Background::Light)
+        );
+        assert_eq!(
+            parse_background_reply("\x1b]11;rgb:1e1e/1e1e/2e2e\x07"),
+            Some(Background::Dark)
+        );
+        assert_eq!(parse_background_reply("\x1b[0n"), None);
+        assert_eq!(parse_colorfgbg("0;15"), Some(Background::Light));
+        assert_eq!(parse_colorfgbg("15;default;0"), Some(Background::Dark));
+    }
+}
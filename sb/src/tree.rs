@@ -0,0 +1,356 @@
+//! Lazily loaded file tree
+//!
+//! A directory is read on a background thread the first time it is expanded,
+//! and shows a loading row until its listing arrives, so the size of the tree
+//! has no bearing on startup. Listings go through the [`DirectoryCache`], so
+//! collapsing and expanding again, or a refresh of an unchanged directory,
+//! does not touch the disk.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use ratatui::text::{Line, Span};
+use tui_tree_widget::TreeItem;
+
+use crate::cache::DirectoryCache;
+use crate::theme::Theme;
+
+type Listing = Result<Vec<TreeItem<'static, String>>, String>;
+
+/// Dotfiles, shown only with `--show-hidden`
+pub fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .starts_with('.')
+}
+
+/// Identifier of the row standing in for a directory's unread contents
+fn placeholder_id(dir: &Path) -> String {
+    // NUL cannot appear in a real path
+    format!("{}\0", dir.display())
+}
+
+/// True for the row standing in for a directory's unread contents
+pub fn is_placeholder(id: &str) -> bool {
+    id.ends_with('\0')
+}
+
+/// One entry of a listing; directories carry a placeholder child so the
+/// tree widget lets them be expanded
+fn entry(path: PathBuf, is_dir: bool) -> TreeItem<'static, String> {
+    let name = path
+        .file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let id = path.display().to_string();
+    if is_dir {
+        let placeholder = TreeItem::new_leaf(placeholder_id(&path), "");
+        TreeItem::new(id.clone(), name.clone(), vec![placeholder])
+            .unwrap_or_else(|_| TreeItem::new_leaf(id, name))
+    } else {
+        TreeItem::new_leaf(id, name)
+    }
+}
+
+fn read_listing(dir: &Path) -> Listing {
+    let mut items: Vec<_> = std::fs::read_dir(dir)
+        .map_err(|e| e.to_string())?
+        .filter_map(|e| e.ok())
+        .map(|e| {
+            let path = e.path();
+            let is_dir = path.is_dir();
+            entry(path, is_dir)
+        })
+        .collect();
+    items.sort_by(|a, b| a.identifier().cmp(b.identifier()));
+    Ok(items)
+}
+
+/// Directory listings read so far, and the worker reading the rest
+#[derive(Debug)]
+pub struct FileTree {
+    root: PathBuf,
+    cache: DirectoryCache,
+    listings: HashMap<PathBuf, Listing>,
+    /// Requested from the worker and not back yet
+    pending: HashSet<PathBuf>,
+    /// Listings taken in since the last [`FileTree::poll`], and whether
+    /// each could be read
+    arrived: Vec<(PathBuf, bool)>,
+    requests: Sender<PathBuf>,
+    results: Receiver<(PathBuf, Listing)>,
+}
+
+impl FileTree {
+    /// A tree rooted at `root`, whose listing starts loading right away
+    pub fn new(root: PathBuf, cache: DirectoryCache) -> Self {
+        let (requests, jobs) = mpsc::channel::<PathBuf>();
+        let (done, results) = mpsc::channel();
+        let worker_cache = cache.clone();
+        let _ = thread::Builder::new()
+            .name("sb-tree".into())
+            .spawn(move || {
+                for dir in jobs {
+                    let listing = match worker_cache.get(&dir) {
+                        Some(items) => Ok(items),
+                        None => read_listing(&dir).inspect(|items| {
+                            worker_cache.insert(dir.clone(), items.clone());
+                        }),
+                    };
+                    if done.send((dir, listing)).is_err() {
+                        break;
+                    }
+                }
+            });
+        let mut tree = Self {
+            root: root.clone(),
+            cache,
+            listings: HashMap::new(),
+            pending: HashSet::new(),
+            arrived: Vec::new(),
+            requests,
+            results,
+        };
+        tree.load(&root);
+        tree
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Whether any listing is still being read
+    pub fn is_loading(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub fn is_loaded(&self, dir: &Path) -> bool {
+        self.listings.contains_key(dir)
+    }
+
+    /// Directories whose listings have been read
+    pub fn loaded(&self) -> impl Iterator<Item = &Path> {
+        self.listings.keys().map(PathBuf::as_path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        if path == self.root {
+            return true;
+        }
+        let Some(listing) = path.parent().and_then(|p| self.listings.get(p)) else {
+            return false;
+        };
+        let id = path.display().to_string();
+        listing
+            .iter()
+            .flatten()
+            .any(|item| item.identifier() == &id && !item.children().is_empty())
+    }
+
+    /// Start reading `dir` unless it is loaded or on its way; fresh cache
+    /// entries are used at once
+    pub fn load(&mut self, dir: &Path) {
+        if self.is_loaded(dir) || self.pending.contains(dir) || !self.is_dir(dir) {
+            return;
+        }
+        if let Some(items) = self.cache.get(dir) {
+            self.listings.insert(dir.to_path_buf(), Ok(items));
+            self.arrived.push((dir.to_path_buf(), true));
+        } else {
+            self.request(dir.to_path_buf());
+        }
+    }
+
+    fn request(&mut self, dir: PathBuf) {
+        if self.requests.send(dir.clone()).is_ok() {
+            self.pending.insert(dir);
+        }
+    }
+
+    /// Read every loaded directory again; the old listings stay on screen
+    /// until the new ones arrive
+    pub fn refresh(&mut self) {
+        let dirs: Vec<PathBuf> = self.listings.keys().cloned().collect();
+        for dir in dirs {
+            self.cache.invalidate(&dir);
+            self.request(dir);
+        }
+    }
+
+    /// Re-read `dir` if it has been loaded, e.g. after it changed on disk
+    pub fn reload(&mut self, dir: &Path) {
+        if self.is_loaded(dir) {
+            self.cache.invalidate(dir);
+            self.request(dir.to_path_buf());
+        }
+    }
+
+    /// Take finished listings from the worker; returns the directories whose
+    /// listings arrived since the last poll, and whether each could be read
+    pub fn poll(&mut self) -> Vec<(PathBuf, bool)> {
+        while let Ok((dir, listing)) = self.results.try_recv() {
+            self.pending.remove(&dir);
+            self.arrived.push((dir.clone(), listing.is_ok()));
+            self.listings.insert(dir, listing);
+        }
+        std::mem::take(&mut self.arrived)
+    }
+
+    /// Items for the tree widget, with selected paths marked
+    pub fn items(
+        &self,
+        show_hidden: bool,
+        selection: &HashSet<String>,
+        theme: &Theme,
+    ) -> Vec<TreeItem<'static, String>> {
+        vec![self.node(&self.root, show_hidden, selection, theme)]
+    }
+
+    fn node(
+        &self,
+        dir: &Path,
+        show_hidden: bool,
+        selection: &HashSet<String>,
+        theme: &Theme,
+    ) -> TreeItem<'static, String> {
+        let children = match self.listings.get(dir) {
+            Some(Ok(items)) => items
+                .iter()
+                .filter(|item| show_hidden || !is_hidden(Path::new(item.identifier())))
+                .map(|item| {
+                    let path = Path::new(item.identifier());
+                    if item.children().is_empty() {
+                        TreeItem::new_leaf(
+                            item.identifier().clone(),
+                            label(path, false, selection, theme),
+                        )
+                    } else {
+                        self.node(path, show_hidden, selection, theme)
+                    }
+                })
+                .collect(),
+            Some(Err(e)) => vec![TreeItem::new_leaf(
+                placeholder_id(dir),
+                Span::styled(format!("({e})"), theme.error),
+            )],
+            None => {
+                let text = if self.pending.contains(dir) {
+                    "⏳ loading…"
+                } else {
+                    "…"
+                };
+                vec![TreeItem::new_leaf(
+                    placeholder_id(dir),
+                    Span::styled(text, theme.dimmed),
+                )]
+            }
+        };
+        let id = dir.display().to_string();
+        let text = label(dir, true, selection, theme);
+        TreeItem::new(id.clone(), text.clone(), children)
+            .unwrap_or_else(|_| TreeItem::new_leaf(id, text))
+    }
+}
+
+fn label(path: &Path, is_dir: bool, selection: &HashSet<String>, theme: &Theme) -> Line<'static> {
+    let name = path
+        .file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.display().to_string());
+    let selected = selection.contains(&path.display().to_string());
+    let mut spans = Vec::new();
+    if selected {
+        // Add checkmark for selected items
+        spans.push(Span::styled("✓ ", theme.selection_mark));
+    }
+    if is_dir {
+        spans.push(Span::styled("📁 ", theme.tree_directory));
+    }
+    if selected {
+        spans.push(Span::styled(name, theme.selection_mark));
+    } else {
+        spans.push(Span::raw(name));
+    }
+    Line::from(spans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    /// Poll until nothing is loading; returns what arrived
+    fn wait(tree: &mut FileTree) -> Vec<(PathBuf, bool)> {
+        let start = Instant::now();
+        let mut arrived = Vec::new();
+        while tree.is_loading() {
+            arrived.extend(tree.poll());
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "listing never arrived"
+            );
+            thread::sleep(Duration::from_millis(5));
+        }
+        arrived
+    }
+
+    fn ids(items: &[TreeItem<String>]) -> Vec<String> {
+        items.iter().map(|item| item.identifier().clone()).collect()
+    }
+
+    #[test]
+    fn test_directories_load_when_expanded() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+        std::fs::create_dir_all(root.join("docs/deep")).unwrap();
+        std::fs::write(root.join("docs/a.md"), "").unwrap();
+        std::fs::write(root.join("b.md"), "").unwrap();
+        std::fs::write(root.join(".hidden"), "").unwrap();
+        let theme = Theme::default();
+        let none = HashSet::new();
+
+        let mut tree = FileTree::new(root.clone(), DirectoryCache::default());
+        assert!(tree.is_loading());
+        let items = tree.items(false, &none, &theme);
+        assert!(is_placeholder(items[0].children()[0].identifier()));
+
+        wait(&mut tree);
+        let items = tree.items(false, &none, &theme);
+        let docs = root.join("docs");
+        assert_eq!(
+            ids(items[0].children()),
+            [root.join("b.md"), docs.clone()].map(|p| p.display().to_string())
+        );
+        assert_eq!(tree.items(true, &none, &theme)[0].children().len(), 3);
+        // docs is not read until it is expanded
+        assert!(!tree.is_loaded(&docs));
+        assert!(is_placeholder(
+            items[0].children()[1].children()[0].identifier()
+        ));
+
+        // Files are never loaded as directories
+        tree.load(&root.join("b.md"));
+        assert!(!tree.is_loading());
+
+        tree.load(&docs);
+        assert_eq!(wait(&mut tree), [(docs.clone(), true)]);
+        let items = tree.items(false, &none, &theme);
+        assert_eq!(items[0].children()[1].children().len(), 2);
+        assert!(!tree.is_loaded(&docs.join("deep")));
+
+        // A refresh picks up new files
+        std::fs::write(docs.join("c.md"), "").unwrap();
+        tree.refresh();
+        wait(&mut tree);
+        let items = tree.items(false, &none, &theme);
+        assert_eq!(items[0].children()[1].children().len(), 3);
+
+        // A directory that can no longer be read arrives as a failure
+        std::fs::remove_dir_all(&docs).unwrap();
+        tree.reload(&docs);
+        assert_eq!(wait(&mut tree), [(docs.clone(), false)]);
+    }
+}
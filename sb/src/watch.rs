@@ -0,0 +1,185 @@
+//! Filesystem watching
+//!
+//! Only the directories sb is showing are watched, each non-recursively, so
+//! the cost follows the expanded part of the tree rather than its size.
+//! Changes are handed out in batches once things have been quiet for
+//! [`DEBOUNCE`], so a `git checkout` touching hundreds of files is one update.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// How long to wait for more events before reporting a batch
+pub const DEBOUNCE: Duration = Duration::from_millis(250);
+
+pub struct FsWatcher {
+    watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    /// Canonical form of each watched directory, to the path sb knows it by
+    watched: HashMap<PathBuf, PathBuf>,
+    /// Each path sb asked to watch, to its canonical form
+    local: HashMap<PathBuf, PathBuf>,
+    changed: HashSet<PathBuf>,
+    last_event: Option<Instant>,
+}
+
+impl std::fmt::Debug for FsWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FsWatcher")
+            .field("watched", &self.watched.len())
+            .field("changed", &self.changed)
+            .finish()
+    }
+}
+
+impl FsWatcher {
+    pub fn new() -> notify::Result<Self> {
+        let (tx, events) = mpsc::channel();
+        let watcher = notify::recommended_watcher(tx)?;
+        Ok(Self {
+            watcher,
+            events,
+            watched: HashMap::new(),
+            local: HashMap::new(),
+            changed: HashSet::new(),
+            last_event: None,
+        })
+    }
+
+    /// Watch the entries of `dir`; watching it again is a no-op that does
+    /// not touch the disk
+    pub fn watch(&mut self, dir: &Path) -> notify::Result<()> {
+        if self.local.contains_key(dir) {
+            return Ok(());
+        }
+        let canonical = dir.canonicalize().map_err(notify::Error::io)?;
+        if !self.watched.contains_key(&canonical) {
+            self.watcher
+                .watch(&canonical, RecursiveMode::NonRecursive)?;
+            self.watched.insert(canonical.clone(), dir.to_path_buf());
+        }
+        self.local.insert(dir.to_path_buf(), canonical);
+        Ok(())
+    }
+
+    /// Stop watching `dir`; the directory it points at stays watched while
+    /// another path to it is
+    pub fn unwatch(&mut self, dir: &Path) {
+        let Some(canonical) = self.local.remove(dir) else {
+            return;
+        };
+        let other = self
+            .local
+            .iter()
+            .find(|(_, c)| **c == canonical)
+            .map(|(d, _)| d.clone());
+        match other {
+            Some(other) => {
+                self.watched.insert(canonical, other);
+            }
+            None => {
+                let _ = self.watcher.unwatch(&canonical);
+                self.watched.remove(&canonical);
+            }
+        }
+    }
+
+    pub fn is_watching(&self, dir: &Path) -> bool {
+        self.local.contains_key(dir)
+    }
+
+    /// Whether changes are waiting out the debounce
+    pub fn is_busy(&self) -> bool {
+        !self.changed.is_empty()
+    }
+
+    /// Paths that changed, as sb knows them, once no event has arrived for
+    /// [`DEBOUNCE`]
+    pub fn poll(&mut self) -> Option<HashSet<PathBuf>> {
+        while let Ok(event) = self.events.try_recv() {
+            let Ok(event) = event else { continue };
+            if matches!(event.kind, EventKind::Access(_)) {
+                continue;
+            }
+            for path in &event.paths {
+                if let Some(path) = self.localize(path) {
+                    self.changed.insert(path);
+                }
+            }
+            self.last_event = Some(Instant::now());
+        }
+        let quiet = self.last_event.is_some_and(|t| t.elapsed() >= DEBOUNCE);
+        if quiet && !self.changed.is_empty() {
+            self.last_event = None;
+            Some(std::mem::take(&mut self.changed))
+        } else {
+            None
+        }
+    }
+
+    /// Map a path reported by the backend back under the directory it was
+    /// watched as
+    fn localize(&self, path: &Path) -> Option<PathBuf> {
+        if let Some(dir) = self.watched.get(path) {
+            return Some(dir.clone());
+        }
+        let dir = self.watched.get(path.parent()?)?;
+        Some(dir.join(path.file_name()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn wait(watcher: &mut FsWatcher) -> HashSet<PathBuf> {
+        let start = Instant::now();
+        loop {
+            if let Some(changed) = watcher.poll() {
+                return changed;
+            }
+            assert!(start.elapsed() < Duration::from_secs(5), "no change seen");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn test_changes_are_reported_by_local_path() {
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::create_dir(temp.path().join("docs")).unwrap();
+        // Events name the real directory; they come back under the link
+        let link = temp.path().join("link");
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(temp.path().join("docs"), &link).unwrap();
+        #[cfg(not(unix))]
+        let link = temp.path().join("docs");
+
+        let mut watcher = FsWatcher::new().unwrap();
+        watcher.watch(&link).unwrap();
+        watcher.watch(&link).unwrap();
+        assert!(watcher.is_watching(&link));
+        assert!(watcher.poll().is_none());
+
+        std::fs::write(temp.path().join("docs/a.md"), "one").unwrap();
+        std::fs::write(temp.path().join("docs/b.md"), "two").unwrap();
+        let changed = wait(&mut watcher);
+        assert!(changed.contains(&link.join("a.md")), "{changed:?}");
+        assert!(changed.contains(&link.join("b.md")), "{changed:?}");
+
+        // Outside the watched directory
+        std::fs::write(temp.path().join("c.md"), "three").unwrap();
+        thread::sleep(DEBOUNCE * 2);
+        assert!(watcher.poll().is_none());
+
+        // No longer watched
+        watcher.unwatch(&link);
+        assert!(!watcher.is_watching(&link));
+        std::fs::write(temp.path().join("docs/d.md"), "four").unwrap();
+        thread::sleep(DEBOUNCE * 2);
+        assert!(watcher.poll().is_none());
+    }
+}
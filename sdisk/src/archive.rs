@@ -0,0 +1,394 @@
+//! Archive-and-remove for stale subtrees, singly or several to one archive.
+//!
+//! A subtree is streamed into a gzip-compressed tar next to the other
+//! archives, read back and compared byte-for-byte with the originals, and only
+//! then removed. An archive that fails verification is kept as `.partial`
+//! for inspection and the original is left untouched. A symlinked source is
+//! archived and removed as the link, never the directory it points at.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use walkdir::WalkDir;
+
+use crate::error::SdiskError;
+use crate::throttle;
+use crate::vfs::{Fs, RealFs};
+
+/// What happened to one archived path
+#[derive(Debug, Clone)]
+pub struct ArchiveOutcome {
+    pub source: PathBuf,
+    pub archive: PathBuf,
+    /// Bytes of regular files in the source
+    pub original_bytes: u64,
+    /// Size of the compressed archive on disk
+    pub archive_bytes: u64,
+}
+
+impl ArchiveOutcome {
+    /// Space reclaimed once the original is removed
+    pub fn saved(&self) -> u64 {
+        self.original_bytes.saturating_sub(self.archive_bytes)
+    }
+}
+
+/// Destination file for archiving `source` into `dest_dir`; a counter is
+/// added when another source of the same name was archived in the same second
+pub fn archive_path(source: &Path, dest_dir: &Path) -> PathBuf {
+    let name = source
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "root".to_string());
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let mut path = dest_dir.join(format!("{name}-{stamp}.tar.gz"));
+    let mut n = 1;
+    while path.exists() || path.with_extension("gz.partial").exists() {
+        path = dest_dir.join(format!("{name}-{stamp}-{n}.tar.gz"));
+        n += 1;
+    }
+    path
+}
+
+/// A path going into an archive, and the name it is stored under
+struct Member {
+    source: PathBuf,
+    name: PathBuf,
+}
+
+/// Archive `source` into `dest_dir`, verify the archive and remove `source`
+pub fn archive_and_remove(source: &Path, dest_dir: &Path) -> Result<ArchiveOutcome> {
+    let source_abs = resolve(source)?;
+    let member = Member {
+        name: entry_root(&source_abs),
+        source: source_abs.clone(),
+    };
+    archive_members(source, &source_abs, &[member], dest_dir)
+}
+
+/// Archive `sources`, all inside `base`, into one archive named after `base`,
+/// verify it and remove the sources. Entries keep their path below the parent
+/// of `base`, so extracting the archive puts them back where they were.
+pub fn archive_group_and_remove(
+    base: &Path,
+    sources: &[PathBuf],
+    dest_dir: &Path,
+) -> Result<ArchiveOutcome> {
+    let base_abs = resolve(base)?;
+    let root = entry_root(&base_abs);
+    let members = sources
+        .iter()
+        .map(|source| {
+            let source_abs = resolve(source)?;
+            let rel = source_abs.strip_prefix(&base_abs).with_context(|| {
+                format!("{} is not inside {}", source.display(), base.display())
+            })?;
+            Ok(Member {
+                name: root.join(rel),
+                source: source_abs,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    archive_members(base, &base_abs, &members, dest_dir)
+}
+
+/// Absolute form of `path` without following a final symlink; fails if
+/// nothing is there
+fn resolve(path: &Path) -> Result<PathBuf> {
+    std::fs::symlink_metadata(path).with_context(|| format!("resolving {}", path.display()))?;
+    std::path::absolute(path).with_context(|| format!("resolving {}", path.display()))
+}
+
+/// Whether `path` is a directory itself, not a link to one
+fn is_real_dir(path: &Path) -> bool {
+    std::fs::symlink_metadata(path).is_ok_and(|m| m.is_dir())
+}
+
+fn archive_members(
+    label: &Path,
+    label_abs: &Path,
+    members: &[Member],
+    dest_dir: &Path,
+) -> Result<ArchiveOutcome> {
+    let inside_source = |dir: &Path| -> Result<()> {
+        if let Some(m) = members.iter().find(|m| dir.starts_with(&m.source)) {
+            anyhow::bail!(
+                "archive directory {} is inside {}, which is about to be removed",
+                dest_dir.display(),
+                m.source.display()
+            );
+        }
+        Ok(())
+    };
+    inside_source(&std::path::absolute(dest_dir)?)?;
+    std::fs::create_dir_all(dest_dir)
+        .with_context(|| format!("creating archive directory {}", dest_dir.display()))?;
+    let dest_abs = dest_dir
+        .canonicalize()
+        .with_context(|| format!("resolving {}", dest_dir.display()))?;
+    inside_source(&dest_abs)?;
+
+    let archive = archive_path(label_abs, &dest_abs);
+    if archive.exists() {
+        anyhow::bail!("{} already exists", archive.display());
+    }
+    let partial = archive.with_extension("gz.partial");
+    write_archive(members, &partial)?;
+    // A failed archive keeps its .partial name, so it is never taken for a
+    // good one and the next run can try again
+    let original_bytes = verify(members, &partial)?;
+    std::fs::rename(&partial, &archive)
+        .with_context(|| format!("renaming {}", partial.display()))?;
+    let archive_bytes = std::fs::metadata(&archive)
+        .with_context(|| format!("reading {}", archive.display()))?
+        .len();
+
+    for m in members {
+        RealFs
+            .remove(&m.source)
+            .with_context(|| format!("removing {}", m.source.display()))?;
+    }
+
+    Ok(ArchiveOutcome {
+        source: label.to_path_buf(),
+        archive,
+        original_bytes,
+        archive_bytes,
+    })
+}
+
+/// Name of the top-level entry in the archive
+fn entry_root(source: &Path) -> PathBuf {
+    PathBuf::from(source.file_name().unwrap_or(source.as_os_str()))
+}
+
+fn write_archive(members: &[Member], archive: &Path) -> Result<()> {
+    let file = File::create(archive).with_context(|| format!("creating {}", archive.display()))?;
+    let encoder = GzEncoder::new(BufWriter::new(file), Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    builder.follow_symlinks(false);
+    for m in members {
+        if is_real_dir(&m.source) {
+            builder.append_dir_all(&m.name, &m.source)
+        } else {
+            builder.append_path_with_name(&m.source, &m.name)
+        }
+        .with_context(|| format!("archiving {}", m.source.display()))?;
+    }
+    builder
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .and_then(|mut writer| std::io::Write::flush(&mut writer))
+        .with_context(|| format!("writing {}", archive.display()))?;
+    Ok(())
+}
+
+/// Re-read the archive and compare every regular file with the original.
+/// Returns the total size of the regular files verified.
+fn verify(members: &[Member], archive: &Path) -> Result<u64> {
+    let fail = |reason: String| SdiskError::ArchiveVerify {
+        archive: archive.to_path_buf(),
+        reason,
+    };
+    let file = File::open(archive).with_context(|| format!("opening {}", archive.display()))?;
+    let mut reader = tar::Archive::new(GzDecoder::new(BufReader::new(file)));
+    let mut files = 0u64;
+    let mut bytes = 0u64;
+    for entry in reader.entries().map_err(|e| fail(e.to_string()))? {
+        let mut entry = entry.map_err(|e| fail(e.to_string()))?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path().map_err(|e| fail(e.to_string()))?.into_owned();
+        let (member, rel) = members
+            .iter()
+            .find_map(|m| Some((m, name.strip_prefix(&m.name).ok()?)))
+            .ok_or_else(|| fail(format!("unexpected entry {}", name.display())))?;
+        let original = if rel.as_os_str().is_empty() {
+            member.source.clone()
+        } else {
+            member.source.join(rel)
+        };
+        let mut on_disk = File::open(&original)
+            .map(BufReader::new)
+            .map_err(|e| fail(format!("{}: {}", original.display(), e)))?;
+        if !same_contents(&mut entry, &mut on_disk).map_err(|e| fail(e.to_string()))? {
+            return Err(fail(format!("{} differs from the original", name.display())).into());
+        }
+        files += 1;
+        bytes += entry.header().size().unwrap_or(0);
+    }
+
+    let expected: u64 = members
+        .iter()
+        .map(|m| {
+            // A symlinked source is stored as the link, not what it points at
+            throttle::walk(WalkDir::new(&m.source).follow_root_links(false))
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_file())
+                .count() as u64
+        })
+        .sum();
+    if files != expected {
+        return Err(fail(format!(
+            "archive holds {files} files but the source has {expected}"
+        ))
+        .into());
+    }
+    Ok(bytes)
+}
+
+fn same_contents(a: &mut impl Read, b: &mut impl Read) -> std::io::Result<bool> {
+    let mut buf_a = vec![0u8; 64 * 1024];
+    let mut buf_b = vec![0u8; 64 * 1024];
+    loop {
+        let n = a.read(&mut buf_a)?;
+        if n == 0 {
+            return Ok(b.read(&mut buf_b[..1])? == 0);
+        }
+        if b.read_exact(&mut buf_b[..n]).is_err() || buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_archive_and_remove_round_trip() {
+        let temp = TempDir::new().unwrap();
+        let source = temp.path().join("project");
+        fs::create_dir_all(source.join("src")).unwrap();
+        fs::write(source.join("src/main.rs"), "fn main() {}\n".repeat(500)).unwrap();
+        fs::write(source.join("README"), "hello").unwrap();
+        let dest = temp.path().join("archives");
+
+        let outcome = archive_and_remove(&source, &dest).unwrap();
+
+        assert!(!source.exists());
+        assert!(outcome.archive.exists());
+        assert_eq!(outcome.original_bytes, 13 * 500 + 5);
+        assert!(outcome.saved() > 0);
+
+        let file = File::open(&outcome.archive).unwrap();
+        let mut reader = tar::Archive::new(GzDecoder::new(file));
+        let names: Vec<String> = reader
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert!(names.contains(&"project/src/main.rs".to_string()));
+    }
+
+    #[test]
+    fn test_same_name_sources_get_separate_archives() {
+        let temp = TempDir::new().unwrap();
+        for dir in ["a", "b"] {
+            fs::create_dir_all(temp.path().join(dir)).unwrap();
+            fs::write(temp.path().join(dir).join("x.log"), dir).unwrap();
+        }
+        let dest = temp.path().join("archives");
+
+        let first = archive_and_remove(&temp.path().join("a/x.log"), &dest).unwrap();
+        let second = archive_and_remove(&temp.path().join("b/x.log"), &dest).unwrap();
+
+        assert_ne!(first.archive, second.archive);
+        assert!(first.archive.exists() && second.archive.exists());
+        assert!(!temp.path().join("b/x.log").exists());
+    }
+
+    #[test]
+    fn test_group_goes_into_one_archive() {
+        let temp = TempDir::new().unwrap();
+        let base = temp.path().join("logs");
+        fs::create_dir_all(base.join("old")).unwrap();
+        fs::write(base.join("a.log"), "a").unwrap();
+        fs::write(base.join("old/b.log"), "bb").unwrap();
+        fs::write(base.join("keep.log"), "keep").unwrap();
+        let dest = temp.path().join("archives");
+
+        let sources = [base.join("a.log"), base.join("old")];
+        let outcome = archive_group_and_remove(&base, &sources, &dest).unwrap();
+
+        assert_eq!(outcome.original_bytes, 3);
+        assert!(!base.join("a.log").exists() && !base.join("old").exists());
+        assert!(base.join("keep.log").exists());
+        assert_eq!(fs::read_dir(&dest).unwrap().count(), 1);
+        let file = File::open(&outcome.archive).unwrap();
+        let mut reader = tar::Archive::new(GzDecoder::new(file));
+        let mut names: Vec<String> = reader
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().to_string_lossy().into_owned())
+            .filter(|n| n.ends_with(".log"))
+            .collect();
+        names.sort();
+        assert_eq!(names, ["logs/a.log", "logs/old/b.log"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_source_is_archived_as_the_link() {
+        let temp = TempDir::new().unwrap();
+        let target = temp.path().join("elsewhere");
+        fs::create_dir_all(&target).unwrap();
+        fs::write(target.join("data"), "keep me").unwrap();
+        let scanned = temp.path().join("scanned");
+        fs::create_dir_all(&scanned).unwrap();
+        let link = scanned.join("link");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        let dest = temp.path().join("archives");
+
+        let outcome = archive_and_remove(&link, &dest).unwrap();
+
+        assert!(fs::symlink_metadata(&link).is_err());
+        assert_eq!(fs::read_to_string(target.join("data")).unwrap(), "keep me");
+        let file = File::open(&outcome.archive).unwrap();
+        let mut reader = tar::Archive::new(GzDecoder::new(file));
+        let kinds: Vec<_> = reader
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().header().entry_type())
+            .collect();
+        assert_eq!(kinds, [tar::EntryType::Symlink]);
+    }
+
+    #[test]
+    fn test_refuses_destination_inside_source() {
+        let temp = TempDir::new().unwrap();
+        let source = temp.path().join("project");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("a"), "a").unwrap();
+
+        let result = archive_and_remove(&source, &source.join("archives"));
+
+        assert!(result.is_err());
+        assert!(source.join("a").exists());
+    }
+
+    #[test]
+    fn test_same_contents_detects_differences() {
+        let mut a: &[u8] = b"hello world";
+        let mut b: &[u8] = b"hello world";
+        assert!(same_contents(&mut a, &mut b).unwrap());
+        let mut a: &[u8] = b"hello world";
+        let mut b: &[u8] = b"hello there";
+        assert!(!same_contents(&mut a, &mut b).unwrap());
+        let mut a: &[u8] = b"hello";
+        let mut b: &[u8] = b"hello world";
+        assert!(!same_contents(&mut a, &mut b).unwrap());
+    }
+}
@@ -0,0 +1,658 @@
+//! Unattended, policy-driven cleanup runs.
+//!
+//! A policy file names the roots to clean, which detectors may nominate
+//! candidates, how old a candidate must be, how many bytes one run may free
+//! and the free space at which to stop. Planning is separate from execution
+//! so a dry run prints exactly the plan a real run would carry out.
+//!
+//! ```toml
+//! roots = ["/srv/cache", "/var/log/app"]
+//! detectors = ["stale", "logs", "cruft"]
+//! stale_days = 30
+//! min_age_days = 7
+//! max_bytes_per_run = "20G"
+//! target_free = "100G"
+//! unattended = true
+//! action = "archive"
+//! archive_to = "/backup/sdisk"
+//! notify = "logger -t sdisk \"freed $SDISK_FREED_BYTES bytes, see $SDISK_REPORT\""
+//! ```
+
+use std::collections::HashMap;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use rayon::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::detect::{self, Candidate, Category};
+use crate::vfs::{Fs, RealFs};
+use crate::{archive, state, units};
+
+/// Journal of every non-dry run, one JSON object per line
+pub const JOURNAL_FILE: &str = "journal.jsonl";
+
+/// What happens to planned candidates
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    #[default]
+    Delete,
+    /// Archive to `archive_to`, one archive per top-level directory of a
+    /// root, verify, then remove
+    Archive,
+}
+
+/// A cleanup policy, read from TOML
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    pub roots: Vec<PathBuf>,
+    #[serde(default = "all_detectors")]
+    pub detectors: Vec<Category>,
+    /// Threshold for the stale detector
+    #[serde(default = "default_stale_days")]
+    pub stale_days: u64,
+    /// Candidates used or modified more recently than this are left alone
+    #[serde(default)]
+    pub min_age_days: u64,
+    /// Most bytes one run may free
+    #[serde(default, deserialize_with = "de_size")]
+    pub max_bytes_per_run: Option<u64>,
+    /// Stop once the filesystem holding a candidate has this much free space
+    #[serde(default, deserialize_with = "de_size")]
+    pub target_free: Option<u64>,
+    /// Run without asking for confirmation
+    #[serde(default)]
+    pub unattended: bool,
+    #[serde(default)]
+    pub action: Action,
+    pub archive_to: Option<PathBuf>,
+    /// Where reports are written (defaults to `reports/` in the state directory)
+    pub report_dir: Option<PathBuf>,
+    /// Shell command run after each run
+    pub notify: Option<String>,
+}
+
+fn all_detectors() -> Vec<Category> {
+    Category::ALL.to_vec()
+}
+
+fn default_stale_days() -> u64 {
+    90
+}
+
+fn de_size<'de, D: Deserializer<'de>>(de: D) -> std::result::Result<Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        Text(String),
+    }
+    match Size::deserialize(de)? {
+        Size::Bytes(bytes) => Ok(Some(bytes)),
+        Size::Text(text) => units::parse_size(&text)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+    }
+}
+
+impl Policy {
+    /// Read and validate a policy file
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read policy {}", path.display()))?;
+        let policy: Policy =
+            toml::from_str(&text).with_context(|| format!("Invalid policy {}", path.display()))?;
+        policy
+            .validate()
+            .with_context(|| format!("Invalid policy {}", path.display()))?;
+        Ok(policy)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.roots.is_empty() {
+            bail!("`roots` must name at least one directory");
+        }
+        if let Some(root) = self.roots.iter().find(|r| !r.is_absolute()) {
+            bail!("root {} must be an absolute path", root.display());
+        }
+        if self.detectors.is_empty() {
+            bail!("`detectors` must name at least one detector");
+        }
+        if self.action == Action::Archive && self.archive_to.is_none() {
+            bail!("`action = \"archive\"` needs `archive_to`");
+        }
+        Ok(())
+    }
+}
+
+/// Run the policy's detectors over its roots
+///
+/// Candidates without a last-used time get their modification time, so the
+/// age threshold applies to every detector.
+pub fn gather(policy: &Policy) -> Vec<Candidate> {
+    let jobs: Vec<(&PathBuf, Category)> = policy
+        .roots
+        .iter()
+        .flat_map(|root| policy.detectors.iter().map(move |&c| (root, c)))
+        .collect();
+    jobs.into_par_iter()
+        .flat_map(|(root, category)| detect::detect(category, root, policy.stale_days))
+        .map(|mut candidate| {
+            if candidate.last_used.is_none() {
+                candidate.last_used = candidate
+                    .path
+                    .symlink_metadata()
+                    .and_then(|m| m.modified())
+                    .ok();
+            }
+            candidate
+        })
+        .collect()
+}
+
+/// A candidate left out of the plan, and why
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Skipped {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// A candidate inside a planned one, which goes with it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Absorbed {
+    pub path: PathBuf,
+    pub into: PathBuf,
+}
+
+/// What a run will do, in order
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub actions: Vec<Candidate>,
+    pub skipped: Vec<Skipped>,
+    pub absorbed: Vec<Absorbed>,
+}
+
+impl Plan {
+    pub fn bytes(&self) -> u64 {
+        self.actions.iter().map(|c| c.size).sum()
+    }
+}
+
+/// Choose which candidates to act on
+///
+/// Oldest candidates go first. `mounts` gives the available bytes per mount
+/// point, used for the free-space target; `refuse` names a reason a path
+/// must not be touched (the safety interlocks). Every candidate is vetted
+/// before nested ones are folded into their parent, so a candidate only goes
+/// with a parent that may itself be touched; the folded paths are listed in
+/// [`Plan::absorbed`].
+pub fn plan(
+    policy: &Policy,
+    mut candidates: Vec<Candidate>,
+    mounts: &[(PathBuf, u64)],
+    now: SystemTime,
+    refuse: impl Fn(&Path) -> Option<String>,
+) -> Plan {
+    let mut out = Plan::default();
+    let min_age = Duration::from_secs(policy.min_age_days * 24 * 60 * 60);
+
+    candidates.sort_by_key(|c| (c.last_used.unwrap_or(UNIX_EPOCH), std::cmp::Reverse(c.size)));
+    let mut vetted: Vec<Candidate> = Vec::with_capacity(candidates.len());
+    for candidate in candidates {
+        let age = now
+            .duration_since(candidate.last_used.unwrap_or(now))
+            .unwrap_or_default();
+        let reason = if age < min_age {
+            Some(format!(
+                "used {} days ago, policy needs {}",
+                age.as_secs() / 86400,
+                policy.min_age_days
+            ))
+        } else {
+            refuse(&candidate.path)
+        };
+        match reason {
+            Some(reason) => out.skipped.push(Skipped {
+                path: candidate.path,
+                reason,
+            }),
+            None => vetted.push(candidate),
+        }
+    }
+
+    // A candidate inside another one goes with it
+    vetted.sort_by(|a, b| a.path.cmp(&b.path));
+    vetted.dedup_by(|b, a| a.path == b.path);
+    let mut kept: Vec<Candidate> = Vec::with_capacity(vetted.len());
+    let mut nested: Vec<Absorbed> = Vec::new();
+    for candidate in vetted {
+        match kept.last() {
+            Some(parent) if candidate.path.starts_with(&parent.path) => nested.push(Absorbed {
+                path: candidate.path,
+                into: parent.path.clone(),
+            }),
+            _ => kept.push(candidate),
+        }
+    }
+    kept.sort_by_key(|c| (c.last_used.unwrap_or(UNIX_EPOCH), std::cmp::Reverse(c.size)));
+
+    let mut avail: HashMap<&Path, u64> = mounts.iter().map(|(m, a)| (m.as_path(), *a)).collect();
+    let mut budget = policy.max_bytes_per_run.unwrap_or(u64::MAX);
+    for candidate in kept {
+        let skip = |reason: String| Skipped {
+            path: candidate.path.clone(),
+            reason,
+        };
+        let mount = mounts
+            .iter()
+            .map(|(m, _)| m.as_path())
+            .filter(|m| candidate.path.starts_with(m))
+            .max_by_key(|m| m.as_os_str().len());
+        if let (Some(target), Some(mount)) = (policy.target_free, mount) {
+            if avail[mount] >= target {
+                out.skipped.push(skip(format!(
+                    "{} already has the target free space",
+                    mount.display()
+                )));
+                continue;
+            }
+        }
+        if candidate.size > budget {
+            out.skipped
+                .push(skip("would exceed the per-run byte budget".to_string()));
+            continue;
+        }
+        budget -= candidate.size;
+        if let Some(mount) = mount {
+            *avail.get_mut(mount).unwrap() += candidate.size;
+        }
+        out.actions.push(candidate);
+    }
+    for absorbed in nested {
+        if out.actions.iter().any(|c| c.path == absorbed.into) {
+            out.absorbed.push(absorbed);
+        } else {
+            out.skipped.push(Skipped {
+                reason: format!("inside {}, which is skipped", absorbed.into.display()),
+                path: absorbed.path,
+            });
+        }
+    }
+    out
+}
+
+/// Result of acting on one planned candidate
+#[derive(Debug, Clone, Serialize)]
+pub struct Outcome {
+    pub path: PathBuf,
+    pub category: Category,
+    /// Bytes freed (for archives, original size minus archive size)
+    pub freed: u64,
+    pub error: Option<String>,
+}
+
+/// Carry out a plan, continuing past individual failures
+pub fn execute(policy: &Policy, plan: &Plan) -> Vec<Outcome> {
+    execute_in(&RealFs, policy, plan)
+}
+
+/// [`execute`] deleting through `fs`; archiving always uses the real
+/// filesystem, since the archive itself has to be written somewhere
+///
+/// Archived candidates are grouped by the top-level directory of their root
+/// that holds them, one archive per group.
+pub fn execute_in(fs: &dyn Fs, policy: &Policy, plan: &Plan) -> Vec<Outcome> {
+    let outcome = |candidate: &Candidate, result: Result<u64>| Outcome {
+        path: candidate.path.clone(),
+        category: candidate.category,
+        freed: *result.as_ref().unwrap_or(&0),
+        error: result.err().map(|e| format!("{:#}", e)),
+    };
+    match policy.action {
+        Action::Delete => plan
+            .actions
+            .iter()
+            .map(|candidate| {
+                outcome(
+                    candidate,
+                    remove(fs, &candidate.path).map(|_| candidate.size),
+                )
+            })
+            .collect(),
+        Action::Archive => {
+            let dest = policy.archive_to.as_deref().unwrap_or(Path::new("."));
+            let mut outcomes = Vec::with_capacity(plan.actions.len());
+            for (base, group) in archive_groups(&policy.roots, &plan.actions) {
+                let sources: Vec<PathBuf> = group.iter().map(|c| c.path.clone()).collect();
+                match archive::archive_group_and_remove(&base, &sources, dest) {
+                    Ok(archived) => {
+                        // Credit the saving to each candidate by its size
+                        let total: u64 = group.iter().map(|c| c.size).sum();
+                        for candidate in group {
+                            let share = (archived.saved() as u128 * candidate.size as u128)
+                                .checked_div(total as u128)
+                                .unwrap_or(0);
+                            outcomes.push(outcome(candidate, Ok(share as u64)));
+                        }
+                    }
+                    Err(e) => {
+                        let e = format!("{:#}", e);
+                        for candidate in group {
+                            outcomes.push(outcome(candidate, Err(anyhow::anyhow!(e.clone()))));
+                        }
+                    }
+                }
+            }
+            outcomes
+        }
+    }
+}
+
+/// Candidates grouped by the top-level entry of the root holding them, in
+/// plan order; a candidate outside every root is a group of its own
+fn archive_groups<'a>(
+    roots: &[PathBuf],
+    candidates: &'a [Candidate],
+) -> Vec<(PathBuf, Vec<&'a Candidate>)> {
+    let mut groups: Vec<(PathBuf, Vec<&Candidate>)> = Vec::new();
+    let mut index: HashMap<PathBuf, usize> = HashMap::new();
+    for candidate in candidates {
+        let base = roots
+            .iter()
+            .find_map(|root| {
+                let top = candidate
+                    .path
+                    .strip_prefix(root)
+                    .ok()?
+                    .components()
+                    .next()?;
+                Some(root.join(top))
+            })
+            .unwrap_or_else(|| candidate.path.clone());
+        match index.get(&base) {
+            Some(&i) => groups[i].1.push(candidate),
+            None => {
+                index.insert(base.clone(), groups.len());
+                groups.push((base, vec![candidate]));
+            }
+        }
+    }
+    groups
+}
+
+fn remove(fs: &dyn Fs, path: &Path) -> Result<()> {
+    if fs.symlink_metadata(path).is_err() {
+        // Already gone, e.g. cleaned up by whatever wrote it
+        return Ok(());
+    }
+    fs.remove(path)
+        .with_context(|| format!("removing {}", path.display()))
+}
+
+/// Everything a run did, written as JSON
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub policy: PathBuf,
+    pub started: u64,
+    pub finished: u64,
+    pub action: Action,
+    pub outcomes: Vec<Outcome>,
+    pub skipped: Vec<Skipped>,
+    /// Paths removed along with a planned parent
+    pub absorbed: Vec<Absorbed>,
+    pub freed_bytes: u64,
+    pub failed: usize,
+}
+
+impl Report {
+    pub fn new(
+        policy_path: &Path,
+        policy: &Policy,
+        started: SystemTime,
+        outcomes: Vec<Outcome>,
+        plan: Plan,
+    ) -> Self {
+        let secs = |t: SystemTime| t.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        Self {
+            policy: std::path::absolute(policy_path).unwrap_or_else(|_| policy_path.to_path_buf()),
+            started: secs(started),
+            finished: secs(SystemTime::now()),
+            action: policy.action,
+            freed_bytes: outcomes.iter().map(|o| o.freed).sum(),
+            failed: outcomes.iter().filter(|o| o.error.is_some()).count(),
+            outcomes,
+            skipped: plan.skipped,
+            absorbed: plan.absorbed,
+        }
+    }
+
+    /// Write the report and append a journal entry; returns the report path
+    pub fn save(&self, policy: &Policy) -> Result<PathBuf> {
+        let dir = match &policy.report_dir {
+            Some(dir) => dir.clone(),
+            None => state::state_dir()?.join("reports"),
+        };
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create report directory {}", dir.display()))?;
+        let json = serde_json::to_string_pretty(self).context("Failed to serialize report")?;
+        // Runs started in the same second get a counter rather than sharing
+        // a report
+        let mut path = dir.join(format!("auto-{}.json", self.started));
+        let mut n = 1;
+        let mut file = loop {
+            match std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    path = dir.join(format!("auto-{}-{}.json", self.started, n));
+                    n += 1;
+                }
+                result => {
+                    break result
+                        .with_context(|| format!("Failed to create report {}", path.display()))?
+                }
+            }
+        };
+        file.write_all(json.as_bytes())
+            .with_context(|| format!("Failed to write report {}", path.display()))?;
+
+        let entry = serde_json::json!({
+            "timestamp": self.finished,
+            "policy": self.policy,
+            "report": path,
+            "removed": self.outcomes.len() - self.failed,
+            "failed": self.failed,
+            "freed_bytes": self.freed_bytes,
+        });
+        let journal = state::state_file(JOURNAL_FILE)?;
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&journal)
+            .with_context(|| format!("Failed to open journal {}", journal.display()))?;
+        writeln!(file, "{}", entry)
+            .with_context(|| format!("Failed to write journal {}", journal.display()))?;
+        Ok(path)
+    }
+
+    /// Run the policy's notification hook, if any
+    ///
+    /// The command runs through the shell with `SDISK_REPORT`,
+    /// `SDISK_FREED_BYTES`, `SDISK_REMOVED` and `SDISK_FAILED` set.
+    pub fn notify(&self, policy: &Policy, report: &Path) -> Result<()> {
+        let Some(command) = &policy.notify else {
+            return Ok(());
+        };
+        #[cfg(unix)]
+        let mut cmd = {
+            let mut cmd = std::process::Command::new("sh");
+            cmd.arg("-c").arg(command);
+            cmd
+        };
+        #[cfg(not(unix))]
+        let mut cmd = {
+            let mut cmd = std::process::Command::new("cmd");
+            cmd.arg("/C").arg(command);
+            cmd
+        };
+        let status = cmd
+            .env("SDISK_REPORT", report)
+            .env("SDISK_FREED_BYTES", self.freed_bytes.to_string())
+            .env(
+                "SDISK_REMOVED",
+                (self.outcomes.len() - self.failed).to_string(),
+            )
+            .env("SDISK_FAILED", self.failed.to_string())
+            .status()
+            .with_context(|| format!("Failed to run notify hook `{}`", command))?;
+        if !status.success() {
+            bail!("notify hook `{}` exited with {}", command, status);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(extra: &str) -> Policy {
+        let policy: Policy = toml::from_str(&format!("roots = [\"/data\"]\n{}", extra)).unwrap();
+        policy.validate().unwrap();
+        policy
+    }
+
+    fn candidate(path: &str, size: u64, days_ago: u64, now: SystemTime) -> Candidate {
+        Candidate {
+            path: PathBuf::from(path),
+            size,
+            category: Category::Stale,
+            last_used: Some(now - Duration::from_secs(days_ago * 86400)),
+        }
+    }
+
+    #[test]
+    fn test_archive_groups_follow_top_level_directories() {
+        let now = SystemTime::now();
+        let candidates = vec![
+            candidate("/data/logs/a.log", 1, 100, now),
+            candidate("/data/cache", 1, 100, now),
+            candidate("/data/logs/old/b.log", 1, 100, now),
+            candidate("/elsewhere/c", 1, 100, now),
+        ];
+
+        let groups: Vec<_> = archive_groups(&[PathBuf::from("/data")], &candidates)
+            .into_iter()
+            .map(|(base, group)| (base, group.len()))
+            .collect();
+
+        assert_eq!(
+            groups,
+            vec![
+                (PathBuf::from("/data/logs"), 2),
+                (PathBuf::from("/data/cache"), 1),
+                (PathBuf::from("/elsewhere/c"), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_policy_parsing_and_validation() {
+        let p = policy("max_bytes_per_run = \"1G\"\ntarget_free = 1000\ndetectors = [\"logs\"]");
+        assert_eq!(p.max_bytes_per_run, Some(1 << 30));
+        assert_eq!(p.target_free, Some(1000));
+        assert_eq!(p.detectors, vec![Category::Logs]);
+        assert!(!p.unattended);
+
+        let bad: Policy = toml::from_str("roots = [\"/data\"]\naction = \"archive\"").unwrap();
+        assert!(bad.validate().is_err());
+        assert!(toml::from_str::<Policy>("roots = []\nbogus = 1").is_err());
+    }
+
+    #[test]
+    fn test_plan_respects_age_budget_and_target() {
+        let now = SystemTime::now();
+        let p = policy("min_age_days = 10\nmax_bytes_per_run = 250\ntarget_free = 1000");
+        let candidates = vec![
+            candidate("/data/new", 10, 1, now),
+            candidate("/data/old", 100, 300, now),
+            candidate("/data/old/inner", 50, 400, now),
+            candidate("/data/huge", 500, 200, now),
+            candidate("/data/mid", 100, 100, now),
+            candidate("/data/last", 100, 50, now),
+            candidate("/data/locked", 1, 500, now),
+        ];
+        let mounts = [(PathBuf::from("/"), 750), (PathBuf::from("/data"), 800)];
+
+        let plan = plan(&p, candidates, &mounts, now, |path| {
+            path.ends_with("locked").then(|| "refused".to_string())
+        });
+
+        let acted: Vec<_> = plan.actions.iter().map(|c| c.path.clone()).collect();
+        assert_eq!(
+            acted,
+            vec![PathBuf::from("/data/old"), PathBuf::from("/data/mid")]
+        );
+        assert_eq!(plan.bytes(), 200);
+        assert_eq!(
+            plan.absorbed,
+            vec![Absorbed {
+                path: PathBuf::from("/data/old/inner"),
+                into: PathBuf::from("/data/old"),
+            }]
+        );
+        let reasons: Vec<_> = plan
+            .skipped
+            .iter()
+            .map(|s| (s.path.to_str().unwrap(), s.reason.as_str()))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                ("/data/locked", "refused"),
+                ("/data/new", "used 1 days ago, policy needs 10"),
+                ("/data/huge", "would exceed the per-run byte budget"),
+                ("/data/last", "/data already has the target free space"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nested_candidates_stay_when_the_parent_is_refused() {
+        let now = SystemTime::now();
+        let p = policy("");
+        let candidates = vec![
+            candidate("/data/repo", 100, 300, now),
+            candidate("/data/repo/target", 80, 300, now),
+            candidate("/data/cache", 50, 300, now),
+            candidate("/data/cache/a", 20, 300, now),
+        ];
+
+        let plan = plan(&p, candidates, &[], now, |path| {
+            path.ends_with("repo")
+                .then(|| "uncommitted changes".to_string())
+        });
+
+        let acted: Vec<_> = plan.actions.iter().map(|c| c.path.clone()).collect();
+        assert_eq!(
+            acted,
+            vec![
+                PathBuf::from("/data/repo/target"),
+                PathBuf::from("/data/cache")
+            ]
+        );
+        assert_eq!(
+            plan.absorbed,
+            vec![Absorbed {
+                path: PathBuf::from("/data/cache/a"),
+                into: PathBuf::from("/data/cache"),
+            }]
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! Compare disk usage of two directory trees.
+//!
+//! Both trees are summarized by path relative to their root, then aligned so
+//! a migration or backup can be checked for parts that are missing on one
+//! side or differ in size or file count. Only the topmost missing entry is
+//! reported, so a directory absent from one side does not also list every
+//! file below it.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::vfs::{self, Fs, RealFs};
+
+/// Usage of one file or subtree
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Usage {
+    pub bytes: u64,
+    pub files: u64,
+    pub dir: bool,
+}
+
+/// Usage of every entry below a root, keyed by relative path (the root
+/// itself is the empty path)
+pub type Tree = BTreeMap<PathBuf, Usage>;
+
+/// How an entry differs between the two sides
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffKind {
+    OnlyInA,
+    OnlyInB,
+    Changed,
+}
+
+/// One aligned entry that differs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Difference {
+    pub path: PathBuf,
+    pub kind: DiffKind,
+    pub a: Option<Usage>,
+    pub b: Option<Usage>,
+}
+
+impl Difference {
+    /// Size change from `a` to `b` in bytes
+    pub fn byte_delta(&self) -> i128 {
+        self.b.map_or(0, |u| u.bytes as i128) - self.a.map_or(0, |u| u.bytes as i128)
+    }
+
+    /// File count change from `a` to `b`
+    pub fn file_delta(&self) -> i128 {
+        self.b.map_or(0, |u| u.files as i128) - self.a.map_or(0, |u| u.files as i128)
+    }
+}
+
+/// Result of comparing two trees
+#[derive(Debug, Clone, Serialize)]
+pub struct Comparison {
+    pub a: PathBuf,
+    pub b: PathBuf,
+    pub total_a: Usage,
+    pub total_b: Usage,
+    /// Differences, largest byte difference first
+    pub differences: Vec<Difference>,
+}
+
+/// Summarize every directory and regular file below `root`
+///
+/// Symlinks are not followed and count neither bytes nor files.
+pub fn summarize(root: &Path) -> Tree {
+    summarize_in(&RealFs, root)
+}
+
+/// [`summarize`] on any [`Fs`]
+pub fn summarize_in(fs: &dyn Fs, root: &Path) -> Tree {
+    let mut tree = Tree::new();
+    for entry in vfs::walk(fs, root).filter_map(|e| e.ok()) {
+        let Ok(rel) = entry.path.strip_prefix(root) else {
+            continue;
+        };
+        if entry.meta.is_dir() {
+            tree.entry(rel.to_path_buf()).or_default().dir = true;
+        } else if entry.meta.is_file() {
+            let bytes = entry.meta.len;
+            tree.insert(
+                rel.to_path_buf(),
+                Usage {
+                    bytes,
+                    files: 1,
+                    dir: false,
+                },
+            );
+            for ancestor in rel.ancestors().skip(1) {
+                let usage = tree.entry(ancestor.to_path_buf()).or_default();
+                usage.bytes += bytes;
+                usage.files += 1;
+            }
+        }
+    }
+    tree
+}
+
+/// Align two summarized trees and collect the entries that differ
+pub fn diff(a: &Tree, b: &Tree) -> Vec<Difference> {
+    let mut out = Vec::new();
+    let mut paths: Vec<&PathBuf> = a.keys().chain(b.keys()).collect();
+    paths.sort();
+    paths.dedup();
+    for path in paths {
+        if path.as_os_str().is_empty() {
+            continue;
+        }
+        let (ua, ub) = (a.get(path).copied(), b.get(path).copied());
+        // Below a one-sided entry everything is one-sided too
+        let parent_missing = path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .is_some_and(|p| !a.contains_key(p) || !b.contains_key(p));
+        if parent_missing {
+            continue;
+        }
+        let kind = match (ua, ub) {
+            (Some(_), None) => DiffKind::OnlyInA,
+            (None, Some(_)) => DiffKind::OnlyInB,
+            (Some(x), Some(y)) if x != y => DiffKind::Changed,
+            _ => continue,
+        };
+        out.push(Difference {
+            path: path.clone(),
+            kind,
+            a: ua,
+            b: ub,
+        });
+    }
+    out.sort_by(|x, y| {
+        y.byte_delta()
+            .abs()
+            .cmp(&x.byte_delta().abs())
+            .then(y.file_delta().abs().cmp(&x.file_delta().abs()))
+            .then(x.path.cmp(&y.path))
+    });
+    out
+}
+
+/// Compare the trees at `a` and `b`
+pub fn compare(a: &Path, b: &Path) -> Comparison {
+    let (tree_a, tree_b) = rayon::join(|| summarize(a), || summarize(b));
+    let total = |tree: &Tree| tree.get(Path::new("")).copied().unwrap_or_default();
+    Comparison {
+        a: a.to_path_buf(),
+        b: b.to_path_buf(),
+        total_a: total(&tree_a),
+        total_b: total(&tree_b),
+        differences: diff(&tree_a, &tree_b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_missing_subtree_is_reported_once() {
+        let temp = TempDir::new().unwrap();
+        let (a, b) = (temp.path().join("a"), temp.path().join("b"));
+        fs::create_dir_all(a.join("photos/2020")).unwrap();
+        fs::create_dir_all(b.join("photos")).unwrap();
+        fs::write(a.join("photos/2020/one.jpg"), vec![0u8; 300]).unwrap();
+        fs::write(a.join("photos/2020/two.jpg"), vec![0u8; 200]).unwrap();
+        fs::write(a.join("same.txt"), "same").unwrap();
+        fs::write(b.join("same.txt"), "same").unwrap();
+
+        let cmp = compare(&a, &b);
+
+        assert_eq!(cmp.total_a.bytes, 504);
+        assert_eq!(cmp.total_b.bytes, 4);
+        let paths: Vec<_> = cmp.differences.iter().map(|d| (&d.path, d.kind)).collect();
+        assert_eq!(
+            paths,
+            vec![
+                (&PathBuf::from("photos"), DiffKind::Changed),
+                (&PathBuf::from("photos/2020"), DiffKind::OnlyInA),
+            ]
+        );
+        assert_eq!(cmp.differences[1].file_delta(), -2);
+    }
+
+    #[test]
+    fn test_differences_ranked_by_size() {
+        let temp = TempDir::new().unwrap();
+        let (a, b) = (temp.path().join("a"), temp.path().join("b"));
+        fs::create_dir_all(&a).unwrap();
+        fs::create_dir_all(&b).unwrap();
+        fs::write(a.join("small"), vec![0u8; 10]).unwrap();
+        fs::write(b.join("small"), vec![0u8; 20]).unwrap();
+        fs::write(a.join("big"), vec![0u8; 100]).unwrap();
+        fs::write(b.join("big"), vec![0u8; 1000]).unwrap();
+        fs::write(b.join("new"), vec![0u8; 50]).unwrap();
+
+        let cmp = compare(&a, &b);
+
+        let order: Vec<_> = cmp.differences.iter().map(|d| d.byte_delta()).collect();
+        assert_eq!(order, vec![900, 50, 10]);
+        assert_eq!(cmp.differences[1].kind, DiffKind::OnlyInB);
+    }
+}
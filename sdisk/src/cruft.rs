@@ -0,0 +1,202 @@
+//! Detection of size-less clutter: empty directories, zero-byte files and
+//! dangling symlinks.
+//!
+//! None of these take meaningful space, so the size-based commands never
+//! surface them, but old checkouts and aborted builds leave plenty behind.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::vfs::{self, Fs, RealFs};
+
+/// Kind of clutter found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CruftKind {
+    /// Directory containing nothing but (possibly nested) empty directories
+    EmptyDir,
+    /// Regular file with a length of zero
+    EmptyFile,
+    /// Symbolic link whose target does not exist
+    BrokenSymlink,
+}
+
+impl fmt::Display for CruftKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            CruftKind::EmptyDir => "empty dir",
+            CruftKind::EmptyFile => "empty file",
+            CruftKind::BrokenSymlink => "broken link",
+        };
+        f.pad(label)
+    }
+}
+
+/// A single piece of clutter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cruft {
+    pub path: PathBuf,
+    pub kind: CruftKind,
+    /// Depth below the scanned root, used to order removal bottom-up
+    pub depth: usize,
+}
+
+/// Find clutter below `root`; the root itself is never reported
+///
+/// Anything inside a `.git` directory is left alone, since git relies on some
+/// of its empty directories existing.
+pub fn find(root: &Path) -> Vec<Cruft> {
+    find_in(&RealFs, root)
+}
+
+/// [`find`] on any [`Fs`]
+pub fn find_in(fs: &dyn Fs, root: &Path) -> Vec<Cruft> {
+    let mut found = Vec::new();
+    // Directories known to hold something other than empty directories
+    let mut occupied: HashSet<PathBuf> = HashSet::new();
+    // A reversed pre-order walk visits every entry before its parent, so a
+    // directory's contents are known by the time it is judged.
+    let entries: Vec<_> = vfs::walk(fs, root).collect();
+    for entry in entries.into_iter().rev() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                // An unreadable directory must never look empty
+                occupied.insert(err.path.clone());
+                if let Some(parent) = err.path.parent() {
+                    occupied.insert(parent.to_path_buf());
+                }
+                continue;
+            }
+        };
+        let path = entry.path.as_path();
+        let in_git = path
+            .strip_prefix(root)
+            .map(|rel| rel.components().any(|c| c.as_os_str() == ".git"))
+            .unwrap_or(false);
+        if in_git {
+            if path.file_name().is_some_and(|name| name == ".git") {
+                if let Some(parent) = path.parent() {
+                    occupied.insert(parent.to_path_buf());
+                }
+            }
+            continue;
+        }
+        let kind = if entry.meta.is_dir() {
+            (!occupied.contains(path)).then_some(CruftKind::EmptyDir)
+        } else if entry.meta.is_symlink() {
+            // `metadata` follows the link, so it fails when the target is gone
+            fs.metadata(path)
+                .is_err()
+                .then_some(CruftKind::BrokenSymlink)
+        } else {
+            (entry.meta.is_file() && entry.meta.len == 0).then_some(CruftKind::EmptyFile)
+        };
+        if kind != Some(CruftKind::EmptyDir) {
+            if let Some(parent) = path.parent() {
+                occupied.insert(parent.to_path_buf());
+            }
+        }
+        if entry.depth == 0 {
+            continue;
+        }
+        if let Some(kind) = kind {
+            found.push(Cruft {
+                path: entry.path,
+                kind,
+                depth: entry.depth,
+            });
+        }
+    }
+    found
+}
+
+/// Sort items so that children are removed before their parent directories
+pub fn sort_bottom_up(items: &mut [Cruft]) {
+    items.sort_by(|a, b| b.depth.cmp(&a.depth).then_with(|| a.path.cmp(&b.path)));
+}
+
+/// Remove one item; directories are only removed if they are (still) empty
+pub fn remove(item: &Cruft) -> std::io::Result<()> {
+    remove_in(&RealFs, item)
+}
+
+/// [`remove`] on any [`Fs`]
+pub fn remove_in(fs: &dyn Fs, item: &Cruft) -> std::io::Result<()> {
+    match item.kind {
+        CruftKind::EmptyDir => fs.remove_dir(&item.path),
+        CruftKind::EmptyFile | CruftKind::BrokenSymlink => fs.remove_file(&item.path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn kinds(found: &[Cruft], root: &Path) -> Vec<(String, CruftKind)> {
+        let mut v: Vec<_> = found
+            .iter()
+            .map(|c| {
+                let rel = c.path.strip_prefix(root).unwrap();
+                (rel.to_string_lossy().into_owned(), c.kind)
+            })
+            .collect();
+        v.sort_by(|a, b| a.0.cmp(&b.0));
+        v
+    }
+
+    #[test]
+    fn test_find_recursively_empty_dirs_and_empty_files() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        fs::create_dir_all(root.join("empty/nested/deeper")).unwrap();
+        fs::create_dir_all(root.join("full/sub")).unwrap();
+        fs::write(root.join("full/sub/data.txt"), "data").unwrap();
+        fs::write(root.join("full/zero"), "").unwrap();
+
+        let found = find(root);
+        assert_eq!(
+            kinds(&found, root),
+            vec![
+                ("empty".to_string(), CruftKind::EmptyDir),
+                ("empty/nested".to_string(), CruftKind::EmptyDir),
+                ("empty/nested/deeper".to_string(), CruftKind::EmptyDir),
+                ("full/zero".to_string(), CruftKind::EmptyFile),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_broken_symlinks() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        fs::write(root.join("target"), "x").unwrap();
+        std::os::unix::fs::symlink(root.join("target"), root.join("good")).unwrap();
+        std::os::unix::fs::symlink(root.join("missing"), root.join("bad")).unwrap();
+
+        let found = find(root);
+        assert_eq!(
+            kinds(&found, root),
+            vec![("bad".to_string(), CruftKind::BrokenSymlink)]
+        );
+    }
+
+    #[test]
+    fn test_remove_bottom_up_clears_nested_dirs() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        fs::create_dir_all(root.join("a/b/c")).unwrap();
+        fs::create_dir_all(root.join("repo/.git/refs")).unwrap();
+
+        let mut found = find(root);
+        sort_bottom_up(&mut found);
+        for item in &found {
+            remove(item).unwrap();
+        }
+        assert!(!root.join("a").exists());
+        assert!(root.join("repo/.git/refs").exists());
+    }
+}
@@ -0,0 +1,429 @@
+//! Details about a file or directory, shown before it is deleted.
+//!
+//! Everything here is read-only and bounded: at most a few kilobytes of a
+//! file are read to detect its type from magic bytes, show its first lines
+//! or find image dimensions. Directories list their biggest children, which
+//! takes a walk, so a [`Sizer`] does that on a background thread.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::SystemTime;
+
+use crate::throttle;
+
+/// Bytes read from the start of a file
+const HEAD_BYTES: usize = 8192;
+/// Lines of text shown
+const TEXT_LINES: usize = 8;
+/// Children listed for a directory
+const CHILDREN: usize = 5;
+
+/// What is known about one path
+#[derive(Debug, Clone, Default)]
+pub struct Details {
+    /// Human-readable type, e.g. "PNG image" or "directory"
+    pub kind: String,
+    pub is_dir: bool,
+    /// Bytes in the file; directories are sized by a [`Sizer`]
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    pub accessed: Option<SystemTime>,
+    pub created: Option<SystemTime>,
+    pub owner: Option<String>,
+    /// Width and height in pixels, for images
+    pub dimensions: Option<(u32, u32)>,
+    /// First lines, for text files
+    pub text: Vec<String>,
+}
+
+/// Inspect `path` without following a final symlink; directories are not
+/// walked
+pub fn inspect(path: &Path) -> std::io::Result<Details> {
+    let meta = fs::symlink_metadata(path)?;
+    let mut details = Details {
+        size: meta.len(),
+        modified: meta.modified().ok(),
+        accessed: meta.accessed().ok(),
+        created: meta.created().ok(),
+        owner: owner(&meta),
+        ..Default::default()
+    };
+    if meta.file_type().is_symlink() {
+        details.kind = match fs::read_link(path) {
+            Ok(target) => format!("symlink to {}", target.display()),
+            Err(_) => "symlink".to_string(),
+        };
+    } else if meta.is_dir() {
+        details.kind = "directory".to_string();
+        details.is_dir = true;
+        details.size = 0;
+    } else {
+        let mut head = Vec::with_capacity(HEAD_BYTES);
+        File::open(path)?
+            .take(HEAD_BYTES as u64)
+            .read_to_end(&mut head)?;
+        details.kind = file_kind(&head).to_string();
+        details.dimensions = image_dimensions(&head);
+        if details.kind == "text" {
+            details.text = first_lines(&head);
+        }
+    }
+    Ok(details)
+}
+
+/// Detect a file type from its first bytes
+pub fn file_kind(head: &[u8]) -> &'static str {
+    const SIGNATURES: &[(usize, &[u8], &str)] = &[
+        (0, b"\x89PNG\r\n\x1a\n", "PNG image"),
+        (0, b"\xff\xd8\xff", "JPEG image"),
+        (0, b"GIF87a", "GIF image"),
+        (0, b"GIF89a", "GIF image"),
+        (0, b"BM", "BMP image"),
+        (0, b"%PDF-", "PDF document"),
+        (0, b"PK\x03\x04", "zip archive"),
+        (0, b"\x1f\x8b", "gzip data"),
+        (0, b"BZh", "bzip2 data"),
+        (0, b"\xfd7zXZ\x00", "xz data"),
+        (0, b"\x28\xb5\x2f\xfd", "zstd data"),
+        (0, b"7z\xbc\xaf\x27\x1c", "7-zip archive"),
+        (0, b"Rar!\x1a\x07", "RAR archive"),
+        (257, b"ustar", "tar archive"),
+        (0, b"\x7fELF", "ELF executable"),
+        (0, b"\xcf\xfa\xed\xfe", "Mach-O executable"),
+        (0, b"MZ", "Windows executable"),
+        (0, b"\x00asm", "WebAssembly module"),
+        (0, b"SQLite format 3\x00", "SQLite database"),
+        (0, b"ID3", "MP3 audio"),
+        (0, b"OggS", "Ogg media"),
+        (0, b"fLaC", "FLAC audio"),
+        (4, b"ftyp", "MP4/QuickTime video"),
+        (0, b"\x1a\x45\xdf\xa3", "Matroska/WebM video"),
+    ];
+    if head.starts_with(b"RIFF") && head.get(8..12) == Some(b"WEBP") {
+        return "WebP image";
+    }
+    for (offset, magic, kind) in SIGNATURES {
+        if head.get(*offset..offset + magic.len()) == Some(*magic) {
+            return kind;
+        }
+    }
+    if head.is_empty() {
+        "empty"
+    } else if is_text(head) {
+        "text"
+    } else {
+        "binary data"
+    }
+}
+
+/// Text is valid UTF-8 without NUL bytes (a multi-byte character cut off at
+/// the end of the sample is allowed)
+fn is_text(head: &[u8]) -> bool {
+    if head.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none() && head.len() - e.valid_up_to() < 4,
+    }
+}
+
+fn first_lines(head: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(head)
+        .lines()
+        .take(TEXT_LINES)
+        .map(|line| line.chars().take(120).collect())
+        .collect()
+}
+
+/// Pixel dimensions of PNG, GIF, BMP and JPEG images
+pub fn image_dimensions(head: &[u8]) -> Option<(u32, u32)> {
+    let be32 = |at: usize| Some(u32::from_be_bytes(head.get(at..at + 4)?.try_into().ok()?));
+    let le16 = |at: usize| Some(u16::from_le_bytes(head.get(at..at + 2)?.try_into().ok()?) as u32);
+    let be16 = |at: usize| Some(u16::from_be_bytes(head.get(at..at + 2)?.try_into().ok()?) as u32);
+    let le32 = |at: usize| Some(i32::from_le_bytes(head.get(at..at + 4)?.try_into().ok()?));
+    match file_kind(head) {
+        "PNG image" => Some((be32(16)?, be32(20)?)),
+        "GIF image" => Some((le16(6)?, le16(8)?)),
+        "BMP image" => Some((le32(18)?.unsigned_abs(), le32(22)?.unsigned_abs())),
+        "JPEG image" => {
+            // Walk the marker segments to the first start-of-frame
+            let mut at = 2;
+            while *head.get(at)? == 0xff {
+                let marker = *head.get(at + 1)?;
+                let len = be16(at + 2)? as usize;
+                let is_sof = matches!(marker, 0xc0..=0xcf) && !matches!(marker, 0xc4 | 0xc8 | 0xcc);
+                if is_sof {
+                    return Some((be16(at + 7)?, be16(at + 5)?));
+                }
+                at += 2 + len;
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+/// Immediate children of `dir` with their total sizes, biggest first, the
+/// last entry summing up the children not listed; `None` if `stop` was set
+/// part way
+fn biggest_children(dir: &Path, stop: &AtomicBool) -> Option<Vec<(PathBuf, u64)>> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Some(Vec::new());
+    };
+    let mut children: Vec<(PathBuf, u64)> = Vec::new();
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        let mut size = 0;
+        for e in throttle::walk_dir(&path).filter_map(|e| e.ok()) {
+            if stop.load(Ordering::Relaxed) {
+                return None;
+            }
+            if e.file_type().is_file() {
+                size += e.metadata().map(|m| m.len()).unwrap_or(0);
+            }
+        }
+        children.push((path, size));
+    }
+    children.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+    // Keep the total correct even though only a few are shown
+    let rest: u64 = children.iter().skip(CHILDREN).map(|(_, s)| s).sum();
+    children.truncate(CHILDREN);
+    if rest > 0 {
+        children.push((dir.join("(other entries)"), rest));
+    }
+    Some(children)
+}
+
+/// Sizes the children of directories on a background thread, most recent
+/// request first, so moving through a list never waits on a walk
+#[derive(Debug)]
+pub struct Sizer {
+    shared: Arc<Shared>,
+    sized: HashMap<PathBuf, Vec<(PathBuf, u64)>>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    queue: Mutex<Queue>,
+    wake: Condvar,
+    /// Set when the sizer is dropped; abandons the walk in progress
+    closed: AtomicBool,
+}
+
+#[derive(Debug, Default)]
+struct Queue {
+    /// Served from the back
+    requests: Vec<PathBuf>,
+    /// Being walked now
+    current: Option<PathBuf>,
+    done: Vec<(PathBuf, Vec<(PathBuf, u64)>)>,
+}
+
+impl Sizer {
+    pub fn new() -> Self {
+        let shared = Arc::new(Shared::default());
+        let worker = Arc::clone(&shared);
+        let _ = thread::Builder::new()
+            .name("sdisk-sizer".into())
+            .spawn(move || worker.run());
+        Self {
+            shared,
+            sized: HashMap::new(),
+        }
+    }
+
+    /// Biggest children of `dir` if they have been sized; otherwise queue
+    /// `dir` ahead of earlier requests and return `None`
+    pub fn children(&mut self, dir: &Path) -> Option<&[(PathBuf, u64)]> {
+        let mut queue = self.shared.queue.lock().unwrap_or_else(|e| e.into_inner());
+        self.sized.extend(queue.done.drain(..));
+        if queue.current.as_deref() == Some(dir) {
+            return None;
+        }
+        if !self.sized.contains_key(dir) {
+            queue.requests.retain(|d| d != dir);
+            queue.requests.push(dir.to_path_buf());
+            self.shared.wake.notify_one();
+            return None;
+        }
+        drop(queue);
+        self.sized.get(dir).map(Vec::as_slice)
+    }
+}
+
+impl Default for Sizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Sizer {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Ordering::Relaxed);
+        self.shared.wake.notify_one();
+    }
+}
+
+impl Shared {
+    fn run(&self) {
+        loop {
+            let dir = {
+                let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+                loop {
+                    if self.closed.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    if let Some(dir) = queue.requests.pop() {
+                        queue.current = Some(dir.clone());
+                        break dir;
+                    }
+                    queue = self.wake.wait(queue).unwrap_or_else(|e| e.into_inner());
+                }
+            };
+            let Some(children) = biggest_children(&dir, &self.closed) else {
+                return;
+            };
+            let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+            queue.current = None;
+            queue.done.push((dir, children));
+        }
+    }
+}
+
+#[cfg(unix)]
+fn owner(meta: &fs::Metadata) -> Option<String> {
+    use std::ffi::CStr;
+    use std::os::unix::fs::MetadataExt;
+
+    let uid = meta.uid();
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; 4096];
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+    // SAFETY: every pointer refers to a live local of the right size
+    let rc = unsafe { libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+    if rc == 0 && !result.is_null() {
+        // SAFETY: on success pw_name points into `buf`, NUL-terminated
+        let name = unsafe { CStr::from_ptr(pwd.pw_name) };
+        return Some(name.to_string_lossy().into_owned());
+    }
+    Some(uid.to_string())
+}
+
+#[cfg(not(unix))]
+fn owner(_meta: &fs::Metadata) -> Option<String> {
+    None
+}
+
+/// Format a timestamp as `YYYY-MM-DD HH:MM UTC`
+pub fn format_time(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let (days, rem) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_magic_bytes() {
+        assert_eq!(file_kind(b"\x89PNG\r\n\x1a\n...."), "PNG image");
+        assert_eq!(file_kind(b"%PDF-1.7"), "PDF document");
+        assert_eq!(file_kind(b"RIFF\0\0\0\0WEBPVP8 "), "WebP image");
+        assert_eq!(file_kind(b"\0\0\0\x18ftypmp42"), "MP4/QuickTime video");
+        assert_eq!(file_kind("héllo\nworld".as_bytes()), "text");
+        assert_eq!(file_kind(&"é".as_bytes()[..1]), "text");
+        assert_eq!(file_kind(b"\x00\x01\x02"), "binary data");
+        assert_eq!(file_kind(b""), "empty");
+    }
+
+    #[test]
+    fn test_image_dimensions() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        png.extend_from_slice(&640u32.to_be_bytes());
+        png.extend_from_slice(&480u32.to_be_bytes());
+        assert_eq!(image_dimensions(&png), Some((640, 480)));
+
+        assert_eq!(
+            image_dimensions(b"GIF89a\x20\x03\x58\x02"),
+            Some((800, 600))
+        );
+
+        // SOI, an APP0 segment, then SOF0 with height 100 and width 200
+        let jpeg = b"\xff\xd8\xff\xe0\x00\x04\x00\x00\xff\xc0\x00\x11\x08\x00\x64\x00\xc8";
+        assert_eq!(image_dimensions(jpeg), Some((200, 100)));
+    }
+
+    #[test]
+    fn test_inspect_text_file_and_directory() {
+        let temp = TempDir::new().unwrap();
+        let dir = temp.path().join("project");
+        fs::create_dir_all(dir.join("build")).unwrap();
+        fs::write(dir.join("build/out.bin"), vec![1u8; 500]).unwrap();
+        fs::write(dir.join("notes.txt"), "first\nsecond\n").unwrap();
+
+        let file = inspect(&dir.join("notes.txt")).unwrap();
+        assert_eq!(file.kind, "text");
+        assert_eq!(file.text, vec!["first", "second"]);
+        assert!(file.modified.is_some());
+
+        let d = inspect(&dir).unwrap();
+        assert_eq!(d.kind, "directory");
+        assert!(d.is_dir);
+        let children = biggest_children(&dir, &AtomicBool::new(false)).unwrap();
+        assert_eq!(children.iter().map(|(_, s)| s).sum::<u64>(), 513);
+        assert_eq!(children[0], (dir.join("build"), 500));
+    }
+
+    #[test]
+    fn test_sizer_answers_in_the_background() {
+        let temp = TempDir::new().unwrap();
+        fs::create_dir_all(temp.path().join("a")).unwrap();
+        fs::write(temp.path().join("a/blob"), vec![0u8; 64]).unwrap();
+
+        let mut sizer = Sizer::new();
+        let start = std::time::Instant::now();
+        let children = loop {
+            if let Some(children) = sizer.children(temp.path()) {
+                break children.to_vec();
+            }
+            assert!(start.elapsed() < Duration::from_secs(5), "never sized");
+            thread::sleep(Duration::from_millis(5));
+        };
+        assert_eq!(children, vec![(temp.path().join("a"), 64)]);
+    }
+
+    #[test]
+    fn test_format_time() {
+        let t = SystemTime::UNIX_EPOCH + Duration::from_secs(951_827_696);
+        assert_eq!(format_time(t), "2000-02-29 12:34 UTC");
+    }
+}
@@ -0,0 +1,202 @@
+//! Detectors that turn a scan root into removal candidates.
+//!
+//! Each category wraps one of the analyses sdisk already performs and reports
+//! candidates whose removal frees space without losing irreplaceable data.
+//! They feed the Prometheus exporter and unattended cleanup runs.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::vfs::{self, Fs, RealFs};
+use crate::{cruft, logs, repos};
+
+/// Detector category
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    /// Files not accessed or modified for the stale threshold
+    Stale,
+    /// Rotated and compressed log files
+    Logs,
+    /// Ignored content (build output) of repositories without local work
+    Repos,
+    /// Empty directories, zero-byte files and broken symlinks
+    Cruft,
+}
+
+impl Category {
+    pub const ALL: [Category; 4] = [
+        Category::Stale,
+        Category::Logs,
+        Category::Repos,
+        Category::Cruft,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Category::Stale => "stale",
+            Category::Logs => "logs",
+            Category::Repos => "repos",
+            Category::Cruft => "cruft",
+        }
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+/// A path a detector considers safe to remove
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub path: PathBuf,
+    pub size: u64,
+    pub category: Category,
+    /// Last access (or modification) time, where meaningful
+    pub last_used: Option<SystemTime>,
+}
+
+/// Run one detector over `root`
+pub fn detect(category: Category, root: &Path, stale_days: u64) -> Vec<Candidate> {
+    let candidate = |path: PathBuf, size: u64, last_used: Option<SystemTime>| Candidate {
+        path,
+        size,
+        category,
+        last_used,
+    };
+    match category {
+        Category::Stale => stale_files(root, stale_days)
+            .into_iter()
+            .map(|(path, size, time)| candidate(path, size, Some(time)))
+            .collect(),
+        Category::Logs => logs::find(root)
+            .into_iter()
+            .filter(|l| matches!(l.kind, logs::LogKind::Rotated | logs::LogKind::Compressed))
+            .map(|l| candidate(l.path, l.size, Some(l.modified)))
+            .collect(),
+        Category::Repos => repos::find(root)
+            .into_par_iter()
+            .filter_map(|path| repos::analyze(&path).ok())
+            .filter(|r| !r.has_local_work())
+            .flat_map(|r| r.ignored)
+            .map(|(path, size)| candidate(path, size, None))
+            .collect(),
+        Category::Cruft => cruft::find(root)
+            .into_iter()
+            .map(|c| candidate(c.path, 0, None))
+            .collect(),
+    }
+}
+
+/// Regular files below `root` last used more than `days` ago
+///
+/// Only files are reported (not directories) so totals never count the same
+/// bytes twice.
+pub fn stale_files(root: &Path, days: u64) -> Vec<(PathBuf, u64, SystemTime)> {
+    stale_files_in(&RealFs, root, days)
+}
+
+/// [`stale_files`] on any [`Fs`]; unreadable directories are skipped
+pub fn stale_files_in(fs: &dyn Fs, root: &Path, days: u64) -> Vec<(PathBuf, u64, SystemTime)> {
+    stale_entries_in(fs, root, days, &mut |_| {})
+        .into_iter()
+        .filter(|e| e.meta.is_file())
+        .map(|e| {
+            let time = e.meta.last_used().unwrap_or(SystemTime::UNIX_EPOCH);
+            (e.path, e.meta.len, time)
+        })
+        .collect()
+}
+
+/// Files, directories and links below `root` (and `root` itself) last used
+/// more than `days` ago, with their full metadata; `visit` sees every entry
+/// walked, e.g. to total the tree for `--record`
+pub fn stale_entries_in(
+    fs: &dyn Fs,
+    root: &Path,
+    days: u64,
+    visit: &mut dyn FnMut(&vfs::Entry),
+) -> Vec<vfs::Entry> {
+    let cutoff = SystemTime::now() - Duration::from_secs(days * 24 * 60 * 60);
+    vfs::walk(fs, root)
+        .filter_map(|e| e.ok())
+        .inspect(|e| visit(e))
+        // Prefer last access; fall back to modified
+        .filter(|e| e.meta.last_used().unwrap_or(SystemTime::UNIX_EPOCH) <= cutoff)
+        .collect()
+}
+
+/// Regular files at most `max_depth` levels below `root`, largest first;
+/// `visit` sees every entry walked
+pub fn largest_files_in(
+    fs: &dyn Fs,
+    root: &Path,
+    max_depth: usize,
+    visit: &mut dyn FnMut(&vfs::Entry),
+) -> Vec<vfs::Entry> {
+    let mut files: Vec<vfs::Entry> = vfs::walk(fs, root)
+        .max_depth(max_depth)
+        .filter_map(|e| e.ok())
+        .inspect(|e| visit(e))
+        .filter(|e| e.meta.is_file())
+        .collect();
+    files.sort_by_key(|e| std::cmp::Reverse(e.meta.len));
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use filetime::FileTime;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_stale_files_uses_access_time() {
+        let temp = TempDir::new().unwrap();
+        let old = temp.path().join("old.bin");
+        let fresh = temp.path().join("fresh.bin");
+        fs::write(&old, vec![0u8; 100]).unwrap();
+        fs::write(&fresh, vec![0u8; 10]).unwrap();
+        let long_ago =
+            FileTime::from_system_time(SystemTime::now() - Duration::from_secs(200 * 24 * 60 * 60));
+        filetime::set_file_times(&old, long_ago, long_ago).unwrap();
+
+        let stale = stale_files(temp.path(), 90);
+
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].0, old);
+        assert_eq!(stale[0].1, 100);
+    }
+
+    #[test]
+    fn test_log_detector_skips_live_logs() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("app.log"), "live").unwrap();
+        fs::write(temp.path().join("app.log.1"), "rotated").unwrap();
+
+        let found = detect(Category::Logs, temp.path(), 90);
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].path, temp.path().join("app.log.1"));
+        assert_eq!(found[0].category, Category::Logs);
+    }
+}
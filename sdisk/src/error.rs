@@ -17,6 +17,8 @@ pub enum SdiskError {
         path: PathBuf,
         source: walkdir::Error,
     },
+    /// An archive did not match the files it was created from
+    ArchiveVerify { archive: PathBuf, reason: String },
 }
 
 impl fmt::Display for SdiskError {
@@ -46,6 +48,14 @@ impl fmt::Display for SdiskError {
                     source
                 )
             }
+            SdiskError::ArchiveVerify { archive, reason } => {
+                write!(
+                    f,
+                    "Archive verification failed for '{}': {}",
+                    archive.display(),
+                    reason
+                )
+            }
         }
     }
 }
@@ -0,0 +1,245 @@
+//! Prometheus node_exporter textfile output.
+//!
+//! Metrics are rendered in the text exposition format and written atomically
+//! (temporary file plus rename in the same directory), so the textfile
+//! collector never reads a half-written file.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+/// A single gauge family with its samples
+#[derive(Debug, Clone)]
+pub struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    samples: Vec<(Vec<(&'static str, String)>, f64)>,
+}
+
+impl Gauge {
+    pub fn new(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Add a sample with the given label pairs
+    pub fn sample(&mut self, labels: &[(&'static str, String)], value: f64) -> &mut Self {
+        self.samples.push((labels.to_vec(), value));
+        self
+    }
+}
+
+/// Escape a label value as required by the exposition format
+pub fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+/// Render gauges in the Prometheus text exposition format
+pub fn render(gauges: &[Gauge]) -> String {
+    let mut out = String::new();
+    for gauge in gauges {
+        if gauge.samples.is_empty() {
+            continue;
+        }
+        let _ = writeln!(out, "# HELP {} {}", gauge.name, gauge.help);
+        let _ = writeln!(out, "# TYPE {} gauge", gauge.name);
+        for (labels, value) in &gauge.samples {
+            out.push_str(gauge.name);
+            if !labels.is_empty() {
+                let rendered: Vec<String> = labels
+                    .iter()
+                    .map(|(k, v)| format!("{}=\"{}\"", k, escape_label(v)))
+                    .collect();
+                let _ = write!(out, "{{{}}}", rendered.join(","));
+            }
+            let _ = writeln!(out, " {}", value);
+        }
+    }
+    out
+}
+
+/// Write `contents` to `path` atomically
+pub fn write_atomic(path: &Path, contents: &str) -> Result<()> {
+    let dir = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let name = path
+        .file_name()
+        .with_context(|| format!("{} is not a file path", path.display()))?;
+    // node_exporter only reads `*.prom`, so the temporary file is ignored
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(name);
+    tmp_name.push(format!(".{}.tmp", std::process::id()));
+    let tmp = dir.join(tmp_name);
+
+    let result = (|| -> std::io::Result<()> {
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, path)
+    })();
+    if result.is_err() {
+        std::fs::remove_file(&tmp).ok();
+    }
+    result.with_context(|| format!("writing metrics to {}", path.display()))
+}
+
+/// Totals per detector category: (bytes, items)
+pub type CategoryTotals = BTreeMap<crate::detect::Category, (u64, u64)>;
+
+/// Inputs for the exporter, gathered by the caller
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// (mount point, device, filesystem, total bytes, available bytes)
+    pub mounts: Vec<(PathBuf, String, String, u64, u64)>,
+    pub inodes: Vec<crate::inodes::MountInodes>,
+    /// (watched directory, total bytes, stale bytes)
+    pub dirs: Vec<(PathBuf, u64, u64)>,
+    pub stale_days: u64,
+    pub reclaimable: CategoryTotals,
+    pub held_bytes: Option<u64>,
+    pub duration_secs: f64,
+    pub timestamp_secs: u64,
+}
+
+/// Build the gauge families for collected metrics
+pub fn gauges(metrics: &Metrics) -> Vec<Gauge> {
+    let mut size = Gauge::new("sdisk_mount_size_bytes", "Filesystem size in bytes.");
+    let mut avail = Gauge::new(
+        "sdisk_mount_avail_bytes",
+        "Filesystem space available in bytes.",
+    );
+    let mut used = Gauge::new("sdisk_mount_used_bytes", "Filesystem space used in bytes.");
+    for (mount, device, fstype, total, available) in &metrics.mounts {
+        let labels = [
+            ("mountpoint", mount.display().to_string()),
+            ("device", device.clone()),
+            ("fstype", fstype.clone()),
+        ];
+        size.sample(&labels, *total as f64);
+        avail.sample(&labels, *available as f64);
+        used.sample(&labels, total.saturating_sub(*available) as f64);
+    }
+
+    let mut inodes_total = Gauge::new("sdisk_mount_inodes_total", "Filesystem inode count.");
+    let mut inodes_free = Gauge::new("sdisk_mount_inodes_free", "Filesystem free inodes.");
+    for mount in metrics.inodes.iter().filter(|m| m.total > 0) {
+        let labels = [("mountpoint", mount.mount_point.display().to_string())];
+        inodes_total.sample(&labels, mount.total as f64);
+        inodes_free.sample(&labels, mount.free as f64);
+    }
+
+    let mut dir_size = Gauge::new(
+        "sdisk_directory_size_bytes",
+        "Total size of regular files below a watched directory.",
+    );
+    let mut stale = Gauge::new(
+        "sdisk_directory_stale_bytes",
+        "Bytes in files below a watched directory not used within the stale threshold.",
+    );
+    for (dir, total, stale_bytes) in &metrics.dirs {
+        let path = dir.display().to_string();
+        dir_size.sample(&[("path", path.clone())], *total as f64);
+        stale.sample(
+            &[
+                ("path", path),
+                ("stale_days", metrics.stale_days.to_string()),
+            ],
+            *stale_bytes as f64,
+        );
+    }
+
+    let mut reclaim_bytes = Gauge::new(
+        "sdisk_reclaimable_bytes",
+        "Bytes a detector considers safe to reclaim below the watched directories.",
+    );
+    let mut reclaim_items = Gauge::new(
+        "sdisk_reclaimable_items",
+        "Number of candidates a detector found below the watched directories.",
+    );
+    for (category, (bytes, items)) in &metrics.reclaimable {
+        let labels = [("category", category.to_string())];
+        reclaim_bytes.sample(&labels, *bytes as f64);
+        reclaim_items.sample(&labels, *items as f64);
+    }
+
+    let mut held = Gauge::new(
+        "sdisk_held_deleted_bytes",
+        "Bytes in deleted files still held open by a process.",
+    );
+    if let Some(bytes) = metrics.held_bytes {
+        held.sample(&[], bytes as f64);
+    }
+
+    let mut duration = Gauge::new(
+        "sdisk_scan_duration_seconds",
+        "Time taken to collect these metrics.",
+    );
+    duration.sample(&[], metrics.duration_secs);
+    let mut last_run = Gauge::new(
+        "sdisk_last_run_timestamp_seconds",
+        "Unix time these metrics were collected.",
+    );
+    last_run.sample(&[], metrics.timestamp_secs as f64);
+
+    vec![
+        size,
+        avail,
+        used,
+        inodes_total,
+        inodes_free,
+        dir_size,
+        stale,
+        reclaim_bytes,
+        reclaim_items,
+        held,
+        duration,
+        last_run,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_render_text_format() {
+        let mut gauge = Gauge::new("sdisk_test_bytes", "A test gauge.");
+        gauge.sample(&[("path", "/data/\"odd\"\\dir".to_string())], 42.0);
+        gauge.sample(&[], 1.5);
+
+        let text = render(&[gauge, Gauge::new("sdisk_empty", "Never rendered.")]);
+
+        assert_eq!(
+            text,
+            "# HELP sdisk_test_bytes A test gauge.\n\
+             # TYPE sdisk_test_bytes gauge\n\
+             sdisk_test_bytes{path=\"/data/\\\"odd\\\"\\\\dir\"} 42\n\
+             sdisk_test_bytes 1.5\n"
+        );
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_file() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("sdisk.prom");
+        std::fs::write(&path, "old").unwrap();
+
+        write_atomic(&path, "new\n").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new\n");
+        let leftovers = std::fs::read_dir(temp.path()).unwrap().count();
+        assert_eq!(leftovers, 1);
+    }
+}
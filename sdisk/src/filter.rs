@@ -0,0 +1,569 @@
+//! Filter expressions for narrowing scan results.
+//!
+//! ```text
+//! size > 100M and mtime < 30d and ext in (log, gz) and not path ~ "backups"
+//! ```
+//!
+//! Comparisons are `FIELD OP VALUE`, combined with `and`, `or`, `not` and
+//! parentheses (`and` binds tighter than `or`). Fields:
+//!
+//! - `size`: bytes (directories count their contents); values like `512`,
+//!   `100M` or `1.5GiB`
+//! - `mtime`, `atime`: last modification and access; a value like `30d` is
+//!   the moment 30 days ago, so `mtime < 30d` means "not modified in the last
+//!   30 days". Units are `s`, `m`/`min`, `h`, `d`, `w` and `y`
+//! - `name`, `ext`, `path`: file name, extension (case-insensitive, without
+//!   the dot) and full path
+//! - `type`: `file`, `dir`, `symlink` or `other`
+//!
+//! Numbers and times take `=`, `!=`, `<`, `<=`, `>` and `>=`; text takes
+//! `=`, `!=`, `~` and `!~` (regular expression search), and `in (a, b, ...)`.
+//! Values containing spaces or operator characters are quoted with `"` or `'`.
+
+use std::fmt;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use regex::Regex;
+
+use crate::units;
+use crate::vfs::{Kind, Meta};
+
+/// A parse error and the character column (from 1) where it was found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.column)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl ParseError {
+    /// The error followed by `source` with a caret under the offending spot
+    pub fn render(&self, source: &str) -> String {
+        format!(
+            "{}\n  {}\n  {}^",
+            self,
+            source,
+            " ".repeat(self.column.saturating_sub(1))
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Size,
+    Mtime,
+    Atime,
+    Name,
+    Ext,
+    Path,
+    Type,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Match,
+    NotMatch,
+    In,
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Size(u64),
+    /// How long before "now"
+    Ago(Duration),
+    Text(String),
+    Pattern(Regex),
+    List(Vec<String>),
+    Kind(Kind),
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare { field: Field, op: Op, value: Value },
+}
+
+/// A parsed filter expression
+#[derive(Debug, Clone)]
+pub struct Filter {
+    expr: Expr,
+    /// Reference point for relative times
+    now: SystemTime,
+}
+
+impl Filter {
+    pub fn parse(source: &str) -> Result<Filter, ParseError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            end: source.chars().count() + 1,
+        };
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(parser.error_at(token, "expected `and`, `or` or the end"));
+        }
+        Ok(Filter {
+            expr,
+            now: SystemTime::now(),
+        })
+    }
+
+    /// Whether an entry at `path` with total `size` and `meta` matches
+    pub fn matches(&self, path: &Path, size: u64, meta: &Meta) -> bool {
+        self.eval(&self.expr, path, size, meta)
+    }
+
+    fn eval(&self, expr: &Expr, path: &Path, size: u64, meta: &Meta) -> bool {
+        match expr {
+            Expr::And(a, b) => self.eval(a, path, size, meta) && self.eval(b, path, size, meta),
+            Expr::Or(a, b) => self.eval(a, path, size, meta) || self.eval(b, path, size, meta),
+            Expr::Not(e) => !self.eval(e, path, size, meta),
+            Expr::Compare { field, op, value } => {
+                let text = |s: Option<&std::ffi::OsStr>| {
+                    s.map(|s| s.to_string_lossy().into_owned())
+                        .unwrap_or_default()
+                };
+                match field {
+                    Field::Size => compare_ord(&size, *op, value_size(value)),
+                    Field::Mtime | Field::Atime => {
+                        let time = if *field == Field::Mtime {
+                            meta.modified
+                        } else {
+                            meta.accessed
+                        };
+                        let (Some(time), Value::Ago(ago)) = (time, value) else {
+                            return false;
+                        };
+                        let then = self.now.checked_sub(*ago).unwrap_or(SystemTime::UNIX_EPOCH);
+                        compare_ord(&time, *op, then)
+                    }
+                    Field::Name => compare_text(&text(path.file_name()), *op, value, false),
+                    Field::Ext => compare_text(&text(path.extension()), *op, value, true),
+                    Field::Path => compare_text(&path.to_string_lossy(), *op, value, false),
+                    Field::Type => {
+                        let Value::Kind(kind) = value else {
+                            return false;
+                        };
+                        (meta.kind == *kind) == (*op == Op::Eq)
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn value_size(value: &Value) -> u64 {
+    match value {
+        Value::Size(n) => *n,
+        _ => 0,
+    }
+}
+
+fn compare_ord<T: PartialOrd>(left: &T, op: Op, right: T) -> bool {
+    match op {
+        Op::Eq => *left == right,
+        Op::Ne => *left != right,
+        Op::Lt => *left < right,
+        Op::Le => *left <= right,
+        Op::Gt => *left > right,
+        Op::Ge => *left >= right,
+        _ => false,
+    }
+}
+
+fn compare_text(left: &str, op: Op, value: &Value, fold: bool) -> bool {
+    let eq = |a: &str, b: &str| {
+        if fold {
+            a.eq_ignore_ascii_case(b)
+        } else {
+            a == b
+        }
+    };
+    match (op, value) {
+        (Op::Eq, Value::Text(t)) => eq(left, t),
+        (Op::Ne, Value::Text(t)) => !eq(left, t),
+        (Op::Match, Value::Pattern(re)) => re.is_match(left),
+        (Op::NotMatch, Value::Pattern(re)) => !re.is_match(left),
+        (Op::In, Value::List(items)) => items.iter().any(|t| eq(left, t)),
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Word(String),
+    Quoted(String),
+    Op(Op),
+    LParen,
+    RParen,
+    Comma,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    tok: Tok,
+    /// Character column, from 1
+    column: usize,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, ParseError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        let two = chars.get(i + 1).copied();
+        let (tok, len) = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => (Tok::LParen, 1),
+            ')' => (Tok::RParen, 1),
+            ',' => (Tok::Comma, 1),
+            '=' if two == Some('=') => (Tok::Op(Op::Eq), 2),
+            '=' => (Tok::Op(Op::Eq), 1),
+            '!' if two == Some('=') => (Tok::Op(Op::Ne), 2),
+            '!' if two == Some('~') => (Tok::Op(Op::NotMatch), 2),
+            '<' if two == Some('=') => (Tok::Op(Op::Le), 2),
+            '<' => (Tok::Op(Op::Lt), 1),
+            '>' if two == Some('=') => (Tok::Op(Op::Ge), 2),
+            '>' => (Tok::Op(Op::Gt), 1),
+            '~' => (Tok::Op(Op::Match), 1),
+            '"' | '\'' => {
+                let mut text = String::new();
+                let mut j = i + 1;
+                loop {
+                    match chars.get(j) {
+                        None => {
+                            return Err(ParseError {
+                                column,
+                                message: "unterminated string".to_string(),
+                            })
+                        }
+                        Some(&q) if q == c => break,
+                        Some('\\') if j + 1 < chars.len() => {
+                            text.push(chars[j + 1]);
+                            j += 2;
+                        }
+                        Some(&other) => {
+                            text.push(other);
+                            j += 1;
+                        }
+                    }
+                }
+                (Tok::Quoted(text), j + 1 - i)
+            }
+            _ => {
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|c| !c.is_whitespace() && !"()=!<>~,\"'".contains(**c))
+                    .count();
+                if len == 0 {
+                    return Err(ParseError {
+                        column,
+                        message: format!("unexpected character {:?}", c),
+                    });
+                }
+                (Tok::Word(chars[i..i + len].iter().collect()), len)
+            }
+        };
+        tokens.push(Token { tok, column });
+        i += len;
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Column just past the end of the input
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self, expected: &str) -> Result<Token, ParseError> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.clone())
+            }
+            None => Err(ParseError {
+                column: self.end,
+                message: format!("expected {} but the expression ended", expected),
+            }),
+        }
+    }
+
+    fn error_at(&self, token: &Token, message: impl Into<String>) -> ParseError {
+        ParseError {
+            column: token.column,
+            message: message.into(),
+        }
+    }
+
+    fn keyword(&mut self, word: &str) -> bool {
+        let found = matches!(
+            self.peek(),
+            Some(Token { tok: Tok::Word(w), .. }) if w.eq_ignore_ascii_case(word)
+        );
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.unary()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if matches!(
+            self.peek(),
+            Some(Token {
+                tok: Tok::LParen,
+                ..
+            })
+        ) {
+            self.pos += 1;
+            let expr = self.or()?;
+            let close = self.next("`)`")?;
+            if close.tok != Tok::RParen {
+                return Err(self.error_at(&close, "expected `)`"));
+            }
+            return Ok(expr);
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, ParseError> {
+        let token = self.next("a field")?;
+        let field = match &token.tok {
+            Tok::Word(w) => match w.to_ascii_lowercase().as_str() {
+                "size" => Field::Size,
+                "mtime" => Field::Mtime,
+                "atime" => Field::Atime,
+                "name" => Field::Name,
+                "ext" => Field::Ext,
+                "path" => Field::Path,
+                "type" => Field::Type,
+                _ => {
+                    return Err(self.error_at(
+                        &token,
+                        format!(
+                        "unknown field `{}` (expected size, mtime, atime, name, ext, path or type)",
+                        w
+                    ),
+                    ))
+                }
+            },
+            _ => return Err(self.error_at(&token, "expected a field")),
+        };
+
+        let op_token = self.next("an operator")?;
+        let op = match &op_token.tok {
+            Tok::Op(op) => *op,
+            Tok::Word(w) if w.eq_ignore_ascii_case("in") => Op::In,
+            _ => return Err(self.error_at(&op_token, "expected an operator")),
+        };
+        let numeric = matches!(field, Field::Size | Field::Mtime | Field::Atime);
+        let allowed = match op {
+            Op::Eq | Op::Ne => true,
+            Op::Lt | Op::Le | Op::Gt | Op::Ge => numeric,
+            Op::Match | Op::NotMatch | Op::In => !numeric && field != Field::Type,
+        };
+        if !allowed {
+            return Err(self.error_at(&op_token, "operator does not apply to this field"));
+        }
+
+        if op == Op::In {
+            let value = Value::List(self.list()?);
+            return Ok(Expr::Compare { field, op, value });
+        }
+        let value_token = self.next("a value")?;
+        let text = match &value_token.tok {
+            Tok::Word(w) | Tok::Quoted(w) => w.clone(),
+            _ => return Err(self.error_at(&value_token, "expected a value")),
+        };
+        let value = match (field, op) {
+            (Field::Size, _) => {
+                Value::Size(units::parse_size(&text).map_err(|e| self.error_at(&value_token, e))?)
+            }
+            (Field::Mtime | Field::Atime, _) => {
+                Value::Ago(parse_age(&text).map_err(|e| self.error_at(&value_token, e))?)
+            }
+            (Field::Type, _) => Value::Kind(match text.to_ascii_lowercase().as_str() {
+                "file" | "f" => Kind::File,
+                "dir" | "directory" | "d" => Kind::Dir,
+                "symlink" | "link" | "l" => Kind::Symlink,
+                "other" => Kind::Other,
+                _ => {
+                    return Err(self.error_at(&value_token, "expected file, dir, symlink or other"))
+                }
+            }),
+            (_, Op::Match | Op::NotMatch) => Value::Pattern(
+                Regex::new(&text)
+                    .map_err(|e| self.error_at(&value_token, format!("invalid pattern: {}", e)))?,
+            ),
+            _ => Value::Text(text),
+        };
+        Ok(Expr::Compare { field, op, value })
+    }
+
+    /// `( value, value, ... )`
+    fn list(&mut self) -> Result<Vec<String>, ParseError> {
+        let open = self.next("`(`")?;
+        if open.tok != Tok::LParen {
+            return Err(self.error_at(&open, "expected `(` after `in`"));
+        }
+        let mut items = Vec::new();
+        loop {
+            let token = self.next("a value or `)`")?;
+            match token.tok {
+                Tok::Word(w) | Tok::Quoted(w) => items.push(w),
+                Tok::RParen if items.is_empty() => return Ok(items),
+                _ => return Err(self.error_at(&token, "expected a value")),
+            }
+            let token = self.next("`,` or `)`")?;
+            match token.tok {
+                Tok::Comma => {}
+                Tok::RParen => return Ok(items),
+                _ => return Err(self.error_at(&token, "expected `,` or `)`")),
+            }
+        }
+    }
+}
+
+/// Parse a duration such as `30d`, `12h` or `1.5y`
+fn parse_age(text: &str) -> Result<Duration, String> {
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration {:?}", text))?;
+    let secs = match unit.to_ascii_lowercase().as_str() {
+        "s" => 1,
+        "m" | "min" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        "w" => 7 * 86_400,
+        "y" => 365 * 86_400,
+        "" => {
+            return Err(format!(
+                "duration {:?} needs a unit (s, m, h, d, w, y)",
+                text
+            ))
+        }
+        other => return Err(format!("unknown duration unit {:?} in {:?}", other, text)),
+    };
+    Duration::try_from_secs_f64(number * secs as f64)
+        .map_err(|_| format!("duration {:?} is too long", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn meta(kind: Kind, days_old: u64) -> Meta {
+        let t = SystemTime::now() - Duration::from_secs(days_old * 86_400);
+        Meta {
+            kind,
+            len: 0,
+            modified: Some(t),
+            accessed: Some(t),
+            inode: None,
+            links: 1,
+        }
+    }
+
+    #[test]
+    fn test_example_expression() {
+        let f = Filter::parse(
+            "size > 100M and mtime < 30d and ext in (log, gz) and not path ~ \"backups\"",
+        )
+        .unwrap();
+        let file = meta(Kind::File, 60);
+        let big = 200 << 20;
+
+        assert!(f.matches(&PathBuf::from("/var/log/app.LOG"), big, &file));
+        assert!(f.matches(&PathBuf::from("/var/log/app.gz"), big, &file));
+        assert!(!f.matches(&PathBuf::from("/var/log/app.log"), 1 << 20, &file));
+        assert!(!f.matches(&PathBuf::from("/var/log/app.txt"), big, &file));
+        assert!(!f.matches(&PathBuf::from("/var/backups/app.log"), big, &file));
+        assert!(!f.matches(
+            &PathBuf::from("/var/log/app.log"),
+            big,
+            &meta(Kind::File, 3)
+        ));
+    }
+
+    #[test]
+    fn test_precedence_and_types() {
+        let f = Filter::parse("type = dir or name = a and size >= 1k").unwrap();
+        assert!(f.matches(Path::new("/x"), 0, &meta(Kind::Dir, 0)));
+        assert!(!f.matches(Path::new("/a"), 0, &meta(Kind::File, 0)));
+        assert!(f.matches(Path::new("/a"), 1024, &meta(Kind::File, 0)));
+
+        let f = Filter::parse("(type = dir or name = a) and size >= 1k").unwrap();
+        assert!(!f.matches(Path::new("/x"), 0, &meta(Kind::Dir, 0)));
+    }
+
+    #[test]
+    fn test_errors_point_at_the_problem() {
+        let column = |source: &str| Filter::parse(source).unwrap_err().column;
+        assert_eq!(column("size > 100X"), 8);
+        assert_eq!(column("colour = red"), 1);
+        assert_eq!(column("ext < log"), 5);
+        assert_eq!(column("size > 1 and"), 13);
+        assert_eq!(column("name = \"open"), 8);
+        assert_eq!(column("ext in (log gz)"), 13);
+        assert_eq!(column("size > 1 size"), 10);
+        assert_eq!(column("mtime < 99999999999999999999y"), 9);
+
+        let err = Filter::parse("mtime < 30").unwrap_err();
+        assert_eq!(
+            err.render("mtime < 30"),
+            "duration \"30\" needs a unit (s, m, h, d, w, y) at column 9\n  mtime < 30\n          ^"
+        );
+    }
+}
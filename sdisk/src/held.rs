@@ -0,0 +1,198 @@
+//! Detection of disk space held by deleted-but-open files.
+//!
+//! When a process keeps a file open after it has been unlinked, the blocks are
+//! not freed until the last descriptor is closed. `df` counts that space as
+//! used while `du` can no longer see it. On Linux the open descriptors are
+//! visible under `/proc/<pid>/fd`, where the link target of an unlinked file
+//! carries a ` (deleted)` suffix.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+/// Suffix the kernel appends to `/proc/<pid>/fd/<n>` targets of unlinked files
+const DELETED_SUFFIX: &str = " (deleted)";
+
+/// A deleted file that is still held open by a process
+#[derive(Debug, Clone)]
+pub struct HeldFile {
+    pub pid: u32,
+    pub command: String,
+    pub fd: u32,
+    pub path: PathBuf,
+    pub size: u64,
+    /// Device and inode, used to avoid counting a file twice when several
+    /// descriptors refer to it
+    pub inode: (u64, u64),
+}
+
+/// Result of scanning `/proc` for held files
+#[derive(Debug, Default)]
+pub struct HeldReport {
+    pub files: Vec<HeldFile>,
+    /// Processes whose descriptors could not be read (usually other users')
+    pub inaccessible: usize,
+}
+
+impl HeldReport {
+    /// Total bytes held, counting each underlying file once
+    pub fn total_bytes(&self) -> u64 {
+        let mut seen = std::collections::HashSet::new();
+        self.files
+            .iter()
+            .filter(|f| seen.insert(f.inode))
+            .map(|f| f.size)
+            .sum()
+    }
+}
+
+/// Return the original path if `target` is the link target of an unlinked file
+pub fn deleted_target(target: &Path) -> Option<PathBuf> {
+    let s = target.to_str()?;
+    let path = s.strip_suffix(DELETED_SUFFIX)?;
+    // Sockets, pipes, anonymous inodes and memfds never occupy disk space
+    if !path.starts_with('/') || path.starts_with("/memfd:") {
+        return None;
+    }
+    Some(PathBuf::from(path))
+}
+
+/// Visit every readable `/proc/<pid>/fd/<n>` link, passing the pid, command
+/// name, fd number, fd link path and link target. Returns the number of
+/// processes whose descriptors could not be read.
+#[cfg(target_os = "linux")]
+fn for_each_fd(mut visit: impl FnMut(u32, &str, u32, &Path, &Path)) -> Result<usize> {
+    use anyhow::Context;
+
+    let mut inaccessible = 0;
+    let proc_dir = std::fs::read_dir("/proc").context("reading /proc")?;
+    for entry in proc_dir.filter_map(|e| e.ok()) {
+        let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|s| s.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        let fd_dir = entry.path().join("fd");
+        let fds = match std::fs::read_dir(&fd_dir) {
+            Ok(fds) => fds,
+            Err(_) => {
+                inaccessible += 1;
+                continue;
+            }
+        };
+        let command = std::fs::read_to_string(entry.path().join("comm"))
+            .map(|s| s.trim().to_string())
+            .unwrap_or_else(|_| "?".to_string());
+        for fd_entry in fds.filter_map(|e| e.ok()) {
+            let Some(fd) = fd_entry
+                .file_name()
+                .to_str()
+                .and_then(|s| s.parse::<u32>().ok())
+            else {
+                continue;
+            };
+            let Ok(target) = std::fs::read_link(fd_entry.path()) else {
+                continue;
+            };
+            visit(pid, &command, fd, &fd_entry.path(), &target);
+        }
+    }
+    Ok(inaccessible)
+}
+
+/// Scan all processes for descriptors pointing at deleted files
+#[cfg(target_os = "linux")]
+pub fn scan() -> Result<HeldReport> {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut report = HeldReport::default();
+    report.inaccessible = for_each_fd(|pid, command, fd, fd_path, target| {
+        let Some(path) = deleted_target(target) else {
+            return;
+        };
+        // Following the fd link stats the open file itself, even though
+        // it no longer has a name.
+        let Ok(meta) = std::fs::metadata(fd_path) else {
+            return;
+        };
+        if !meta.is_file() {
+            return;
+        }
+        report.files.push(HeldFile {
+            pid,
+            command: command.to_string(),
+            fd,
+            path,
+            size: meta.len(),
+            inode: (meta.dev(), meta.ino()),
+        });
+    })?;
+    report
+        .files
+        .sort_by_key(|f| (std::cmp::Reverse(f.size), f.pid, f.fd));
+    Ok(report)
+}
+
+/// Paths currently open by any (inspectable) process, with the pid and
+/// command name of one process holding each
+#[cfg(target_os = "linux")]
+pub fn open_files() -> Result<Vec<(PathBuf, u32, String)>> {
+    let mut open = Vec::new();
+    for_each_fd(|pid, command, _, _, target| {
+        if target.is_absolute() && deleted_target(target).is_none() {
+            open.push((target.to_path_buf(), pid, command.to_string()));
+        }
+    })?;
+    Ok(open)
+}
+
+/// Paths currently open by any process (not available on this platform)
+#[cfg(not(target_os = "linux"))]
+pub fn open_files() -> Result<Vec<(PathBuf, u32, String)>> {
+    Ok(Vec::new())
+}
+
+/// Scan all processes for descriptors pointing at deleted files
+#[cfg(not(target_os = "linux"))]
+pub fn scan() -> Result<HeldReport> {
+    anyhow::bail!("`sdisk held` is only supported on Linux (it reads /proc/*/fd)")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deleted_target_detects_suffix() {
+        assert_eq!(
+            deleted_target(Path::new("/var/log/app.log (deleted)")),
+            Some(PathBuf::from("/var/log/app.log"))
+        );
+        assert_eq!(deleted_target(Path::new("/var/log/app.log")), None);
+    }
+
+    #[test]
+    fn test_deleted_target_ignores_pseudo_files() {
+        assert_eq!(deleted_target(Path::new("socket:[12345]")), None);
+        assert_eq!(deleted_target(Path::new("/memfd:buf (deleted)")), None);
+    }
+
+    #[test]
+    fn test_total_counts_each_inode_once() {
+        let file = |pid, fd, size, ino| HeldFile {
+            pid,
+            command: "app".to_string(),
+            fd,
+            path: PathBuf::from("/tmp/x"),
+            size,
+            inode: (1, ino),
+        };
+        let report = HeldReport {
+            files: vec![file(1, 3, 100, 7), file(2, 4, 100, 7), file(1, 5, 50, 8)],
+            inaccessible: 0,
+        };
+        assert_eq!(report.total_bytes(), 150);
+    }
+}
@@ -0,0 +1,339 @@
+//! Usage history and growth trends.
+//!
+//! Scans run with `--record` append one compact line per sample to
+//! `history.jsonl` in the state directory: used/total bytes per mount and the
+//! size of each scanned root and its top-level directories. `sdisk trend`
+//! reads the series back, draws a sparkline and fits a straight line to
+//! estimate when the filesystem fills up.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::state;
+use crate::vfs::{self, Fs};
+
+const HISTORY_FILE: &str = "history.jsonl";
+
+/// Usage of one mount at sample time
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MountSample {
+    #[serde(rename = "m")]
+    pub mount_point: PathBuf,
+    #[serde(rename = "u")]
+    pub used: u64,
+    #[serde(rename = "s")]
+    pub total: u64,
+}
+
+/// One recorded sample
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sample {
+    /// Unix seconds
+    pub t: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mounts: Vec<MountSample>,
+    /// Directory totals: (path, bytes)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dirs: Vec<(PathBuf, u64)>,
+}
+
+/// Append a sample to the history store in the state directory
+pub fn append(sample: &Sample) -> Result<()> {
+    append_to(&state::state_file(HISTORY_FILE)?, sample)
+}
+
+/// Read every sample from the history store in the state directory
+pub fn load() -> Result<Vec<Sample>> {
+    load_from(&state::state_file(HISTORY_FILE)?)
+}
+
+fn append_to(path: &Path, sample: &Sample) -> Result<()> {
+    let line = serde_json::to_string(sample).context("Failed to serialize history sample")?;
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open history {:?}", path))?;
+    writeln!(file, "{}", line).with_context(|| format!("Failed to write history {:?}", path))
+}
+
+fn load_from(path: &Path) -> Result<Vec<Sample>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("Failed to read history {:?}", path))?;
+    // A line cut short by a crash mid-append is skipped rather than fatal
+    Ok(contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Bytes in regular files below a root and below each of its top-level
+/// directories, added up from the entries of a walk the scan makes anyway
+#[derive(Debug, Clone)]
+pub struct DirTotals {
+    root: PathBuf,
+    total: u64,
+    dirs: BTreeMap<PathBuf, u64>,
+    /// Hardlinked files already counted
+    seen: HashSet<(u64, u64)>,
+}
+
+impl DirTotals {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            total: 0,
+            dirs: BTreeMap::new(),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Walk `root` only to total it
+    pub fn scan(fs: &dyn Fs, root: &Path) -> Self {
+        let mut totals = Self::new(root);
+        for entry in vfs::walk(fs, root).filter_map(|e| e.ok()) {
+            totals.add(&entry);
+        }
+        totals
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Count one entry of a walk of the root
+    pub fn add(&mut self, entry: &vfs::Entry) {
+        let Some(first) = entry
+            .path
+            .strip_prefix(&self.root)
+            .ok()
+            .and_then(|rel| rel.components().next())
+        else {
+            return;
+        };
+        let top = self.root.join(first);
+        if entry.depth == 1 && entry.meta.is_dir() {
+            self.dirs.entry(top.clone()).or_default();
+        }
+        if !entry.meta.is_file() {
+            return;
+        }
+        if let Some(inode) = entry.meta.inode.filter(|_| entry.meta.links > 1) {
+            if !self.seen.insert(inode) {
+                return;
+            }
+        }
+        self.total += entry.meta.len;
+        if entry.depth > 1 {
+            *self.dirs.entry(top).or_default() += entry.meta.len;
+        }
+    }
+
+    /// Sample entries: the root first, then its top-level directories, all
+    /// under the canonical path of the root
+    pub fn into_dirs(self) -> Vec<(PathBuf, u64)> {
+        let root = self
+            .root
+            .canonicalize()
+            .unwrap_or_else(|_| self.root.clone());
+        let rebase = |dir: &Path| match dir.strip_prefix(&self.root) {
+            Ok(rel) => root.join(rel),
+            Err(_) => dir.to_path_buf(),
+        };
+        let mut dirs = vec![(root.clone(), self.total)];
+        dirs.extend(self.dirs.iter().map(|(dir, bytes)| (rebase(dir), *bytes)));
+        dirs
+    }
+}
+
+/// A time series: (unix seconds, bytes), oldest first
+pub type Series = Vec<(u64, u64)>;
+
+/// Used bytes of `mount` over time, with the most recent total size
+pub fn mount_series(samples: &[Sample], mount: &Path) -> (Series, Option<u64>) {
+    let mut series = Series::new();
+    let mut total = None;
+    for sample in samples {
+        if let Some(m) = sample.mounts.iter().find(|m| m.mount_point == mount) {
+            series.push((sample.t, m.used));
+            total = Some(m.total);
+        }
+    }
+    series.sort_by_key(|(t, _)| *t);
+    (series, total)
+}
+
+/// Size of directory `dir` over time
+pub fn dir_series(samples: &[Sample], dir: &Path) -> Series {
+    let mut series: Series = samples
+        .iter()
+        .filter_map(|s| {
+            let (_, bytes) = s.dirs.iter().find(|(p, _)| p == dir)?;
+            Some((s.t, *bytes))
+        })
+        .collect();
+    series.sort_by_key(|(t, _)| *t);
+    series
+}
+
+/// Least-squares growth rate in bytes per day, if the series spans any time
+pub fn growth_per_day(series: &[(u64, u64)]) -> Option<f64> {
+    let (first, last) = (series.first()?.0, series.last()?.0);
+    if series.len() < 2 || first == last {
+        return None;
+    }
+    let n = series.len() as f64;
+    // Relative to the first sample to keep the sums well conditioned
+    let xs: Vec<f64> = series.iter().map(|(t, _)| (t - first) as f64).collect();
+    let ys: Vec<f64> = series.iter().map(|(_, v)| *v as f64).collect();
+    let mean_x = xs.iter().sum::<f64>() / n;
+    let mean_y = ys.iter().sum::<f64>() / n;
+    let (mut cov, mut var) = (0.0, 0.0);
+    for (x, y) in xs.iter().zip(&ys) {
+        cov += (x - mean_x) * (y - mean_y);
+        var += (x - mean_x) * (x - mean_x);
+    }
+    Some(cov / var * 86_400.0)
+}
+
+/// Days until `free` bytes are used up at `per_day`, if usage is growing
+pub fn days_until_full(free: u64, per_day: f64) -> Option<f64> {
+    (per_day > 0.0).then(|| free as f64 / per_day)
+}
+
+/// Draw `series` as a sparkline at most `width` characters wide
+///
+/// Samples are grouped into equal time buckets, so gaps in sampling do not
+/// distort the slope; each bucket shows its latest value.
+pub fn sparkline(series: &[(u64, u64)], width: usize) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let (Some(first), Some(last)) = (series.first(), series.last()) else {
+        return String::new();
+    };
+    let columns = width.clamp(1, series.len());
+    let span = (last.0 - first.0).max(1);
+    let mut buckets: Vec<Option<u64>> = vec![None; columns];
+    for (t, v) in series {
+        let i = ((t - first.0) as u128 * (columns as u128 - 1) / span as u128) as usize;
+        buckets[i] = Some(*v);
+    }
+    // Empty buckets repeat the previous value
+    let mut prev = first.1;
+    let values: Vec<u64> = buckets
+        .into_iter()
+        .map(|b| {
+            prev = b.unwrap_or(prev);
+            prev
+        })
+        .collect();
+    let min = *values.iter().min().unwrap_or(&0);
+    let max = *values.iter().max().unwrap_or(&0);
+    values
+        .iter()
+        .map(|v| {
+            if max == min {
+                BARS[0]
+            } else {
+                BARS[((v - min) as u128 * 7 / (max - min) as u128) as usize]
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_dir_totals_from_walk() {
+        let fs = vfs::MemFs::new()
+            .file("/scan/a/x", 10)
+            .hardlink("/scan/a/x", "/scan/a/y")
+            .file("/scan/b/deep/z", 5)
+            .dir("/scan/empty")
+            .file("/scan/top", 1);
+
+        let totals = DirTotals::scan(&fs, Path::new("/scan"));
+
+        assert_eq!(totals.total(), 16);
+        assert_eq!(
+            totals.into_dirs(),
+            vec![
+                (PathBuf::from("/scan"), 16),
+                (PathBuf::from("/scan/a"), 10),
+                (PathBuf::from("/scan/b"), 5),
+                (PathBuf::from("/scan/empty"), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_append_and_load_round_trip() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join(HISTORY_FILE);
+        let sample = Sample {
+            t: 100,
+            mounts: vec![MountSample {
+                mount_point: PathBuf::from("/"),
+                used: 10,
+                total: 100,
+            }],
+            dirs: vec![(PathBuf::from("/srv"), 5)],
+        };
+        append_to(&path, &sample).unwrap();
+        append_to(
+            &path,
+            &Sample {
+                t: 200,
+                ..sample.clone()
+            },
+        )
+        .unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"t\":30")
+            .unwrap();
+
+        let samples = load_from(&path).unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0], sample);
+        assert_eq!(
+            dir_series(&samples, Path::new("/srv")),
+            vec![(100, 5), (200, 5)]
+        );
+        assert_eq!(
+            mount_series(&samples, Path::new("/")),
+            (vec![(100, 10), (200, 10)], Some(100))
+        );
+    }
+
+    #[test]
+    fn test_growth_fit_and_days_until_full() {
+        let day = 86_400;
+        let series = vec![(0, 1000), (day, 1100), (2 * day, 1200), (3 * day, 1300)];
+        let per_day = growth_per_day(&series).unwrap();
+        assert!((per_day - 100.0).abs() < 1e-6);
+        assert_eq!(days_until_full(500, per_day), Some(5.0));
+        assert_eq!(days_until_full(500, -3.0), None);
+        assert_eq!(growth_per_day(&series[..1]), None);
+    }
+
+    #[test]
+    fn test_sparkline() {
+        let series = vec![(0, 0), (10, 70), (20, 35), (30, 70)];
+        assert_eq!(sparkline(&series, 80), "▁█▄█");
+        assert_eq!(sparkline(&series, 2), "▁█");
+        assert_eq!(sparkline(&[], 10), "");
+    }
+}
@@ -0,0 +1,157 @@
+//! Entry-count (inode) analysis.
+//!
+//! Filesystems with many tiny files can run out of inodes long before they
+//! run out of bytes. This module counts files and directories per subtree and
+//! reports per-mount inode usage so the heavy subtrees can be found.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::vfs::{self, RealFs};
+
+/// Entry counts for a single directory
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirCount {
+    pub path: PathBuf,
+    /// Depth below the scanned root (the root itself is 0)
+    pub depth: usize,
+    /// Files (and other non-directories) anywhere below this directory
+    pub files: u64,
+    /// Directories anywhere below this directory
+    pub dirs: u64,
+    /// Immediate children only
+    pub direct: u64,
+}
+
+impl DirCount {
+    /// Total number of entries (and therefore inodes) in the subtree
+    pub fn entries(&self) -> u64 {
+        self.files + self.dirs
+    }
+}
+
+/// Inode usage of a mounted filesystem
+#[derive(Debug, Clone)]
+pub struct MountInodes {
+    pub mount_point: PathBuf,
+    pub total: u64,
+    pub free: u64,
+}
+
+impl MountInodes {
+    pub fn used(&self) -> u64 {
+        self.total.saturating_sub(self.free)
+    }
+
+    /// Used inodes as a percentage, or `None` if the filesystem does not
+    /// report a fixed inode table (e.g. btrfs)
+    pub fn percent_used(&self) -> Option<f64> {
+        (self.total > 0).then(|| self.used() as f64 * 100.0 / self.total as f64)
+    }
+}
+
+/// Count entries below `root`, returning one record per directory; `visit`
+/// sees every entry walked
+pub fn count_tree(root: &Path, visit: &mut dyn FnMut(&vfs::Entry)) -> Vec<DirCount> {
+    let mut counts: HashMap<PathBuf, DirCount> = HashMap::new();
+    for entry in vfs::walk(&RealFs, root).filter_map(|e| e.ok()) {
+        visit(&entry);
+        let is_dir = entry.meta.is_dir();
+        if is_dir {
+            counts
+                .entry(entry.path.clone())
+                .or_insert_with(|| DirCount {
+                    path: entry.path.clone(),
+                    depth: entry.depth,
+                    ..DirCount::default()
+                });
+        }
+        if entry.depth == 0 {
+            continue;
+        }
+        // Credit the entry to every ancestor up to and including the root
+        let mut ancestor = entry.path.parent();
+        let mut direct = true;
+        for _ in 0..entry.depth {
+            let Some(dir) = ancestor else { break };
+            if let Some(count) = counts.get_mut(dir) {
+                if is_dir {
+                    count.dirs += 1;
+                } else {
+                    count.files += 1;
+                }
+                if direct {
+                    count.direct += 1;
+                }
+            }
+            direct = false;
+            ancestor = dir.parent();
+        }
+    }
+    counts.into_values().collect()
+}
+
+/// Inode usage for every mounted filesystem that reports it
+#[cfg(unix)]
+pub fn mount_usage() -> Vec<MountInodes> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let disks = sysinfo::Disks::new_with_refreshed_list();
+    let mut mounts = Vec::new();
+    for disk in disks.list() {
+        let mount_point = disk.mount_point().to_path_buf();
+        let Ok(c_path) = CString::new(mount_point.as_os_str().as_bytes()) else {
+            continue;
+        };
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        // SAFETY: `c_path` is a valid NUL-terminated string and `stat` is a
+        // properly sized, writable statvfs struct.
+        if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+            continue;
+        }
+        mounts.push(MountInodes {
+            mount_point,
+            total: stat.f_files as u64,
+            free: stat.f_ffree as u64,
+        });
+    }
+    mounts
+}
+
+/// Inode usage for every mounted filesystem that reports it
+#[cfg(not(unix))]
+pub fn mount_usage() -> Vec<MountInodes> {
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_count_tree_aggregates_subtrees() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("top.txt"), "x").unwrap();
+        for i in 0..5 {
+            fs::write(root.join(format!("a/b/f{i}")), "x").unwrap();
+        }
+
+        let counts = count_tree(root, &mut |_| {});
+        let get = |p: &Path| counts.iter().find(|c| c.path == p).unwrap().clone();
+
+        let top = get(root);
+        assert_eq!(top.files, 6);
+        assert_eq!(top.dirs, 2);
+        assert_eq!(top.direct, 2);
+
+        let b = get(&root.join("a/b"));
+        assert_eq!(b.entries(), 5);
+        assert_eq!(b.direct, 5);
+        assert_eq!(b.depth, 2);
+    }
+}
@@ -0,0 +1,216 @@
+//! Look inside `.zip` and `.tar(.gz)` archives.
+//!
+//! Members are listed with their uncompressed size and, for zip archives,
+//! their compressed size. Tarballs are compressed as one stream, so only the
+//! archive as a whole has a compressed size. Each member is also checked
+//! against the archive's directory: a file of the same size at the same
+//! relative path means the archived copy is probably redundant.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+
+/// Archive formats that can be listed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl Format {
+    /// Guess the format from the file name
+    pub fn detect(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(Format::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Format::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(Format::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+/// One file stored in an archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Member {
+    pub name: PathBuf,
+    /// Uncompressed size
+    pub size: u64,
+    /// Stored size, where the format records it per member
+    pub compressed: Option<u64>,
+    /// A file of the same size exists at the same path next to the archive
+    pub on_disk: bool,
+}
+
+/// Members of one archive
+#[derive(Debug, Clone)]
+pub struct Listing {
+    /// Size of the archive file itself
+    pub archive_bytes: u64,
+    /// Members, largest first
+    pub members: Vec<Member>,
+}
+
+impl Listing {
+    pub fn uncompressed_bytes(&self) -> u64 {
+        self.members.iter().map(|m| m.size).sum()
+    }
+
+    /// Uncompressed bytes of members that also exist on disk
+    pub fn redundant_bytes(&self) -> u64 {
+        self.members
+            .iter()
+            .filter(|m| m.on_disk)
+            .map(|m| m.size)
+            .sum()
+    }
+}
+
+/// List the members of the archive at `path`
+pub fn list(path: &Path) -> Result<Listing> {
+    let format = Format::detect(path)
+        .with_context(|| format!("{} is not a zip or tar archive", path.display()))?;
+    let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let archive_bytes = file.metadata().map(|m| m.len()).unwrap_or(0);
+    let mut members = match format {
+        Format::Zip => zip_members(file),
+        Format::Tar => tar_members(tar::Archive::new(BufReader::new(file))),
+        Format::TarGz => tar_members(tar::Archive::new(GzDecoder::new(BufReader::new(file)))),
+    }
+    .with_context(|| format!("reading archive {}", path.display()))?;
+
+    let base = path.parent().unwrap_or(Path::new("."));
+    for member in &mut members {
+        member.on_disk = std::fs::symlink_metadata(base.join(&member.name))
+            .map(|m| m.is_file() && m.len() == member.size)
+            .unwrap_or(false);
+    }
+    members.sort_by_key(|m| std::cmp::Reverse(m.size));
+    Ok(Listing {
+        archive_bytes,
+        members,
+    })
+}
+
+fn zip_members(file: File) -> Result<Vec<Member>> {
+    let mut archive = zip::ZipArchive::new(BufReader::new(file))?;
+    let mut members = Vec::new();
+    for i in 0..archive.len() {
+        // Raw access reads only the headers; nothing is decompressed
+        let entry = archive.by_index_raw(i)?;
+        if entry.is_dir() {
+            continue;
+        }
+        // Names that would escape the archive directory are never on disk
+        let Some(name) = entry.enclosed_name() else {
+            continue;
+        };
+        members.push(Member {
+            name,
+            size: entry.size(),
+            compressed: Some(entry.compressed_size()),
+            on_disk: false,
+        });
+    }
+    Ok(members)
+}
+
+fn tar_members<R: std::io::Read>(mut archive: tar::Archive<R>) -> Result<Vec<Member>> {
+    let mut members = Vec::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path()?.into_owned();
+        if name.is_absolute()
+            || name
+                .components()
+                .any(|c| matches!(c, std::path::Component::ParentDir))
+        {
+            continue;
+        }
+        members.push(Member {
+            name,
+            size: entry.size(),
+            compressed: None,
+            on_disk: false,
+        });
+    }
+    Ok(members)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_tar_gz_members_and_redundancy() {
+        let temp = TempDir::new().unwrap();
+        let data = temp.path().join("data");
+        fs::create_dir_all(&data).unwrap();
+        fs::write(data.join("big.bin"), vec![7u8; 4096]).unwrap();
+        fs::write(data.join("small.txt"), "hello").unwrap();
+
+        let path = temp.path().join("data.tar.gz");
+        let enc = flate2::write::GzEncoder::new(
+            File::create(&path).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut builder = tar::Builder::new(enc);
+        builder.append_dir_all("data", &data).unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+        // Only one member still matches what is on disk
+        fs::write(data.join("small.txt"), "changed").unwrap();
+
+        let listing = list(&path).unwrap();
+
+        assert_eq!(Format::detect(&path), Some(Format::TarGz));
+        assert_eq!(listing.members.len(), 2);
+        assert_eq!(listing.members[0].name, PathBuf::from("data/big.bin"));
+        assert_eq!(listing.members[0].compressed, None);
+        assert!(listing.members[0].on_disk);
+        assert!(!listing.members[1].on_disk);
+        assert_eq!(listing.uncompressed_bytes(), 4101);
+        assert_eq!(listing.redundant_bytes(), 4096);
+    }
+
+    #[test]
+    fn test_zip_members_have_compressed_sizes() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("bundle.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+        writer
+            .start_file(
+                "docs/readme.txt",
+                zip::write::SimpleFileOptions::default()
+                    .compression_method(zip::CompressionMethod::Stored),
+            )
+            .unwrap();
+        writer.write_all(b"read me").unwrap();
+        writer.finish().unwrap();
+
+        let listing = list(&path).unwrap();
+
+        assert_eq!(Format::detect(&path), Some(Format::Zip));
+        assert_eq!(
+            listing.members,
+            vec![Member {
+                name: PathBuf::from("docs/readme.txt"),
+                size: 7,
+                compressed: Some(7),
+                on_disk: false,
+            }]
+        );
+    }
+}
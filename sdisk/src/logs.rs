@@ -0,0 +1,354 @@
+//! Log file detection and maintenance.
+//!
+//! Finds live logs (`*.log`), rotations (`*.log.1`, `*.log-20240101`,
+//! `*.log.2.gz`) and systemd journal files, estimates how fast each one grows
+//! by comparing with the snapshot taken on the previous run, and implements
+//! the compress, truncate and delete actions.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+
+use crate::{state, throttle};
+
+/// Snapshot file name inside the state directory
+const SNAPSHOT_FILE: &str = "logs.json";
+
+/// Extensions of already-compressed rotations
+const COMPRESSED_EXTS: &[&str] = &["gz", "xz", "bz2", "zst", "lz4"];
+
+/// Kind of log file, which decides which actions are safe
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogKind {
+    /// Currently written log (`app.log`)
+    Live,
+    /// Uncompressed rotation (`app.log.1`, `app.log-20240101`, `app.log.old`)
+    Rotated,
+    /// Compressed rotation (`app.log.2.gz`)
+    Compressed,
+    /// systemd journal; archived ones carry an `@` in their name
+    Journal { archived: bool },
+}
+
+impl LogKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            LogKind::Live => "live",
+            LogKind::Rotated => "rotated",
+            LogKind::Compressed => "compressed",
+            LogKind::Journal { archived: true } => "journal (archived)",
+            LogKind::Journal { archived: false } => "journal (active)",
+        }
+    }
+}
+
+/// A log file found on disk
+#[derive(Debug, Clone)]
+pub struct LogFile {
+    pub path: PathBuf,
+    pub kind: LogKind,
+    pub size: u64,
+    pub modified: SystemTime,
+    /// Bytes per day since the previous snapshot; `None` on the first run
+    pub growth_per_day: Option<f64>,
+}
+
+/// Actions that can be applied to selected logs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogAction {
+    /// Gzip uncompressed rotations and remove the originals
+    Compress,
+    /// Empty live logs in place, keeping the inode writers hold open
+    Truncate,
+    /// Remove the files
+    Delete,
+}
+
+impl LogAction {
+    /// Whether the action makes sense for a file of the given kind
+    pub fn applies_to(&self, kind: LogKind) -> bool {
+        match self {
+            LogAction::Compress => kind == LogKind::Rotated,
+            LogAction::Truncate => kind == LogKind::Live,
+            // Active journals are owned by journald; use `journalctl --vacuum-*`
+            LogAction::Delete => kind != LogKind::Journal { archived: false },
+        }
+    }
+}
+
+/// Classify a file name, returning `None` for anything that is not a log
+pub fn classify(name: &str) -> Option<LogKind> {
+    if let Some(stem) = name
+        .strip_suffix(".journal")
+        .or_else(|| name.strip_suffix(".journal~"))
+    {
+        return Some(LogKind::Journal {
+            archived: stem.contains('@') || name.ends_with('~'),
+        });
+    }
+    if name.ends_with(".log") {
+        return Some(LogKind::Live);
+    }
+    let (base, compressed) = match name.rsplit_once('.') {
+        Some((base, ext)) if COMPRESSED_EXTS.contains(&ext) => (base, true),
+        _ => (name, false),
+    };
+    let idx = base.find(".log")?;
+    let suffix = &base[idx + ".log".len()..];
+    // `.log.1`, `.log-20240101`, `.log.old` and `.log` itself (when compressed)
+    let rotated = suffix.is_empty()
+        || suffix == ".old"
+        || (suffix.len() > 1
+            && matches!(&suffix[..1], "." | "-" | "_")
+            && suffix[1..].chars().all(|c| c.is_ascii_digit()));
+    match (rotated, compressed) {
+        (true, true) => Some(LogKind::Compressed),
+        (true, false) if !suffix.is_empty() => Some(LogKind::Rotated),
+        _ => None,
+    }
+}
+
+/// Find log files below `root`
+pub fn find(root: &Path) -> Vec<LogFile> {
+    let mut logs = Vec::new();
+    for entry in throttle::walk_dir(root).filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let Some(kind) = entry.file_name().to_str().and_then(classify) else {
+            continue;
+        };
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        logs.push(LogFile {
+            path: entry.path().to_path_buf(),
+            kind,
+            size: meta.len(),
+            modified: meta.modified().unwrap_or(UNIX_EPOCH),
+            growth_per_day: None,
+        });
+    }
+    logs
+}
+
+/// Size of a log as recorded in a snapshot
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Seen {
+    size: u64,
+    /// Unix seconds at which the size was recorded
+    at: u64,
+}
+
+/// Sizes of all logs seen on previous runs
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    files: BTreeMap<PathBuf, Seen>,
+}
+
+impl Snapshot {
+    pub fn load() -> Result<Self> {
+        let path = state::state_file(SNAPSHOT_FILE)?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read log snapshot from {:?}", path))?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse log snapshot from {:?}", path))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = state::state_file(SNAPSHOT_FILE)?;
+        let contents = serde_json::to_string(self).context("Failed to serialize log snapshot")?;
+        fs::write(&path, contents)
+            .with_context(|| format!("Failed to write log snapshot to {:?}", path))
+    }
+
+    /// Fill in `growth_per_day` from the snapshot and record the current sizes
+    pub fn update(&mut self, logs: &mut [LogFile], now: SystemTime) {
+        let now_secs = unix_secs(now);
+        for log in logs.iter_mut() {
+            if let Some(prev) = self.files.get(&log.path) {
+                let elapsed = now_secs.saturating_sub(prev.at);
+                log.growth_per_day = Some(if unix_secs(log.modified) <= prev.at || elapsed == 0 {
+                    // Not written since the previous run
+                    0.0
+                } else {
+                    (log.size as f64 - prev.size as f64) * 86_400.0 / elapsed as f64
+                });
+            }
+            self.files.insert(
+                log.path.clone(),
+                Seen {
+                    size: log.size,
+                    at: now_secs,
+                },
+            );
+        }
+        // Forget rotations that no longer exist so the file stays small
+        self.files.retain(|path, _| path.exists());
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Apply `action` to one log, returning the number of bytes freed
+pub fn apply(action: LogAction, log: &LogFile) -> Result<u64> {
+    match action {
+        LogAction::Compress => compress(&log.path),
+        LogAction::Truncate => {
+            // Truncating (rather than replacing) keeps the inode, so writers
+            // holding the file open carry on appending to the same file.
+            let file = OpenOptions::new()
+                .write(true)
+                .open(&log.path)
+                .with_context(|| format!("opening {}", log.path.display()))?;
+            file.set_len(0)
+                .with_context(|| format!("truncating {}", log.path.display()))?;
+            Ok(log.size)
+        }
+        LogAction::Delete => {
+            fs::remove_file(&log.path)
+                .with_context(|| format!("removing file {}", log.path.display()))?;
+            Ok(log.size)
+        }
+    }
+}
+
+/// Gzip `path` to `path.gz`, preserving its mtime, and remove the original
+fn compress(path: &Path) -> Result<u64> {
+    let mut target = path.as_os_str().to_owned();
+    target.push(".gz");
+    let target = PathBuf::from(target);
+    if target.exists() {
+        anyhow::bail!("{} already exists", target.display());
+    }
+    let meta = fs::metadata(path).with_context(|| format!("reading {}", path.display()))?;
+    let mut input =
+        BufReader::new(File::open(path).with_context(|| format!("opening {}", path.display()))?);
+    let output = File::create(&target).with_context(|| format!("creating {}", target.display()))?;
+    let mut encoder = GzEncoder::new(BufWriter::new(output), Compression::default());
+    let written = std::io::copy(&mut input, &mut encoder)
+        .and_then(|_| encoder.finish())
+        .and_then(|mut w| std::io::Write::flush(&mut w));
+    if let Err(e) = written {
+        fs::remove_file(&target).ok();
+        return Err(e).with_context(|| format!("compressing {}", path.display()));
+    }
+    if let Ok(mtime) = meta.modified() {
+        filetime::set_file_mtime(&target, filetime::FileTime::from_system_time(mtime)).ok();
+    }
+    let compressed = fs::metadata(&target)
+        .with_context(|| format!("reading {}", target.display()))?
+        .len();
+    fs::remove_file(path).with_context(|| format!("removing file {}", path.display()))?;
+    Ok(meta.len().saturating_sub(compressed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_classify_log_names() {
+        assert_eq!(classify("app.log"), Some(LogKind::Live));
+        assert_eq!(classify("app.log.1"), Some(LogKind::Rotated));
+        assert_eq!(classify("app.log-20240101"), Some(LogKind::Rotated));
+        assert_eq!(classify("app.log.old"), Some(LogKind::Rotated));
+        assert_eq!(classify("app.log.2.gz"), Some(LogKind::Compressed));
+        assert_eq!(classify("app.log.gz"), Some(LogKind::Compressed));
+        assert_eq!(
+            classify("system@0005f1-abc.journal"),
+            Some(LogKind::Journal { archived: true })
+        );
+        assert_eq!(
+            classify("system.journal"),
+            Some(LogKind::Journal { archived: false })
+        );
+        assert_eq!(classify("changelog.md"), None);
+        assert_eq!(classify("app.logger"), None);
+        assert_eq!(classify("backup.tar.gz"), None);
+    }
+
+    #[test]
+    fn test_snapshot_growth() {
+        let now = SystemTime::now();
+        let day_ago = now - Duration::from_secs(86_400);
+        let mut snapshot = Snapshot::default();
+        snapshot.files.insert(
+            PathBuf::from("/var/log/app.log"),
+            Seen {
+                size: 1000,
+                at: unix_secs(day_ago),
+            },
+        );
+        snapshot.files.insert(
+            PathBuf::from("/var/log/old.log.1"),
+            Seen {
+                size: 500,
+                at: unix_secs(day_ago),
+            },
+        );
+        let log = |path: &str, size, modified| LogFile {
+            path: PathBuf::from(path),
+            kind: LogKind::Live,
+            size,
+            modified,
+            growth_per_day: None,
+        };
+        let mut logs = vec![
+            log("/var/log/app.log", 3000, now),
+            log("/var/log/old.log.1", 500, day_ago - Duration::from_secs(60)),
+            log("/var/log/new.log", 10, now),
+        ];
+
+        snapshot.update(&mut logs, now);
+
+        let growth = logs[0].growth_per_day.unwrap();
+        assert!((growth - 2000.0).abs() < 1.0);
+        assert_eq!(logs[1].growth_per_day, Some(0.0));
+        assert_eq!(logs[2].growth_per_day, None);
+    }
+
+    #[test]
+    fn test_truncate_keeps_inode_and_compress_replaces_file() {
+        let temp = TempDir::new().unwrap();
+        let live = temp.path().join("app.log");
+        let rotated = temp.path().join("app.log.1");
+        fs::write(&live, "line\n".repeat(100)).unwrap();
+        fs::write(&rotated, "line\n".repeat(1000)).unwrap();
+        let mut logs = find(temp.path());
+        logs.sort_by(|a, b| a.path.cmp(&b.path));
+
+        #[cfg(unix)]
+        let inode = {
+            use std::os::unix::fs::MetadataExt;
+            fs::metadata(&live).unwrap().ino()
+        };
+        assert_eq!(apply(LogAction::Truncate, &logs[0]).unwrap(), 500);
+        assert_eq!(fs::metadata(&live).unwrap().len(), 0);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(fs::metadata(&live).unwrap().ino(), inode);
+        }
+
+        assert!(apply(LogAction::Compress, &logs[1]).unwrap() > 0);
+        assert!(!rotated.exists());
+        assert!(temp.path().join("app.log.1.gz").exists());
+    }
+}
@@ -1,15 +1,37 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 use console::style;
-use dialoguer::{theme::ColorfulTheme, MultiSelect};
+use dialoguer::{theme::ColorfulTheme, Input, MultiSelect, Select};
 use humansize::{format_size, BINARY};
 use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
 use sysinfo::Disks;
-use walkdir::WalkDir;
 
+mod archive;
+mod auto;
+mod compare;
+mod cruft;
+mod details;
+mod detect;
 mod error;
+mod export;
+mod filter;
+mod held;
+mod history;
+mod inodes;
+mod inspect;
+mod logs;
+mod picker;
+mod repos;
+mod safety;
+mod state;
+#[cfg(test)]
+mod tests;
+mod throttle;
+mod units;
+mod vfs;
 use error::SdiskError;
 
 /// sdisk: Analyze disk usage and suggest cleanups
@@ -33,6 +55,29 @@ struct Cli {
     /// Dry run: show what would be removed
     #[arg(global = true, long)]
     dry_run: bool,
+    /// Allow removing mount points, system paths, paths outside the scanned
+    /// roots and git content with uncommitted changes
+    #[arg(global = true, long)]
+    force_unsafe: bool,
+    /// List the members of .zip/.tar/.tar.gz archives found by top and stale
+    #[arg(global = true, long)]
+    inspect_archives: bool,
+
+    /// Append per-mount and per-directory totals to the history used by `sdisk trend`
+    /// (info, top, stale, clean, export, inodes)
+    #[arg(global = true, long)]
+    record: bool,
+
+    /// Lower the I/O priority of the scan (Linux)
+    #[arg(global = true, long, value_enum, value_name = "LEVEL")]
+    io_nice: Option<throttle::IoNice>,
+    /// Visit at most N directory entries per second across all scans
+    #[arg(global = true, long, value_name = "N")]
+    max_rate: Option<u64>,
+    /// Use at most N threads for parallel scanning (the roots of top, stale
+    /// and export, repositories, detectors); each root is walked by one thread
+    #[arg(global = true, long, value_name = "N")]
+    threads: Option<usize>,
 
     #[command(subcommand)]
     command: Option<Commands>,
@@ -47,6 +92,10 @@ enum Commands {
         /// Number of entries to show
         #[arg(short, long, default_value_t = 20)]
         count: usize,
+        /// Only list results matching EXPR, e.g.
+        /// `size > 100M and mtime < 30d and ext in (log, gz) and not path ~ "backups"`
+        #[arg(long, value_name = "EXPR", value_parser = parse_filter)]
+        filter: Option<filter::Filter>,
         /// Optional paths to analyze (defaults to CWD if none and no --path)
         #[arg(value_name = "PATH")]
         paths: Vec<PathBuf>,
@@ -56,6 +105,13 @@ enum Commands {
         /// Show at most N items
         #[arg(short, long, default_value_t = 100)]
         limit: usize,
+        /// Directory for the interactive "archive" action
+        #[arg(long, value_name = "DIR")]
+        archive_to: Option<PathBuf>,
+        /// Only list results matching EXPR, e.g.
+        /// `size > 100M and mtime < 30d and ext in (log, gz) and not path ~ "backups"`
+        #[arg(long, value_name = "EXPR", value_parser = parse_filter)]
+        filter: Option<filter::Filter>,
         /// Optional paths to analyze (defaults to CWD if none and no --path)
         #[arg(value_name = "PATH")]
         paths: Vec<PathBuf>,
@@ -65,31 +121,262 @@ enum Commands {
         /// Show at most N candidates
         #[arg(short, long, default_value_t = 100)]
         limit: usize,
+        /// Archive candidates into DIR instead of deleting them
+        #[arg(long, value_name = "DIR")]
+        archive_to: Option<PathBuf>,
+        /// Only list results matching EXPR, e.g.
+        /// `size > 100M and mtime < 30d and ext in (log, gz) and not path ~ "backups"`
+        #[arg(long, value_name = "EXPR", value_parser = parse_filter)]
+        filter: Option<filter::Filter>,
+        /// Optional paths to analyze (defaults to CWD if none and no --path)
+        #[arg(value_name = "PATH")]
+        paths: Vec<PathBuf>,
+    },
+    /// Archive paths into compressed tarballs, verify them, then remove the originals
+    Archive {
+        /// Directory to write the archives to
+        #[arg(long, value_name = "DIR")]
+        to: PathBuf,
+        /// Files or directories to archive
+        #[arg(value_name = "PATH", required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// Find log files and rank them by size or growth since the previous run
+    Logs {
+        /// Show at most N logs
+        #[arg(short, long, default_value_t = 50)]
+        limit: usize,
+        /// Rank by growth per day instead of size
+        #[arg(long)]
+        by_growth: bool,
+        /// Apply this action to every listed log that supports it (non-interactive)
+        #[arg(long, value_enum)]
+        action: Option<logs::LogAction>,
+        /// Optional paths to analyze (defaults to CWD if none and no --path)
+        #[arg(value_name = "PATH")]
+        paths: Vec<PathBuf>,
+    },
+    /// Analyze git repositories: .git size, ignored build output and local-only work
+    Repos {
+        /// Show at most N repositories
+        #[arg(short, long, default_value_t = 50)]
+        limit: usize,
+        /// Optional paths to analyze (defaults to CWD if none and no --path)
+        #[arg(value_name = "PATH")]
+        paths: Vec<PathBuf>,
+    },
+    /// Clean up according to a policy file, e.g. from cron
+    Auto {
+        /// Policy file (TOML) naming roots, detectors, thresholds and budgets
+        #[arg(long, value_name = "FILE")]
+        policy: PathBuf,
+    },
+    /// Chart usage history and estimate days until full
+    Trend {
+        /// Mount point or recorded directory (defaults to every mount)
+        #[arg(value_name = "PATH")]
+        path: Option<PathBuf>,
+        /// Width of the chart in characters
+        #[arg(long, default_value_t = 60)]
+        width: usize,
+    },
+    /// Compare two directory trees and rank the parts that differ
+    Compare {
+        /// First tree (e.g. the source of a migration)
+        a: PathBuf,
+        /// Second tree (e.g. the copy or backup)
+        b: PathBuf,
+        /// Show at most N differences
+        #[arg(short, long, default_value_t = 50)]
+        limit: usize,
+        /// Only report entries up to this depth below the roots
+        #[arg(long)]
+        depth: Option<usize>,
+        /// Print the comparison as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Write disk metrics for the Prometheus node_exporter textfile collector
+    Export {
+        /// Metrics file to write atomically (e.g. /var/lib/node_exporter/textfile/sdisk.prom)
+        #[arg(long, value_name = "FILE")]
+        prometheus: PathBuf,
+        /// Watched directories to size (defaults to CWD if none and no --path)
+        #[arg(value_name = "PATH")]
+        paths: Vec<PathBuf>,
+    },
+    /// Find space held by deleted files that are still open (Linux)
+    Held,
+    /// Rank subtrees by entry count and show inode usage per mount
+    Inodes {
+        /// Number of directories to show
+        #[arg(short, long, default_value_t = 20)]
+        count: usize,
+        /// Only rank directories up to this depth below each root
+        #[arg(long, default_value_t = 3)]
+        depth: usize,
+        /// Flag directories with at least this many immediate children
+        #[arg(long, default_value_t = 10_000)]
+        fanout: u64,
+        /// Optional paths to analyze (defaults to CWD if none and no --path)
+        #[arg(value_name = "PATH")]
+        paths: Vec<PathBuf>,
+    },
+    /// Find empty directories, zero-byte files and broken symlinks
+    Cruft {
+        /// Show at most N items
+        #[arg(short, long, default_value_t = 200)]
+        limit: usize,
         /// Optional paths to analyze (defaults to CWD if none and no --path)
         #[arg(value_name = "PATH")]
         paths: Vec<PathBuf>,
     },
 }
 
+/// Subcommands whose scan can feed `--record`
+const RECORDS: [&str; 6] = ["info", "top", "stale", "clean", "export", "inodes"];
+
+/// What top and stale report besides their listing
+#[derive(Debug, Clone, Copy)]
+struct ScanOutput {
+    /// List the members of archives found
+    inspect: bool,
+    /// Append the totals of the scanned roots to the history
+    record: bool,
+}
+
+impl ScanOutput {
+    fn from_cli(cli: &Cli) -> Self {
+        Self {
+            inspect: cli.inspect_archives,
+            record: cli.record,
+        }
+    }
+}
+
+/// How candidates are selected and confirmed before anything is removed
+#[derive(Debug, Clone, Copy)]
+struct DeleteFlow {
+    /// Show the selection UI
+    interactive: bool,
+    /// Ask for confirmation before removing
+    prompt: bool,
+    /// Only report what would be removed
+    dry_run: bool,
+    /// Override the safety interlocks
+    force_unsafe: bool,
+}
+
+impl DeleteFlow {
+    fn from_cli(cli: &Cli) -> Self {
+        Self {
+            interactive: !cli.non_interactive,
+            prompt: !cli.yes,
+            dry_run: cli.dry_run,
+            force_unsafe: cli.force_unsafe,
+        }
+    }
+}
+
 fn main() -> Result<()> {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let command = matches.subcommand_name().unwrap_or("info");
+    if cli.record && !RECORDS.contains(&command) {
+        anyhow::bail!("--record is not supported by {}", command);
+    }
+    let flow = DeleteFlow::from_cli(&cli);
+    let output = ScanOutput::from_cli(&cli);
+    if let Some(level) = cli.io_nice {
+        throttle::set_io_priority(level)?;
+    }
+    if let Some(rate) = cli.max_rate {
+        throttle::set_max_rate(rate);
+    }
+    if let Some(threads) = cli.threads {
+        throttle::set_threads(threads)?;
+    }
+    let record = cli.record;
     match cli.command.unwrap_or(Commands::Info) {
-        Commands::Info => cmd_info(),
-        Commands::Top { count, paths } => {
+        Commands::Info => {
+            cmd_info()?;
+            if record {
+                record_history(Vec::new())?;
+            }
+            Ok(())
+        }
+        Commands::Top {
+            count,
+            filter,
+            paths,
+        } => {
             let roots = collect_roots(cli.path, paths)?;
-            cmd_top(roots, count, !cli.non_interactive, cli.yes, cli.dry_run)
+            cmd_top(roots, count, filter.as_ref(), output, flow)
+        }
+        Commands::Stale {
+            limit,
+            archive_to,
+            filter,
+            paths,
         }
-        Commands::Stale { limit, paths } | Commands::Clean { limit, paths } => {
+        | Commands::Clean {
+            limit,
+            archive_to,
+            filter,
+            paths,
+        } => {
             let roots = collect_roots(cli.path, paths)?;
             cmd_stale(
                 roots,
                 cli.stale_days,
                 limit,
-                !cli.non_interactive,
-                !cli.yes,
-                cli.dry_run,
+                archive_to,
+                filter.as_ref(),
+                output,
+                flow,
             )
         }
+        Commands::Archive { to, paths } => cmd_archive(paths, to, flow),
+        Commands::Logs {
+            limit,
+            by_growth,
+            action,
+            paths,
+        } => {
+            let roots = collect_roots(cli.path, paths)?;
+            cmd_logs(roots, limit, by_growth, action, flow)
+        }
+        Commands::Repos { limit, paths } => {
+            let roots = collect_roots(cli.path, paths)?;
+            cmd_repos(roots, limit, flow)
+        }
+        Commands::Auto { policy } => cmd_auto(&policy, cli.dry_run, cli.yes),
+        Commands::Compare {
+            a,
+            b,
+            limit,
+            depth,
+            json,
+        } => cmd_compare(&a, &b, limit, depth, json),
+        Commands::Export { prometheus, paths } => {
+            let roots = collect_roots(cli.path, paths)?;
+            cmd_export(roots, &prometheus, cli.stale_days, record)
+        }
+        Commands::Trend { path, width } => cmd_trend(path, width),
+        Commands::Held => cmd_held(),
+        Commands::Inodes {
+            count,
+            depth,
+            fanout,
+            paths,
+        } => {
+            let roots = collect_roots(cli.path, paths)?;
+            cmd_inodes(roots, count, depth, fanout, record)
+        }
+        Commands::Cruft { limit, paths } => {
+            let roots = collect_roots(cli.path, paths)?;
+            cmd_cruft(roots, limit, flow)
+        }
     }
 }
 
@@ -109,6 +396,914 @@ fn cmd_info() -> Result<()> {
     Ok(())
 }
 
+fn cmd_logs(
+    roots: Vec<PathBuf>,
+    limit: usize,
+    by_growth: bool,
+    action: Option<logs::LogAction>,
+    flow: DeleteFlow,
+) -> Result<()> {
+    use logs::LogAction;
+
+    for root in &roots {
+        println!("{} {}", style("Finding logs in").bold(), root.display());
+    }
+    let pb = spinner().context("Failed to create progress bar")?;
+    pb.set_message("Finding log files...");
+    let mut found = Vec::new();
+    for root in &roots {
+        found.extend(logs::find(root));
+    }
+    pb.finish_and_clear();
+
+    let mut snapshot = logs::Snapshot::load()?;
+    snapshot.update(&mut found, std::time::SystemTime::now());
+    // A dry run reports growth but leaves the baseline for the next real run
+    if !flow.dry_run {
+        snapshot.save()?;
+    }
+
+    if by_growth {
+        found.sort_by(|a, b| {
+            let growth = |l: &logs::LogFile| l.growth_per_day.unwrap_or(f64::MIN);
+            growth(b).total_cmp(&growth(a))
+        });
+    } else {
+        found.sort_by_key(|l| std::cmp::Reverse(l.size));
+    }
+    found.truncate(limit);
+    let label = |l: &logs::LogFile| {
+        let growth = match l.growth_per_day {
+            None => "new".to_string(),
+            Some(g) if g >= 0.0 => format!("+{}/day", format_size(g as u64, BINARY)),
+            Some(g) => format!("-{}/day", format_size((-g) as u64, BINARY)),
+        };
+        format!(
+            "{} — {} — {} — {}",
+            format_size(l.size, BINARY),
+            growth,
+            l.kind.label(),
+            l.path.display()
+        )
+    };
+    for (i, log) in found.iter().enumerate() {
+        println!("{:>3}. {}", i + 1, label(log));
+    }
+    if found.is_empty() {
+        println!("No log files found.");
+        return Ok(());
+    }
+
+    let (selected, action): (Vec<&logs::LogFile>, LogAction) = if flow.interactive {
+        let labels: Vec<String> = found.iter().map(label).collect();
+        let theme = ColorfulTheme::default();
+        let selection = MultiSelect::with_theme(&theme)
+            .with_prompt("Select logs (space to toggle, enter to confirm)")
+            .items(&labels)
+            .interact()?;
+        if selection.is_empty() {
+            return Ok(());
+        }
+        let actions = [LogAction::Compress, LogAction::Truncate, LogAction::Delete];
+        let choice = Select::with_theme(&theme)
+            .with_prompt("Action")
+            .items(&[
+                "Compress old rotations",
+                "Truncate live logs in place",
+                "Delete",
+                "Cancel",
+            ])
+            .default(0)
+            .interact()?;
+        let Some(&action) = actions.get(choice) else {
+            println!("Aborted.");
+            return Ok(());
+        };
+        (selection.into_iter().map(|i| &found[i]).collect(), action)
+    } else {
+        match action {
+            Some(action) => (found.iter().collect(), action),
+            None => return Ok(()),
+        }
+    };
+
+    let (applicable, skipped): (Vec<_>, Vec<_>) = selected
+        .into_iter()
+        .partition(|l| action.applies_to(l.kind));
+    let allowed = vet(
+        &roots,
+        applicable.iter().map(|l| l.path.clone()).collect(),
+        flow.force_unsafe,
+    );
+    let applicable: Vec<_> = applicable
+        .into_iter()
+        .filter(|l| allowed.contains(&l.path))
+        .collect();
+    for log in &skipped {
+        println!(
+            "{} {} ({} logs do not support {:?})",
+            style("Skipping").yellow(),
+            log.path.display(),
+            log.kind.label(),
+            action
+        );
+    }
+    if applicable.is_empty() {
+        return Ok(());
+    }
+    if flow.dry_run {
+        println!("Would {:?}:", action);
+        for log in applicable {
+            println!("- {}", log.path.display());
+        }
+        return Ok(());
+    }
+    if flow.prompt && !confirm(&format!("{:?} {} logs?", action, applicable.len()))? {
+        println!("Aborted.");
+        return Ok(());
+    }
+    let mut freed: u64 = 0;
+    for log in applicable {
+        match logs::apply(action, log) {
+            Ok(bytes) => {
+                freed = freed.saturating_add(bytes);
+                println!("{:?} {}", action, log.path.display());
+            }
+            Err(e) => println!("{} {}: {:#}", style("Failed").red(), log.path.display(), e),
+        }
+    }
+    println!("Space freed: {}", style(format_size(freed, BINARY)).bold());
+    Ok(())
+}
+
+fn cmd_repos(roots: Vec<PathBuf>, limit: usize, flow: DeleteFlow) -> Result<()> {
+    use std::time::SystemTime;
+
+    for root in &roots {
+        println!(
+            "{} {}",
+            style("Finding git repositories in").bold(),
+            root.display()
+        );
+    }
+    let pb = spinner().context("Failed to create progress bar")?;
+    pb.set_message("Analyzing repositories...");
+    let paths: Vec<PathBuf> = roots.iter().flat_map(|root| repos::find(root)).collect();
+    let results: Vec<_> = paths.par_iter().map(|path| repos::analyze(path)).collect();
+    let mut reports = Vec::new();
+    for result in results {
+        match result {
+            Ok(report) => reports.push(report),
+            Err(e) => pb.println(format!("{} {:#}", style("Skipped").yellow(), e)),
+        }
+    }
+    pb.finish_and_clear();
+    reports.sort_by_key(|r| std::cmp::Reverse(r.ignored_bytes()));
+    reports.truncate(limit);
+
+    for (i, r) in reports.iter().enumerate() {
+        let last_commit = match r.last_commit {
+            Some(t) => format!(
+                "last commit {} days ago",
+                SystemTime::now()
+                    .duration_since(t)
+                    .unwrap_or_default()
+                    .as_secs()
+                    / 86400
+            ),
+            None => "no commits".to_string(),
+        };
+        let mut work = Vec::new();
+        if r.uncommitted {
+            work.push("uncommitted changes");
+        }
+        if r.untracked {
+            work.push("untracked files");
+        }
+        if r.unpushed {
+            work.push("unpushed commits");
+        }
+        let state = if !r.has_local_work() {
+            style("clean".to_string()).green()
+        } else {
+            style(work.join(", ")).red()
+        };
+        println!("{:>3}. {}", i + 1, r.path.display());
+        println!(
+            "     .git {} — ignored {} — untracked {} — {} — {}",
+            format_size(r.git_bytes, BINARY),
+            format_size(r.ignored_bytes(), BINARY),
+            format_size(r.untracked_bytes, BINARY),
+            last_commit,
+            state
+        );
+    }
+    if reports.is_empty() {
+        println!("No git repositories found.");
+        return Ok(());
+    }
+
+    // Only ignored content is ever offered; the checkouts themselves are not,
+    // since they may hold work that exists nowhere else.
+    let mut candidates: Vec<&(PathBuf, u64)> =
+        reports.iter().flat_map(|r| r.ignored.iter()).collect();
+    candidates.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+    if !flow.interactive || candidates.is_empty() {
+        return Ok(());
+    }
+    let labels: Vec<String> = candidates
+        .iter()
+        .map(|(p, s)| format!("{} — {} (ignored)", format_size(*s, BINARY), p.display()))
+        .collect();
+    let theme = ColorfulTheme::default();
+    let selection = MultiSelect::with_theme(&theme)
+        .with_prompt("Select ignored content to delete (space to toggle, enter to confirm)")
+        .items(&labels)
+        .interact()?;
+    if selection.is_empty() {
+        return Ok(());
+    }
+    let paths = vet(
+        &roots,
+        selection
+            .iter()
+            .map(|&idx| candidates[idx].0.clone())
+            .collect(),
+        flow.force_unsafe,
+    );
+    if paths.is_empty() {
+        return Ok(());
+    }
+    if flow.dry_run {
+        println!("Would remove:");
+        for path in &paths {
+            println!("- {}", path.display());
+        }
+        return Ok(());
+    }
+    if flow.prompt && !confirm("Delete selected ignored content?")? {
+        println!("Aborted.");
+        return Ok(());
+    }
+    remove_paths(&vfs::RealFs, &paths)
+}
+
+fn cmd_auto(policy_path: &Path, dry_run: bool, yes: bool) -> Result<()> {
+    use std::time::SystemTime;
+
+    let started = SystemTime::now();
+    let policy = auto::Policy::load(policy_path)?;
+    let pb = spinner().context("Failed to create progress bar")?;
+    pb.set_message("Running detectors...");
+    let candidates = auto::gather(&policy);
+    pb.finish_and_clear();
+
+    let mounts: Vec<(PathBuf, u64)> = Disks::new_with_refreshed_list()
+        .list()
+        .iter()
+        .map(|d| (d.mount_point().to_path_buf(), d.available_space()))
+        .collect();
+    let guard = safety::Guard::new(&policy.roots);
+    let plan = auto::plan(&policy, candidates, &mounts, started, |path| {
+        let hazards: Vec<String> = guard
+            .check(path)
+            .iter()
+            .filter(|h| h.is_blocking())
+            .map(|h| h.to_string())
+            .collect();
+        (!hazards.is_empty()).then(|| hazards.join(", "))
+    });
+
+    let verb = match (policy.action, dry_run) {
+        (auto::Action::Delete, true) => "Would delete",
+        (auto::Action::Delete, false) => "Delete",
+        (auto::Action::Archive, true) => "Would archive",
+        (auto::Action::Archive, false) => "Archive",
+    };
+    for c in &plan.actions {
+        println!(
+            "{} {} — {} ({})",
+            verb,
+            c.path.display(),
+            format_size(c.size, BINARY),
+            c.category
+        );
+        for a in plan.absorbed.iter().filter(|a| a.into == c.path) {
+            println!("  {} {}", style("with").dim(), a.path.display());
+        }
+    }
+    for s in &plan.skipped {
+        println!("{} {}: {}", style("Skip").dim(), s.path.display(), s.reason);
+    }
+    println!(
+        "{} {} items, {}",
+        style("Plan:").bold(),
+        plan.actions.len(),
+        format_size(plan.bytes(), BINARY)
+    );
+
+    if dry_run {
+        if let Some(dest) = &policy.archive_to {
+            if policy.action == auto::Action::Archive {
+                println!("Archives would be written to {}", dest.display());
+            }
+        }
+        if !policy.unattended {
+            println!("A real run would ask for confirmation (policy is not unattended)");
+        }
+        if let Some(command) = &policy.notify {
+            println!("A real run would write a report and then run `{}`", command);
+        } else {
+            println!("A real run would write a report and a journal entry");
+        }
+        return Ok(());
+    }
+    // Nothing to do still gets a report and a journal entry
+    if !policy.unattended && !plan.actions.is_empty() {
+        if !console::user_attended() {
+            anyhow::bail!(
+                "{} does not allow unattended runs; set `unattended = true` to run without prompts",
+                policy_path.display()
+            );
+        }
+        if !yes && !confirm("Carry out this plan?")? {
+            println!("Aborted.");
+            return Ok(());
+        }
+    }
+
+    let pb = spinner().context("Failed to create progress bar")?;
+    pb.set_message("Cleaning up...");
+    let outcomes = auto::execute(&policy, &plan);
+    pb.finish_and_clear();
+    for o in outcomes.iter().filter(|o| o.error.is_some()) {
+        println!(
+            "{} {}: {}",
+            style("Failed").red(),
+            o.path.display(),
+            o.error.as_deref().unwrap_or_default()
+        );
+    }
+
+    let report = auto::Report::new(policy_path, &policy, started, outcomes, plan);
+    let report_path = report.save(&policy)?;
+    println!(
+        "Freed {} ({} failed); report: {}",
+        style(format_size(report.freed_bytes, BINARY)).bold(),
+        report.failed,
+        report_path.display()
+    );
+    if let Err(e) = report.notify(&policy, &report_path) {
+        println!("{} {:#}", style("Warning").yellow(), e);
+    }
+    if report.failed > 0 {
+        anyhow::bail!(
+            "{} of {} items failed; see {}",
+            report.failed,
+            report.outcomes.len(),
+            report_path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Append mount usage and the directory totals gathered by a scan to the
+/// history
+fn record_history(totals: Vec<history::DirTotals>) -> Result<()> {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let mut sample = history::Sample {
+        t: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        ..Default::default()
+    };
+    for disk in Disks::new_with_refreshed_list().list() {
+        sample.mounts.push(history::MountSample {
+            mount_point: disk.mount_point().to_path_buf(),
+            used: disk.total_space().saturating_sub(disk.available_space()),
+            total: disk.total_space(),
+        });
+    }
+    for root in totals {
+        sample.dirs.extend(root.into_dirs());
+    }
+    history::append(&sample)
+}
+
+fn cmd_trend(path: Option<PathBuf>, width: usize) -> Result<()> {
+    let samples = history::load()?;
+    let Some(latest) = samples.iter().max_by_key(|s| s.t) else {
+        println!(
+            "No history recorded yet. Add --record to scans, e.g. `sdisk info --record` from cron."
+        );
+        return Ok(());
+    };
+    let print = |label: &Path, series: &history::Series, free: Option<u64>| {
+        let (first, last) = (series[0], series[series.len() - 1]);
+        println!("{}", style(label.display()).bold());
+        println!("  {}", history::sparkline(series, width));
+        println!(
+            "  {} → {} over {} samples, {} days",
+            format_size(first.1, BINARY),
+            format_size(last.1, BINARY),
+            series.len(),
+            (last.0 - first.0) / 86_400
+        );
+        match history::growth_per_day(series) {
+            Some(per_day) => {
+                let sign = if per_day < 0.0 { "-" } else { "+" };
+                let rate = format_size(per_day.abs() as u64, BINARY);
+                match free.and_then(|free| history::days_until_full(free, per_day)) {
+                    Some(days) => println!(
+                        "  {}{}/day; filesystem full in about {:.0} days",
+                        sign,
+                        rate,
+                        style(days).yellow().bold()
+                    ),
+                    None => println!("  {}{}/day; not filling up", sign, rate),
+                }
+            }
+            None => println!("  Not enough history to estimate growth"),
+        }
+    };
+    let free_on = |dir: &Path| {
+        latest
+            .mounts
+            .iter()
+            .filter(|m| dir.starts_with(&m.mount_point))
+            .max_by_key(|m| m.mount_point.as_os_str().len())
+            .map(|m| m.total.saturating_sub(m.used))
+    };
+
+    let Some(path) = path else {
+        for mount in &latest.mounts {
+            let (series, total) = history::mount_series(&samples, &mount.mount_point);
+            let free = total.map(|t| t.saturating_sub(series.last().map_or(0, |(_, u)| *u)));
+            print(&mount.mount_point, &series, free);
+        }
+        return Ok(());
+    };
+    let path = path.canonicalize().unwrap_or(path);
+    let (series, total) = history::mount_series(&samples, &path);
+    if !series.is_empty() {
+        let free = total.map(|t| t.saturating_sub(series[series.len() - 1].1));
+        print(&path, &series, free);
+        return Ok(());
+    }
+    let series = history::dir_series(&samples, &path);
+    if series.is_empty() {
+        anyhow::bail!(
+            "No history for {}; record it with e.g. `sdisk top {} --record`",
+            path.display(),
+            path.display()
+        );
+    }
+    // A directory fills its filesystem when the free space runs out
+    print(&path, &series, free_on(&path));
+    Ok(())
+}
+
+fn cmd_compare(a: &Path, b: &Path, limit: usize, depth: Option<usize>, json: bool) -> Result<()> {
+    for root in [a, b] {
+        if !root.is_dir() {
+            anyhow::bail!("{} is not a directory", root.display());
+        }
+    }
+    let pb = spinner().context("Failed to create progress bar")?;
+    pb.set_message("Comparing trees...");
+    let mut cmp = compare::compare(a, b);
+    pb.finish_and_clear();
+
+    if let Some(depth) = depth {
+        cmp.differences
+            .retain(|d| d.path.components().count() <= depth);
+    }
+    cmp.differences.truncate(limit);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&cmp)?);
+        return Ok(());
+    }
+
+    println!(
+        "{} {} ({}, {} files) vs {} ({}, {} files)",
+        style("Comparing").bold(),
+        a.display(),
+        format_size(cmp.total_a.bytes, BINARY),
+        cmp.total_a.files,
+        b.display(),
+        format_size(cmp.total_b.bytes, BINARY),
+        cmp.total_b.files
+    );
+    if cmp.differences.is_empty() {
+        println!("{}", style("No differences found").green());
+        return Ok(());
+    }
+    let signed = |delta: i128| {
+        let size = format_size(delta.unsigned_abs() as u64, BINARY);
+        if delta < 0 {
+            format!("-{}", size)
+        } else {
+            format!("+{}", size)
+        }
+    };
+    for (i, d) in cmp.differences.iter().enumerate() {
+        let kind = match d.kind {
+            compare::DiffKind::OnlyInA => style("only in a").red(),
+            compare::DiffKind::OnlyInB => style("only in b").yellow(),
+            compare::DiffKind::Changed => style("differs").cyan(),
+        };
+        println!(
+            "{:>3}. {:>12} {:>+7} files  {:<9}  {}",
+            i + 1,
+            signed(d.byte_delta()),
+            d.file_delta(),
+            kind,
+            d.path.display()
+        );
+    }
+    Ok(())
+}
+
+fn cmd_export(roots: Vec<PathBuf>, file: &Path, stale_days: u64, record: bool) -> Result<()> {
+    use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+    let started = Instant::now();
+    let mut metrics = export::Metrics {
+        stale_days,
+        inodes: inodes::mount_usage(),
+        ..Default::default()
+    };
+    for disk in Disks::new_with_refreshed_list().list() {
+        metrics.mounts.push((
+            disk.mount_point().to_path_buf(),
+            disk.name().to_string_lossy().into_owned(),
+            disk.file_system().to_string_lossy().into_owned(),
+            disk.total_space(),
+            disk.available_space(),
+        ));
+    }
+    // Every (root, detector) pair is an independent walk
+    let jobs: Vec<(&PathBuf, detect::Category)> = roots
+        .iter()
+        .flat_map(|root| detect::Category::ALL.map(|category| (root, category)))
+        .collect();
+    let found: Vec<(&PathBuf, detect::Category, u64, u64)> = jobs
+        .into_par_iter()
+        .map(|(root, category)| {
+            let found = detect::detect(category, root, stale_days);
+            let bytes = found.iter().map(|c| c.size).sum();
+            (root, category, bytes, found.len() as u64)
+        })
+        .collect();
+    for (_, category, bytes, items) in &found {
+        let totals = metrics.reclaimable.entry(*category).or_default();
+        totals.0 += bytes;
+        totals.1 += items;
+    }
+    let totals: Vec<history::DirTotals> = roots
+        .par_iter()
+        .map(|root| history::DirTotals::scan(&vfs::RealFs, root))
+        .collect();
+    metrics.dirs = roots
+        .iter()
+        .zip(&totals)
+        .map(|(root, size)| {
+            let stale_bytes = found
+                .iter()
+                .find(|(r, c, _, _)| *r == root && *c == detect::Category::Stale)
+                .map_or(0, |(_, _, bytes, _)| *bytes);
+            (root.clone(), size.total(), stale_bytes)
+        })
+        .collect();
+    metrics.held_bytes = held::scan().ok().map(|r| r.total_bytes());
+    metrics.duration_secs = started.elapsed().as_secs_f64();
+    metrics.timestamp_secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    if record {
+        record_history(totals)?;
+    }
+
+    let text = export::render(&export::gauges(&metrics));
+    export::write_atomic(file, &text)?;
+    println!(
+        "Wrote {} samples to {}",
+        text.lines().filter(|l| !l.starts_with('#')).count(),
+        file.display()
+    );
+    Ok(())
+}
+
+fn cmd_held() -> Result<()> {
+    println!("{}", style("Deleted files still held open").bold());
+    let report = held::scan()?;
+    if report.files.is_empty() {
+        println!("No deleted-but-open files found.");
+    }
+    for (i, f) in report.files.iter().enumerate() {
+        println!(
+            "{:>3}. {} — pid {} ({}) fd {} — {}",
+            i + 1,
+            format_size(f.size, BINARY),
+            f.pid,
+            f.command,
+            f.fd,
+            f.path.display()
+        );
+    }
+    if report.inaccessible > 0 {
+        println!(
+            "{} {} processes could not be inspected; run as root to see all of them",
+            style("Note:").yellow(),
+            report.inaccessible
+        );
+    }
+    if !report.files.is_empty() {
+        println!(
+            "Total held: {}",
+            style(format_size(report.total_bytes(), BINARY)).bold()
+        );
+        println!(
+            "{} the space is freed once the process closes the file. Restart the process \
+             or ask it to reopen its logs (e.g. `kill -HUP <pid>`), or truncate the file in \
+             place with `: > /proc/<pid>/fd/<fd>`.",
+            style("Hint:").cyan()
+        );
+    }
+    Ok(())
+}
+
+fn cmd_inodes(
+    roots: Vec<PathBuf>,
+    count: usize,
+    depth: usize,
+    fanout: u64,
+    record: bool,
+) -> Result<()> {
+    println!("{}", style("Inode usage per mount").bold());
+    for mount in inodes::mount_usage() {
+        match mount.percent_used() {
+            Some(pct) => println!(
+                "- {} used: {}, free: {} ({:.1}% used)",
+                mount.mount_point.display(),
+                mount.used(),
+                mount.free,
+                pct
+            ),
+            None => println!("- {} (no fixed inode limit)", mount.mount_point.display()),
+        }
+    }
+
+    for root in &roots {
+        println!("{} {}", style("Counting entries in").bold(), root.display());
+    }
+    let pb = spinner().context("Failed to create progress bar")?;
+    pb.set_message("Counting entries...");
+    let mut dirs = Vec::new();
+    let mut totals = Vec::new();
+    for root in &roots {
+        let mut tally = history::DirTotals::new(root);
+        dirs.extend(inodes::count_tree(root, &mut |e| {
+            if record {
+                tally.add(e)
+            }
+        }));
+        totals.push(tally);
+    }
+    pb.finish_and_clear();
+    if record {
+        record_history(totals)?;
+    }
+
+    let mut ranked: Vec<_> = dirs.iter().filter(|d| d.depth <= depth).collect();
+    ranked.sort_by_key(|d| std::cmp::Reverse(d.entries()));
+    for (i, d) in ranked.iter().take(count).enumerate() {
+        println!(
+            "{:>3}. {} entries ({} files, {} dirs) — {}",
+            i + 1,
+            d.entries(),
+            d.files,
+            d.dirs,
+            d.path.display()
+        );
+    }
+
+    let mut wide: Vec<_> = dirs.iter().filter(|d| d.direct >= fanout).collect();
+    if !wide.is_empty() {
+        wide.sort_by_key(|d| std::cmp::Reverse(d.direct));
+        println!(
+            "{}",
+            style(format!("Directories with {}+ immediate children", fanout))
+                .yellow()
+                .bold()
+        );
+        for d in wide.into_iter().take(count) {
+            println!("- {} children — {}", d.direct, d.path.display());
+        }
+    }
+    Ok(())
+}
+
+fn cmd_cruft(roots: Vec<PathBuf>, limit: usize, flow: DeleteFlow) -> Result<()> {
+    for root in &roots {
+        println!(
+            "{} {}",
+            style("Finding empty dirs, empty files and broken links in").bold(),
+            root.display()
+        );
+    }
+    let pb = spinner().context("Failed to create progress bar")?;
+    pb.set_message("Scanning for cruft...");
+    let mut items = Vec::new();
+    for root in &roots {
+        items.extend(cruft::find(root));
+    }
+    pb.finish_and_clear();
+    items.sort_by(|a, b| a.path.cmp(&b.path));
+    items.truncate(limit);
+    for (i, item) in items.iter().enumerate() {
+        println!("{:>3}. {:<11} — {}", i + 1, item.kind, item.path.display());
+    }
+
+    if items.is_empty() {
+        println!("No cruft found.");
+        return Ok(());
+    }
+    let mut selected: Vec<cruft::Cruft> = if flow.dry_run || !flow.interactive {
+        items
+    } else {
+        let labels: Vec<String> = items
+            .iter()
+            .map(|c| format!("{} — {}", c.kind, c.path.display()))
+            .collect();
+        let theme = ColorfulTheme::default();
+        let selection = MultiSelect::with_theme(&theme)
+            .with_prompt("Select items to remove (space to toggle, enter to confirm)")
+            .items(&labels)
+            .interact()?;
+        selection
+            .into_iter()
+            .map(|idx| items[idx].clone())
+            .collect()
+    };
+    let allowed = vet(
+        &roots,
+        selected.iter().map(|c| c.path.clone()).collect(),
+        flow.force_unsafe,
+    );
+    selected.retain(|c| allowed.contains(&c.path));
+    if selected.is_empty() {
+        return Ok(());
+    }
+    if flow.dry_run {
+        println!(
+            "Would remove {} items (children before parents).",
+            selected.len()
+        );
+        return Ok(());
+    }
+    if flow.prompt && !confirm("Remove selected items?")? {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    cruft::sort_bottom_up(&mut selected);
+    for item in &selected {
+        match cruft::remove(item) {
+            Ok(()) => println!("Removed {}", item.path.display()),
+            Err(e) => println!(
+                "{} {}: {}",
+                style("Skipped").yellow(),
+                item.path.display(),
+                e
+            ),
+        }
+    }
+    Ok(())
+}
+
+fn cmd_archive(paths: Vec<PathBuf>, dest: PathBuf, flow: DeleteFlow) -> Result<()> {
+    // The given paths are their own roots; the other interlocks still apply
+    let paths = vet(&paths.clone(), paths, flow.force_unsafe);
+    for path in &paths {
+        let size = if path.is_file() {
+            path.metadata().map(|m| m.len()).unwrap_or(0)
+        } else {
+            dir_size(path).unwrap_or(0)
+        };
+        println!(
+            "- {} — {} → {}",
+            format_size(size, BINARY),
+            path.display(),
+            archive::archive_path(path, &dest).display()
+        );
+    }
+    if paths.is_empty() {
+        return Ok(());
+    }
+    if flow.dry_run {
+        println!("Dry run: nothing archived or removed.");
+        return Ok(());
+    }
+    if flow.prompt && !confirm("Archive and then remove the above paths?")? {
+        println!("Aborted.");
+        return Ok(());
+    }
+    archive_paths(paths, &dest)
+}
+
+/// Archive each path into `dest`, removing originals whose archive verified
+fn archive_paths(paths: Vec<PathBuf>, dest: &Path) -> Result<()> {
+    let mut saved: u64 = 0;
+    let mut failed = 0;
+    for path in paths {
+        // A parent directory archived earlier in the list already took it
+        if path.symlink_metadata().is_err() {
+            continue;
+        }
+        let pb = spinner().context("Failed to create progress bar")?;
+        pb.set_message(format!("Archiving {}...", path.display()));
+        let result = archive::archive_and_remove(&path, dest);
+        pb.finish_and_clear();
+        match result {
+            Ok(outcome) => {
+                saved = saved.saturating_add(outcome.saved());
+                println!(
+                    "Archived {} ({} → {}) to {}",
+                    outcome.source.display(),
+                    format_size(outcome.original_bytes, BINARY),
+                    format_size(outcome.archive_bytes, BINARY),
+                    outcome.archive.display()
+                );
+            }
+            Err(e) => {
+                failed += 1;
+                println!("{} {}: {:#}", style("Kept").yellow(), path.display(), e);
+            }
+        }
+    }
+    println!("Space saved: {}", style(format_size(saved, BINARY)).bold());
+    if failed > 0 {
+        anyhow::bail!(
+            "{} paths could not be archived and were left in place",
+            failed
+        );
+    }
+    Ok(())
+}
+
+/// Number of members shown per archive with --inspect-archives
+const ARCHIVE_MEMBERS_SHOWN: usize = 10;
+
+/// Print the largest members of `path` if it is an archive
+fn print_archive_members(path: &Path) {
+    if inspect::Format::detect(path).is_none() || !path.is_file() {
+        return;
+    }
+    let listing = match inspect::list(path) {
+        Ok(listing) => listing,
+        Err(e) => {
+            println!("       {} {:#}", style("cannot inspect:").yellow(), e);
+            return;
+        }
+    };
+    println!(
+        "       {} members, {} uncompressed from {}, {} already on disk",
+        listing.members.len(),
+        format_size(listing.uncompressed_bytes(), BINARY),
+        format_size(listing.archive_bytes, BINARY),
+        format_size(listing.redundant_bytes(), BINARY)
+    );
+    for member in listing.members.iter().take(ARCHIVE_MEMBERS_SHOWN) {
+        let compressed = member
+            .compressed
+            .map(|c| format!(" ({} compressed)", format_size(c, BINARY)))
+            .unwrap_or_default();
+        let on_disk = if member.on_disk {
+            style(" [on disk]").dim().to_string()
+        } else {
+            String::new()
+        };
+        println!(
+            "       - {}{} — {}{}",
+            format_size(member.size, BINARY),
+            compressed,
+            member.name.display(),
+            on_disk
+        );
+    }
+    if listing.members.len() > ARCHIVE_MEMBERS_SHOWN {
+        println!(
+            "       … {} more",
+            listing.members.len() - ARCHIVE_MEMBERS_SHOWN
+        );
+    }
+}
+
 fn collect_roots(opt_root: Option<PathBuf>, extra: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
     let mut roots: Vec<PathBuf> = Vec::new();
     if let Some(r) = opt_root {
@@ -125,36 +1320,34 @@ fn collect_roots(opt_root: Option<PathBuf>, extra: Vec<PathBuf>) -> Result<Vec<P
     Ok(roots)
 }
 
+fn parse_filter(source: &str) -> Result<filter::Filter, String> {
+    filter::Filter::parse(source).map_err(|e| e.render(source))
+}
+
 fn cmd_top(
     roots: Vec<PathBuf>,
     count: usize,
-    interactive: bool,
-    yes: bool,
-    dry_run: bool,
+    filter: Option<&filter::Filter>,
+    output: ScanOutput,
+    flow: DeleteFlow,
 ) -> Result<()> {
     for root in &roots {
         println!("{} {}", style("Scanning").bold(), root.display());
     }
     let pb = spinner().context("Failed to create progress bar")?;
     pb.set_message("Scanning directories...");
-    let mut entries: Vec<(PathBuf, u64)> = Vec::new();
-    for root in &roots {
-        for entry in WalkDir::new(root)
-            .max_depth(3)
-            .into_iter()
-            .filter_map(|e| e.ok())
-        {
-            let path = entry.path();
-            if path.is_file() {
-                if let Ok(meta) = path.metadata() {
-                    entries.push((path.to_path_buf(), meta.len()));
-                }
-            }
-        }
-    }
+    let mut totals = Vec::new();
+    let entries = top_files(
+        &vfs::RealFs,
+        &roots,
+        count,
+        filter,
+        output.record.then_some(&mut totals),
+    );
     pb.finish_and_clear();
-    entries.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
-    let entries: Vec<(PathBuf, u64)> = entries.into_iter().take(count).collect();
+    if output.record {
+        record_history(totals)?;
+    }
     for (i, (path, size)) in entries.iter().enumerate() {
         println!(
             "{:>3}. {} — {}",
@@ -162,57 +1355,159 @@ fn cmd_top(
             format_size(*size, BINARY),
             path.display()
         );
+        if output.inspect {
+            print_archive_members(path);
+        }
     }
-    if interactive && !entries.is_empty() {
+    if flow.interactive && !entries.is_empty() {
         let items: Vec<String> = entries
             .iter()
             .map(|(p, s)| format!("{} — {}", format_size(*s, BINARY), p.display()))
             .collect();
-        let theme = ColorfulTheme::default();
-        let selection = MultiSelect::with_theme(&theme)
-            .with_prompt("Select files to delete (space to toggle, enter to confirm)")
-            .items(&items)
-            .interact()?;
+        let paths: Vec<PathBuf> = entries.iter().map(|(p, _)| p.clone()).collect();
+        let sizes: Vec<u64> = entries.iter().map(|(_, s)| *s).collect();
+        let selection = picker::pick("Select files to delete", &items, &paths, &sizes)?;
         if selection.is_empty() {
             return Ok(());
         }
-        if dry_run {
+        let paths = vet(
+            &roots,
+            selection
+                .iter()
+                .map(|&idx| entries[idx].0.clone())
+                .collect(),
+            flow.force_unsafe,
+        );
+        if paths.is_empty() {
+            return Ok(());
+        }
+        if flow.dry_run {
             println!("Would remove:");
-            for idx in selection {
-                println!("- {}", entries[idx].0.display());
+            for path in &paths {
+                println!("- {}", path.display());
             }
             return Ok(());
         }
-        if !yes && !confirm("Delete selected files?")? {
+        if flow.prompt && !confirm("Delete selected files?")? {
             println!("Aborted.");
             return Ok(());
         }
-        for idx in selection {
-            let path = &entries[idx].0;
-            if path.is_file() {
-                std::fs::remove_file(path)
-                    .with_context(|| format!("removing file {}", path.display()))?;
-            } else {
-                std::fs::remove_dir_all(path)
-                    .with_context(|| format!("removing directory {}", path.display()))?;
-            }
-            println!("Removed {}", path.display());
-        }
+        remove_paths(&vfs::RealFs, &paths)?;
     }
     Ok(())
 }
 
+/// The `count` largest files at most three levels below `roots` that pass
+/// `filter`, largest first
+///
+/// Roots are walked in parallel, one thread each. With `totals`, the whole
+/// of each root is walked and totalled for `--record`; only the top three
+/// levels are still listed.
+fn top_files(
+    fs: &dyn vfs::Fs,
+    roots: &[PathBuf],
+    count: usize,
+    filter: Option<&filter::Filter>,
+    totals: Option<&mut Vec<history::DirTotals>>,
+) -> Vec<(PathBuf, u64)> {
+    const DEPTH: usize = 3;
+    let record = totals.is_some();
+    let scans: Vec<(Vec<vfs::Entry>, Option<history::DirTotals>)> = roots
+        .par_iter()
+        .map(|root| {
+            let mut tally = record.then(|| history::DirTotals::new(root));
+            let depth = if record { usize::MAX } else { DEPTH };
+            let files = detect::largest_files_in(fs, root, depth, &mut |e| {
+                if let Some(tally) = tally.as_mut() {
+                    tally.add(e);
+                }
+            });
+            (files, tally)
+        })
+        .collect();
+    let (files, tallies): (Vec<_>, Vec<_>) = scans.into_iter().unzip();
+    if let Some(totals) = totals {
+        totals.extend(tallies.into_iter().flatten());
+    }
+    let mut entries: Vec<(PathBuf, u64)> = files
+        .into_iter()
+        .flatten()
+        .filter(|e| e.depth <= DEPTH)
+        .filter(|e| filter.is_none_or(|f| f.matches(&e.path, e.meta.len, &e.meta)))
+        .map(|e| (e.path, e.meta.len))
+        .collect();
+    entries.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+    entries.truncate(count);
+    entries
+}
+
+/// Up to `limit` files below `roots` unused for `days` that pass `filter`,
+/// largest first, with their last use; with `totals`, each root is also
+/// totalled for `--record` from the same walk
+///
+/// Directories are listed as well as files, sized by the files below them.
+/// Roots are walked in parallel, one thread each.
+fn stale_items(
+    fs: &dyn vfs::Fs,
+    roots: &[PathBuf],
+    days: u64,
+    limit: usize,
+    filter: Option<&filter::Filter>,
+    totals: Option<&mut Vec<history::DirTotals>>,
+) -> Vec<(PathBuf, u64, std::time::SystemTime)> {
+    let record = totals.is_some();
+    let scans: Vec<(Vec<vfs::Entry>, Option<history::DirTotals>)> = roots
+        .par_iter()
+        .map(|root| {
+            let mut tally = record.then(|| history::DirTotals::new(root));
+            let found = detect::stale_entries_in(fs, root, days, &mut |e| {
+                if let Some(tally) = tally.as_mut() {
+                    tally.add(e);
+                }
+            });
+            (found, tally)
+        })
+        .collect();
+    let (found, tallies): (Vec<_>, Vec<_>) = scans.into_iter().unzip();
+    if let Some(totals) = totals {
+        totals.extend(tallies.into_iter().flatten());
+    }
+    let mut items: Vec<_> = found
+        .into_iter()
+        .flatten()
+        .filter_map(|e| {
+            let size = if e.meta.is_file() {
+                e.meta.len
+            } else {
+                vfs::tree_size(fs, &e.path)
+            };
+            if !filter.is_none_or(|f| f.matches(&e.path, size, &e.meta)) {
+                return None;
+            }
+            let time = e
+                .meta
+                .last_used()
+                .unwrap_or(std::time::SystemTime::UNIX_EPOCH);
+            Some((e.path, size, time))
+        })
+        .collect();
+    // Largest first
+    items.sort_by_key(|(_, size, _)| std::cmp::Reverse(*size));
+    items.truncate(limit);
+    items
+}
+
 fn cmd_stale(
     roots: Vec<PathBuf>,
     days: u64,
     limit: usize,
-    interactive: bool,
-    prompt: bool,
-    dry_run: bool,
+    archive_to: Option<PathBuf>,
+    filter: Option<&filter::Filter>,
+    output: ScanOutput,
+    flow: DeleteFlow,
 ) -> Result<()> {
-    use std::time::{Duration, SystemTime};
+    use std::time::SystemTime;
 
-    let cutoff = SystemTime::now() - Duration::from_secs(days * 24 * 60 * 60);
     for root in &roots {
         println!(
             "{} {} (older than {} days)",
@@ -223,32 +1518,19 @@ fn cmd_stale(
     }
     let pb = spinner().context("Failed to create progress bar")?;
     pb.set_message("Finding stale files...");
-    let mut items: Vec<(PathBuf, u64, SystemTime)> = Vec::new();
-    for root in &roots {
-        for entry in WalkDir::new(root).into_iter().filter_map(|e| e.ok()) {
-            let path = entry.path().to_path_buf();
-            if let Ok(meta) = path.symlink_metadata() {
-                // Prefer last access; fall back to modified
-                let time = meta
-                    .accessed()
-                    .ok()
-                    .or_else(|| meta.modified().ok())
-                    .unwrap_or(SystemTime::UNIX_EPOCH);
-                if time <= cutoff {
-                    let size = if meta.is_file() {
-                        meta.len()
-                    } else {
-                        dir_size(&path).unwrap_or(0)
-                    };
-                    items.push((path, size, time));
-                }
-            }
-        }
-    }
+    let mut totals = Vec::new();
+    let items = stale_items(
+        &vfs::RealFs,
+        &roots,
+        days,
+        limit,
+        filter,
+        output.record.then_some(&mut totals),
+    );
     pb.finish_and_clear();
-    // Largest first
-    items.sort_by_key(|(_, size, _)| std::cmp::Reverse(*size));
-    let items = items.into_iter().take(limit).collect::<Vec<_>>();
+    if output.record {
+        record_history(totals)?;
+    }
     for (i, (path, size, time)) in items.iter().enumerate() {
         let age_days = SystemTime::now()
             .duration_since(*time)
@@ -262,13 +1544,16 @@ fn cmd_stale(
             path.display(),
             age_days
         );
+        if output.inspect {
+            print_archive_members(path);
+        }
     }
 
-    if dry_run || items.is_empty() {
+    if flow.dry_run || items.is_empty() {
         return Ok(());
     }
 
-    if interactive {
+    if flow.interactive {
         let labels: Vec<String> = items
             .iter()
             .map(|(p, s, t)| {
@@ -285,55 +1570,109 @@ fn cmd_stale(
                 )
             })
             .collect();
+        let paths: Vec<PathBuf> = items.iter().map(|(p, _, _)| p.clone()).collect();
+        let sizes: Vec<u64> = items.iter().map(|(_, s, _)| *s).collect();
+        let selection = picker::pick("Select items to delete", &labels, &paths, &sizes)?;
         let theme = ColorfulTheme::default();
-        let selection = MultiSelect::with_theme(&theme)
-            .with_prompt("Select items to delete (space to toggle, enter to confirm)")
-            .items(&labels)
-            .interact()?;
-        if selection.is_empty() {
+        let paths = vet(
+            &roots,
+            selection.iter().map(|&idx| items[idx].0.clone()).collect(),
+            flow.force_unsafe,
+        );
+        if paths.is_empty() {
             return Ok(());
         }
-        if dry_run {
-            println!("Would remove:");
-            for idx in selection {
-                println!("- {}", items[idx].0.display());
-            }
-            return Ok(());
+        let action = Select::with_theme(&theme)
+            .with_prompt("What should happen to the selected items?")
+            .items(&["Delete", "Archive, verify, then delete", "Cancel"])
+            .default(0)
+            .interact()?;
+        if action == 1 {
+            let dest = match archive_to {
+                Some(dest) => dest,
+                None => PathBuf::from(
+                    Input::<String>::with_theme(&theme)
+                        .with_prompt("Archive directory")
+                        .interact_text()?,
+                ),
+            };
+            return archive_paths(paths, &dest);
         }
-        if !confirm("Delete selected items?")? {
+        if action == 2 || !confirm("Delete selected items?")? {
             println!("Aborted.");
             return Ok(());
         }
-        for idx in selection {
-            let path = &items[idx].0;
-            if path.is_file() {
-                std::fs::remove_file(path)
-                    .with_context(|| format!("removing file {}", path.display()))?;
-            } else {
-                std::fs::remove_dir_all(path)
-                    .with_context(|| format!("removing directory {}", path.display()))?;
-            }
-            println!("Removed {}", path.display());
-        }
+        return remove_paths(&vfs::RealFs, &paths);
+    }
+
+    let paths = vet(
+        &roots,
+        items.into_iter().map(|(p, _, _)| p).collect(),
+        flow.force_unsafe,
+    );
+    if paths.is_empty() {
         return Ok(());
     }
 
-    if prompt && !confirm("Delete the above items?")? {
+    if let Some(dest) = archive_to {
+        if flow.prompt && !confirm(&format!("Archive the above items into {}?", dest.display()))? {
+            println!("Aborted.");
+            return Ok(());
+        }
+        return archive_paths(paths, &dest);
+    }
+
+    if flow.prompt && !confirm("Delete the above items?")? {
         println!("Aborted.");
         return Ok(());
     }
 
-    for (path, _, _) in items {
-        if path.is_file() {
-            std::fs::remove_file(&path)
-                .with_context(|| format!("removing file {}", path.display()))?;
+    remove_paths(&vfs::RealFs, &paths)
+}
+
+/// Run the safety interlocks over `paths`, returning the ones that may be
+/// removed. Refusals and warnings are printed as they are found.
+fn vet(roots: &[PathBuf], paths: Vec<PathBuf>, force_unsafe: bool) -> Vec<PathBuf> {
+    let guard = safety::Guard::new(roots);
+    let mut allowed = Vec::with_capacity(paths.len());
+    let mut refused = 0;
+    for path in paths {
+        let hazards = guard.check(&path);
+        let blocking = hazards.iter().any(|h| h.is_blocking());
+        for hazard in &hazards {
+            let label = match (hazard.is_blocking(), force_unsafe) {
+                (true, false) => style("Refusing").red(),
+                (true, true) => style("Overriding").yellow(),
+                (false, _) => style("Warning").yellow(),
+            };
+            println!("{} {}: {}", label, path.display(), hazard);
+        }
+        if blocking && !force_unsafe {
+            refused += 1;
         } else {
-            std::fs::remove_dir_all(&path)
-                .with_context(|| format!("removing directory {}", path.display()))?;
+            allowed.push(path);
         }
-        println!("Removed {}", path.display());
     }
+    if refused > 0 {
+        println!(
+            "{} items were left alone; pass --force-unsafe to override the safety checks",
+            refused
+        );
+    }
+    allowed
+}
 
+/// Remove files and directory trees, skipping paths already removed along
+/// with a parent earlier in the list
+fn remove_paths(fs: &dyn vfs::Fs, paths: &[PathBuf]) -> Result<()> {
+    for path in paths {
+        if fs.symlink_metadata(path).is_err() {
+            continue;
+        }
+        fs.remove(path)
+            .with_context(|| format!("removing {}", path.display()))?;
+        println!("Removed {}", path.display());
+    }
     Ok(())
 }
 
@@ -348,17 +1687,8 @@ fn spinner() -> Result<ProgressBar> {
     Ok(pb)
 }
 
-fn dir_size(path: &PathBuf) -> Result<u64> {
-    let mut size: u64 = 0;
-    for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
-        let p = entry.path();
-        if p.is_file() {
-            if let Ok(meta) = p.metadata() {
-                size = size.saturating_add(meta.len());
-            }
-        }
-    }
-    Ok(size)
+fn dir_size(path: &Path) -> Result<u64> {
+    Ok(vfs::tree_size(&vfs::RealFs, path))
 }
 
 fn confirm(prompt: &str) -> Result<bool> {
@@ -0,0 +1,444 @@
+//! Multi-select list with a detail pane for the highlighted item.
+//!
+//! Works like dialoguer's `MultiSelect` (space toggles, enter confirms) but
+//! shows what the highlighted path is before anything is chosen: its type,
+//! times, owner, the first lines of text files, image dimensions or, for
+//! directories, the biggest children. Those are sized in the background and
+//! show as "counting" until they are ready, at which point the pane redraws
+//! without waiting for a key. `/` narrows
+//! the list with a filter expression, and `o` opens a `.zip`/`.tar(.gz)` to
+//! browse its members.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use console::{style, Key, Term};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use humansize::{format_size, BINARY};
+
+use crate::details::{self, Details, Sizer};
+use crate::filter::Filter;
+use crate::inspect;
+use crate::vfs::Meta;
+
+/// Lines reserved for the detail pane
+const DETAIL_LINES: usize = 14;
+
+/// How often to look for finished sizes while the detail pane shows "counting"
+const SIZER_POLL: Duration = Duration::from_millis(100);
+
+/// Let the user pick items; returns the chosen indices (empty if cancelled)
+///
+/// `/` narrows the list with a filter expression (see [`crate::filter`]);
+/// items hidden by the filter are never returned.
+pub fn pick(
+    prompt: &str,
+    labels: &[String],
+    paths: &[PathBuf],
+    sizes: &[u64],
+) -> Result<Vec<usize>> {
+    let term = Term::stderr();
+    let mut chosen = vec![false; labels.len()];
+    // Indices of the items shown, and the position of the cursor among them
+    let mut visible: Vec<usize> = (0..labels.len()).collect();
+    let mut cursor = 0usize;
+    let mut query = String::new();
+    let mut status: Option<String> = None;
+    let mut metas: Vec<Option<Option<Meta>>> = vec![None; labels.len()];
+    let mut inspected: HashMap<usize, Result<Details, String>> = HashMap::new();
+    let mut sizer = Sizer::new();
+    let mut drawn = 0usize;
+
+    term.hide_cursor()?;
+    let result = loop {
+        let (rows, cols) = term.size();
+        let list_rows = (rows as usize)
+            .saturating_sub(DETAIL_LINES + 4)
+            .max(3)
+            .min(visible.len().max(1));
+        let (start, end) = window(cursor, visible.len(), list_rows);
+
+        let mut lines = vec![format!(
+            "{} {}",
+            style("?").yellow(),
+            style(format!(
+                "{} (↑/↓ move, space toggle, a all, / filter, o open archive, enter confirm, esc cancel)",
+                prompt
+            ))
+            .bold()
+        )];
+        match (&status, query.is_empty()) {
+            (Some(error), _) => lines.extend(error.lines().map(|l| style(l).red().to_string())),
+            (None, false) => lines.push(
+                style(format!(
+                    "filter: {} ({} of {})",
+                    query,
+                    visible.len(),
+                    labels.len()
+                ))
+                .dim()
+                .to_string(),
+            ),
+            (None, true) => {}
+        }
+        for (row, &i) in visible[start..end].iter().enumerate() {
+            let current = start + row == cursor;
+            let mark = if chosen[i] { "[x]" } else { "[ ]" };
+            let line = format!("{} {} {}", if current { ">" } else { " " }, mark, labels[i]);
+            lines.push(if current {
+                style(line).cyan().to_string()
+            } else {
+                line
+            });
+        }
+        if visible.is_empty() {
+            lines.push(style("  (nothing matches the filter)").dim().to_string());
+        }
+        lines.push(
+            style("─".repeat((cols as usize).saturating_sub(1)))
+                .dim()
+                .to_string(),
+        );
+        // Directory whose sizes are still being counted, if one is shown
+        let mut counting = None;
+        if let Some(&i) = visible.get(cursor) {
+            let d = inspected
+                .entry(i)
+                .or_insert_with(|| details::inspect(&paths[i]).map_err(|e| e.to_string()));
+            let children = match d {
+                Ok(d) if d.is_dir => Some(sizer.children(&paths[i])),
+                _ => None,
+            };
+            if children == Some(None) {
+                counting = Some(&paths[i]);
+            }
+            lines.extend(detail_lines(d, children));
+        }
+        lines.resize(
+            lines.len().max(end - start + 3 + DETAIL_LINES),
+            String::new(),
+        );
+
+        term.clear_last_lines(drawn)?;
+        for line in &lines {
+            term.write_line(&console::truncate_str(line, cols as usize, "…"))?;
+        }
+        drawn = lines.len();
+
+        let key = match counting {
+            None => read_key(None)?,
+            // Redraw once the sizes arrive, or sooner on a key
+            Some(dir) => loop {
+                match read_key(Some(SIZER_POLL))? {
+                    Some(key) => break Some(key),
+                    None if sizer.children(dir).is_some() => break None,
+                    None => {}
+                }
+            },
+        };
+        let Some(key) = key else { continue };
+        let last = visible.len().saturating_sub(1);
+        match key {
+            Key::ArrowUp | Key::Char('k') => cursor = cursor.saturating_sub(1),
+            Key::ArrowDown | Key::Char('j') => cursor = (cursor + 1).min(last),
+            Key::PageUp => cursor = cursor.saturating_sub(list_rows),
+            Key::PageDown => cursor = (cursor + list_rows).min(last),
+            Key::Home => cursor = 0,
+            Key::End => cursor = last,
+            Key::Char(' ') => {
+                if let Some(&i) = visible.get(cursor) {
+                    chosen[i] = !chosen[i];
+                }
+            }
+            Key::Char('a') => {
+                let all = visible.iter().all(|&i| chosen[i]);
+                visible.iter().for_each(|&i| chosen[i] = !all);
+            }
+            Key::ArrowRight | Key::Char('o') => {
+                if let Some(&i) = visible.get(cursor) {
+                    if inspect::Format::detect(&paths[i]).is_some() && paths[i].is_file() {
+                        browse_archive(&term, &paths[i], &mut drawn)?;
+                    }
+                }
+            }
+            Key::Char('/') => {
+                term.show_cursor()?;
+                let input = read_filter(&term, &query)?;
+                term.hide_cursor()?;
+                let input = input.trim().to_string();
+                let filter = if input.is_empty() {
+                    Ok(None)
+                } else {
+                    Filter::parse(&input).map(Some)
+                };
+                match filter {
+                    Ok(filter) => {
+                        visible = (0..labels.len())
+                            .filter(|&i| {
+                                let Some(filter) = &filter else {
+                                    return true;
+                                };
+                                let meta = metas[i].get_or_insert_with(|| {
+                                    std::fs::symlink_metadata(&paths[i]).ok().map(Meta::from)
+                                });
+                                meta.as_ref()
+                                    .is_some_and(|m| filter.matches(&paths[i], sizes[i], m))
+                            })
+                            .collect();
+                        cursor = 0;
+                        status = None;
+                    }
+                    Err(e) => status = Some(e.render(&input)),
+                }
+                query = input;
+            }
+            Key::Enter => {
+                break visible.iter().copied().filter(|&i| chosen[i]).collect();
+            }
+            Key::Escape | Key::Char('q') => break Vec::new(),
+            _ => {}
+        }
+    };
+    term.clear_last_lines(drawn)?;
+    term.show_cursor()?;
+    Ok(result)
+}
+
+/// Show the members of the archive at `path`, largest first, until the user
+/// goes back to the list
+fn browse_archive(term: &Term, path: &Path, drawn: &mut usize) -> Result<()> {
+    let listing = inspect::list(path);
+    let members = listing.as_ref().map_or(&[][..], |l| l.members.as_slice());
+    let mut cursor = 0usize;
+    loop {
+        let (rows, cols) = term.size();
+        let list_rows = (rows as usize)
+            .saturating_sub(4)
+            .max(3)
+            .min(members.len().max(1));
+        let (start, end) = window(cursor, members.len(), list_rows);
+
+        let mut lines = vec![format!(
+            "{} {}",
+            style("?").yellow(),
+            style(format!("{} (↑/↓ move, ← back)", path.display())).bold()
+        )];
+        match &listing {
+            Ok(listing) => lines.push(
+                style(format!(
+                    "{} members, {} uncompressed from {}, {} already on disk",
+                    members.len(),
+                    format_size(listing.uncompressed_bytes(), BINARY),
+                    format_size(listing.archive_bytes, BINARY),
+                    format_size(listing.redundant_bytes(), BINARY)
+                ))
+                .dim()
+                .to_string(),
+            ),
+            Err(e) => lines.push(format!("{} {:#}", style("Cannot inspect:").red(), e)),
+        }
+        lines.push(
+            style(format!("  {:>10}  {:>10}  name", "size", "compressed"))
+                .dim()
+                .to_string(),
+        );
+        for (row, member) in members[start..end].iter().enumerate() {
+            let current = start + row == cursor;
+            // Tarballs are compressed as a whole, not per member
+            let compressed = member
+                .compressed
+                .map_or_else(|| "—".to_string(), |c| format_size(c, BINARY));
+            let line = format!(
+                "{} {:>10}  {:>10}  {}{}",
+                if current { ">" } else { " " },
+                format_size(member.size, BINARY),
+                compressed,
+                member.name.display(),
+                if member.on_disk { " [on disk]" } else { "" }
+            );
+            lines.push(if current {
+                style(line).cyan().to_string()
+            } else {
+                line
+            });
+        }
+
+        term.clear_last_lines(*drawn)?;
+        for line in &lines {
+            term.write_line(&console::truncate_str(line, cols as usize, "…"))?;
+        }
+        *drawn = lines.len();
+
+        let last = members.len().saturating_sub(1);
+        let Some(key) = read_key(None)? else {
+            continue;
+        };
+        match key {
+            Key::ArrowUp | Key::Char('k') => cursor = cursor.saturating_sub(1),
+            Key::ArrowDown | Key::Char('j') => cursor = (cursor + 1).min(last),
+            Key::PageUp => cursor = cursor.saturating_sub(list_rows),
+            Key::PageDown => cursor = (cursor + list_rows).min(last),
+            Key::Home => cursor = 0,
+            Key::End => cursor = last,
+            Key::ArrowLeft | Key::Backspace | Key::Escape | Key::Char('q') => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+/// Wait up to `timeout` (forever if `None`) for a key press; `None` if none
+/// came
+///
+/// Keys are read in raw mode only while waiting, so the lines written between
+/// reads still end in a carriage return. Ctrl-C reads as escape.
+fn read_key(timeout: Option<Duration>) -> Result<Option<Key>> {
+    crossterm::terminal::enable_raw_mode()?;
+    let key = next_key(timeout);
+    crossterm::terminal::disable_raw_mode()?;
+    key
+}
+
+fn next_key(timeout: Option<Duration>) -> Result<Option<Key>> {
+    loop {
+        if let Some(timeout) = timeout {
+            if !event::poll(timeout)? {
+                return Ok(None);
+            }
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind == KeyEventKind::Release {
+            continue;
+        }
+        return Ok(Some(match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Key::Escape,
+            KeyCode::Char(c) => Key::Char(c),
+            KeyCode::Up => Key::ArrowUp,
+            KeyCode::Down => Key::ArrowDown,
+            KeyCode::Left => Key::ArrowLeft,
+            KeyCode::Right => Key::ArrowRight,
+            KeyCode::PageUp => Key::PageUp,
+            KeyCode::PageDown => Key::PageDown,
+            KeyCode::Home => Key::Home,
+            KeyCode::End => Key::End,
+            KeyCode::Enter => Key::Enter,
+            KeyCode::Esc => Key::Escape,
+            KeyCode::Backspace => Key::Backspace,
+            KeyCode::Tab => Key::Tab,
+            KeyCode::Delete => Key::Del,
+            _ => Key::Unknown,
+        }));
+    }
+}
+
+/// Edit the filter on a `filter>` prompt line, starting from `initial`;
+/// escape keeps `initial`. The prompt line is cleared before returning, as
+/// it is not part of what the next draw clears.
+fn read_filter(term: &Term, initial: &str) -> Result<String> {
+    let mut input = initial.to_string();
+    let result = loop {
+        term.clear_line()?;
+        term.write_str(&format!("filter> {}", input))?;
+        match read_key(None)? {
+            Some(Key::Enter) => break input,
+            Some(Key::Escape) => break initial.to_string(),
+            Some(Key::Backspace) => {
+                input.pop();
+            }
+            Some(Key::Char(c)) if !c.is_control() => input.push(c),
+            _ => {}
+        }
+    };
+    term.clear_line()?;
+    Ok(result)
+}
+
+/// Range of items to show so `cursor` stays visible in `rows` lines
+fn window(cursor: usize, len: usize, rows: usize) -> (usize, usize) {
+    let start = cursor
+        .saturating_sub(rows / 2)
+        .min(len.saturating_sub(rows));
+    (start, (start + rows).min(len))
+}
+
+/// Lines of the detail pane; `children` is `Some` for directories, holding
+/// `None` while they are still being sized
+fn detail_lines(
+    d: &Result<Details, String>,
+    children: Option<Option<&[(PathBuf, u64)]>>,
+) -> Vec<String> {
+    let d = match d {
+        Ok(d) => d,
+        Err(e) => return vec![format!("{} {}", style("Cannot inspect:").red(), e)],
+    };
+    let size = match children {
+        Some(Some(children)) => format_size(children.iter().map(|(_, s)| s).sum::<u64>(), BINARY),
+        Some(None) => "counting…".to_string(),
+        None => format_size(d.size, BINARY),
+    };
+    let mut lines = vec![format!(
+        "{} — {}{}",
+        style(&d.kind).bold(),
+        size,
+        d.dimensions
+            .map(|(w, h)| format!(", {}×{} px", w, h))
+            .unwrap_or_default()
+    )];
+    let time = |label: &str, t: Option<SystemTime>| {
+        t.map(|t| {
+            let days = SystemTime::now()
+                .duration_since(t)
+                .unwrap_or_default()
+                .as_secs()
+                / 86_400;
+            format!(
+                "{:<9} {} ({} days ago)",
+                label,
+                details::format_time(t),
+                days
+            )
+        })
+    };
+    lines.extend(time("Modified", d.modified));
+    lines.extend(time("Accessed", d.accessed));
+    lines.extend(time("Created", d.created));
+    if let Some(owner) = &d.owner {
+        lines.push(format!("{:<9} {}", "Owner", owner));
+    }
+    for text in &d.text {
+        lines.push(style(format!("│ {}", text)).dim().to_string());
+    }
+    match children {
+        Some(None) => lines.push(style("Biggest children: counting…").dim().to_string()),
+        Some(Some(children)) if !children.is_empty() => {
+            lines.push("Biggest children:".to_string());
+            for (child, size) in children {
+                let name = child.file_name().unwrap_or(child.as_os_str());
+                lines.push(format!(
+                    "  {:>10}  {}",
+                    format_size(*size, BINARY),
+                    name.to_string_lossy()
+                ));
+            }
+        }
+        _ => {}
+    }
+    lines.truncate(DETAIL_LINES);
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_keeps_cursor_visible() {
+        assert_eq!(window(0, 100, 10), (0, 10));
+        assert_eq!(window(50, 100, 10), (45, 55));
+        assert_eq!(window(99, 100, 10), (90, 100));
+        assert_eq!(window(2, 3, 10), (0, 3));
+    }
+}
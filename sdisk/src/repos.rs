@@ -0,0 +1,218 @@
+//! Git-aware analysis of repository checkouts.
+//!
+//! For every repository below the scanned roots this reports how much space
+//! the `.git` directory and the ignored/untracked files (usually build output)
+//! take, when the last commit was made, and whether the checkout holds work
+//! that exists nowhere else. Only ignored content is ever suggested for
+//! deletion.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use git2::{BranchType, Repository, Status, StatusOptions};
+
+use crate::throttle;
+
+/// Summary of one repository
+#[derive(Debug, Clone)]
+pub struct RepoReport {
+    /// Working tree root
+    pub path: PathBuf,
+    /// Size of the `.git` directory (objects, packs, index, ...)
+    pub git_bytes: u64,
+    /// Ignored files and directories with their sizes, largest first
+    pub ignored: Vec<(PathBuf, u64)>,
+    /// Total size of untracked, non-ignored files
+    pub untracked_bytes: u64,
+    /// Time of the commit HEAD points at, if any
+    pub last_commit: Option<SystemTime>,
+    /// Tracked files modified in the working tree or index
+    pub uncommitted: bool,
+    /// Untracked, non-ignored files exist
+    pub untracked: bool,
+    /// Commits not present on any remote (ahead of upstream, branches without
+    /// upstream, stashes, or no remote at all)
+    pub unpushed: bool,
+}
+
+impl RepoReport {
+    pub fn ignored_bytes(&self) -> u64 {
+        self.ignored.iter().map(|(_, size)| size).sum()
+    }
+
+    /// True if removing the checkout would lose work that exists nowhere else
+    pub fn has_local_work(&self) -> bool {
+        self.uncommitted || self.untracked || self.unpushed
+    }
+}
+
+/// Find working trees below `root`; nested repositories are not descended into
+pub fn find(root: &Path) -> Vec<PathBuf> {
+    let mut repos = Vec::new();
+    let mut it = throttle::walk_dir(root);
+    while let Some(entry) = it.next() {
+        let Ok(entry) = entry else { continue };
+        if !entry.file_type().is_dir() {
+            continue;
+        }
+        if entry.path().join(".git").exists() {
+            repos.push(entry.path().to_path_buf());
+            it.skip_current_dir();
+        }
+    }
+    repos
+}
+
+/// Analyze the repository whose working tree is at `path`
+pub fn analyze(path: &Path) -> Result<RepoReport> {
+    let repo =
+        Repository::open(path).with_context(|| format!("opening repository {}", path.display()))?;
+
+    let mut opts = StatusOptions::new();
+    opts.include_ignored(true)
+        .include_untracked(true)
+        .recurse_ignored_dirs(false)
+        .recurse_untracked_dirs(true)
+        .exclude_submodules(true);
+    let statuses = repo
+        .statuses(Some(&mut opts))
+        .with_context(|| format!("reading status of {}", path.display()))?;
+
+    let mut ignored = Vec::new();
+    let mut untracked_bytes = 0u64;
+    let mut untracked = false;
+    let mut uncommitted = false;
+    for entry in statuses.iter() {
+        let status = entry.status();
+        let Some(rel) = entry.path() else { continue };
+        let full = path.join(rel.trim_end_matches('/'));
+        if status.contains(Status::IGNORED) {
+            ignored.push((full.clone(), tree_size(&full)));
+        } else if status.contains(Status::WT_NEW) {
+            untracked = true;
+            untracked_bytes += tree_size(&full);
+        } else if !status.is_empty() {
+            uncommitted = true;
+        }
+    }
+    ignored.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+
+    let last_commit = repo
+        .head()
+        .ok()
+        .and_then(|head| head.peel_to_commit().ok())
+        .map(|commit| UNIX_EPOCH + Duration::from_secs(commit.time().seconds().max(0) as u64));
+
+    Ok(RepoReport {
+        path: path.to_path_buf(),
+        git_bytes: tree_size(repo.path()),
+        ignored,
+        untracked_bytes,
+        last_commit,
+        uncommitted,
+        untracked,
+        unpushed: has_unpushed(&repo),
+    })
+}
+
+/// Whether any commit in `repo` is missing from its remotes
+fn has_unpushed(repo: &Repository) -> bool {
+    // Any failure to tell counts as unpushed so the repo is treated as precious
+    let has_remote = repo.remotes().map(|r| !r.is_empty()).unwrap_or(false);
+    if !has_remote {
+        return repo.head().is_ok();
+    }
+    let Ok(branches) = repo.branches(Some(BranchType::Local)) else {
+        return true;
+    };
+    for branch in branches {
+        let Ok((branch, _)) = branch else { return true };
+        let Some(local) = branch.get().target() else {
+            continue;
+        };
+        let Ok(upstream) = branch.upstream() else {
+            return true;
+        };
+        let Some(remote) = upstream.get().target() else {
+            return true;
+        };
+        match repo.graph_ahead_behind(local, remote) {
+            Ok((0, _)) => {}
+            _ => return true,
+        }
+    }
+    repo.find_reference("refs/stash").is_ok()
+}
+
+/// Size of a file, or of all files below a directory
+fn tree_size(path: &Path) -> u64 {
+    throttle::walk_dir(path)
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.metadata().ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn commit_all(repo: &Repository) {
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = git2::Signature::now("test", "test@example.com").unwrap();
+        let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+        let parents: Vec<_> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &sig, &sig, "commit", &tree, &parents)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_find_skips_nested_repositories() {
+        let temp = TempDir::new().unwrap();
+        Repository::init(temp.path().join("a")).unwrap();
+        Repository::init(temp.path().join("a/vendor/b")).unwrap();
+        Repository::init(temp.path().join("c")).unwrap();
+
+        let mut repos = find(temp.path());
+        repos.sort();
+
+        assert_eq!(repos, vec![temp.path().join("a"), temp.path().join("c")]);
+    }
+
+    #[test]
+    fn test_analyze_reports_ignored_and_local_work() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("repo");
+        let repo = Repository::init(&path).unwrap();
+        fs::write(path.join(".gitignore"), "target/\n").unwrap();
+        fs::write(path.join("main.rs"), "fn main() {}").unwrap();
+        commit_all(&repo);
+        fs::create_dir_all(path.join("target/debug")).unwrap();
+        fs::write(path.join("target/debug/app"), vec![0u8; 4096]).unwrap();
+
+        let report = analyze(&path).unwrap();
+        assert_eq!(report.ignored, vec![(path.join("target"), 4096)]);
+        assert!(report.last_commit.is_some());
+        assert!(!report.uncommitted);
+        assert!(!report.untracked);
+        // No remote configured, so every commit is local-only
+        assert!(report.unpushed);
+        assert!(report.has_local_work());
+
+        fs::write(path.join("main.rs"), "fn main() { changed() }").unwrap();
+        fs::write(path.join("notes.txt"), "todo").unwrap();
+        let report = analyze(&path).unwrap();
+        assert!(report.uncommitted);
+        assert!(report.untracked);
+        assert_eq!(report.untracked_bytes, 4);
+    }
+}
@@ -0,0 +1,262 @@
+//! Safety interlocks run before anything is removed.
+//!
+//! Every deletion path checks its targets here first. Targets that are mount
+//! points, system paths, the home directory itself, outside the scanned roots
+//! or hold uncommitted git work are refused unless `--force-unsafe` is given;
+//! files still open by a process only produce a warning, since removing them
+//! is safe but does not free space until the process lets go.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use git2::{Repository, Status, StatusOptions};
+
+use crate::{held, repos};
+
+/// Directories whose whole subtree belongs to the operating system
+const SYSTEM_TREES: &[&str] = &[
+    "/bin", "/boot", "/dev", "/etc", "/lib", "/lib32", "/lib64", "/proc", "/sbin", "/sys", "/usr",
+    "/System",
+];
+
+/// Directories that must never be removed themselves, though their contents may
+const SYSTEM_DIRS: &[&str] = &[
+    "/",
+    "/home",
+    "/opt",
+    "/root",
+    "/srv",
+    "/tmp",
+    "/var",
+    "/Applications",
+    "/Library",
+    "/Users",
+];
+
+/// A reason not to (silently) remove a path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Hazard {
+    /// The path is, or contains, a mount point
+    MountPoint(PathBuf),
+    /// The path is an operating system directory or the home directory
+    SystemPath,
+    /// The path is not inside any of the scanned roots
+    OutsideRoots,
+    /// Git-tracked content under the path has uncommitted changes
+    GitChanges,
+    /// The path is a repository holding unpushed or uncommitted work
+    GitLocalWork,
+    /// A process has the file (or a file below the directory) open
+    OpenByProcess { pid: u32, command: String },
+}
+
+impl Hazard {
+    /// Whether the hazard refuses removal (rather than just warning)
+    pub fn is_blocking(&self) -> bool {
+        !matches!(self, Hazard::OpenByProcess { .. })
+    }
+}
+
+impl fmt::Display for Hazard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Hazard::MountPoint(mount) => write!(f, "mount point {}", mount.display()),
+            Hazard::SystemPath => write!(f, "system path"),
+            Hazard::OutsideRoots => write!(f, "outside the scanned roots"),
+            Hazard::GitChanges => write!(f, "git-tracked with uncommitted changes"),
+            Hazard::GitLocalWork => write!(f, "git repository with unpushed or uncommitted work"),
+            Hazard::OpenByProcess { pid, command } => {
+                write!(
+                    f,
+                    "open by pid {} ({}); space is not freed until it closes",
+                    pid, command
+                )
+            }
+        }
+    }
+}
+
+/// Checks candidate paths against the interlocks
+pub struct Guard {
+    roots: Vec<PathBuf>,
+    mounts: Vec<PathBuf>,
+    home: Option<PathBuf>,
+    open: Vec<(PathBuf, u32, String)>,
+}
+
+impl Guard {
+    /// Build a guard for deletions below `roots`
+    pub fn new(roots: &[PathBuf]) -> Self {
+        let mounts = sysinfo::Disks::new_with_refreshed_list()
+            .list()
+            .iter()
+            .map(|d| d.mount_point().to_path_buf())
+            .collect();
+        Self {
+            roots: roots.iter().map(|r| resolve(r)).collect(),
+            mounts,
+            home: dirs::home_dir().map(|h| resolve(&h)),
+            open: held::open_files().unwrap_or_default(),
+        }
+    }
+
+    /// All hazards that apply to `path`
+    pub fn check(&self, path: &Path) -> Vec<Hazard> {
+        let path = resolve(path);
+        let mut hazards = Vec::new();
+
+        if let Some(mount) = self.mounts.iter().find(|m| m.starts_with(&path)) {
+            hazards.push(Hazard::MountPoint(mount.clone()));
+        }
+        if is_system_path(&path) || self.home.as_deref() == Some(path.as_path()) {
+            hazards.push(Hazard::SystemPath);
+        }
+        if !self.roots.iter().any(|root| path.starts_with(root)) {
+            hazards.push(Hazard::OutsideRoots);
+        }
+        hazards.extend(git_hazard(&path));
+        if let Some((_, pid, command)) = self.open.iter().find(|(p, _, _)| p.starts_with(&path)) {
+            hazards.push(Hazard::OpenByProcess {
+                pid: *pid,
+                command: command.clone(),
+            });
+        }
+        hazards
+    }
+}
+
+/// Absolute form of `path` with its parent resolved, but without following
+/// the final component, so a symlink is judged by where it lives rather than
+/// what it points at
+fn resolve(path: &Path) -> PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    match (absolute.parent(), absolute.file_name()) {
+        (Some(parent), Some(name)) => parent
+            .canonicalize()
+            .map(|p| p.join(name))
+            .unwrap_or(absolute),
+        _ => absolute,
+    }
+}
+
+fn is_system_path(path: &Path) -> bool {
+    SYSTEM_TREES.iter().any(|tree| path.starts_with(tree))
+        || SYSTEM_DIRS.iter().any(|dir| path == Path::new(dir))
+}
+
+/// Git hazards for `path`, if it lies inside a working tree
+fn git_hazard(path: &Path) -> Option<Hazard> {
+    if path.join(".git").exists() {
+        let report = repos::analyze(path).ok()?;
+        return report.has_local_work().then_some(Hazard::GitLocalWork);
+    }
+    let repo = Repository::discover(path.parent()?).ok()?;
+    let workdir = repo.workdir()?.canonicalize().ok()?;
+    let rel = path.strip_prefix(&workdir).ok()?;
+    if rel.components().any(|c| c.as_os_str() == ".git") {
+        // Git metadata is as precious as the work it records
+        let report = repos::analyze(&workdir).ok()?;
+        return report.has_local_work().then_some(Hazard::GitLocalWork);
+    }
+    let changed =
+        |status: Status| !status.is_empty() && !status.intersects(Status::IGNORED | Status::WT_NEW);
+    let dirty = if path.is_dir() {
+        let mut opts = StatusOptions::new();
+        opts.pathspec(rel).include_untracked(false);
+        repo.statuses(Some(&mut opts))
+            .map(|s| s.iter().any(|e| changed(e.status())))
+            .unwrap_or(false)
+    } else {
+        repo.status_file(rel).map(changed).unwrap_or(false)
+    };
+    dirty.then_some(Hazard::GitChanges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn guard(root: &Path) -> Guard {
+        Guard {
+            roots: vec![resolve(root)],
+            mounts: vec![PathBuf::from("/")],
+            home: Some(PathBuf::from("/home/someone")),
+            open: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_system_paths_and_home_are_blocked() {
+        let g = guard(Path::new("/"));
+        assert!(g
+            .check(Path::new("/usr/lib/libc.so"))
+            .contains(&Hazard::SystemPath));
+        assert!(g.check(Path::new("/etc")).contains(&Hazard::SystemPath));
+        assert!(g
+            .check(Path::new("/home/someone"))
+            .contains(&Hazard::SystemPath));
+        assert!(g
+            .check(Path::new("/"))
+            .contains(&Hazard::MountPoint(PathBuf::from("/"))));
+        assert!(!g
+            .check(Path::new("/home/someone/cache"))
+            .contains(&Hazard::SystemPath));
+    }
+
+    #[test]
+    fn test_outside_roots_is_blocked() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path().join("scan");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(temp.path().join("elsewhere"), "x").unwrap();
+        fs::write(root.join("inside"), "x").unwrap();
+
+        let g = guard(&root);
+        assert!(g.check(&root.join("inside")).is_empty());
+        assert_eq!(
+            g.check(&temp.path().join("elsewhere")),
+            vec![Hazard::OutsideRoots]
+        );
+        assert_eq!(
+            g.check(&root.join("../elsewhere")),
+            vec![Hazard::OutsideRoots]
+        );
+    }
+
+    #[test]
+    fn test_modified_tracked_file_is_blocked() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        let repo = Repository::init(root).unwrap();
+        fs::write(root.join("tracked.txt"), "v1").unwrap();
+        fs::write(root.join("clean.txt"), "v1").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("tracked.txt")).unwrap();
+        index.add_path(Path::new("clean.txt")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = git2::Signature::now("test", "test@example.com").unwrap();
+        repo.commit(Some("HEAD"), &sig, &sig, "init", &tree, &[])
+            .unwrap();
+        fs::write(root.join("tracked.txt"), "v2").unwrap();
+
+        let g = guard(root);
+        assert_eq!(g.check(&root.join("tracked.txt")), vec![Hazard::GitChanges]);
+        assert!(g.check(&root.join("clean.txt")).is_empty());
+    }
+
+    #[test]
+    fn test_open_files_only_warn() {
+        let temp = TempDir::new().unwrap();
+        let mut g = guard(temp.path());
+        let file = resolve(&temp.path().join("busy.log"));
+        g.open.push((file.clone(), 42, "daemon".to_string()));
+
+        let hazards = g.check(&file);
+        assert_eq!(hazards.len(), 1);
+        assert!(!hazards[0].is_blocking());
+        assert_eq!(g.check(temp.path()).len(), 1);
+    }
+}
@@ -0,0 +1,31 @@
+//! Location of files sdisk keeps between runs (snapshots, history, journal).
+
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+
+/// Environment variable that overrides the state directory
+pub const STATE_DIR_ENV: &str = "SDISK_STATE_DIR";
+
+/// Directory for persistent state, created on demand
+///
+/// Uses `$SDISK_STATE_DIR` if set, otherwise the platform state directory
+/// (`~/.local/state/sdisk` on Linux), falling back to the local data directory
+/// on platforms without one.
+pub fn state_dir() -> Result<PathBuf> {
+    let dir = match std::env::var_os(STATE_DIR_ENV) {
+        Some(dir) => PathBuf::from(dir),
+        None => dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .context("Failed to find state directory")?
+            .join("sdisk"),
+    };
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create state directory: {:?}", dir))?;
+    Ok(dir)
+}
+
+/// Path of a named file inside the state directory
+pub fn state_file(name: &str) -> Result<PathBuf> {
+    Ok(state_dir()?.join(name))
+}
@@ -1,210 +1,358 @@
-#[cfg(test)]
-mod integration_tests {
-    use super::*;
-    use crate::commands::Commands;
-    use clap::Parser;
-    use std::fs;
-    use std::path::PathBuf;
-    use std::time::{Duration, SystemTime};
-    use tempfile::TempDir;
-
-    fn create_test_directory_structure() -> TempDir {
-        let temp_dir = TempDir::new().unwrap();
-        let root = temp_dir.path();
-
-        // Create test directory structure
-        fs::create_dir(root.join("src")).unwrap();
-        fs::create_dir(root.join("docs")).unwrap();
-        fs::create_dir(root.join("temp")).unwrap();
-
-        // Create files with different sizes and ages
-        fs::write(root.join("large_file.bin"), vec![0u8; 1024 * 1024]).unwrap(); // 1MB
-        fs::write(root.join("small_file.txt"), "Hello, world!").unwrap();
-        fs::write(root.join("src/code.rs"), "fn main() {}").unwrap();
-        fs::write(root.join("docs/readme.md"), "# Documentation").unwrap();
-
-        // Create some old files (simulate by creating and then modifying timestamps if possible)
-        let old_file = root.join("old_file.tmp");
-        fs::write(&old_file, "This is old").unwrap();
-
-        temp_dir
-    }
-
-    #[test]
-    fn test_disk_info_command() {
-        let temp_dir = create_test_directory_structure();
-
-        // Test the info command
-        let result = cmd_info();
-        assert!(result.is_ok());
-    }
-
-    #[test]
-    fn test_top_command_integration() {
-        let temp_dir = create_test_directory_structure();
-        let roots = vec![temp_dir.path().to_path_buf()];
-
-        // Test the top command
-        let result = cmd_top(roots, 10, false, true, false);
-        assert!(result.is_ok());
-    }
-
-    #[test]
-    fn test_stale_command_integration() {
-        let temp_dir = create_test_directory_structure();
-        let roots = vec![temp_dir.path().to_path_buf()];
-
-        // Test the stale command with a very high threshold to catch all files
-        let result = cmd_stale(roots, 365 * 24 * 60 * 60, 20, false, true, false);
-        assert!(result.is_ok());
-    }
-
-    #[test]
-    fn test_directory_traversal_integration() {
-        let temp_dir = create_test_directory_structure();
-        let roots = vec![temp_dir.path().to_path_buf()];
-
-        // Test directory traversal
-        let mut entries: Vec<(PathBuf, u64)> = Vec::new();
-        for root in &roots {
-            for entry in walkdir::WalkDir::new(root)
-                .max_depth(3)
-                .into_iter()
-                .filter_map(|e| e.ok())
-            {
-                let path = entry.path();
-                if path.is_file() {
-                    if let Ok(meta) = path.metadata() {
-                        entries.push((path.to_path_buf(), meta.len()));
-                    }
-                }
-            }
-        }
-
-        // Should find multiple files
-        assert!(!entries.is_empty());
-
-        // Should include our test files
-        let file_names: Vec<_> = entries.iter().map(|(p, _)| p.file_name().unwrap().to_str().unwrap()).collect();
-        assert!(file_names.contains(&"large_file.bin"));
-        assert!(file_names.contains(&"small_file.txt"));
-    }
-
-    #[test]
-    fn test_file_size_calculation_integration() {
-        let temp_dir = create_test_directory_structure();
-
-        // Test file size calculation
-        let large_file = temp_dir.path().join("large_file.bin");
-        let small_file = temp_dir.path().join("small_file.txt");
-
-        let large_size = large_file.metadata().unwrap().len();
-        let small_size = small_file.metadata().unwrap().len();
-
-        // Large file should be bigger
-        assert!(large_size > small_size);
-        assert_eq!(large_size, 1024 * 1024); // 1MB
-        assert!(small_size > 0);
+//! Scenario tests running the scanners and cleaners against an in-memory
+//! filesystem, where timestamps, hardlinks and failures are easy to set up.
+
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::auto::{self, Policy};
+use crate::compare;
+use crate::cruft::{self, CruftKind};
+use crate::detect::{self, Candidate, Category};
+use crate::vfs::{self, Fs, MemFs};
+
+fn paths(found: impl IntoIterator<Item = PathBuf>) -> Vec<String> {
+    let mut v: Vec<String> = found
+        .into_iter()
+        .map(|p| p.to_string_lossy().into_owned())
+        .collect();
+    v.sort();
+    v
+}
+
+fn policy(extra: &str) -> Policy {
+    toml::from_str(&format!("roots = [\"/data\"]\n{}", extra)).unwrap()
+}
+
+fn stale_candidates(fs: &dyn Fs, root: &str, days: u64) -> Vec<Candidate> {
+    detect::stale_files_in(fs, Path::new(root), days)
+        .into_iter()
+        .map(|(path, size, time)| Candidate {
+            path,
+            size,
+            category: Category::Stale,
+            last_used: Some(time),
+        })
+        .collect()
+}
+
+#[test]
+fn test_stale_uses_access_time_and_skips_unreadable_dirs() {
+    let fs = MemFs::new()
+        .file("/data/old.bin", 100)
+        .aged("/data/old.bin", 200)
+        .file("/data/fresh.bin", 10)
+        .file("/data/locked/ancient.bin", 999)
+        .aged("/data/locked/ancient.bin", 900)
+        .fail("/data/locked", ErrorKind::PermissionDenied);
+    // Modified long ago but read yesterday: still in use
+    let year_ago = SystemTime::now() - std::time::Duration::from_secs(365 * 86_400);
+    let fs = fs
+        .file("/data/read.bin", 50)
+        .times("/data/read.bin", year_ago, SystemTime::now());
+
+    let stale = detect::stale_files_in(&fs, Path::new("/data"), 90);
+
+    assert_eq!(paths(stale.iter().map(|s| s.0.clone())), ["/data/old.bin"]);
+    assert_eq!(stale[0].1, 100);
+}
+
+#[test]
+fn test_stale_deletion_plan_lists_files_and_directories() {
+    let fs = MemFs::new()
+        .file("/data/old/big.bin", 900)
+        .aged("/data/old/big.bin", 200)
+        .file("/data/old/small.bin", 10)
+        .aged("/data/old/small.bin", 200)
+        .aged("/data/old", 200)
+        .file("/data/notes.txt", 300)
+        .aged("/data/notes.txt", 100)
+        .file("/data/fresh.bin", 50);
+    let roots = [PathBuf::from("/data")];
+
+    let mut totals = Vec::new();
+    let items = crate::stale_items(&fs, &roots, 90, 3, None, Some(&mut totals));
+
+    // Directories are sized by the files below them
+    assert_eq!(
+        items.iter().map(|i| (i.0.clone(), i.1)).collect::<Vec<_>>(),
+        vec![
+            (PathBuf::from("/data/old"), 910),
+            (PathBuf::from("/data/old/big.bin"), 900),
+            (PathBuf::from("/data/notes.txt"), 300),
+        ]
+    );
+
+    // --record totals come from the same walk
+    assert_eq!(totals.len(), 1);
+    assert_eq!(totals[0].total(), 1260);
+
+    // Paths removed with their directory are skipped
+    let plan: Vec<PathBuf> = items.into_iter().map(|i| i.0).collect();
+    crate::remove_paths(&fs, &plan).unwrap();
+
+    assert!(!fs.exists("/data/old"));
+    assert!(!fs.exists("/data/notes.txt"));
+    assert!(fs.exists("/data/fresh.bin"));
+}
+
+#[test]
+fn test_hardlinked_files_are_counted_once() {
+    let fs = MemFs::new()
+        .file("/data/a/blob", 1000)
+        .hardlink("/data/a/blob", "/data/b/blob")
+        .file("/data/b/other", 24);
+
+    assert_eq!(vfs::tree_size(&fs, Path::new("/data")), 1024);
+    // Each side on its own still holds the full file
+    assert_eq!(vfs::tree_size(&fs, Path::new("/data/b")), 1024);
+}
+
+#[test]
+fn test_cruft_never_reports_unreadable_dirs_as_empty() {
+    let fs = MemFs::new()
+        .dir("/data/empty/nested")
+        .dir("/data/locked")
+        .fail("/data/locked", ErrorKind::PermissionDenied)
+        .file("/data/zero", 0)
+        .symlink("/data/dangling", "missing")
+        .symlink("/data/fine", "zero")
+        .dir("/data/repo/.git/refs");
+
+    let mut found: Vec<(String, CruftKind)> = cruft::find_in(&fs, Path::new("/data"))
+        .into_iter()
+        .map(|c| (c.path.to_string_lossy().into_owned(), c.kind))
+        .collect();
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+
+    assert_eq!(
+        found,
+        vec![
+            ("/data/dangling".to_string(), CruftKind::BrokenSymlink),
+            ("/data/empty".to_string(), CruftKind::EmptyDir),
+            ("/data/empty/nested".to_string(), CruftKind::EmptyDir),
+            ("/data/zero".to_string(), CruftKind::EmptyFile),
+        ]
+    );
+}
+
+#[test]
+fn test_cruft_removal_goes_bottom_up() {
+    let fs = MemFs::new().dir("/data/a/b/c").file("/data/keep", 5);
+
+    let mut found = cruft::find_in(&fs, Path::new("/data"));
+    cruft::sort_bottom_up(&mut found);
+    for item in &found {
+        cruft::remove_in(&fs, item).unwrap();
     }
 
-    #[test]
-    fn test_collect_roots_integration() {
-        let temp_dir = create_test_directory_structure();
-
-        // Test with explicit root
-        let roots = collect_roots(Some(temp_dir.path().to_path_buf()), vec![]);
-        assert!(roots.is_ok());
-        assert_eq!(roots.unwrap().len(), 1);
-
-        // Test with additional paths
-        let extra_paths = vec![temp_dir.path().join("src"), temp_dir.path().join("docs")];
-        let roots = collect_roots(Some(temp_dir.path().to_path_buf()), extra_paths);
-        assert!(roots.is_ok());
-        let roots = roots.unwrap();
-        assert!(roots.len() >= 3); // At least the main path and the two extras (if they don't overlap)
-
-        // Test with no explicit root (should use current dir)
-        let roots = collect_roots(None, vec![]);
-        assert!(roots.is_ok());
-        assert_eq!(roots.unwrap().len(), 1);
-    }
-
-    #[test]
-    fn test_spinner_creation_integration() {
-        // Test spinner creation
-        let result = spinner();
-        assert!(result.is_ok());
-
-        let pb = result.unwrap();
-        // Progress bar should be created successfully
-        assert!(!pb.is_finished());
-    }
-
-    #[test]
-    fn test_error_handling_integration() {
-        // Test with non-existent directory
-        let nonexistent = PathBuf::from("/definitely/does/not/exist");
-        let roots = vec![nonexistent];
-
-        // Should handle gracefully
-        let result = cmd_top(roots, 10, false, true, false);
-        // May or may not error depending on implementation, but shouldn't panic
-        // We mainly want to ensure no panic occurs
-    }
-
-    #[test]
-    fn test_large_directory_handling() {
-        let temp_dir = TempDir::new().unwrap();
-        let root = temp_dir.path();
-
-        // Create many files
-        for i in 0..100 {
-            let file_path = root.join(format!("file_{}.txt", i));
-            fs::write(&file_path, format!("Content of file {}", i)).unwrap();
-        }
-
-        let roots = vec![root.to_path_buf()];
-
-        // Should handle large number of files without issues
-        let result = cmd_top(roots, 50, false, true, false);
-        assert!(result.is_ok());
-    }
-
-    #[test]
-    fn test_file_age_detection() {
-        let temp_dir = create_test_directory_structure();
-        let old_file = temp_dir.path().join("old_file.tmp");
-
-        // Get file metadata
-        let metadata = old_file.metadata().unwrap();
-        let created = metadata.created().unwrap();
-        let now = SystemTime::now();
+    assert!(!fs.exists("/data/a"));
+    assert!(fs.exists("/data/keep"));
+}
+
+#[test]
+fn test_auto_run_records_failures_and_keeps_going() {
+    let fs = MemFs::new()
+        .file("/data/cache/one.bin", 300)
+        .aged("/data/cache/one.bin", 400)
+        .file("/data/cache/two.bin", 200)
+        .aged("/data/cache/two.bin", 300)
+        .file("/data/cache/three.bin", 100)
+        .aged("/data/cache/three.bin", 200)
+        .fail("/data/cache/two.bin", ErrorKind::PermissionDenied);
+    let p = policy("max_bytes_per_run = 600");
+
+    let candidates = stale_candidates(&fs, "/data", p.stale_days);
+    let plan = auto::plan(&p, candidates, &[], SystemTime::now(), |_| None);
+    assert_eq!(plan.bytes(), 600);
+
+    let outcomes = auto::execute_in(&fs, &p, &plan);
+
+    let freed: u64 = outcomes.iter().map(|o| o.freed).sum();
+    assert_eq!(freed, 400);
+    let failed: Vec<_> = outcomes.iter().filter(|o| o.error.is_some()).collect();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].path, PathBuf::from("/data/cache/two.bin"));
+    assert!(fs.exists("/data/cache/two.bin"));
+    assert!(!fs.exists("/data/cache/one.bin"));
+    assert!(!fs.exists("/data/cache/three.bin"));
+}
+
+#[test]
+fn test_auto_budget_stops_before_newer_candidates() {
+    let fs = MemFs::new()
+        .file("/data/oldest", 500)
+        .aged("/data/oldest", 500)
+        .file("/data/older", 400)
+        .aged("/data/older", 400)
+        .file("/data/old", 300)
+        .aged("/data/old", 300);
+    let p = policy("max_bytes_per_run = 850");
+
+    let candidates = stale_candidates(&fs, "/data", p.stale_days);
+    let plan = auto::plan(&p, candidates, &[], SystemTime::now(), |_| None);
+    auto::execute_in(&fs, &p, &plan);
+
+    // Oldest first; the 400 byte file would exceed the budget, the 300 fits
+    assert!(!fs.exists("/data/oldest"));
+    assert!(fs.exists("/data/older"));
+    assert!(!fs.exists("/data/old"));
+}
+
+#[test]
+fn test_compare_trees_in_memory() {
+    let fs = MemFs::new()
+        .file("/a/docs/x", 10)
+        .file("/a/only-a", 5)
+        .file("/b/docs/x", 12)
+        .symlink("/b/link", "docs/x");
+
+    let a = compare::summarize_in(&fs, Path::new("/a"));
+    let b = compare::summarize_in(&fs, Path::new("/b"));
+
+    assert_eq!(a[Path::new("")].bytes, 15);
+    assert_eq!(b[Path::new("")].bytes, 12);
+    assert!(!b.contains_key(Path::new("link")));
+    assert_eq!(compare::diff(&a, &b).len(), 3);
+}
+
+fn test_tree() -> MemFs {
+    MemFs::new()
+        .file("/data/large_file.bin", 1024 * 1024)
+        .file("/data/small_file.txt", 13)
+        .file("/data/src/code.rs", 12)
+        .file("/data/docs/readme.md", 15)
+        .file("/data/old_file.tmp", 11)
+        .aged("/data/old_file.tmp", 400)
+        .dir("/data/temp")
+}
+
+#[test]
+fn test_disk_info_command() {
+    assert!(crate::cmd_info().is_ok());
+}
+
+#[test]
+fn test_top_command_integration() {
+    let fs = test_tree();
+    let top = crate::top_files(&fs, &[PathBuf::from("/data")], 2, None, None);
+
+    assert_eq!(
+        top,
+        vec![
+            (PathBuf::from("/data/large_file.bin"), 1024 * 1024),
+            (PathBuf::from("/data/docs/readme.md"), 15),
+        ]
+    );
+}
+
+#[test]
+fn test_stale_command_integration() {
+    let fs = test_tree();
+    let stale = crate::stale_items(&fs, &[PathBuf::from("/data")], 365, 20, None, None);
+
+    assert_eq!(
+        paths(stale.into_iter().map(|s| s.0)),
+        ["/data/old_file.tmp"]
+    );
+}
+
+#[test]
+fn test_directory_traversal_integration() {
+    let fs = test_tree();
+    let found = detect::largest_files_in(&fs, Path::new("/data"), 3, &mut |_| {});
+
+    assert_eq!(
+        paths(found.into_iter().map(|e| e.path)),
+        [
+            "/data/docs/readme.md",
+            "/data/large_file.bin",
+            "/data/old_file.tmp",
+            "/data/small_file.txt",
+            "/data/src/code.rs",
+        ]
+    );
+}
+
+#[test]
+fn test_file_size_calculation_integration() {
+    let fs = test_tree();
+    let large = fs.metadata(Path::new("/data/large_file.bin")).unwrap().len;
+    let small = fs.metadata(Path::new("/data/small_file.txt")).unwrap().len;
+
+    assert!(large > small);
+    assert_eq!(large, 1024 * 1024);
+    assert!(small > 0);
+}
+
+#[test]
+fn test_collect_roots_integration() {
+    let root = PathBuf::from("/data");
+
+    let roots = crate::collect_roots(Some(root.clone()), vec![]).unwrap();
+    assert_eq!(roots, vec![root.clone()]);
+
+    // Extra paths are added once each
+    let extra = vec![root.join("src"), root.join("docs"), root.clone()];
+    let roots = crate::collect_roots(Some(root.clone()), extra).unwrap();
+    assert_eq!(roots, [root.clone(), root.join("src"), root.join("docs")]);
+
+    // No explicit root: the current directory
+    let roots = crate::collect_roots(None, vec![]).unwrap();
+    assert_eq!(roots.len(), 1);
+}
+
+#[test]
+fn test_spinner_creation_integration() {
+    let pb = crate::spinner().unwrap();
+    assert!(!pb.is_finished());
+    pb.finish_and_clear();
+}
+
+#[test]
+fn test_error_handling_integration() {
+    let fs = test_tree();
+    let roots = [PathBuf::from("/definitely/does/not/exist")];
+
+    assert!(crate::top_files(&fs, &roots, 10, None, None).is_empty());
+    assert!(crate::stale_items(&fs, &roots, 90, 10, None, None).is_empty());
+}
+
+#[test]
+fn test_large_directory_handling() {
+    let fs = (0..100).fold(MemFs::new(), |fs, i| {
+        fs.file(&format!("/data/file_{}.txt", i), 100 + i)
+    });
+
+    let top = crate::top_files(&fs, &[PathBuf::from("/data")], 50, None, None);
+
+    assert_eq!(top.len(), 50);
+    assert_eq!(top[0], (PathBuf::from("/data/file_99.txt"), 199));
+}
+
+#[test]
+fn test_file_age_detection() {
+    let fs = test_tree();
+    let now = SystemTime::now();
+    let age = |path: &str| {
+        let used = fs.metadata(Path::new(path)).unwrap().last_used().unwrap();
+        now.duration_since(used).unwrap_or_default()
+    };
+
+    assert!(age("/data/small_file.txt") < std::time::Duration::from_secs(60));
+    assert!(age("/data/old_file.tmp") > std::time::Duration::from_secs(399 * 86_400));
+}
+
+#[test]
+fn test_command_line_parsing_integration() {
+    use clap::Parser;
 
-        // File should have been created recently (within last few seconds)
-        let age = now.duration_since(created).unwrap();
-        assert!(age < Duration::from_secs(60)); // Less than a minute old
-    }
+    let cli =
+        crate::Cli::try_parse_from(["sdisk", "top", "--count", "5", "--path", "/tmp"]).unwrap();
 
-    #[test]
-    fn test_command_line_parsing_integration() {
-        // Test CLI parsing
-        let args = vec!["sdisk", "top", "--count", "5", "--path", "/tmp"];
-        let cli = Cli::try_parse_from(args);
-
-        match cli {
-            Ok(cli) => {
-                match cli.command {
-                    Some(Commands::Top { count, .. }) => {
-                        assert_eq!(count, 5);
-                    }
-                    _ => panic!("Expected Top command"),
-                }
-            }
-            Err(e) => {
-                // If parsing fails, it should be for a valid reason
-                // This test mainly ensures no panics occur during parsing
-                println!("CLI parsing failed (expected in test env): {}", e);
-            }
-        }
+    assert_eq!(cli.path, Some(PathBuf::from("/tmp")));
+    match cli.command {
+        Some(crate::Commands::Top { count, .. }) => assert_eq!(count, 5),
+        other => panic!("expected top, got {:?}", other),
     }
-}
\ No newline at end of file
+}
@@ -1,2 +1,2 @@
 // Integration tests for Saorsa Disk
-pub mod integration_tests;
\ No newline at end of file
+pub mod integration_tests;
@@ -0,0 +1,171 @@
+//! Keeping scans gentle on busy hosts.
+//!
+//! Three knobs, all set once at startup: the Linux I/O priority of the
+//! process, a cap on directory entries visited per second (shared by every
+//! walker, across threads), and the size of the thread pool used to scan
+//! roots and repositories in parallel.
+
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use walkdir::{DirEntry, WalkDir};
+
+/// I/O scheduling priority for the whole process
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum IoNice {
+    /// Only use the disk when no one else does
+    Idle,
+    /// Lowest best-effort priority
+    Low,
+}
+
+/// Set the I/O priority of this process (Linux `ioprio_set`)
+#[cfg(target_os = "linux")]
+pub fn set_io_priority(level: IoNice) -> Result<()> {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+    const IOPRIO_CLASS_BE: libc::c_int = 2;
+    const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+
+    let prio = match level {
+        IoNice::Idle => IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+        IoNice::Low => (IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT) | 7,
+    };
+    // SAFETY: ioprio_set takes three integers and touches no memory of ours
+    let rc = unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, prio) };
+    if rc != 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to set I/O priority");
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_io_priority(_level: IoNice) -> Result<()> {
+    anyhow::bail!("--io-nice is only supported on Linux")
+}
+
+/// Cap the worker threads used for parallel scanning
+///
+/// The pool runs whole walks side by side (one per root, repository or
+/// detector); a single root is always walked by one thread, so `--threads`
+/// only speeds up scans of several roots.
+pub fn set_threads(threads: usize) -> Result<()> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+        .context("Failed to configure thread pool")
+}
+
+/// Spaces out events to at most a given number per second
+#[derive(Debug, Default)]
+pub struct Limiter {
+    /// Events per second; 0 means unlimited
+    rate: AtomicU64,
+    /// Earliest time the next event may happen
+    next: Mutex<Option<Instant>>,
+}
+
+impl Limiter {
+    pub const fn new() -> Self {
+        Self {
+            rate: AtomicU64::new(0),
+            next: Mutex::new(None),
+        }
+    }
+
+    pub fn set_rate(&self, per_sec: u64) {
+        self.rate.store(per_sec, Ordering::Relaxed);
+    }
+
+    /// Block until the next event is allowed
+    pub fn wait(&self) {
+        let rate = self.rate.load(Ordering::Relaxed);
+        if rate == 0 {
+            return;
+        }
+        let interval = Duration::from_secs(1) / rate.min(u32::MAX as u64) as u32;
+        let now = Instant::now();
+        let slot = {
+            let mut next = self.next.lock().unwrap_or_else(|e| e.into_inner());
+            let slot = next.map_or(now, |n| n.max(now));
+            *next = Some(slot + interval);
+            slot
+        };
+        if slot > now {
+            std::thread::sleep(slot - now);
+        }
+    }
+}
+
+/// Shared by every walker in the process
+static WALK_LIMITER: Limiter = Limiter::new();
+
+/// Limit all directory walks to `per_sec` entries per second
+pub fn set_max_rate(per_sec: u64) {
+    WALK_LIMITER.set_rate(per_sec);
+}
+
+/// Wait for the next walk slot; for walkers not built on [`walk`]
+pub fn pace() {
+    WALK_LIMITER.wait();
+}
+
+/// A directory walk that honours `--max-rate`
+pub struct Paced {
+    inner: walkdir::IntoIter,
+}
+
+impl Paced {
+    /// See [`walkdir::IntoIter::skip_current_dir`]
+    pub fn skip_current_dir(&mut self) {
+        self.inner.skip_current_dir();
+    }
+}
+
+impl Iterator for Paced {
+    type Item = walkdir::Result<DirEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.inner.next()?;
+        pace();
+        Some(item)
+    }
+}
+
+/// Walk with the configured rate limit applied
+pub fn walk(walker: WalkDir) -> Paced {
+    Paced {
+        inner: walker.into_iter(),
+    }
+}
+
+/// Walk everything below `root` with the configured rate limit applied
+pub fn walk_dir(root: &Path) -> Paced {
+    walk(WalkDir::new(root))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limiter_spaces_events() {
+        let limiter = Limiter::new();
+        let start = Instant::now();
+        for _ in 0..5 {
+            limiter.wait();
+        }
+        assert!(start.elapsed() < Duration::from_millis(50));
+
+        limiter.set_rate(100);
+        let start = Instant::now();
+        for _ in 0..21 {
+            limiter.wait();
+        }
+        // The first event goes immediately, the other 20 are 10ms apart
+        assert!(start.elapsed() >= Duration::from_millis(195));
+    }
+}
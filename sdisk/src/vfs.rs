@@ -0,0 +1,288 @@
+//! Filesystem access behind a trait.
+//!
+//! The scanners and cleaners read and remove through [`Fs`] so the same code
+//! runs against the real filesystem ([`RealFs`]) and, in tests, against an
+//! in-memory tree with arbitrary sizes, timestamps, hardlinks and injected
+//! failures (`MemFs`).
+
+use std::collections::HashSet;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::throttle;
+
+#[cfg(test)]
+mod mem;
+#[cfg(test)]
+pub use mem::MemFs;
+
+/// Type of a filesystem entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    File,
+    Dir,
+    Symlink,
+    /// Sockets, devices, FIFOs
+    Other,
+}
+
+/// The metadata sdisk looks at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Meta {
+    pub kind: Kind,
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+    pub accessed: Option<SystemTime>,
+    /// (device, inode), where the platform has them
+    pub inode: Option<(u64, u64)>,
+    /// Number of hardlinks
+    pub links: u64,
+}
+
+impl Meta {
+    pub fn is_file(&self) -> bool {
+        self.kind == Kind::File
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.kind == Kind::Dir
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.kind == Kind::Symlink
+    }
+
+    /// Last access, falling back to last modification
+    pub fn last_used(&self) -> Option<SystemTime> {
+        self.accessed.or(self.modified)
+    }
+}
+
+/// Read and remove operations used by the scanners and cleaners
+pub trait Fs: Send + Sync {
+    /// Metadata of `path` itself, not following a final symlink
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Meta>;
+    /// Metadata of `path`, following symlinks
+    fn metadata(&self, path: &Path) -> io::Result<Meta>;
+    /// Paths of the entries in a directory, in no particular order
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>>;
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+    /// Remove an empty directory
+    fn remove_dir(&self, path: &Path) -> io::Result<()>;
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    /// Remove a file, symlink or whole directory tree
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        if self.symlink_metadata(path)?.is_dir() {
+            self.remove_dir_all(path)
+        } else {
+            self.remove_file(path)
+        }
+    }
+}
+
+/// The real filesystem
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealFs;
+
+impl From<std::fs::Metadata> for Meta {
+    fn from(meta: std::fs::Metadata) -> Self {
+        let ft = meta.file_type();
+        let kind = if ft.is_symlink() {
+            Kind::Symlink
+        } else if ft.is_dir() {
+            Kind::Dir
+        } else if ft.is_file() {
+            Kind::File
+        } else {
+            Kind::Other
+        };
+        #[cfg(unix)]
+        let (inode, links) = {
+            use std::os::unix::fs::MetadataExt;
+            (Some((meta.dev(), meta.ino())), meta.nlink())
+        };
+        #[cfg(not(unix))]
+        let (inode, links) = (None, 1);
+        Meta {
+            kind,
+            len: meta.len(),
+            modified: meta.modified().ok(),
+            accessed: meta.accessed().ok(),
+            inode,
+            links,
+        }
+    }
+}
+
+impl Fs for RealFs {
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Meta> {
+        std::fs::symlink_metadata(path).map(Meta::from)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Meta> {
+        std::fs::metadata(path).map(Meta::from)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        std::fs::read_dir(path)?
+            .map(|e| e.map(|e| e.path()))
+            .collect()
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        std::fs::remove_file(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        std::fs::remove_dir(path)
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        std::fs::remove_dir_all(path)
+    }
+}
+
+/// One entry produced by [`walk`]
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub path: PathBuf,
+    /// Depth below the walk root (the root itself is 0)
+    pub depth: usize,
+    pub meta: Meta,
+}
+
+/// A path that could not be read during a walk
+#[derive(Debug)]
+pub struct WalkError {
+    pub path: PathBuf,
+    pub error: io::Error,
+}
+
+impl fmt::Display for WalkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.error)
+    }
+}
+
+impl std::error::Error for WalkError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+enum Pending {
+    Visit(PathBuf, usize),
+    Failed(WalkError),
+}
+
+/// Pre-order walk that does not follow symlinks and honours `--max-rate`
+///
+/// Directories are yielded before their contents, siblings in name order.
+/// An unreadable directory is yielded, then followed by an error.
+pub struct Walk<'a> {
+    fs: &'a dyn Fs,
+    stack: Vec<Pending>,
+    max_depth: usize,
+}
+
+impl Walk<'_> {
+    /// Do not descend below `depth`; the root is depth 0
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+}
+
+impl Iterator for Walk<'_> {
+    type Item = Result<Entry, WalkError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, depth) = match self.stack.pop()? {
+            Pending::Visit(path, depth) => (path, depth),
+            Pending::Failed(err) => return Some(Err(err)),
+        };
+        throttle::pace();
+        let meta = match self.fs.symlink_metadata(&path) {
+            Ok(meta) => meta,
+            Err(error) => return Some(Err(WalkError { path, error })),
+        };
+        if meta.is_dir() && depth < self.max_depth {
+            match self.fs.read_dir(&path) {
+                Ok(mut children) => {
+                    children.sort();
+                    self.stack.extend(
+                        children
+                            .into_iter()
+                            .rev()
+                            .map(|child| Pending::Visit(child, depth + 1)),
+                    );
+                }
+                Err(error) => self.stack.push(Pending::Failed(WalkError {
+                    path: path.clone(),
+                    error,
+                })),
+            }
+        }
+        Some(Ok(Entry { path, depth, meta }))
+    }
+}
+
+/// Walk everything below (and including) `root`
+pub fn walk<'a>(fs: &'a dyn Fs, root: &Path) -> Walk<'a> {
+    Walk {
+        fs,
+        stack: vec![Pending::Visit(root.to_path_buf(), 0)],
+        max_depth: usize::MAX,
+    }
+}
+
+/// Bytes in regular files below `root`, counting hardlinked files once
+pub fn tree_size(fs: &dyn Fs, root: &Path) -> u64 {
+    let mut seen: HashSet<(u64, u64)> = HashSet::new();
+    walk(fs, root)
+        .filter_map(|e| e.ok())
+        .filter(|e| e.meta.is_file())
+        .filter(|e| match e.meta.inode {
+            Some(inode) if e.meta.links > 1 => seen.insert(inode),
+            _ => true,
+        })
+        .map(|e| e.meta.len)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_real_walk_is_pre_order_and_sorted() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        fs::create_dir_all(root.join("b/inner")).unwrap();
+        fs::write(root.join("a.txt"), "aaa").unwrap();
+        fs::write(root.join("b/inner/c.txt"), "cc").unwrap();
+
+        let order: Vec<(PathBuf, usize)> = walk(&RealFs, root)
+            .map(|e| e.unwrap())
+            .map(|e| (e.path.strip_prefix(root).unwrap().to_path_buf(), e.depth))
+            .collect();
+
+        assert_eq!(
+            order,
+            vec![
+                (PathBuf::new(), 0),
+                (PathBuf::from("a.txt"), 1),
+                (PathBuf::from("b"), 1),
+                (PathBuf::from("b/inner"), 2),
+                (PathBuf::from("b/inner/c.txt"), 3),
+            ]
+        );
+        assert_eq!(tree_size(&RealFs, root), 5);
+        assert_eq!(walk(&RealFs, root).max_depth(1).count(), 3);
+    }
+}
@@ -0,0 +1,266 @@
+//! In-memory filesystem for deterministic tests.
+//!
+//! Trees are built with chained calls; parents are created as needed:
+//!
+//! ```ignore
+//! let fs = MemFs::new()
+//!     .file("/data/old.bin", 4096)
+//!     .aged("/data/old.bin", 200)
+//!     .hardlink("/data/old.bin", "/data/copy.bin")
+//!     .fail("/data/locked", io::ErrorKind::PermissionDenied);
+//! ```
+//!
+//! A failure registered for a path makes listing and removing it (or removing
+//! anything containing it) fail; its metadata can still be read, as with a
+//! directory that lacks read permission.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use super::{Fs, Kind, Meta};
+
+#[derive(Debug, Clone)]
+enum NodeKind {
+    File,
+    Dir,
+    Symlink(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    kind: NodeKind,
+    len: u64,
+    modified: SystemTime,
+    accessed: SystemTime,
+    ino: u64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    nodes: BTreeMap<PathBuf, Node>,
+    failures: HashMap<PathBuf, io::ErrorKind>,
+    next_ino: u64,
+}
+
+/// A filesystem that lives in memory
+#[derive(Debug, Default)]
+pub struct MemFs {
+    state: Mutex<State>,
+}
+
+impl MemFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&mut State) -> R) -> R {
+        f(&mut self.state.lock().unwrap())
+    }
+
+    fn insert(self, path: &str, kind: NodeKind, len: u64) -> Self {
+        self.with(|s| {
+            let path = PathBuf::from(path);
+            for ancestor in path.ancestors().skip(1) {
+                if !s.nodes.contains_key(ancestor) {
+                    let node = s.new_node(NodeKind::Dir, 0);
+                    s.nodes.insert(ancestor.to_path_buf(), node);
+                }
+            }
+            let node = s.new_node(kind, len);
+            s.nodes.insert(path, node);
+        });
+        self
+    }
+
+    /// Add a directory
+    pub fn dir(self, path: &str) -> Self {
+        self.insert(path, NodeKind::Dir, 0)
+    }
+
+    /// Add a regular file of `len` bytes
+    pub fn file(self, path: &str, len: u64) -> Self {
+        self.insert(path, NodeKind::File, len)
+    }
+
+    /// Add a symlink pointing at `target`
+    pub fn symlink(self, path: &str, target: &str) -> Self {
+        let len = target.len() as u64;
+        self.insert(path, NodeKind::Symlink(PathBuf::from(target)), len)
+    }
+
+    /// Add `link` as another name for the file at `existing`
+    pub fn hardlink(self, existing: &str, link: &str) -> Self {
+        self.with(|s| {
+            let node = s.nodes[Path::new(existing)].clone();
+            s.nodes.insert(PathBuf::from(link), node);
+        });
+        self
+    }
+
+    /// Set access and modification time to `days` ago
+    pub fn aged(self, path: &str, days: u64) -> Self {
+        let then = SystemTime::now() - Duration::from_secs(days * 86_400);
+        self.times(path, then, then)
+    }
+
+    /// Set modification and access times
+    pub fn times(self, path: &str, modified: SystemTime, accessed: SystemTime) -> Self {
+        self.with(|s| {
+            let ino = s.nodes[Path::new(path)].ino;
+            // Hardlinks share their times
+            for node in s.nodes.values_mut().filter(|n| n.ino == ino) {
+                node.modified = modified;
+                node.accessed = accessed;
+            }
+        });
+        self
+    }
+
+    /// Make listing or removing `path` fail with `kind`
+    pub fn fail(self, path: &str, kind: io::ErrorKind) -> Self {
+        self.with(|s| s.failures.insert(PathBuf::from(path), kind));
+        self
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        self.with(|s| s.nodes.contains_key(Path::new(path)))
+    }
+}
+
+impl State {
+    fn new_node(&mut self, kind: NodeKind, len: u64) -> Node {
+        self.next_ino += 1;
+        let now = SystemTime::now();
+        Node {
+            kind,
+            len,
+            modified: now,
+            accessed: now,
+            ino: self.next_ino,
+        }
+    }
+
+    fn node(&self, path: &Path) -> io::Result<&Node> {
+        self.nodes
+            .get(path)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, path.display().to_string()))
+    }
+
+    fn check(&self, path: &Path) -> io::Result<()> {
+        match self.failures.get(path) {
+            Some(kind) => Err(io::Error::new(*kind, path.display().to_string())),
+            None => Ok(()),
+        }
+    }
+
+    fn meta(&self, node: &Node) -> Meta {
+        Meta {
+            kind: match node.kind {
+                NodeKind::File => Kind::File,
+                NodeKind::Dir => Kind::Dir,
+                NodeKind::Symlink(_) => Kind::Symlink,
+            },
+            len: node.len,
+            modified: Some(node.modified),
+            accessed: Some(node.accessed),
+            inode: Some((1, node.ino)),
+            links: self.nodes.values().filter(|n| n.ino == node.ino).count() as u64,
+        }
+    }
+}
+
+impl Fs for MemFs {
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Meta> {
+        self.with(|s| s.node(path).map(|n| s.meta(n)))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Meta> {
+        self.with(|s| {
+            let mut path = path.to_path_buf();
+            for _ in 0..40 {
+                let node = s.node(&path)?;
+                match &node.kind {
+                    NodeKind::Symlink(target) => {
+                        path = path.parent().unwrap_or(Path::new("/")).join(target);
+                    }
+                    _ => return Ok(s.meta(node)),
+                }
+            }
+            Err(io::Error::other("too many levels of symbolic links"))
+        })
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        self.with(|s| {
+            if !matches!(s.node(path)?.kind, NodeKind::Dir) {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotADirectory,
+                    "not a directory",
+                ));
+            }
+            s.check(path)?;
+            Ok(s.nodes
+                .keys()
+                .filter(|p| p.parent() == Some(path))
+                .cloned()
+                .collect())
+        })
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.with(|s| {
+            if matches!(s.node(path)?.kind, NodeKind::Dir) {
+                return Err(io::Error::new(
+                    io::ErrorKind::IsADirectory,
+                    "is a directory",
+                ));
+            }
+            s.check(path)?;
+            s.nodes.remove(path);
+            Ok(())
+        })
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        self.with(|s| {
+            if !matches!(s.node(path)?.kind, NodeKind::Dir) {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotADirectory,
+                    "not a directory",
+                ));
+            }
+            s.check(path)?;
+            if s.nodes.keys().any(|p| p.parent() == Some(path)) {
+                return Err(io::Error::new(
+                    io::ErrorKind::DirectoryNotEmpty,
+                    "directory not empty",
+                ));
+            }
+            s.nodes.remove(path);
+            Ok(())
+        })
+    }
+
+    /// Removes nothing if any path in the tree has a registered failure
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        self.with(|s| {
+            s.node(path)?;
+            let doomed: Vec<PathBuf> = s
+                .nodes
+                .keys()
+                .filter(|p| p.starts_with(path))
+                .cloned()
+                .collect();
+            for p in &doomed {
+                s.check(p)?;
+            }
+            for p in doomed {
+                s.nodes.remove(&p);
+            }
+            Ok(())
+        })
+    }
+}